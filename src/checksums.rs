@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use rattler_digest::{digest::Digest, Sha256, Sha256Hash};
+use serde::{Deserialize, Serialize};
+
+use crate::CHECKSUMS_PATH;
+
+/// Checksums of all files in a directory pack, written to `checksums.json`.
+///
+/// `digest` is a Merkle-style hash over the whole layout: every directory is hashed over the
+/// sorted list of its entries (their kind, name and digest). Two directory packs have the same
+/// digest if and only if they contain the same files with the same contents, regardless of
+/// timestamps. `checksums.json` itself is not included.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryChecksums {
+    /// The hex-encoded sha256 digest of the directory.
+    pub digest: String,
+    /// The hex-encoded sha256 hash of every file, keyed by its `/`-separated relative path.
+    pub files: BTreeMap<String, String>,
+}
+
+impl DirectoryChecksums {
+    /// Hash all files in `dir`. Symbolic links are not followed, their target is hashed instead.
+    pub fn new(dir: &Path) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        let digest = hash_directory(dir, Path::new(""), &mut files)?;
+        Ok(Self {
+            digest: format!("{:x}", digest),
+            files,
+        })
+    }
+}
+
+fn hash_directory(
+    root: &Path,
+    relative_dir: &Path,
+    files: &mut BTreeMap<String, String>,
+) -> Result<Sha256Hash> {
+    let dir = root.join(relative_dir);
+    let mut entries = std::fs::read_dir(&dir)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .map_err(|e| anyhow!("could not read directory {}: {}", dir.display(), e))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        let name = entry.file_name();
+        let relative_path: PathBuf = relative_dir.join(&name);
+        if relative_path == Path::new(CHECKSUMS_PATH) {
+            continue;
+        }
+        let name = name
+            .to_str()
+            .ok_or(anyhow!("invalid file name: {}", relative_path.display()))?;
+        let file_type = entry
+            .file_type()
+            .map_err(|e| anyhow!("could not read {}: {}", relative_path.display(), e))?;
+        let (kind, digest) = if file_type.is_dir() {
+            ("dir", hash_directory(root, &relative_path, files)?)
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(entry.path())
+                .map_err(|e| anyhow!("could not read {}: {}", relative_path.display(), e))?;
+            ("link", Sha256::digest(target.to_string_lossy().as_bytes()))
+        } else {
+            let digest = rattler_digest::compute_file_digest::<Sha256>(entry.path())
+                .map_err(|e| anyhow!("could not hash {}: {}", relative_path.display(), e))?;
+            files.insert(
+                relative_path
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                format!("{:x}", digest),
+            );
+            ("file", digest)
+        };
+        hasher.update(format!("{} {:x} {}\n", kind, digest, name).as_bytes());
+    }
+    Ok(hasher.finalize())
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    fn create_pack_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("channel").join("noarch")).unwrap();
+        std::fs::write(dir.path().join("channel/noarch/repodata.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("pixi-pack.json"), "{}").unwrap();
+        dir
+    }
+
+    #[rstest]
+    fn test_directory_checksums() {
+        let a = create_pack_dir();
+        let b = create_pack_dir();
+        let checksums = DirectoryChecksums::new(a.path()).unwrap();
+        assert_eq!(
+            checksums.files.keys().collect::<Vec<_>>(),
+            vec!["channel/noarch/repodata.json", "pixi-pack.json"]
+        );
+        assert_eq!(
+            checksums.files["pixi-pack.json"],
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+        assert_eq!(checksums, DirectoryChecksums::new(b.path()).unwrap());
+
+        // checksums.json itself is not part of the digest
+        std::fs::write(
+            a.path().join(CHECKSUMS_PATH),
+            serde_json::to_string(&checksums).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(checksums, DirectoryChecksums::new(a.path()).unwrap());
+
+        // neither file contents nor the layout may change
+        std::fs::write(b.path().join("pixi-pack.json"), "{ }").unwrap();
+        assert_ne!(
+            checksums.digest,
+            DirectoryChecksums::new(b.path()).unwrap().digest
+        );
+        let c = create_pack_dir();
+        std::fs::create_dir(c.path().join("channel/linux-64")).unwrap();
+        assert_ne!(
+            checksums.digest,
+            DirectoryChecksums::new(c.path()).unwrap().digest
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    future::Future,
+    io::Write,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Serialize;
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Print [`Event`]s as lines of JSON to stdout instead of human-readable messages to stderr, e.g.
+/// for CI log parsers. Progress bars are hidden as well.
+pub fn enable_json_output() {
+    JSON_OUTPUT.store(true, Ordering::Relaxed);
+    crate::util::hide_progress_bars();
+}
+
+/// Whether [`enable_json_output`] was called.
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+tokio::task_local! {
+    static SUPPRESSED: ();
+}
+
+/// Run `future` without printing status messages or emitting events, e.g. for the packs of the
+/// environments that [`crate::pack_environments`] combines and reports as one.
+pub(crate) async fn suppress_output<F: Future>(future: F) -> F::Output {
+    SUPPRESSED.scope((), future).await
+}
+
+/// Whether the current task runs in [`suppress_output`].
+pub(crate) fn output_suppressed() -> bool {
+    SUPPRESSED.try_with(|_| ()).is_ok()
+}
+
+/// A machine-readable progress or result event of packing or unpacking, see
+/// [`enable_json_output`].
+///
+/// Events are serialized with the kebab-case name of the variant in the `event` field, e.g.
+/// `{"event":"archive-created","output-file":"environment.tar",...}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "event",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+#[non_exhaustive]
+pub enum Event {
+    /// A package was added to the pack, either downloaded or copied from the cache.
+    PackageDownloaded {
+        file_name: String,
+        subdir: String,
+        /// The size recorded in the lockfile, if any.
+        size: Option<u64>,
+        cached: bool,
+    },
+    /// The pack was written, or pushed to a registry for OCI images.
+    ArchiveCreated {
+        output_file: PathBuf,
+        size_bytes: Option<u64>,
+        /// The sha256 digest of the pack as hex string, if it is a file.
+        sha256: Option<String>,
+        /// The number of zstd worker threads, see [`crate::PackResult::compression_threads`].
+        compression_threads: u32,
+    },
+    /// A non-fatal issue, see [`crate::PackWarning`].
+    Warning { message: String },
+    /// The environment was installed into the prefix.
+    PrefixInstalled {
+        prefix: PathBuf,
+        package_count: usize,
+        size_bytes: u64,
+    },
+    /// The prefix already contained the pack, see [`crate::UnpackOptionsBuilder::idempotent`].
+    PrefixUpToDate { prefix: PathBuf },
+    /// The system requirements of the pack were checked against the host, see
+    /// [`crate::UnpackOptionsBuilder::verify_only`].
+    SystemRequirementsChecked {
+        pack_file: PathBuf,
+        requirements: Vec<SystemRequirementCheck>,
+    },
+    /// The pack can be unpacked, see [`crate::UnpackOptionsBuilder::verify_only`].
+    PackVerified {
+        pack_file: PathBuf,
+        prefix: PathBuf,
+        package_count: usize,
+    },
+    /// The command failed.
+    Error { message: String },
+}
+
+/// Whether the host meets a [`crate::SystemRequirement`] of a pack, see
+/// [`crate::HostVirtualPackages::check`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SystemRequirementCheck {
+    /// The virtual package, e.g. `__cuda`.
+    pub name: String,
+    /// The version constraint, empty if any version is accepted.
+    pub spec: String,
+    /// The packages with this requirement.
+    pub packages: Vec<String>,
+    /// Whether the host meets the requirement, `None` if it cannot be checked.
+    pub met: Option<bool>,
+    /// Why the requirement is not met.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Event {
+    /// Print the event as a line of JSON to stdout if JSON output is enabled.
+    pub fn emit(&self) {
+        if !json_output() || output_suppressed() {
+            return;
+        }
+        let line = serde_json::to_string(self).expect("events can always be serialized");
+        if let Err(e) = writeln!(std::io::stdout().lock(), "{}", line) {
+            tracing::warn!("could not write event: {}", e);
+        }
+    }
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+    use serde_json::json;
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_suppress_output() {
+        assert!(!output_suppressed());
+        assert!(suppress_output(async { output_suppressed() }).await);
+        assert!(!output_suppressed());
+    }
+
+    #[rstest]
+    fn test_event_serialization() {
+        let event = Event::ArchiveCreated {
+            output_file: PathBuf::from("environment.tar"),
+            size_bytes: Some(2048),
+            sha256: None,
+            compression_threads: 4,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "event": "archive-created",
+                "output-file": "environment.tar",
+                "size-bytes": 2048,
+                "sha256": null,
+                "compression-threads": 4,
+            })
+        );
+        let event = Event::Warning {
+            message: "something happened".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"event": "warning", "message": "something happened"})
+        );
+        let event = Event::SystemRequirementsChecked {
+            pack_file: PathBuf::from("environment.tar"),
+            requirements: vec![
+                SystemRequirementCheck {
+                    name: "__cuda".to_string(),
+                    spec: ">=12".to_string(),
+                    packages: vec!["pytorch".to_string()],
+                    met: Some(false),
+                    reason: Some("__cuda is not available".to_string()),
+                },
+                SystemRequirementCheck {
+                    name: "__archspec".to_string(),
+                    spec: "1 x86_64_v3".to_string(),
+                    packages: vec!["numpy".to_string()],
+                    met: None,
+                    reason: None,
+                },
+            ],
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "event": "system-requirements-checked",
+                "pack-file": "environment.tar",
+                "requirements": [
+                    {
+                        "name": "__cuda",
+                        "spec": ">=12",
+                        "packages": ["pytorch"],
+                        "met": false,
+                        "reason": "__cuda is not available",
+                    },
+                    {
+                        "name": "__archspec",
+                        "spec": "1 x86_64_v3",
+                        "packages": ["numpy"],
+                        "met": null,
+                    },
+                ],
+            })
+        );
+    }
+}
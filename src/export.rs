@@ -0,0 +1,307 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use rattler_conda_types::PrefixRecord;
+
+/// The name of the requirements file written next to the exported `environment.yml`.
+const REQUIREMENTS_FILE_NAME: &str = "requirements.txt";
+
+/// The files written by [`export`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedEnvironment {
+    /// The path of the written `environment.yml`.
+    pub environment_file: PathBuf,
+    /// The path of the written `requirements.txt`, if PyPI packages are installed in the prefix.
+    pub requirements_file: Option<PathBuf>,
+    /// The number of exported conda packages.
+    pub conda_packages: usize,
+    /// The number of exported PyPI packages.
+    pub pypi_packages: usize,
+}
+
+/// A Python package that was installed into the prefix without conda, e.g. with `pip`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct PypiPackage {
+    name: String,
+    version: String,
+}
+
+/// Reconstruct an `environment.yml` from the conda packages recorded in `conda-meta` of an
+/// unpacked environment and write it to `output_file`.
+///
+/// Python packages whose `.dist-info` directory does not belong to a conda package are written
+/// to a `requirements.txt` next to `output_file` that is referenced from its `pip` section.
+/// Channels are only listed if they are recorded in `conda-meta`; packages unpacked with
+/// `pixi-pack` come from the local channel of the pack, so their specs pin the exact builds
+/// instead.
+pub async fn export(prefix: &Path, output_file: &Path) -> Result<ExportedEnvironment> {
+    if !prefix.join("conda-meta").is_dir() {
+        anyhow::bail!("{} is not a conda environment", prefix.display());
+    }
+    let mut records = PrefixRecord::collect_from_prefix(prefix)
+        .map_err(|e| anyhow!("could not read installed packages: {}", e))?;
+    records.sort_by(|a, b| {
+        let a = &a.repodata_record.package_record.name;
+        let b = &b.repodata_record.package_record.name;
+        a.as_normalized().cmp(b.as_normalized())
+    });
+    let pypi_packages = pypi_packages(prefix, &records)?;
+
+    let output_dir = match output_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let requirements_file = if pypi_packages.is_empty() {
+        None
+    } else {
+        let requirements_file = output_dir.join(REQUIREMENTS_FILE_NAME);
+        let requirements: String = pypi_packages
+            .iter()
+            .map(|package| format!("{}=={}\n", package.name, package.version))
+            .collect();
+        tokio::fs::write(&requirements_file, requirements)
+            .await
+            .map_err(|e| anyhow!("could not write {}: {}", requirements_file.display(), e))?;
+        Some(requirements_file)
+    };
+
+    tokio::fs::write(
+        output_file,
+        environment_yml(&records, requirements_file.is_some()),
+    )
+    .await
+    .map_err(|e| anyhow!("could not write {}: {}", output_file.display(), e))?;
+
+    Ok(ExportedEnvironment {
+        environment_file: output_file.to_path_buf(),
+        requirements_file,
+        conda_packages: records.len(),
+        pypi_packages: pypi_packages.len(),
+    })
+}
+
+/// Render the `environment.yml` for the given conda packages.
+fn environment_yml(records: &[PrefixRecord], with_requirements: bool) -> String {
+    let mut environment = String::new();
+
+    let channels: BTreeSet<&str> = records
+        .iter()
+        .filter_map(|r| r.repodata_record.channel.as_deref())
+        .filter(|channel| !channel.is_empty())
+        .collect();
+    if !channels.is_empty() {
+        environment.push_str("channels:\n");
+        for channel in channels {
+            environment.push_str(&format!("  - {}\n", channel));
+        }
+    }
+
+    environment.push_str("dependencies:\n");
+    for record in records {
+        let package = &record.repodata_record.package_record;
+        environment.push_str(&format!(
+            "  - {}={}={}\n",
+            package.name.as_normalized(),
+            package.version,
+            package.build
+        ));
+    }
+    if with_requirements {
+        environment.push_str("  - pip:\n");
+        environment.push_str(&format!("    - -r {}\n", REQUIREMENTS_FILE_NAME));
+    }
+    environment
+}
+
+/// The `site-packages` directories of all Python installations in `prefix`.
+fn site_packages_dirs(prefix: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    // Windows: `Lib/site-packages`.
+    if prefix.join("Lib").join("site-packages").is_dir() {
+        dirs.push(PathBuf::from("Lib").join("site-packages"));
+    }
+    // Unix: `lib/python3.12/site-packages`.
+    let lib = prefix.join("lib");
+    if lib.is_dir() {
+        for entry in std::fs::read_dir(&lib)
+            .map_err(|e| anyhow!("could not read {}: {}", lib.display(), e))?
+        {
+            let name = entry?.file_name();
+            let site_packages = Path::new("lib").join(&name).join("site-packages");
+            if name.to_string_lossy().starts_with("python") && prefix.join(&site_packages).is_dir()
+            {
+                dirs.push(site_packages);
+            }
+        }
+    }
+    Ok(dirs)
+}
+
+/// The Python packages in `prefix` that are not part of any of the conda packages in `records`.
+fn pypi_packages(prefix: &Path, records: &[PrefixRecord]) -> Result<Vec<PypiPackage>> {
+    let conda_files: HashSet<&Path> = records
+        .iter()
+        .flat_map(|r| r.files.iter().map(PathBuf::as_path))
+        .collect();
+
+    let mut packages = BTreeSet::new();
+    for site_packages in site_packages_dirs(prefix)? {
+        let dir = prefix.join(&site_packages);
+        for entry in std::fs::read_dir(&dir)
+            .map_err(|e| anyhow!("could not read {}: {}", dir.display(), e))?
+        {
+            let name = entry?.file_name();
+            if !name.to_string_lossy().ends_with(".dist-info") {
+                continue;
+            }
+            // Conda records paths with forward slashes on all platforms.
+            let metadata = site_packages.join(&name).join("METADATA");
+            let conda_metadata = PathBuf::from(metadata.to_string_lossy().replace('\\', "/"));
+            if conda_files.contains(metadata.as_path())
+                || conda_files.contains(conda_metadata.as_path())
+            {
+                continue;
+            }
+            let contents = std::fs::read_to_string(prefix.join(&metadata))
+                .map_err(|e| anyhow!("could not read {}: {}", metadata.display(), e))?;
+            packages.insert(
+                parse_metadata(&contents)
+                    .ok_or_else(|| anyhow!("{} has no name or version", metadata.display()))?,
+            );
+        }
+    }
+    Ok(packages.into_iter().collect())
+}
+
+/// Read the name and version from the headers of a `METADATA` file of a `.dist-info` directory.
+fn parse_metadata(contents: &str) -> Option<PypiPackage> {
+    let mut name = None;
+    let mut version = None;
+    for line in contents.lines().take_while(|line| !line.is_empty()) {
+        if let Some(value) = line.strip_prefix("Name:") {
+            name = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("Version:") {
+            version = Some(value.trim().to_string());
+        }
+    }
+    Some(PypiPackage {
+        name: name?,
+        version: version?,
+    })
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rattler_conda_types::{PackageRecord, RepoDataRecord};
+    use rstest::*;
+
+    fn write_record(prefix: &Path, name: &str, channel: Option<&str>, files: &[&str]) {
+        let file_name = format!("{}-1.0-0.conda", name);
+        let record = RepoDataRecord {
+            package_record: PackageRecord::new(
+                name.parse().unwrap(),
+                "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+                "0".to_string(),
+            ),
+            url: url::Url::parse(&format!("file:///channel/{}", file_name)).unwrap(),
+            file_name,
+            channel: channel.map(str::to_string),
+        };
+        let mut record = PrefixRecord::from_repodata_record(record, None, None, vec![], None, None);
+        record.files = files.iter().map(PathBuf::from).collect();
+        record
+            .write_to_path(prefix.join(format!("conda-meta/{}-1.0-0.json", name)), true)
+            .unwrap();
+    }
+
+    fn write_dist_info(prefix: &Path, name: &str, version: &str) {
+        let dist_info = prefix
+            .join("lib/python3.12/site-packages")
+            .join(format!("{}-{}.dist-info", name, version));
+        std::fs::create_dir_all(&dist_info).unwrap();
+        std::fs::write(
+            dist_info.join("METADATA"),
+            format!(
+                "Metadata-Version: 2.1\nName: {}\nVersion: {}\n\nName: not-a-header\n",
+                name, version
+            ),
+        )
+        .unwrap();
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("env");
+        std::fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        write_record(&prefix, "python", None, &[]);
+        write_record(
+            &prefix,
+            "numpy",
+            Some("https://conda.anaconda.org/conda-forge/"),
+            &["lib/python3.12/site-packages/numpy-1.0.dist-info/METADATA"],
+        );
+        write_dist_info(&prefix, "numpy", "1.0");
+        write_dist_info(&prefix, "requests", "2.32.3");
+
+        let output_file = dir.path().join("environment.yml");
+        let exported = export(&prefix, &output_file).await.unwrap();
+        assert_eq!(exported.conda_packages, 2);
+        assert_eq!(exported.pypi_packages, 1);
+        assert_eq!(
+            std::fs::read_to_string(&output_file).unwrap(),
+            "channels:\n  - https://conda.anaconda.org/conda-forge/\ndependencies:\n  - numpy=1.0=0\n  - python=1.0=0\n  - pip:\n    - -r requirements.txt\n"
+        );
+        assert_eq!(
+            exported.requirements_file,
+            Some(dir.path().join("requirements.txt"))
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("requirements.txt")).unwrap(),
+            "requests==2.32.3\n"
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_export_without_pypi_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("conda-meta")).unwrap();
+        write_record(dir.path(), "python", None, &[]);
+
+        let output_file = dir.path().join("environment.yml");
+        let exported = export(dir.path(), &output_file).await.unwrap();
+        assert_eq!(exported.requirements_file, None);
+        assert_eq!(
+            std::fs::read_to_string(&output_file).unwrap(),
+            "dependencies:\n  - python=1.0=0\n"
+        );
+
+        assert!(export(&dir.path().join("missing"), &output_file)
+            .await
+            .is_err());
+    }
+
+    #[rstest]
+    #[case("Name: requests\nVersion: 2.32.3\n", Some(("requests", "2.32.3")))]
+    #[case("Metadata-Version: 2.1\nName: a\n\nVersion: 1.0\n", None)]
+    #[case("", None)]
+    fn test_parse_metadata(#[case] contents: &str, #[case] expected: Option<(&str, &str)>) {
+        assert_eq!(
+            parse_metadata(contents),
+            expected.map(|(name, version)| PypiPackage {
+                name: name.to_string(),
+                version: version.to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use rattler_conda_types::Platform;
+use serde::{Deserialize, Serialize};
+
+use crate::PIXI_PACK_VERSION;
+
+/// A self-contained description of a failed command, e.g. to attach to a support ticket from an
+/// air-gapped machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FailureReport {
+    /// The command line of the failed invocation.
+    pub command: Vec<String>,
+    /// The step that was running when the error occurred, if known.
+    pub phase: Option<String>,
+    /// The error followed by its causes.
+    pub errors: Vec<String>,
+    /// The package files mentioned in the errors.
+    pub packages: Vec<String>,
+    /// The version of pixi-pack.
+    pub pixi_pack_version: String,
+    /// The platform pixi-pack is running on.
+    pub platform: Platform,
+    /// The working directory of the failed invocation.
+    pub working_directory: Option<PathBuf>,
+}
+
+impl FailureReport {
+    /// Describe `error`, which occurred while running `phase`, for the current process.
+    pub fn new(error: &anyhow::Error, phase: Option<String>) -> Self {
+        let errors: Vec<String> = error.chain().map(|cause| cause.to_string()).collect();
+        let packages = packages_in_errors(&errors);
+        Self {
+            command: std::env::args().collect(),
+            phase,
+            errors,
+            packages,
+            pixi_pack_version: PIXI_PACK_VERSION.to_string(),
+            platform: Platform::current(),
+            working_directory: std::env::current_dir().ok(),
+        }
+    }
+
+    /// Write the report as JSON to `path`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, contents).map_err(|e| {
+            anyhow!(
+                "could not write failure report to {}: {}",
+                path.display(),
+                e
+            )
+        })
+    }
+}
+
+/// The conda package files that are mentioned in `errors`, in order of appearance.
+fn packages_in_errors(errors: &[String]) -> Vec<String> {
+    let mut packages: Vec<String> = Vec::new();
+    for word in errors.iter().flat_map(|error| error.split_whitespace()) {
+        let word = word.trim_matches(|c: char| "\"'`()[]{},;:".contains(c));
+        let file_name = word.rsplit(['/', '\\']).next().unwrap_or(word);
+        let is_package = file_name.ends_with(".conda") || file_name.ends_with(".tar.bz2");
+        if is_package && !packages.iter().any(|p| p == file_name) {
+            packages.push(file_name.to_string());
+        }
+    }
+    packages
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case(
+        "could not download package: https://conda.anaconda.org/conda-forge/noarch/tzdata-2024a-h0c530f3_0.conda: timeout",
+        vec!["tzdata-2024a-h0c530f3_0.conda"]
+    )]
+    #[case(
+        "could not extract \"python\": \"/tmp/channel/linux-64/python-3.12.3-h2628c8c_0.tar.bz2\", \"bzip2-1.0.8-0.conda\"",
+        vec!["python-3.12.3-h2628c8c_0.tar.bz2", "bzip2-1.0.8-0.conda"]
+    )]
+    #[case("could not create prefix: permission denied", vec![])]
+    fn test_packages_in_errors(#[case] error: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(packages_in_errors(&[error.to_string()]), expected);
+    }
+
+    #[rstest]
+    fn test_failure_report() {
+        let error = anyhow!("a-1.0-0.conda is corrupt").context("could not download package");
+        let report = FailureReport::new(&error, Some("Downloading 1 packages...".into()));
+        assert_eq!(
+            report.errors,
+            vec!["could not download package", "a-1.0-0.conda is corrupt"]
+        );
+        assert_eq!(report.packages, vec!["a-1.0-0.conda"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pixi-pack-failure.json");
+        report.write(&path).unwrap();
+        let written: FailureReport =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, report);
+    }
+}
@@ -0,0 +1,55 @@
+@echo off
+setlocal
+
+for %%a in (%*) do (
+    if /i "%%~a"=="-h" goto :usage
+    if /i "%%~a"=="--help" goto :usage
+)
+
+set "TEMPDIR=%TEMP%\pixi-pack-%RANDOM%%RANDOM%"
+mkdir "%TEMPDIR%"
+if errorlevel 1 (
+    echo ERROR: Failed to create temporary directory %TEMPDIR%. 1>&2
+    exit /b 1
+)
+
+rem The payload is a base64 encoded tar archive containing the packed environment
+rem and the pixi-pack executable, see the end of this file.
+certutil -f -decode "%~f0" "%TEMPDIR%\payload.tar" >nul
+if errorlevel 1 (
+    echo ERROR: Failed to decode the embedded payload. 1>&2
+    rmdir /s /q "%TEMPDIR%"
+    exit /b 1
+)
+
+tar -xf "%TEMPDIR%\payload.tar" -C "%TEMPDIR%"
+if errorlevel 1 (
+    echo ERROR: Failed to extract the embedded payload. 1>&2
+    rmdir /s /q "%TEMPDIR%"
+    exit /b 1
+)
+
+"%TEMPDIR%\pixi-pack.exe" unpack %* "%TEMPDIR%\environment.tar"
+set "EXIT_CODE=%ERRORLEVEL%"
+
+rmdir /s /q "%TEMPDIR%"
+exit /b %EXIT_CODE%
+
+:usage
+echo Usage: %~nx0 [OPTIONS]
+echo.
+echo Arguments:
+echo     Path to an environment packed using pixi-pack
+echo.
+echo Options:
+echo     -o, --output-directory ^<DIR^>    Where to unpack the environment. The environment will be unpacked into a subdirectory of this path [default: env]
+echo     -e, --env-name ^<NAME^>           Name of the environment [default: env]
+echo     -s, --shell ^<SHELL^>             Sets the shell [options: bash, zsh, xonsh, cmd, powershell, fish, nushell]
+echo         --activation-script ^<PATH^>  Where to write the activation script [default: activate.^<extension^> in the output directory]
+echo         --skip-activation-script    Don't write an activation script
+echo         --environment ^<NAME^>        The environment to unpack from a pack of multiple environments
+echo         --verify-only               Only check whether the pack can be unpacked and print the packages that would be installed
+echo     -v, --verbose                   Increase logging verbosity
+echo     -q, --quiet                     Decrease logging verbosity
+echo     -h, --help                      Print help
+exit /b 0
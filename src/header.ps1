@@ -18,6 +18,10 @@ Options:
     -o, --output-directory <DIR>    Where to unpack the environment. The environment will be unpacked into a subdirectory of this path [default: env]
     -e, --env-name <NAME>           Name of the environment [default: env]
     -s, --shell <SHELL>             Sets the shell [options: bash, zsh, xonsh, cmd, powershell, fish, nushell]
+        --activation-script <PATH>  Where to write the activation script [default: activate.<extension> in the output directory]
+        --skip-activation-script    Don't write an activation script
+        --environment <NAME>        The environment to unpack from a pack of multiple environments
+        --verify-only               Only check whether the pack can be unpacked and print the packages that would be installed
     -v, --verbose                   Increase logging verbosity
     -q, --quiet                     Decrease logging verbosity
     -h, --help                      Print help
@@ -30,53 +34,51 @@ foreach ($arg in $args) {
     }
 }
 
-# Extract the archive and pixi-pack executable, and decode them
-$scriptContent = Get-Content -Raw -Path $MyInvocation.MyCommand.Path
-$lines = $scriptContent -split "`r?`n"
+# Extract the archive and pixi-pack executable, and decode them.
+# The base64 content is decoded line by line while reading the script, so the payload is never
+# held in memory as a whole.
+$archivePath = "$TEMPDIR\archive.tar"
+$pixiPackPath = "$TEMPDIR\pixi-pack.exe"
 
-$headerLine = $null
-$archiveLine = $null
+$reader = New-Object System.IO.StreamReader($MyInvocation.MyCommand.Path)
+$archiveFile = $null
+$pixiPackFile = $null
+$output = $null
 
-# Find the lines where __END_HEADER__ and __END_ARCHIVE__ occur
-for ($i = 0; $i -lt $lines.Count; $i++) {
-    if ($lines[$i] -like "*__END_HEADER__*") {
-        $headerLine = $i + 2
-    }
-    if ($lines[$i] -like "*__END_ARCHIVE__*") {
-        $archiveLine = $i + 1
-    }
-}
-
-if (-not $headerLine -or -not $archiveLine) {
-    Write-Error "ERROR: Markers __END_HEADER__ or __END_ARCHIVE__ not found."
-    exit 1
-}
-
-# Extract Base64 content for the tar archive
-$archiveContent = $lines[($headerLine)..($archiveLine - 2)] -join ""
-$archiveContent = $archiveContent.Trim()
-
-# Decode Base64 content into tar file
 try {
-    $decodedArchive = [System.Convert]::FromBase64String($archiveContent)
-    $archivePath = "$TEMPDIR\archive.tar"
-    [System.IO.File]::WriteAllBytes($archivePath, $decodedArchive)
+    while ($null -ne ($line = $reader.ReadLine())) {
+        # The markers are at the start of a line, in contrast to their mentions in this header
+        if ($line -like "__END_HEADER__*") {
+            $archiveFile = [System.IO.File]::Create($archivePath)
+            $output = $archiveFile
+            continue
+        }
+        if ($line -like "__END_ARCHIVE__*") {
+            $pixiPackFile = [System.IO.File]::Create($pixiPackPath)
+            $output = $pixiPackFile
+            continue
+        }
+        if ($null -eq $output) {
+            continue
+        }
+        $line = $line.Trim()
+        if ($line.Length -eq 0) {
+            continue
+        }
+        $decoded = [System.Convert]::FromBase64String($line)
+        $output.Write($decoded, 0, $decoded.Length)
+    }
 } catch {
-    Write-Error "ERROR: Failed to decode Base64 archive content: $_"
+    Write-Error "ERROR: Failed to decode Base64 content: $_"
     exit 1
+} finally {
+    $reader.Close()
+    if ($archiveFile) { $archiveFile.Close() }
+    if ($pixiPackFile) { $pixiPackFile.Close() }
 }
 
-# Extract Base64 content for pixi-pack executable
-$pixiPackContent = $lines[($archiveLine)..($lines.Count - 1)] -join ""
-$pixiPackContent = $pixiPackContent.Trim()
-
-# Decode Base64 content into the pixi-pack executable file
-try {
-    $decodedPixiPack = [System.Convert]::FromBase64String($pixiPackContent)
-    $pixiPackPath = "$TEMPDIR\pixi-pack.exe"
-    [System.IO.File]::WriteAllBytes($pixiPackPath, $decodedPixiPack)
-} catch {
-    Write-Error "Failed to decode Base64 pixi-pack content: $_"
+if (-not $archiveFile -or -not $pixiPackFile) {
+    Write-Error "ERROR: Markers __END_HEADER__ or __END_ARCHIVE__ not found."
     exit 1
 }
 
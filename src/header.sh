@@ -12,6 +12,10 @@ Options:
   -o, --output-directory <DIR>    Where to unpack the environment. The environment will be unpacked into a subdirectory of this path [default: env]
   -e, --env-name <NAME>           Name of the environment [default: env]
   -s, --shell <SHELL>             Sets the shell [options: bash, zsh, xonsh, cmd, powershell, fish, nushell]
+      --activation-script <PATH>  Where to write the activation script [default: activate.<extension> in the output directory]
+      --skip-activation-script    Don't write an activation script
+      --environment <NAME>        The environment to unpack from a pack of multiple environments
+      --verify-only               Only check whether the pack can be unpacked and print the packages that would be installed
   -v, --verbose                   Increase logging verbosity
   -q, --quiet                     Decrease logging verbosity
   -h, --help                      Print help
@@ -37,7 +41,9 @@ archive_begin=$((archive_begin + 2))
 archive_end=$((archive_end - 1))
 pixi_pack_start=$((archive_end + 2))
 
-sed -n "$archive_begin,${archive_end}p" "$0" | base64 -d > "$TEMPDIR/archive.tar"
+# The base64 content is wrapped into short lines, so it is decoded as a stream without
+# holding the payload in memory.
+sed -n "${archive_begin},${archive_end}p;${archive_end}q" "$0" | base64 -d > "$TEMPDIR/archive.tar"
 sed -n "$pixi_pack_start,\$p" "$0" | base64 -d > "$TEMPDIR/pixi-pack"
 
 chmod +x "$TEMPDIR/pixi-pack"
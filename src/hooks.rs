@@ -0,0 +1,113 @@
+use std::{fmt, path::Path};
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use rattler_conda_types::Platform;
+use tokio::process::Command;
+
+/// A check that runs on the contents of a pack after all packages are downloaded and before the
+/// pack is archived, e.g. a virus scanner or an internal policy check.
+///
+/// The staging directory has the same layout as a directory pack. For packs of multiple
+/// environments, hooks run once on the combined pack, see [`crate::pack_environments`].
+/// Returning an error aborts packing. Hooks are added with [`crate::PackOptionsBuilder::hook`].
+pub trait PackHook: fmt::Debug + Send + Sync {
+    /// A short description of the hook for progress and error messages.
+    fn name(&self) -> String;
+
+    /// Check the contents of the pack for `platform` in `staging_dir`.
+    fn run<'a>(&'a self, staging_dir: &'a Path, platform: Platform) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Runs a shell command with the staging directory appended as last argument.
+///
+/// The staging directory and the platform are also available in the `PIXI_PACK_STAGING_DIR` and
+/// `PIXI_PACK_PLATFORM` environment variables. A non-zero exit code aborts packing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandHook {
+    command: String,
+}
+
+impl CommandHook {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+        }
+    }
+
+    fn command(&self, staging_dir: &Path) -> Command {
+        #[cfg(not(windows))]
+        let mut command = {
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(format!("{} \"$@\"", self.command))
+                .arg("sh")
+                .arg(staging_dir);
+            command
+        };
+        #[cfg(windows)]
+        let mut command = {
+            let mut command = Command::new("cmd");
+            command
+                .arg("/C")
+                .arg(format!("{} \"{}\"", self.command, staging_dir.display()));
+            command
+        };
+        command.env("PIXI_PACK_STAGING_DIR", staging_dir);
+        command
+    }
+}
+
+impl PackHook for CommandHook {
+    fn name(&self) -> String {
+        format!("`{}`", self.command)
+    }
+
+    fn run<'a>(&'a self, staging_dir: &'a Path, platform: Platform) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let status = self
+                .command(staging_dir)
+                .env("PIXI_PACK_PLATFORM", platform.as_str())
+                .status()
+                .await
+                .map_err(|e| anyhow!("could not run {}: {}", self.name(), e))?;
+            if !status.success() {
+                anyhow::bail!("{} failed with {}", self.name(), status);
+            }
+            Ok(())
+        })
+    }
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[cfg(not(windows))]
+    #[rstest]
+    #[tokio::test]
+    async fn test_command_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let staging_dir = dir.path().join("staging dir");
+        std::fs::create_dir(&staging_dir).unwrap();
+        std::fs::write(staging_dir.join("pixi-pack.json"), "{}").unwrap();
+
+        let hook = CommandHook::new(
+            "test \"$PIXI_PACK_STAGING_DIR\" = \"$1\" && test \"$PIXI_PACK_PLATFORM\" = linux-64 && test -f",
+        );
+        let error = hook.run(&staging_dir, Platform::Linux64).await.unwrap_err();
+        assert!(error.to_string().ends_with("failed with exit status: 1"));
+
+        let hook = CommandHook::new(
+            "test \"$PIXI_PACK_PLATFORM\" = linux-64 && test -f \"$PIXI_PACK_STAGING_DIR/pixi-pack.json\" && test -d",
+        );
+        hook.run(&staging_dir, Platform::Linux64).await.unwrap();
+        assert!(hook.run(&staging_dir, Platform::Win64).await.is_err());
+    }
+}
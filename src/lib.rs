@@ -1,15 +1,91 @@
+/// Print a human-readable status message to stderr, unless JSON output is enabled, see
+/// [`enable_json_output`], or the output is suppressed, see `events::suppress_output`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::json_output() && !$crate::events::output_suppressed() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+mod checksums;
+mod events;
+mod export;
+mod failure_report;
+mod hooks;
+mod list;
+mod manifest;
+mod network_log;
+mod oci;
 mod pack;
+mod permissions;
+#[cfg(unix)]
+mod serve;
+mod signature;
+mod sources;
+mod ssh;
+mod storage;
 mod unpack;
 mod util;
+mod validate;
+mod virtual_packages;
+
+use std::{collections::BTreeMap, fmt};
 
-pub use pack::{pack, PackOptions};
-use rattler_conda_types::Platform;
+use anyhow::{anyhow, Result};
+pub use checksums::DirectoryChecksums;
+pub use events::{enable_json_output, json_output, Event, SystemRequirementCheck};
+pub use export::{export, ExportedEnvironment};
+pub use failure_report::FailureReport;
+pub use hooks::{CommandHook, PackHook};
+pub use list::{list, PackContents, PackedPackage};
+pub use network_log::{NetworkLog, NetworkLogEntry};
+pub use oci::{pull_from_registry, push_to_registry, OciReference};
+pub use pack::{
+    executable_platforms, fetch, lockfile_environments, lockfile_platforms, pack,
+    pack_environments, pack_many, render_header, render_instructions, ChannelPriority, Compression,
+    DownloadOrder, ExecutableFormat, HttpOptions, LineEndings, LockfileEnvironment, OutputFormat,
+    PackOptions, PackOptionsBuilder, PackResult, PackWarning, WindowsFlavor,
+};
+pub use permissions::{Ownership, PermissionPolicy};
+use rattler_conda_types::{Platform, Version};
 use serde::{Deserialize, Serialize};
-pub use unpack::{unarchive, unpack, UnpackOptions};
-pub use util::{get_size, ProgressReporter};
+use serde_json::Value;
+#[cfg(unix)]
+pub use serve::serve;
+pub use signature::{signature_path, SigningKey, VerifyingKey, SIGNATURE_EXTENSION};
+pub use sources::{PackSources, PackageSource};
+pub use ssh::SshDestination;
+pub use storage::{FetchProgress, FileStorage, HttpStorage, RetryPolicy, Storage, Storages};
+pub use unpack::{
+    read_instructions, read_metadata, unarchive, unpack, validate_compatibility,
+    write_activation_script, LinkScriptsReport, PathModification, UnpackOptions,
+    UnpackOptionsBuilder, UnpackOutcome,
+};
+pub use util::{get_size, ProgressReporter, SizeReport};
+pub use validate::{validate, ValidationReport};
+pub use virtual_packages::HostVirtualPackages;
+
+/// Low-level building blocks of [`pack`] and [`unpack`].
+///
+/// These functions can be used to compose custom packing flows, e.g., to create a pack from a
+/// list of package records that does not originate from a pixi lockfile.
+pub mod low_level {
+    pub use crate::pack::{
+        archive_directory, create_environment_file, create_repodata_files,
+        default_executable_header, download_package, download_pixi_pack_executable, http_client,
+        reqwest_client, reqwest_client_from_auth_storage, write_binary_executable,
+        write_self_extracting_executable,
+    };
+    pub use crate::unpack::{create_prefix, embedded_pack};
+}
 
 pub const CHANNEL_DIRECTORY_NAME: &str = "channel";
 pub const PIXI_PACK_METADATA_PATH: &str = "pixi-pack.json";
+pub const INSTRUCTIONS_PATH: &str = "INSTRUCTIONS.md";
+pub const CHECKSUMS_PATH: &str = "checksums.json";
+pub const SOURCES_PATH: &str = "sources.json";
+pub const ENVIRONMENTS_DIRECTORY_NAME: &str = "environments";
 pub const DEFAULT_PIXI_PACK_VERSION: &str = "1";
 pub const PIXI_PACK_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -23,6 +99,32 @@ pub struct PixiPackMetadata {
     pub pixi_pack_version: Option<String>,
     /// The platform the pack was created for.
     pub platform: Platform,
+    /// Packages that were injected into the pack and are not part of the lockfile.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub injected: Vec<InjectedPackage>,
+    /// Unix timestamp of the build date of the pack, only recorded when it is set explicitly
+    /// (e.g. via `SOURCE_DATE_EPOCH`) to keep packs reproducible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    /// The dependencies requested in the manifest as match specs, e.g. `python >=3.12`.
+    /// They are recorded in `conda-meta` when unpacking.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requested_specs: Vec<String>,
+    /// Constraints of the packages on the host system, e.g. on the CUDA driver or glibc.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub system_requirements: Vec<SystemRequirement>,
+    /// The packages that are left out of a delta pack because they are contained in its base
+    /// pack, as paths relative to the pack, e.g. `channel/noarch/tzdata-2024a-h0c530f3_0.conda`.
+    /// They are listed in the repodata of the pack nevertheless.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub base_packages: Vec<String>,
+    /// The environments contained in a pack of multiple environments. Each of them is a pack of
+    /// its own in `environments/<name>`, one of them is selected when unpacking.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub environments: Vec<String>,
+    /// Custom key/value pairs set when packing, e.g. a build ID or the git commit of a release.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
 }
 
 impl Default for PixiPackMetadata {
@@ -31,7 +133,182 @@ impl Default for PixiPackMetadata {
             version: DEFAULT_PIXI_PACK_VERSION.to_string(),
             pixi_pack_version: Some(PIXI_PACK_VERSION.to_string()),
             platform: Platform::current(),
+            injected: Vec::new(),
+            created: None,
+            requested_specs: Vec::new(),
+            system_requirements: Vec::new(),
+            base_packages: Vec::new(),
+            environments: Vec::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+/// A package that was added to a pack via `--inject` or `--inject-channel`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct InjectedPackage {
+    /// The name of the package.
+    pub name: String,
+    /// The version of the package.
+    pub version: String,
+    /// The build string of the package.
+    pub build: String,
+    /// The file name of the package inside the pack.
+    pub filename: String,
+    /// The hex-encoded sha256 hash of the package file.
+    pub sha256: String,
+}
+
+/// A constraint of packages in a pack on a virtual package, i.e. on the host system.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SystemRequirement {
+    /// The virtual package, e.g. `__cuda` or `__glibc`.
+    pub name: String,
+    /// The version constraint, e.g. `>=12.2`. Empty if any version is accepted.
+    pub spec: String,
+    /// The packages with this constraint.
+    pub packages: Vec<String>,
+}
+
+/// The minimum Linux driver versions that support a CUDA version, from the CUDA release notes.
+const CUDA_DRIVER_VERSIONS: [(&str, &str); 16] = [
+    ("11.0", "450.36.06"),
+    ("11.1", "455.23.05"),
+    ("11.2", "460.27.03"),
+    ("11.3", "465.19.01"),
+    ("11.4", "470.42.01"),
+    ("11.5", "495.29.05"),
+    ("11.6", "510.39.01"),
+    ("11.7", "515.43.04"),
+    ("11.8", "520.61.05"),
+    ("12.0", "525.60.13"),
+    ("12.1", "530.30.02"),
+    ("12.2", "535.54.03"),
+    ("12.3", "545.23.06"),
+    ("12.4", "550.54.14"),
+    ("12.5", "555.42.02"),
+    ("12.6", "560.28.03"),
+];
+
+impl SystemRequirement {
+    /// A human-readable description of what the host needs, e.g.
+    /// `requires NVIDIA driver >= 535.54.03`.
+    pub fn host_requirement(&self) -> String {
+        let lower_bound = self
+            .spec
+            .split([',', '|'])
+            .find_map(|constraint| constraint.trim().strip_prefix(">="))
+            .and_then(|version| version.parse::<Version>().ok());
+        match (self.name.as_str(), lower_bound) {
+            ("__cuda", Some(cuda)) => {
+                // The driver versions are listed per `major.minor` release, e.g. `12` is `12.0`.
+                let cuda_version = cuda.to_string();
+                let mut segments = cuda_version.split('.');
+                let major_minor = format!(
+                    "{}.{}",
+                    segments.next().unwrap_or_default(),
+                    segments.next().unwrap_or("0")
+                );
+                let driver = CUDA_DRIVER_VERSIONS
+                    .iter()
+                    .find(|(version, _)| *version == major_minor)
+                    .map(|(_, driver)| *driver);
+                match driver {
+                    Some(driver) => format!("requires NVIDIA driver >= {}", driver),
+                    None => format!(
+                        "requires NVIDIA driver supporting CUDA >= {} (minimum driver version unknown)",
+                        cuda
+                    ),
+                }
+            }
+            ("__cuda", None) => "requires NVIDIA driver".to_string(),
+            ("__glibc", Some(glibc)) => format!("requires glibc >= {}", glibc),
+            _ => format!("requires {}", self),
+        }
+    }
+}
+
+impl fmt::Display for SystemRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.spec.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} {}", self.name, self.spec)
+        }
+    }
+}
+
+/// A non-fatal issue found while reading a `pixi-pack.json` file.
+///
+/// Older (or newer) packs may carry metadata that differs from what this version of pixi-pack
+/// writes. Instead of failing, such differences are reported as warnings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataWarning {
+    /// The pack does not record which version of pixi-pack created it.
+    MissingPixiPackVersion,
+    /// The pack was created with a different version of pixi-pack.
+    DifferentPixiPackVersion(String),
+    /// The metadata contains fields that are unknown to this version of pixi-pack.
+    UnknownFields(Vec<String>),
+}
+
+impl fmt::Display for MetadataWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataWarning::MissingPixiPackVersion => write!(
+                f,
+                "The pack does not specify which version of pixi-pack created it"
+            ),
+            MetadataWarning::DifferentPixiPackVersion(version) => write!(
+                f,
+                "The pack was created with a different version of pixi-pack: {} (current: {})",
+                version, PIXI_PACK_VERSION
+            ),
+            MetadataWarning::UnknownFields(fields) => write!(
+                f,
+                "The pack metadata contains fields unknown to this version of pixi-pack: {}",
+                fields.join(", ")
+            ),
+        }
+    }
+}
+
+/// Raw representation of `pixi-pack.json` that keeps track of unknown fields.
+#[derive(Deserialize)]
+struct RawPixiPackMetadata {
+    #[serde(flatten)]
+    metadata: PixiPackMetadata,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, Value>,
+}
+
+impl PixiPackMetadata {
+    /// Read metadata from the contents of a `pixi-pack.json` file.
+    ///
+    /// Differences to the current metadata schema that do not prevent installing the pack
+    /// (e.g., a missing `pixi-pack-version` or fields added by newer versions) are returned as
+    /// warnings. Malformed metadata results in an error.
+    pub fn from_json(contents: &str) -> Result<(Self, Vec<MetadataWarning>)> {
+        let raw: RawPixiPackMetadata = serde_json::from_str(contents)
+            .map_err(|e| anyhow!("could not parse pack metadata: {}", e))?;
+
+        let mut warnings = Vec::new();
+        match &raw.metadata.pixi_pack_version {
+            None => warnings.push(MetadataWarning::MissingPixiPackVersion),
+            Some(version) if version != PIXI_PACK_VERSION => {
+                warnings.push(MetadataWarning::DifferentPixiPackVersion(version.clone()))
+            }
+            Some(_) => {}
         }
+        if !raw.unknown.is_empty() {
+            warnings.push(MetadataWarning::UnknownFields(
+                raw.unknown.into_keys().collect(),
+            ));
+        }
+
+        Ok((raw.metadata, warnings))
     }
 }
 
@@ -51,6 +328,13 @@ mod tests {
             version: DEFAULT_PIXI_PACK_VERSION.to_string(),
             pixi_pack_version: Some(PIXI_PACK_VERSION.to_string()),
             platform: Platform::Linux64,
+            injected: vec![],
+            created: None,
+            requested_specs: vec![],
+            system_requirements: vec![],
+            base_packages: vec![],
+            environments: vec![],
+            extra: BTreeMap::new(),
         };
         let result = json!(metadata).to_string();
         assert_eq!(
@@ -78,11 +362,108 @@ mod tests {
         assert_eq!(metadata.platform, Platform::Linux64);
     }
 
+    #[rstest]
+    fn test_metadata_serialization_created() {
+        let metadata = PixiPackMetadata {
+            created: Some(1700000000),
+            ..PixiPackMetadata::default()
+        };
+        let result = json!(metadata);
+        assert_eq!(result["created"], 1700000000);
+        let (deserialized, warnings) = PixiPackMetadata::from_json(&result.to_string()).unwrap();
+        assert_eq!(deserialized, metadata);
+        assert!(warnings.is_empty());
+    }
+
+    #[rstest]
+    fn test_metadata_serialization_extra() {
+        let metadata = PixiPackMetadata {
+            extra: BTreeMap::from([
+                ("build-id".to_string(), "1234".to_string()),
+                ("git-sha".to_string(), "abc123".to_string()),
+            ]),
+            ..PixiPackMetadata::default()
+        };
+        let result = json!(metadata);
+        assert_eq!(result["extra"]["git-sha"], "abc123");
+        let (deserialized, warnings) = PixiPackMetadata::from_json(&result.to_string()).unwrap();
+        assert_eq!(deserialized, metadata);
+        assert!(warnings.is_empty());
+    }
+
+    #[rstest]
+    fn test_metadata_serialization_injected() {
+        let metadata = PixiPackMetadata {
+            injected: vec![InjectedPackage {
+                name: "my-webserver".to_string(),
+                version: "0.1.0".to_string(),
+                build: "pyh4616a5c_0".to_string(),
+                filename: "my-webserver-0.1.0-pyh4616a5c_0.conda".to_string(),
+                sha256: "abc".to_string(),
+            }],
+            ..PixiPackMetadata::default()
+        };
+        let result = json!(metadata);
+        assert_eq!(result["injected"][0]["name"], "my-webserver");
+        let (deserialized, warnings) = PixiPackMetadata::from_json(&result.to_string()).unwrap();
+        assert_eq!(deserialized, metadata);
+        assert!(warnings.is_empty());
+    }
+
+    #[rstest]
+    #[case("__cuda", ">=12.2", "requires NVIDIA driver >= 535.54.03")]
+    #[case("__cuda", ">=12", "requires NVIDIA driver >= 525.60.13")]
+    #[case("__cuda", ">=11.8", "requires NVIDIA driver >= 520.61.05")]
+    #[case("__cuda", ">=12.0", "requires NVIDIA driver >= 525.60.13")]
+    #[case("__cuda", ">=12.4", "requires NVIDIA driver >= 550.54.14")]
+    #[case("__cuda", ">=12.4.1", "requires NVIDIA driver >= 550.54.14")]
+    #[case(
+        "__cuda",
+        ">=13.0",
+        "requires NVIDIA driver supporting CUDA >= 13.0 (minimum driver version unknown)"
+    )]
+    #[case("__cuda", "", "requires NVIDIA driver")]
+    #[case("__glibc", ">=2.17,<3.0.a0", "requires glibc >= 2.17")]
+    #[case("__glibc", "2.28.*", "requires __glibc 2.28.*")]
+    fn test_system_requirement_host_requirement(
+        #[case] name: &str,
+        #[case] spec: &str,
+        #[case] expected: &str,
+    ) {
+        let requirement = SystemRequirement {
+            name: name.to_string(),
+            spec: spec.to_string(),
+            packages: vec![],
+        };
+        assert_eq!(requirement.host_requirement(), expected);
+    }
+
     #[rstest]
     #[case(json!({"version": "1", "platform": "linux64"}))]
     #[case(json!({"version": 1.0, "platform": "linux-64"}))]
     #[case(json!({"version": 1, "platform": "linux-64"}))]
     fn test_metadata_serialization_failure(#[case] invalid: Value) {
         assert!(serde_json::from_str::<PixiPackMetadata>(&invalid.to_string()).is_err());
+        assert!(PixiPackMetadata::from_json(&invalid.to_string()).is_err());
+    }
+
+    #[rstest]
+    #[case(json!({"version": "1", "pixi-pack-version": PIXI_PACK_VERSION, "platform": "linux-64"}), vec![])]
+    #[case(json!({"version": "1", "platform": "linux-64"}), vec![MetadataWarning::MissingPixiPackVersion])]
+    #[case(
+        json!({"version": "1", "pixi-pack-version": "0.0.1", "platform": "linux-64"}),
+        vec![MetadataWarning::DifferentPixiPackVersion("0.0.1".to_string())]
+    )]
+    #[case(
+        json!({"version": "1", "pixi-pack-version": PIXI_PACK_VERSION, "platform": "linux-64", "b": 1, "a": {}}),
+        vec![MetadataWarning::UnknownFields(vec!["a".to_string(), "b".to_string()])]
+    )]
+    fn test_metadata_from_json_warnings(
+        #[case] metadata: Value,
+        #[case] expected: Vec<MetadataWarning>,
+    ) {
+        let (metadata, warnings) = PixiPackMetadata::from_json(&metadata.to_string()).unwrap();
+        assert_eq!(metadata.platform, Platform::Linux64);
+        assert_eq!(warnings, expected);
     }
 }
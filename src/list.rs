@@ -0,0 +1,260 @@
+use std::{fmt, path::Path};
+
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use indicatif::HumanBytes;
+use rattler_conda_types::{PackageRecord, RepoData};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    unpack::open_archive, PixiPackMetadata, CHANNEL_DIRECTORY_NAME, PIXI_PACK_METADATA_PATH,
+};
+
+/// The contents of a pack, see [`list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackContents {
+    /// The metadata from `pixi-pack.json`.
+    pub metadata: PixiPackMetadata,
+    /// The conda packages in the channel of the pack, sorted by name.
+    pub packages: Vec<PackedPackage>,
+}
+
+/// A conda package in a pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedPackage {
+    /// The file name of the package, e.g. `python-3.12.3-h2628c8c_0_cpython.conda`.
+    pub file_name: String,
+    /// The package record from the repodata of the pack.
+    pub record: PackageRecord,
+}
+
+/// List the packages and the metadata of a pack without unpacking it.
+///
+/// `pack_file` can either be a tarball or a directory created with `--format dir`.
+/// Tarballs are read in a single pass, so `-` can be used to read the pack from stdin.
+pub async fn list(pack_file: &Path) -> Result<PackContents> {
+    let mut metadata = None;
+    let mut repodata = Vec::new();
+
+    if pack_file.is_dir() {
+        metadata = Some(
+            std::fs::read_to_string(pack_file.join(PIXI_PACK_METADATA_PATH))
+                .map_err(|e| anyhow!("could not read metadata file: {}", e))?,
+        );
+        let channel_dir = pack_file.join(CHANNEL_DIRECTORY_NAME);
+        for entry in std::fs::read_dir(&channel_dir)
+            .map_err(|e| anyhow!("could not read channel directory: {}", e))?
+        {
+            let repodata_path = entry?.path().join("repodata.json");
+            if repodata_path.is_file() {
+                repodata.push(
+                    std::fs::read_to_string(&repodata_path).map_err(|e| {
+                        anyhow!("could not read {}: {}", repodata_path.display(), e)
+                    })?,
+                );
+            }
+        }
+    } else {
+        let mut archive = open_archive(pack_file).await?;
+        let mut entries = archive
+            .entries()
+            .map_err(|e| anyhow!("could not read archive: {}", e))?;
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry.map_err(|e| anyhow!("could not read archive entry: {}", e))?;
+            let path = entry.path()?.into_owned();
+            let is_metadata = path == Path::new(PIXI_PACK_METADATA_PATH);
+            let is_repodata = path.starts_with(CHANNEL_DIRECTORY_NAME)
+                && path.components().count() == 3
+                && path.file_name() == Some("repodata.json".as_ref());
+            if is_metadata || is_repodata {
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).await?;
+                if is_metadata {
+                    metadata = Some(contents);
+                } else {
+                    repodata.push(contents);
+                }
+            }
+        }
+    }
+
+    let metadata = metadata.ok_or_else(|| {
+        anyhow!(
+            "{} not found in {}",
+            PIXI_PACK_METADATA_PATH,
+            pack_file.display()
+        )
+    })?;
+    let (metadata, warnings) = PixiPackMetadata::from_json(&metadata)?;
+    for warning in warnings {
+        tracing::warn!("{}", warning);
+    }
+
+    let mut packages = Vec::new();
+    for contents in repodata {
+        let repodata: RepoData = serde_json::from_str(&contents)
+            .map_err(|e| anyhow!("could not parse repodata: {}", e))?;
+        packages.extend(
+            repodata
+                .packages
+                .into_iter()
+                .chain(repodata.conda_packages)
+                .map(|(file_name, record)| PackedPackage { file_name, record }),
+        );
+    }
+    packages.sort_by(|a, b| {
+        (a.record.name.as_normalized(), &a.file_name)
+            .cmp(&(b.record.name.as_normalized(), &b.file_name))
+    });
+
+    Ok(PackContents { metadata, packages })
+}
+
+impl fmt::Display for PackContents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Platform: {}", self.metadata.platform)?;
+        writeln!(
+            f,
+            "Created with pixi-pack {}",
+            self.metadata
+                .pixi_pack_version
+                .as_deref()
+                .unwrap_or("(unknown)")
+        )?;
+        if !self.metadata.injected.is_empty() {
+            writeln!(f, "Injected packages: {}", self.metadata.injected.len())?;
+        }
+        for (key, value) in &self.metadata.extra {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        if !self.metadata.base_packages.is_empty() {
+            writeln!(
+                f,
+                "Delta pack: {} packages are taken from the base pack",
+                self.metadata.base_packages.len()
+            )?;
+        }
+
+        let mut rows: Vec<[String; 4]> =
+            vec![["Package", "Version", "Build", "Size"].map(String::from)];
+        rows.extend(self.packages.iter().map(|p| {
+            [
+                p.record.name.as_normalized().to_string(),
+                p.record.version.to_string(),
+                p.record.build.clone(),
+                p.record
+                    .size
+                    .map(|size| HumanBytes(size).to_string())
+                    .unwrap_or_default(),
+            ]
+        }));
+        let widths: Vec<usize> = (0..3)
+            .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+            .collect();
+        writeln!(f)?;
+        for row in rows {
+            writeln!(
+                f,
+                "  {:<w0$}  {:<w1$}  {:<w2$}  {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+            )?;
+        }
+
+        let total_size: u64 = self.packages.iter().filter_map(|p| p.record.size).sum();
+        write!(
+            f,
+            "{} packages ({}).",
+            self.packages.len(),
+            HumanBytes(total_size)
+        )
+    }
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        low_level::archive_directory, Compression, LineEndings, OutputFormat, WindowsFlavor,
+    };
+    use rattler_conda_types::Platform;
+    use rstest::*;
+    use serde_json::json;
+
+    fn create_pack(dir: &Path) {
+        let subdir = dir.join(CHANNEL_DIRECTORY_NAME).join("noarch");
+        std::fs::create_dir_all(&subdir).unwrap();
+        let package = |name: &str, size: u64| {
+            json!({
+                "name": name,
+                "version": "1.0",
+                "build": "0",
+                "build_number": 0,
+                "depends": [],
+                "subdir": "noarch",
+                "size": size,
+            })
+        };
+        let repodata = json!({
+            "info": {"subdir": "noarch"},
+            "packages": {"zlib-1.0-0.tar.bz2": package("zlib", 1024)},
+            "packages.conda": {"bzip2-1.0-0.conda": package("bzip2", 2048)},
+        });
+        std::fs::write(subdir.join("repodata.json"), repodata.to_string()).unwrap();
+        std::fs::write(
+            dir.join(PIXI_PACK_METADATA_PATH),
+            serde_json::to_string(&PixiPackMetadata::default()).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_list(#[values(true, false)] as_tarball: bool) {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_dir = dir.path().join("pack");
+        create_pack(&pack_dir);
+        let pack_file = if as_tarball {
+            let tarball = dir.path().join("environment.tar");
+            archive_directory(
+                &pack_dir,
+                &tarball,
+                OutputFormat::Tar,
+                Platform::current(),
+                WindowsFlavor::default(),
+                None,
+                None,
+                LineEndings::default(),
+                Compression::default(),
+            )
+            .await
+            .unwrap();
+            tarball
+        } else {
+            pack_dir
+        };
+
+        let contents = list(&pack_file).await.unwrap();
+        assert_eq!(contents.metadata, PixiPackMetadata::default());
+        let file_names: Vec<&str> = contents
+            .packages
+            .iter()
+            .map(|p| p.file_name.as_str())
+            .collect();
+        assert_eq!(file_names, vec!["bzip2-1.0-0.conda", "zlib-1.0-0.tar.bz2"]);
+
+        let output = contents.to_string();
+        assert!(output.contains("  Package  Version  Build  Size\n"));
+        assert!(output.contains("  bzip2    1.0      0      2.00 KiB\n"));
+        assert!(output.ends_with("2 packages (3.00 KiB)."));
+    }
+}
@@ -1,16 +1,40 @@
-use std::path::PathBuf;
+use std::{
+    ffi::OsString,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use clap_verbosity_flag::Verbosity;
-use rattler_conda_types::Platform;
+use rattler_conda_types::{PackageName, Platform};
 
 use anyhow::Result;
 use pixi_pack::{
-    pack, unpack, PackOptions, PixiPackMetadata, UnpackOptions, DEFAULT_PIXI_PACK_VERSION,
-    PIXI_PACK_VERSION,
+    enable_json_output, export, fetch, json_output, list, lockfile_environments,
+    lockfile_platforms, low_level::embedded_pack, pack, pack_environments, pack_many,
+    read_instructions, read_metadata, unpack, validate, write_activation_script, ChannelPriority,
+    CommandHook, Compression, DownloadOrder, Event, ExecutableFormat, FailureReport, HttpOptions,
+    LineEndings, LinkScriptsReport, NetworkLog, OutputFormat, Ownership, PackOptions,
+    PathModification, PermissionPolicy, RetryPolicy, SshDestination, UnpackOptions, UnpackOutcome,
+    WindowsFlavor,
 };
 use rattler_shell::shell::ShellEnum;
 use tracing_log::AsTrace;
+use tracing_subscriber::{
+    filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, Layer,
+};
+
+/// Print a human-readable status message to stderr unless `--json` is passed, like the
+/// `status!` macro of the library.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !json_output() {
+            eprintln!($($arg)*);
+        }
+    };
+}
 
 /* -------------------------------------------- CLI -------------------------------------------- */
 
@@ -18,6 +42,64 @@ fn cwd() -> PathBuf {
     std::env::current_dir().expect("failed to obtain current working directory")
 }
 
+/// The default file name of a pack when packing multiple platforms, e.g. `environment-linux-64.tar`.
+fn platform_output_file(
+    format: OutputFormat,
+    platform: Platform,
+    windows_flavor: WindowsFlavor,
+    executable_format: ExecutableFormat,
+    compression: Compression,
+) -> PathBuf {
+    let default_output_file =
+        format.default_output_file(platform, windows_flavor, executable_format, compression);
+    let default_file_name = default_output_file.to_string_lossy();
+    // Keep all extensions, e.g. `.tar.zst`.
+    let extensions = default_file_name
+        .strip_prefix("environment")
+        .unwrap_or_default();
+    PathBuf::from(format!("environment-{}{}", platform, extensions))
+}
+
+/// The compression of `tar` packs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum CompressionArg {
+    None,
+    Zstd,
+}
+
+/// Parse a platform, accepting `any` as an alias of `noarch`.
+fn parse_platform(platform: &str) -> Result<Platform, String> {
+    match platform {
+        "any" => Ok(Platform::NoArch),
+        platform => platform.parse().map_err(|e| format!("{}", e)),
+    }
+}
+
+/// Parse a `key=value` pair of `--metadata`.
+fn parse_key_value(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected key=value, got: {}", pair)),
+    }
+}
+
+/// Parse a number of threads, using the available parallelism for `auto`.
+fn parse_threads(threads: &str) -> Result<u32, String> {
+    match threads {
+        "auto" => Ok(std::thread::available_parallelism().map_or(1, |n| n.get() as u32)),
+        threads => threads
+            .parse()
+            .map_err(|_| format!("expected a number or auto, got: {}", threads)),
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or(format!("invalid octal mode: {}", mode))
+}
+
 /// The pixi-pack CLI.
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -27,20 +109,52 @@ struct Cli {
 
     #[command(flatten)]
     verbose: Verbosity,
+
+    /// Write a report with the error, the failed step and environment information to this file
+    /// if the command fails, e.g. to attach it to a support ticket
+    #[arg(
+        long,
+        global = true,
+        env = "PIXI_PACK_FAILURE_REPORT",
+        num_args = 0..=1,
+        default_missing_value = "pixi-pack-failure.json"
+    )]
+    failure_report: Option<PathBuf>,
+
+    /// Print progress and result events as lines of JSON to stdout instead of human-readable
+    /// messages, e.g. for CI log parsers
+    #[arg(long, global = true)]
+    json: bool,
 }
 
 /// The subcommands for the pixi-pack CLI.
+// Only a single value is created when parsing the arguments, so the size doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Pack a pixi environment
+    #[command(long_flag = "pack")]
     Pack {
-        /// Environment to pack
-        #[arg(short, long, default_value = "default")]
-        environment: String,
+        /// Environment to pack.
+        /// Can be passed multiple times to pack several environments into one pack, one of them
+        /// is selected with `unpack --environment`
+        #[arg(short, long = "environment", default_value = "default")]
+        environments: Vec<String>,
 
-        /// Platform to pack
-        #[arg(short, long, default_value = Platform::current().as_str())]
-        platform: Platform,
+        /// Pack all environments of the lockfile into one pack
+        #[arg(long, default_value = "false", conflicts_with = "environments")]
+        all_environments: bool,
+
+        /// Platform to pack.
+        /// Can be passed multiple times to create one pack per platform concurrently.
+        /// Use `noarch` (or `any`) for environments that only contain noarch packages
+        #[arg(
+            short,
+            long = "platform",
+            default_value = Platform::current().as_str(),
+            value_parser = parse_platform
+        )]
+        platforms: Vec<Platform>,
 
         /// Authentication file for fetching packages
         #[arg(long)] // TODO: Read from environment variable?
@@ -50,25 +164,225 @@ enum Commands {
         #[arg(default_value = cwd().join("pixi.toml").into_os_string())]
         manifest_path: PathBuf,
 
-        /// Output file to write the pack to (will be an archive)
+        /// Output file to write the pack to (will be an archive).
+        /// Use `ssh://[user@]host[:port]/path` to send the pack to a remote host.
+        /// For `--format oci`, this is the `oci://registry/repository[:tag]` to push the pack to.
+        /// When packing multiple platforms, this is the directory to write the packs to
         #[arg(short, long)]
         output_file: Option<PathBuf>,
 
-        /// Inject an additional conda package into the final prefix
+        /// Unpack the environment on the remote host after sending it there via ssh.
+        /// Requires `pixi-pack` on the remote host unless the pack is a self-extracting executable
+        #[arg(long, default_value = "false")]
+        remote_unpack: bool,
+
+        /// Inject additional conda packages into the final prefix.
+        /// Accepts package files, directories containing packages and glob patterns
         #[arg(short, long, num_args(0..))]
         inject: Vec<PathBuf>,
 
+        /// Markdown template with usage instructions to bundle with the pack.
+        /// Supports the placeholders `{{ environment }}`, `{{ platform }}`, `{{ env_name }}` and
+        /// `{{ activation_command }}`
+        #[arg(long)]
+        instructions: Option<PathBuf>,
+
+        /// Script that replaces the built-in header of self-extracting executables.
+        /// Supports the placeholders `{{ end_header_marker }}`, `{{ end_archive_marker }}`,
+        /// `{{ platform }}`, `{{ env_name }}` and `{{ pixi_pack_version }}`
+        #[arg(long)]
+        header_template: Option<PathBuf>,
+
+        /// Inject all packages of a local channel into the final prefix
+        #[arg(long)]
+        inject_channel: Vec<PathBuf>,
+
+        /// Additional channel to list in the `environment.yml` of the pack, e.g. a mirror.
+        /// Can be passed multiple times
+        #[arg(long)]
+        extra_channel: Vec<String>,
+
+        /// The channel priority to set in the `environment.yml` of the pack
+        #[arg(long, value_enum)]
+        channel_priority: Option<ChannelPriority>,
+
         /// PyPI dependencies are not supported.
         /// This flag allows packing even if PyPI dependencies are present.
         #[arg(long, default_value = "false")]
         ignore_pypi_errors: bool,
 
-        /// Create self-extracting executable
+        /// Fail if the environment contains PyPI packages but no pip conda package.
+        /// By default, only a warning is printed.
         #[arg(long, default_value = "false")]
+        require_pip: bool,
+
+        /// The format of the pack
+        #[arg(long, value_enum, default_value_t = OutputFormat::Tar)]
+        format: OutputFormat,
+
+        /// Compress the tar archive (`.tar.zst`). Packs are decompressed transparently when unpacking
+        #[arg(long, value_enum)]
+        compression: Option<CompressionArg>,
+
+        /// The zstd compression level (1-22, default: 3)
+        #[arg(long, requires = "compression", value_parser = clap::value_parser!(i32).range(1..=22))]
+        compression_level: Option<i32>,
+
+        /// Compress with this many worker threads, or `auto` for one per available CPU.
+        /// By default, the pack is compressed on a single thread
+        #[arg(long, requires = "compression", value_parser = parse_threads)]
+        compression_threads: Option<u32>,
+
+        /// The kind of self-extracting executable to create for Windows platforms
+        #[arg(long, value_enum, default_value_t = WindowsFlavor::Powershell)]
+        windows_flavor: WindowsFlavor,
+
+        /// How self-extracting executables embed the pack. `binary` creates a native executable
+        /// instead of a base64-encoded script
+        #[arg(long, value_enum, default_value_t = ExecutableFormat::Script)]
+        executable_format: ExecutableFormat,
+
+        /// A pixi-pack executable for the target platform to embed into self-extracting
+        /// executables, e.g. `target/release/pixi-pack`, instead of downloading it from GitHub
+        #[arg(long, env = "PIXI_PACK_UNPACK_EXECUTABLE")]
+        unpack_executable: Option<PathBuf>,
+
+        /// Line endings of self-extracting executables.
+        /// `auto` uses crlf for Windows platforms and lf otherwise
+        #[arg(long, value_enum, default_value_t = LineEndings::Auto)]
+        line_endings: LineEndings,
+
+        /// Reuse unchanged packages from a previously created pack instead of downloading them again
+        #[arg(long)]
+        reuse_from: Option<PathBuf>,
+
+        /// Create a delta pack that only contains the packages which are not part of this previous
+        /// pack. Unpacking it requires the base pack or an environment unpacked from it
+        #[arg(long)]
+        base_pack: Option<PathBuf>,
+
+        /// Compress the delta pack with a zstd dictionary trained on the base pack.
+        /// Unpacking it always requires `--base-pack`
+        #[arg(long, default_value = "false", requires = "base_pack")]
+        zstd_dictionary: bool,
+
+        /// Unix timestamp to use for all entries of the pack and to record as build date.
+        /// By default, all timestamps are zeroed out
+        #[arg(long, env = "SOURCE_DATE_EPOCH")]
+        source_date_epoch: Option<u64>,
+
+        /// Store a custom `key=value` pair in the metadata of the pack, e.g. a build ID or a git
+        /// commit. Can be specified multiple times
+        #[arg(long = "metadata", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+        extra_metadata: Vec<(String, String)>,
+
+        /// Cache downloaded packages in this directory and use packages that are already cached
+        #[arg(long)]
+        use_cache: Option<PathBuf>,
+
+        /// The order in which packages are downloaded
+        #[arg(long, value_enum, default_value_t = DownloadOrder::Size)]
+        download_order: DownloadOrder,
+
+        #[command(flatten)]
+        http: HttpArgs,
+
+        /// Only report which packages would be downloaded and the estimated size of the pack,
+        /// without downloading or writing anything
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+
+        /// Sign the pack with this unencrypted minisign secret key (`minisign -G -W`).
+        /// The detached signature is written to `<output-file>.minisig`
+        #[arg(long, env = "PIXI_PACK_SIGN_KEY")]
+        sign_key: Option<PathBuf>,
+
+        /// Add the first 12 characters of the sha256 digest of the pack to its file name, e.g.
+        /// `environment-3f2a9c81d0b4.tar`, for content-addressed artifact stores
+        #[arg(long, default_value = "false")]
+        digest_name: bool,
+
+        /// Shell command to check the contents of the pack before it is archived, e.g. a virus
+        /// scanner. The staging directory is appended as last argument and a non-zero exit code
+        /// aborts packing. Can be passed multiple times
+        #[arg(long)]
+        hook: Vec<String>,
+
+        /// Create self-extracting executable (deprecated, use `--format executable`)
+        #[arg(long, default_value = "false", conflicts_with = "format", hide = true)]
         create_executable: bool,
     },
 
+    /// Download all packages of an environment into a cache without creating a pack
+    Fetch {
+        /// Environment to fetch
+        #[arg(short, long, default_value = "default")]
+        environment: String,
+
+        /// Platform to fetch, can be passed multiple times
+        #[arg(short, long = "platform", default_value = Platform::current().as_str())]
+        platforms: Vec<Platform>,
+
+        /// Authentication file for fetching packages
+        #[arg(long)]
+        auth_file: Option<PathBuf>,
+
+        /// The path to 'pixi.toml' or 'pyproject.toml'
+        #[arg(default_value = cwd().join("pixi.toml").into_os_string())]
+        manifest_path: PathBuf,
+
+        /// The cache directory to download packages to (use it with `pixi-pack pack --use-cache`)
+        #[arg(long)]
+        use_cache: PathBuf,
+
+        /// Skip PyPI packages instead of failing
+        #[arg(long, default_value = "false")]
+        ignore_pypi_errors: bool,
+
+        /// The order in which packages are downloaded
+        #[arg(long, value_enum, default_value_t = DownloadOrder::Size)]
+        download_order: DownloadOrder,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+
+    /// List the environments in the lockfile with their platforms and number of packages
+    Environments {
+        /// The path to 'pixi.toml' or 'pyproject.toml'
+        #[arg(default_value = cwd().join("pixi.toml").into_os_string())]
+        manifest_path: PathBuf,
+    },
+
+    /// List the platforms that are locked for an environment
+    Platforms {
+        /// Environment to list the platforms of
+        #[arg(short, long, default_value = "default")]
+        environment: String,
+
+        /// The path to 'pixi.toml' or 'pyproject.toml'
+        #[arg(default_value = cwd().join("pixi.toml").into_os_string())]
+        manifest_path: PathBuf,
+    },
+
+    /// List the packages and metadata of a pack without unpacking it
+    List {
+        /// Path to the pack file, or `-` to stream a tarball from stdin
+        #[arg()]
+        pack_file: PathBuf,
+    },
+
+    /// Check the structural integrity of a pack, e.g. in CI before publishing it
+    #[command(visible_alias = "verify")]
+    Validate {
+        /// Path to the pack file or self-extracting executable, or `-` to stream a tarball from
+        /// stdin
+        #[arg()]
+        pack_file: PathBuf,
+    },
+
     /// Unpack a pixi environment
+    #[command(long_flag = "unpack")]
     Unpack {
         /// Where to unpack the environment.
         /// The environment will be unpacked into a subdirectory of this path
@@ -81,26 +395,425 @@ enum Commands {
         #[arg(short, long, default_value = "env")]
         env_name: String,
 
-        /// Path to the pack file
+        /// Install the environment into this absolute path instead of a subdirectory of the
+        /// output directory. The activation script is still written to the output directory
+        #[arg(long, conflicts_with = "env_name")]
+        target_prefix: Option<PathBuf>,
+
+        /// Path to the pack file, `-` to stream a tarball from stdin, an `https://` URL to stream
+        /// the tarball while downloading it or an `oci://registry/repository[:tag]` reference to
+        /// pull the pack from a registry
         #[arg()]
         pack_file: PathBuf,
 
         /// Sets the shell, options: [`bash`, `zsh`, `xonsh`, `cmd`, `powershell`, `fish`, `nushell`]
         #[arg(short, long)]
         shell: Option<ShellEnum>,
+
+        /// Print information and instructions bundled with the pack instead of unpacking it
+        #[arg(long, default_value = "false")]
+        info: bool,
+
+        /// Print the metadata of the pack (`pixi-pack.json`) as JSON instead of unpacking it,
+        /// including the custom metadata set with `pixi-pack pack --metadata`
+        #[arg(long, default_value = "false", conflicts_with = "info")]
+        show_metadata: bool,
+
+        /// Change the owner of the environment and the activation script (`user[:group]` or `:group`)
+        #[arg(long)]
+        chown: Option<Ownership>,
+
+        /// Octal mode for files in the environment, e.g. `644`.
+        /// Executable files stay executable for everyone who can read them
+        #[arg(long, value_parser = parse_mode)]
+        file_mode: Option<u32>,
+
+        /// Octal mode for directories in the environment, e.g. `755`
+        #[arg(long, value_parser = parse_mode)]
+        dir_mode: Option<u32>,
+
+        /// Make the environment readable and executable by the group regardless of the umask,
+        /// e.g. for shared project directories
+        #[arg(long, default_value = "false")]
+        group_shared: bool,
+
+        /// Skip unpacking if the environment was already unpacked from the same pack
+        #[arg(long, default_value = "false")]
+        idempotent: bool,
+
+        /// Don't check whether there is enough disk space before unpacking
+        #[arg(long, default_value = "false")]
+        skip_disk_space_check: bool,
+
+        /// Abort unpacking after this many seconds.
+        /// A newly created environment is removed again
+        #[arg(long)]
+        timeout: Option<u64>,
+
+        /// Mode for Kubernetes init containers and similar automation.
+        /// Implies `--idempotent` and prints a single line of JSON with the status to stdout.
+        /// Exits with 0 if the environment is installed, 124 on timeout and 1 on other errors
+        #[arg(long, default_value = "false")]
+        init_container: bool,
+
+        /// Set the setgid bit on all directories of the environment
+        #[arg(long, default_value = "false", requires = "group_shared")]
+        setgid_dirs: bool,
+
+        /// Packages whose post-link scripts may run, e.g. `--allow-link-scripts pkg-a,pkg-b`.
+        /// Post-link scripts of all other packages are skipped
+        #[arg(long, value_delimiter = ',')]
+        allow_link_scripts: Vec<PackageName>,
+
+        /// The number of packages to extract in parallel [default: number of CPUs].
+        /// Lower values reduce the memory usage while unpacking
+        #[arg(long)]
+        extract_concurrency: Option<NonZeroUsize>,
+
+        /// The pack that a delta pack was created against.
+        /// Not needed if the environment was unpacked from the base pack before
+        #[arg(long)]
+        base_pack: Option<PathBuf>,
+
+        /// Verify the signature in `<pack-file>.minisig` with this minisign public key.
+        /// Packs with an invalid signature are rejected
+        #[arg(long)]
+        verify_key: Option<PathBuf>,
+
+        /// Reject packs that are not signed
+        #[arg(long, default_value = "false", requires = "verify_key")]
+        require_signature: bool,
+
+        /// How the activation script modifies `PATH`. `replace` drops all other directories
+        /// from `PATH`, e.g. for controlled shells that should only see the environment
+        #[arg(long, value_enum, default_value_t = PathModification::Prepend)]
+        path_modification: PathModification,
+
+        /// Don't write an activation script, e.g. if the environment is activated by other means
+        #[arg(long, default_value = "false")]
+        skip_activation_script: bool,
+
+        /// Where to write the activation script [default: `activate.<extension>` in the output
+        /// directory]. Relative paths are resolved against the output directory
+        #[arg(long, conflicts_with = "skip_activation_script")]
+        activation_script: Option<PathBuf>,
+
+        /// The environment to unpack from a pack of multiple environments
+        #[arg(long)]
+        environment: Option<String>,
+
+        /// Only check whether the pack can be unpacked (signature, platform, disk space) and
+        /// print the packages that would be installed, without touching the prefix
+        #[arg(long, default_value = "false", conflicts_with_all = ["idempotent", "init_container"])]
+        verify_only: bool,
+
+        #[command(flatten)]
+        http: HttpArgs,
     },
+
+    /// Regenerate the activation script of an unpacked environment, e.g. for another shell
+    ActivateScript {
+        /// The prefix of the unpacked environment
+        #[arg(short, long)]
+        prefix: PathBuf,
+
+        /// Sets the shell, options: [`bash`, `zsh`, `xonsh`, `cmd`, `powershell`, `fish`, `nushell`]
+        #[arg(short, long)]
+        shell: Option<ShellEnum>,
+
+        /// Where to write the activation script (default: the parent directory of the prefix)
+        #[arg(short, long)]
+        output_directory: Option<PathBuf>,
+
+        /// How the activation script modifies `PATH`
+        #[arg(long, value_enum, default_value_t = PathModification::Prepend)]
+        path_modification: PathModification,
+    },
+
+    /// Reconstruct an `environment.yml` (and a `requirements.txt` for PyPI packages) from an
+    /// unpacked environment
+    Export {
+        /// The prefix of the unpacked environment
+        #[arg(short, long)]
+        prefix: PathBuf,
+
+        /// Where to write the `environment.yml`
+        #[arg(short, long, default_value = "environment.yml")]
+        output_file: PathBuf,
+    },
+
+    /// Serve a JSON-RPC API for unpacking environments on a unix socket
+    #[cfg(unix)]
+    Serve {
+        /// The path of the socket to listen on
+        #[arg(long)]
+        socket: PathBuf,
+    },
+
+    /// Generate shell completion scripts
+    Completion {
+        /// The shell to generate the completion script for
+        #[arg(short, long)]
+        shell: clap_complete::Shell,
+    },
+}
+
+/// The default of `--pool-idle-timeout` in seconds, see [`HttpOptions::pool_idle_timeout`].
+fn default_pool_idle_timeout() -> u64 {
+    HttpOptions::default()
+        .pool_idle_timeout
+        .map_or(0, |timeout| timeout.as_secs())
+}
+
+/// Settings of the HTTP client that downloads packages.
+#[derive(clap::Args, Debug, Clone)]
+struct HttpArgs {
+    /// Maximum number of idle connections to keep open per host
+    #[arg(long, env = "PIXI_PACK_POOL_MAX_IDLE_PER_HOST", default_value_t = HttpOptions::default().pool_max_idle_per_host)]
+    pool_max_idle_per_host: usize,
+
+    /// Seconds after which idle connections are closed
+    #[arg(long, env = "PIXI_PACK_POOL_IDLE_TIMEOUT", default_value_t = default_pool_idle_timeout())]
+    pool_idle_timeout: u64,
+
+    /// Only use HTTP/1.1, e.g. for proxies that don't handle HTTP/2 correctly
+    #[arg(long, env = "PIXI_PACK_NO_HTTP2", default_value = "false")]
+    no_http2: bool,
+
+    /// Send TCP keep-alive probes in this interval (in seconds)
+    #[arg(long, env = "PIXI_PACK_TCP_KEEPALIVE")]
+    tcp_keepalive: Option<u64>,
+
+    /// How often a failed download is retried
+    #[arg(long, env = "PIXI_PACK_MAX_RETRIES", default_value_t = RetryPolicy::default().max_retries)]
+    max_retries: u32,
+
+    /// Maximum delay before the first retry in seconds, doubled for every further retry
+    #[arg(long, env = "PIXI_PACK_RETRY_BACKOFF", default_value_t = 1)]
+    retry_backoff: u64,
+
+    /// Record every HTTP request with its URL, status, size and duration in this file, one line
+    /// of JSON per request
+    #[arg(long, env = "PIXI_PACK_NETWORK_LOG")]
+    network_log: Option<PathBuf>,
+}
+
+impl HttpArgs {
+    /// Create the network log, if requested.
+    fn network_log(&self) -> Result<Option<NetworkLog>> {
+        self.network_log
+            .as_ref()
+            .map(NetworkLog::create)
+            .transpose()
+    }
+}
+
+impl From<HttpArgs> for HttpOptions {
+    fn from(args: HttpArgs) -> Self {
+        HttpOptions {
+            pool_max_idle_per_host: args.pool_max_idle_per_host,
+            pool_idle_timeout: Some(Duration::from_secs(args.pool_idle_timeout)),
+            http2: !args.no_http2,
+            tcp_keepalive: args.tcp_keepalive.map(Duration::from_secs),
+            retry_policy: RetryPolicy {
+                max_retries: args.max_retries,
+                initial_backoff: Duration::from_secs(args.retry_backoff),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Send a pack to a remote host and optionally unpack it there.
+async fn deploy_over_ssh(
+    pack_file: &Path,
+    destination: &SshDestination,
+    format: OutputFormat,
+    remote_unpack: bool,
+) -> Result<()> {
+    tracing::info!("Sending pack to {}", destination);
+    destination.upload(pack_file).await?;
+    status!("📤 Sent pack to {}", destination);
+
+    if remote_unpack {
+        let output_directory = Path::new(&destination.path)
+            .parent()
+            .and_then(Path::to_str)
+            .filter(|parent| !parent.is_empty())
+            .unwrap_or(".");
+        let args = ["--output-directory", output_directory];
+        match format {
+            OutputFormat::Executable => {
+                destination
+                    .run("sh", &[&[destination.path.as_str()], &args[..]].concat())
+                    .await?
+            }
+            _ => {
+                destination
+                    .run(
+                        "pixi-pack",
+                        &[&["unpack", destination.path.as_str()], &args[..]].concat(),
+                    )
+                    .await?
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Error returned when unpacking takes longer than `--timeout`.
+#[derive(Debug)]
+struct TimedOut(Duration);
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unpacking timed out after {} seconds", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Exit code when pixi-pack is interrupted with Ctrl-C (128 + SIGINT, like shells do).
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Remembers the last step that pixi-pack logged, for failure reports.
+///
+/// Steps are logged on the info level, so this works regardless of the verbosity.
+#[derive(Clone, Default)]
+struct PhaseRecorder(Arc<Mutex<Option<String>>>);
+
+impl PhaseRecorder {
+    fn phase(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl<S: tracing::Subscriber> Layer<S> for PhaseRecorder {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        struct Message(Option<String>);
+        impl tracing::field::Visit for Message {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = Some(format!("{:?}", value));
+                }
+            }
+        }
+
+        let metadata = event.metadata();
+        if *metadata.level() != tracing::Level::INFO || !metadata.target().starts_with("pixi_pack")
+        {
+            return;
+        }
+        let mut message = Message(None);
+        event.record(&mut message);
+        if let Some(message) = message.0 {
+            *self.0.lock().unwrap() = Some(message);
+        }
+    }
+}
+
+/// Removes a file or directory that is created by a command when dropped, unless it was kept.
+///
+/// Commands are dropped when they are interrupted, so this cleans up partial output.
+struct PartialOutput(Option<PathBuf>);
+
+impl PartialOutput {
+    /// Track `path` if it does not exist yet. Existing files and directories are never removed.
+    fn new(path: PathBuf) -> Self {
+        Self(Some(path).filter(|path| !path.exists()))
+    }
+
+    /// The output is complete, keep it.
+    fn keep(mut self) {
+        self.0 = None;
+    }
 }
 
-fn default_output_file(platform: Platform, create_executable: bool) -> PathBuf {
-    if create_executable {
-        if platform.is_windows() {
-            cwd().join("environment.ps1")
+impl Drop for PartialOutput {
+    fn drop(&mut self) {
+        let Some(path) = self.0.take() else {
+            return;
+        };
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else if path.exists() {
+            std::fs::remove_file(&path)
         } else {
-            cwd().join("environment.sh")
+            Ok(())
+        };
+        match result {
+            Ok(()) => tracing::debug!("Removed partial output {}", path.display()),
+            Err(e) => tracing::warn!("could not remove {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Unpack, aborting after `timeout`.
+/// Removes the prefix on timeout or interruption if it did not exist before.
+async fn unpack_with_timeout(
+    options: UnpackOptions,
+    timeout: Option<Duration>,
+) -> Result<UnpackOutcome> {
+    let partial_prefix = PartialOutput::new(options.prefix());
+    let result = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, unpack(options))
+            .await
+            .unwrap_or_else(|_| Err(TimedOut(timeout).into())),
+        None => unpack(options).await,
+    };
+    match &result {
+        Err(e) if e.is::<TimedOut>() => {}
+        _ => partial_prefix.keep(),
+    }
+    result
+}
+
+/// Print the metadata and bundled instructions of a pack.
+async fn print_pack_info(pack_file: &Path) -> Result<()> {
+    if pack_file == Path::new("-") {
+        anyhow::bail!("--info is not supported when reading the pack from stdin");
+    }
+    let metadata = read_metadata(pack_file).await?;
+    println!("Platform: {}", metadata.platform);
+    println!(
+        "Created with pixi-pack {}",
+        metadata.pixi_pack_version.as_deref().unwrap_or("(unknown)")
+    );
+    if !metadata.extra.is_empty() {
+        println!("\nMetadata:");
+        for (key, value) in &metadata.extra {
+            println!("  {}: {}", key, value);
+        }
+    }
+    if !metadata.system_requirements.is_empty() {
+        println!("\nSystem requirements:");
+        for requirement in &metadata.system_requirements {
+            println!(
+                "  {}: {} ({})",
+                requirement.host_requirement(),
+                requirement,
+                requirement.packages.join(", ")
+            );
         }
-    } else {
-        cwd().join("environment.tar")
     }
+    match read_instructions(pack_file).await? {
+        Some(instructions) => println!("\n{}", instructions),
+        None => println!("\nThe pack does not contain any instructions."),
+    }
+    Ok(())
+}
+
+/// The path of the running executable if it is a binary self-extracting executable.
+fn self_extracting_pack() -> Option<PathBuf> {
+    let executable = std::env::current_exe().ok()?;
+    embedded_pack(&executable)
+        .ok()
+        .flatten()
+        .map(|_| executable)
 }
 
 /* -------------------------------------------- MAIN ------------------------------------------- */
@@ -108,63 +821,521 @@ fn default_output_file(platform: Platform, create_executable: bool) -> PathBuf {
 /// The main entrypoint for the pixi-pack CLI.
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    // Binary self-extracting executables are pixi-pack itself with the pack appended.
+    let cli = match self_extracting_pack() {
+        Some(pack_file) => Cli::parse_from(
+            [OsString::from("pixi-pack"), OsString::from("unpack")]
+                .into_iter()
+                .chain(std::env::args_os().skip(1))
+                .chain([pack_file.into_os_string()]),
+        ),
+        None => Cli::parse(),
+    };
 
-    tracing_subscriber::FmtSubscriber::builder()
-        .with_max_level(cli.verbose.log_level_filter().as_trace())
-        .init();
+    let phase_recorder = PhaseRecorder::default();
+    let subscriber = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer().with_filter(cli.verbose.log_level_filter().as_trace()),
+        )
+        .with(phase_recorder.clone().with_filter(LevelFilter::INFO));
+    subscriber.init();
 
     tracing::debug!("Starting pixi-pack CLI");
+    if cli.json {
+        enable_json_output();
+    }
+
+    // On Ctrl-C, the command is dropped before exiting. This removes temporary directories, e.g.
+    // the staging directories of multi-environment and delta packs, partially written cache
+    // entries and the output file or prefix if it was tracked with `PartialOutput`. Files that
+    // are written after the output is complete, like signatures, are kept.
+    let result = tokio::select! {
+        result = run(cli.command) => result,
+        _ = tokio::signal::ctrl_c() => {
+            tracing::debug!("Interrupted, removed temporary directories and unfinished output");
+            Event::Error {
+                message: "interrupted".to_string(),
+            }
+            .emit();
+            status!("❌ Interrupted");
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    };
+    if let (Err(e), Some(path)) = (&result, &cli.failure_report) {
+        match FailureReport::new(e, phase_recorder.phase()).write(path) {
+            Ok(()) => status!("📝 Wrote failure report to {}", path.display()),
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
+    if let Err(e) = &result {
+        Event::Error {
+            message: format!("{:#}", e),
+        }
+        .emit();
+    }
+    result?;
+    tracing::debug!("Finished running pixi-pack");
+
+    Ok(())
+}
 
-    match cli.command {
+/// Run a pixi-pack command.
+async fn run(command: Commands) -> Result<()> {
+    match command {
         Commands::Pack {
-            environment,
-            platform,
+            environments,
+            all_environments,
+            platforms,
             auth_file,
             manifest_path,
             output_file,
             inject,
+            inject_channel,
+            instructions,
+            header_template,
+            extra_channel,
+            channel_priority,
             ignore_pypi_errors,
+            require_pip,
+            format,
+            compression,
+            compression_level,
+            compression_threads,
+            windows_flavor,
+            executable_format,
+            unpack_executable,
+            line_endings,
+            reuse_from,
+            base_pack,
+            zstd_dictionary,
+            use_cache,
+            source_date_epoch,
+            extra_metadata,
+            download_order,
+            http,
             create_executable,
+            remote_unpack,
+            dry_run,
+            sign_key,
+            digest_name,
+            hook,
         } => {
-            let output_file =
-                output_file.unwrap_or_else(|| default_output_file(platform, create_executable));
-
-            let options = PackOptions {
-                environment,
-                platform,
-                auth_file,
-                output_file,
-                manifest_path,
-                metadata: PixiPackMetadata {
-                    version: DEFAULT_PIXI_PACK_VERSION.to_string(),
-                    pixi_pack_version: Some(PIXI_PACK_VERSION.to_string()),
+            let compression = match compression {
+                Some(CompressionArg::None) if compression_level.is_some() => {
+                    anyhow::bail!("`--compression-level` requires `--compression zstd`")
+                }
+                None | Some(CompressionArg::None) => Compression::None,
+                Some(CompressionArg::Zstd) => {
+                    Compression::Zstd(compression_level.unwrap_or(Compression::DEFAULT_ZSTD_LEVEL))
+                }
+            };
+            let format = if create_executable {
+                tracing::warn!(
+                    "`--create-executable` is deprecated, use `--format executable` instead"
+                );
+                OutputFormat::Executable
+            } else {
+                format
+            };
+            let ssh_destination = output_file
+                .as_ref()
+                .and_then(|path| path.to_str())
+                .filter(|path| path.starts_with("ssh://"))
+                .map(str::parse::<SshDestination>)
+                .transpose()?;
+            if ssh_destination.is_some() && format == OutputFormat::Dir {
+                anyhow::bail!("directory packs cannot be sent via ssh");
+            }
+            if digest_name && ssh_destination.is_some() {
+                anyhow::bail!("`--digest-name` is not supported for packs sent via ssh");
+            }
+            if remote_unpack && ssh_destination.is_none() {
+                anyhow::bail!("`--remote-unpack` requires an ssh:// output file");
+            }
+            if base_pack.is_some() && platforms.len() > 1 {
+                anyhow::bail!("`--base-pack` requires a single platform");
+            }
+            if unpack_executable.is_some() && platforms.len() > 1 {
+                anyhow::bail!("`--unpack-executable` requires a single platform");
+            }
+            let environments = if all_environments {
+                lockfile_environments(&manifest_path)?
+                    .into_iter()
+                    .map(|environment| environment.name)
+                    .collect()
+            } else {
+                environments
+            };
+            if environments.is_empty() {
+                anyhow::bail!("the lockfile contains no environments");
+            }
+            if environments.len() > 1 && platforms.len() > 1 {
+                anyhow::bail!("packing multiple environments requires a single platform");
+            }
+            if environments.len() > 1 && remote_unpack {
+                anyhow::bail!("`--remote-unpack` requires a single environment");
+            }
+            // Dry runs don't send any requests.
+            let network_log = if dry_run { None } else { http.network_log()? };
+
+            let builder = PackOptions::builder()
+                .environment(environments[0].clone())
+                .auth_file(auth_file)
+                .manifest_path(manifest_path)
+                .injected_packages(inject)
+                .injected_channels(inject_channel)
+                .instructions(instructions)
+                .header_template(header_template)
+                .extra_channels(extra_channel)
+                .channel_priority(channel_priority)
+                .ignore_pypi_errors(ignore_pypi_errors)
+                .require_pip(require_pip)
+                .output_format(format)
+                .windows_flavor(windows_flavor)
+                .executable_format(executable_format)
+                .unpack_executable(unpack_executable)
+                .line_endings(line_endings)
+                .compression(compression)
+                .compression_threads(compression_threads.unwrap_or(0))
+                .download_order(download_order)
+                .http_options(http.into())
+                .reuse_from(reuse_from)
+                .base_pack(base_pack)
+                .zstd_dictionary(zstd_dictionary)
+                .cache_dir(use_cache)
+                .source_date_epoch(source_date_epoch)
+                .dry_run(dry_run)
+                .sign_key(sign_key)
+                .digest_name(digest_name)
+                .network_log(network_log);
+            let builder = hook.into_iter().fold(builder, |builder, command| {
+                builder.hook(Arc::new(CommandHook::new(command)))
+            });
+            let builder = extra_metadata
+                .into_iter()
+                .fold(builder, |builder, (key, value)| {
+                    builder.extra_metadata(key, value)
+                });
+
+            let platform = match platforms[..] {
+                [platform] => platform,
+                _ => {
+                    if ssh_destination.is_some() {
+                        anyhow::bail!("packs can only be sent via ssh for a single platform");
+                    }
+                    if format == OutputFormat::Oci {
+                        anyhow::bail!(
+                            "packs can only be pushed to a registry for a single platform"
+                        );
+                    }
+                    let output_directory = output_file.unwrap_or_else(cwd);
+                    if !dry_run {
+                        std::fs::create_dir_all(&output_directory)?;
+                    }
+                    let options = platforms
+                        .iter()
+                        .map(|&platform| {
+                            builder
+                                .clone()
+                                .platform(platform)
+                                .output_file(output_directory.join(platform_output_file(
+                                    format,
+                                    platform,
+                                    windows_flavor,
+                                    executable_format,
+                                    compression,
+                                )))
+                                .build()
+                        })
+                        .collect::<Vec<_>>();
+                    tracing::debug!("Running pack command with options: {:?}", options);
+                    let partial_outputs = options
+                        .iter()
+                        .map(|options| PartialOutput::new(options.output_file.clone()))
+                        .collect::<Vec<_>>();
+                    pack_many(options).await?;
+                    partial_outputs.into_iter().for_each(PartialOutput::keep);
+                    return Ok(());
+                }
+            };
+            if remote_unpack && platform.is_windows() {
+                anyhow::bail!("`--remote-unpack` is not supported for Windows packs");
+            }
+
+            // Packs for remote hosts are created in a temporary directory first.
+            let tmp_dir = tempfile::tempdir()?;
+            let output_file = match (&ssh_destination, output_file) {
+                (Some(destination), _) => tmp_dir.path().join(
+                    Path::new(&destination.path).file_name().unwrap_or(
+                        format
+                            .default_output_file(
+                                platform,
+                                windows_flavor,
+                                executable_format,
+                                compression,
+                            )
+                            .as_os_str(),
+                    ),
+                ),
+                (None, Some(output_file)) => output_file,
+                // The default OCI reference is not a path.
+                (None, None) if format == OutputFormat::Oci => format.default_output_file(
                     platform,
-                },
-                injected_packages: inject,
-                ignore_pypi_errors,
-                create_executable,
+                    windows_flavor,
+                    executable_format,
+                    compression,
+                ),
+                (None, None) => cwd().join(format.default_output_file(
+                    platform,
+                    windows_flavor,
+                    executable_format,
+                    compression,
+                )),
             };
+
+            let options = builder.platform(platform).output_file(output_file).build();
             tracing::debug!("Running pack command with options: {:?}", options);
-            pack(options).await?
+            let partial_output = PartialOutput::new(options.output_file.clone());
+            let result = if environments.len() > 1 {
+                pack_environments(options, environments).await?
+            } else {
+                pack(options).await?
+            };
+            partial_output.keep();
+            tracing::debug!("Created pack: {:?}", result);
+            if dry_run {
+                return Ok(());
+            }
+            if let Some(destination) = ssh_destination {
+                deploy_over_ssh(&result.output_file, &destination, format, remote_unpack).await?;
+            }
+        }
+        Commands::Fetch {
+            environment,
+            platforms,
+            auth_file,
+            manifest_path,
+            use_cache,
+            ignore_pypi_errors,
+            download_order,
+            http,
+        } => {
+            let network_log = http.network_log()?;
+            for platform in platforms {
+                let options = PackOptions::builder()
+                    .environment(environment.clone())
+                    .platform(platform)
+                    .auth_file(auth_file.clone())
+                    .manifest_path(manifest_path.clone())
+                    .cache_dir(use_cache.clone())
+                    .ignore_pypi_errors(ignore_pypi_errors)
+                    .download_order(download_order)
+                    .http_options(http.clone().into())
+                    .network_log(network_log.clone())
+                    .build();
+                tracing::debug!("Running fetch command with options: {:?}", options);
+                fetch(options).await?;
+            }
+        }
+        Commands::Environments { manifest_path } => {
+            for environment in lockfile_environments(&manifest_path)? {
+                println!("{}", environment.name);
+                for (platform, packages) in environment.platforms {
+                    println!("  {:<16} {} packages", platform.as_str(), packages);
+                }
+            }
+        }
+        Commands::Platforms {
+            environment,
+            manifest_path,
+        } => {
+            for platform in lockfile_platforms(&manifest_path, &environment)? {
+                println!("{}", platform);
+            }
+        }
+        Commands::List { pack_file } => {
+            println!("{}", list(&pack_file).await?);
+        }
+        Commands::Validate { pack_file } => {
+            let report = validate(&pack_file).await?;
+            for problem in &report.problems {
+                status!("❌ {}", problem);
+            }
+            if !report.is_valid() {
+                anyhow::bail!(
+                    "{} is invalid: found {} problems",
+                    pack_file.display(),
+                    report.problems.len()
+                );
+            }
+            status!(
+                "✅ {} is valid ({} packages).",
+                pack_file.display(),
+                report.packages
+            );
         }
         Commands::Unpack {
             output_directory,
             env_name,
+            target_prefix,
             pack_file,
             shell,
+            info,
+            show_metadata,
+            chown,
+            file_mode,
+            dir_mode,
+            group_shared,
+            setgid_dirs,
+            idempotent,
+            skip_disk_space_check,
+            timeout,
+            init_container,
+            allow_link_scripts,
+            extract_concurrency,
+            base_pack,
+            verify_key,
+            require_signature,
+            path_modification,
+            skip_activation_script,
+            activation_script,
+            environment,
+            verify_only,
+            http,
         } => {
-            let options = UnpackOptions {
-                pack_file,
-                output_directory,
-                env_name,
-                shell,
-            };
+            if info {
+                print_pack_info(&pack_file).await?;
+                return Ok(());
+            }
+            if show_metadata {
+                let metadata = read_metadata(&pack_file).await?;
+                println!("{}", serde_json::to_string_pretty(&metadata)?);
+                return Ok(());
+            }
+            let options = UnpackOptions::builder()
+                .pack_file(pack_file)
+                .output_directory(output_directory)
+                .env_name(env_name)
+                .shell(shell)
+                .permissions(PermissionPolicy {
+                    owner: chown,
+                    file_mode,
+                    dir_mode,
+                    group_shared,
+                    setgid_dirs,
+                })
+                .idempotent(idempotent || init_container)
+                .skip_disk_space_check(skip_disk_space_check)
+                .allow_link_scripts(allow_link_scripts)
+                .extract_concurrency(extract_concurrency)
+                .base_pack(base_pack)
+                .target_prefix(target_prefix)
+                .verify_key(verify_key)
+                .require_signature(require_signature)
+                .path_modification(path_modification)
+                .skip_activation_script(skip_activation_script)
+                .activation_script(activation_script)
+                .environment(environment)
+                .verify_only(verify_only)
+                .network_log(http.network_log()?)
+                .http_options(http.into())
+                .build();
             tracing::debug!("Running unpack command with options: {:?}", options);
-            unpack(options).await?
+            let timeout = timeout.map(Duration::from_secs);
+            if init_container {
+                let prefix = options.prefix();
+                let start = Instant::now();
+                let result = unpack_with_timeout(options, timeout).await;
+                let (status, exit_code) = match &result {
+                    Ok(UnpackOutcome::Installed) => ("installed", 0),
+                    Ok(UnpackOutcome::AlreadyInstalled) => ("already-installed", 0),
+                    Ok(UnpackOutcome::Verified) => ("verified", 0),
+                    Err(e) if e.is::<TimedOut>() => ("timeout", 124),
+                    Err(_) => ("failed", 1),
+                };
+                let mut report = serde_json::json!({
+                    "status": status,
+                    "prefix": prefix,
+                    "elapsed-seconds": start.elapsed().as_secs_f64(),
+                });
+                match result {
+                    Ok(_) => {
+                        if let Ok(Some(link_scripts)) = LinkScriptsReport::read(&prefix) {
+                            report["link-scripts"] = serde_json::to_value(link_scripts)?;
+                        }
+                    }
+                    Err(e) => report["error"] = format!("{:#}", e).into(),
+                }
+                println!("{}", report);
+                std::process::exit(exit_code);
+            }
+            unpack_with_timeout(options, timeout).await?;
+        }
+        Commands::ActivateScript {
+            prefix,
+            shell,
+            output_directory,
+            path_modification,
+        } => {
+            let output_directory = output_directory.unwrap_or_else(|| match prefix.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            });
+            let activation_script = write_activation_script(
+                &prefix,
+                &output_directory,
+                shell.unwrap_or_default(),
+                path_modification,
+            )
+            .await?;
+            status!(
+                "📜 Wrote activation script to {}.",
+                activation_script.display()
+            );
+        }
+        Commands::Export {
+            prefix,
+            output_file,
+        } => {
+            let exported = export(&prefix, &output_file).await?;
+            status!(
+                "📝 Exported {} conda packages to {}.",
+                exported.conda_packages,
+                exported.environment_file.display()
+            );
+            if let Some(requirements_file) = exported.requirements_file {
+                status!(
+                    "🐍 Exported {} PyPI packages to {}.",
+                    exported.pypi_packages,
+                    requirements_file.display()
+                );
+            }
+        }
+        #[cfg(unix)]
+        Commands::Serve { socket } => pixi_pack::serve(&socket).await?,
+        Commands::Completion { shell } => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
         }
     };
-    tracing::debug!("Finished running pixi-pack");
 
     Ok(())
 }
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    fn test_parse_threads() {
+        assert!(parse_threads("auto").unwrap() > 0);
+        assert_eq!(parse_threads("4"), Ok(4));
+        assert!(parse_threads("many").is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use rattler_conda_types::Platform;
+use toml_edit::{DocumentMut, Item, Value};
+
+/// The file names of pixi manifests, in the order in which they are looked for in a directory.
+const MANIFEST_FILE_NAMES: [&str; 2] = ["pixi.toml", "pyproject.toml"];
+
+/// The manifest of the pixi workspace that `manifest_path` belongs to. The lockfile is next to it.
+///
+/// `manifest_path` can be a directory, `pixi.toml` or `pyproject.toml`. If it is not a workspace
+/// manifest, e.g. a missing `pixi.toml` in a `pyproject.toml`-only project or the
+/// `pyproject.toml` of a nested project without `[tool.pixi]`, the closest workspace manifest in
+/// the directory or its parents is used like pixi does. Falls back to `manifest_path` (or
+/// `pixi.toml` in it) if there is none.
+pub(crate) fn workspace_manifest(manifest_path: &Path) -> Result<PathBuf> {
+    let (directory, fallback) = if manifest_path.is_dir() {
+        (manifest_path, manifest_path.join("pixi.toml"))
+    } else {
+        if is_workspace_manifest(manifest_path) {
+            return Ok(manifest_path.to_path_buf());
+        }
+        let directory = manifest_path
+            .parent()
+            .ok_or(anyhow!("could not get parent directory"))?;
+        (directory, manifest_path.to_path_buf())
+    };
+    let directory = std::path::absolute(directory)
+        .map_err(|e| anyhow!("could not resolve {}: {}", directory.display(), e))?;
+    let workspace_manifest = directory
+        .ancestors()
+        .flat_map(|directory| MANIFEST_FILE_NAMES.map(|name| directory.join(name)))
+        .find(|candidate| is_workspace_manifest(candidate));
+    if let Some(workspace_manifest) = &workspace_manifest {
+        tracing::debug!("Using workspace manifest {}", workspace_manifest.display());
+    }
+    Ok(workspace_manifest.unwrap_or(fallback))
+}
+
+/// Whether `path` is a `pixi.toml` or `pyproject.toml` that defines a pixi workspace.
+///
+/// `pyproject.toml` files only need a `[tool.pixi]` table that is not just a package definition,
+/// the name of the workspace is taken from `[project]` then.
+fn is_workspace_manifest(path: &Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    let Ok(document) = read_manifest(path) else {
+        return false;
+    };
+    let Ok(manifest) = pixi_table(path, &document) else {
+        return false;
+    };
+    manifest.get("workspace").is_some()
+        || manifest.get("project").is_some()
+        || (is_pyproject(path) && manifest.get("package").is_none())
+}
+
+fn is_pyproject(path: &Path) -> bool {
+    path.file_name() == Some("pyproject.toml".as_ref())
+}
+
+fn read_manifest(manifest_path: &Path) -> Result<DocumentMut> {
+    let contents = std::fs::read_to_string(manifest_path)
+        .map_err(|e| anyhow!("could not read {}: {}", manifest_path.display(), e))?;
+    contents
+        .parse()
+        .map_err(|e| anyhow!("could not parse {}: {}", manifest_path.display(), e))
+}
+
+/// The pixi configuration of a manifest: `[tool.pixi]` in `pyproject.toml` and the whole document
+/// otherwise.
+fn pixi_table<'a>(manifest_path: &Path, document: &'a DocumentMut) -> Result<&'a Item> {
+    if is_pyproject(manifest_path) {
+        document
+            .get("tool")
+            .and_then(|tool| tool.get("pixi"))
+            .ok_or_else(|| anyhow!("{} has no [tool.pixi] table", manifest_path.display()))
+    } else {
+        Ok(document.as_item())
+    }
+}
+
+/// The conda dependencies that are requested for `environment` on `platform` in `pixi.toml` or
+/// `pyproject.toml`, as match specs sorted by package name, e.g. `python >=3.12`.
+///
+/// Dependencies of later features and of matching `target` tables take precedence.
+pub(crate) fn requested_specs(
+    manifest_path: &Path,
+    environment: &str,
+    platform: Platform,
+) -> Result<Vec<String>> {
+    let document = read_manifest(manifest_path)?;
+    let manifest = pixi_table(manifest_path, &document)?;
+
+    let mut specs = BTreeMap::new();
+    for feature in environment_features(manifest, environment)? {
+        let feature_table = if feature == "default" {
+            Some(manifest)
+        } else {
+            manifest.get("feature").and_then(|f| f.get(&feature))
+        };
+        let Some(feature_table) = feature_table else {
+            anyhow::bail!("feature not found in manifest: {}", feature);
+        };
+
+        let targets = feature_table.get("target");
+        let target_dependencies = target_selectors(platform)
+            .into_iter()
+            .filter_map(|selector| targets.and_then(|t| t.get(selector)));
+        for dependencies in std::iter::once(feature_table)
+            .chain(target_dependencies)
+            .filter_map(|table| table.get("dependencies"))
+        {
+            let Some(dependencies) = dependencies.as_table_like() else {
+                continue;
+            };
+            for (name, value) in dependencies.iter() {
+                specs.insert(name.to_string(), match_spec(name, value)?);
+            }
+        }
+    }
+    Ok(specs.into_values().collect())
+}
+
+/// The features of `environment` in the order of increasing precedence.
+fn environment_features(manifest: &Item, environment: &str) -> Result<Vec<String>> {
+    let definition = manifest
+        .get("environments")
+        .and_then(|environments| environments.get(environment));
+    let (features, no_default_feature) = match definition {
+        None if environment == "default" => (None, false),
+        None => anyhow::bail!("environment not found in manifest: {}", environment),
+        Some(definition) => match definition.as_array() {
+            Some(features) => (Some(features), false),
+            None => (
+                definition.get("features").and_then(Item::as_array),
+                definition
+                    .get("no-default-feature")
+                    .and_then(Item::as_bool)
+                    .unwrap_or(false),
+            ),
+        },
+    };
+
+    let mut names = Vec::new();
+    if !no_default_feature {
+        names.push("default".to_string());
+    }
+    for feature in features.into_iter().flatten() {
+        let feature = feature
+            .as_str()
+            .ok_or_else(|| anyhow!("invalid feature of environment {}", environment))?;
+        names.push(feature.to_string());
+    }
+    Ok(names)
+}
+
+/// The keys of the `target` tables that apply to `platform`, in the order of increasing precedence.
+fn target_selectors(platform: Platform) -> Vec<&'static str> {
+    let mut selectors = Vec::new();
+    if platform.is_unix() {
+        selectors.push("unix");
+    }
+    if platform.is_linux() {
+        selectors.push("linux");
+    }
+    if platform.is_osx() {
+        selectors.push("osx");
+    }
+    if platform.is_windows() {
+        selectors.push("win");
+    }
+    selectors.push(platform.as_str());
+    selectors
+}
+
+/// Convert a dependency like `python = ">=3.12"` or `openssl = { version = "3.3.1", build = "*_0" }`
+/// to a match spec.
+fn match_spec(name: &str, value: &Item) -> Result<String> {
+    let (version, build) = match value {
+        Item::Value(Value::String(version)) => (Some(version.value().as_str()), None),
+        value if value.is_table_like() => {
+            let field = |key| value.get(key).and_then(Item::as_str);
+            (field("version"), field("build"))
+        }
+        _ => anyhow::bail!("invalid dependency specification for {}", name),
+    };
+    Ok(match (version.unwrap_or("*"), build) {
+        ("*", None) => name.to_string(),
+        (version, None) => format!("{} {}", name, version),
+        (version, Some(build)) => format!("{} {} {}", name, version, build),
+    })
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    const MANIFEST: &str = r#"
+[project]
+name = "test"
+
+[dependencies]
+python = ">=3.12"
+openssl = { version = "3.3.1", build = "*_0" }
+pip = "*"
+
+[target.unix.dependencies]
+bash = "5.*"
+
+[target.win-64.dependencies]
+python = "3.12.*"
+
+[feature.test.dependencies]
+pytest = "8.*"
+python = "3.13.*"
+
+[environments]
+test = ["test"]
+minimal = { features = ["test"], no-default-feature = true }
+"#;
+
+    fn write_manifest(dir: &Path, file_name: &str, contents: &str) -> std::path::PathBuf {
+        let path = dir.join(file_name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[rstest]
+    #[case("default", Platform::Linux64, vec!["bash 5.*", "openssl 3.3.1 *_0", "pip", "python >=3.12"])]
+    #[case("default", Platform::Win64, vec!["openssl 3.3.1 *_0", "pip", "python 3.12.*"])]
+    #[case("test", Platform::Linux64, vec!["bash 5.*", "openssl 3.3.1 *_0", "pip", "pytest 8.*", "python 3.13.*"])]
+    #[case("minimal", Platform::Linux64, vec!["pytest 8.*", "python 3.13.*"])]
+    fn test_requested_specs(
+        #[case] environment: &str,
+        #[case] platform: Platform,
+        #[case] expected: Vec<&str>,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = write_manifest(dir.path(), "pixi.toml", MANIFEST);
+        assert_eq!(
+            requested_specs(&manifest, environment, platform).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    fn test_requested_specs_pyproject() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = write_manifest(
+            dir.path(),
+            "pyproject.toml",
+            "[project]\nname = \"test\"\n\n[tool.pixi.dependencies]\npython = \"3.12.*\"\n",
+        );
+        assert_eq!(
+            requested_specs(&manifest, "default", Platform::Linux64).unwrap(),
+            vec!["python 3.12.*"]
+        );
+    }
+
+    #[rstest]
+    #[case::pyproject_only("pixi.toml", "pyproject.toml")]
+    #[case::directory("", "pyproject.toml")]
+    #[case::nested_pyproject("member/pyproject.toml", "pyproject.toml")]
+    #[case::nested_package("member/pixi.toml", "pyproject.toml")]
+    #[case::nested_workspace("nested/pixi.toml", "nested/pixi.toml")]
+    fn test_workspace_manifest(#[case] manifest_path: &str, #[case] expected: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        write_manifest(
+            dir.path(),
+            "pyproject.toml",
+            "[project]\nname = \"test\"\n\n[tool.pixi.dependencies]\npython = \"3.12.*\"\n",
+        );
+        std::fs::create_dir(dir.path().join("member")).unwrap();
+        write_manifest(
+            &dir.path().join("member"),
+            "pyproject.toml",
+            "[project]\nname = \"member\"\n",
+        );
+        write_manifest(
+            &dir.path().join("member"),
+            "pixi.toml",
+            "[package]\nname = \"member\"\n",
+        );
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        write_manifest(&dir.path().join("nested"), "pixi.toml", MANIFEST);
+
+        let dir_path = std::path::absolute(dir.path()).unwrap();
+        assert_eq!(
+            workspace_manifest(&dir_path.join(manifest_path)).unwrap(),
+            dir_path.join(expected)
+        );
+    }
+
+    #[rstest]
+    fn test_requested_specs_unknown_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = write_manifest(dir.path(), "pixi.toml", MANIFEST);
+        assert!(requested_specs(&manifest, "does-not-exist", Platform::Linux64).is_err());
+    }
+}
@@ -0,0 +1,519 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{mpsc, Arc, Mutex},
+    task::{Context, Poll},
+    thread::JoinHandle,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use http::Extensions;
+use http_body::{Body, Frame, SizeHint};
+use reqwest::{redirect, Request, Response, ResponseBuilderExt};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The maximum number of redirects that are followed, like reqwest's default policy.
+const MAX_REDIRECTS: usize = 10;
+
+tokio::task_local! {
+    /// The redirects of the request that the current task sends, see
+    /// [`NetworkLog::redirect_policy`].
+    static REDIRECTS: Arc<Mutex<Vec<Url>>>;
+}
+
+/// A log of all HTTP requests sent while packing, e.g. as evidence of what was downloaded from
+/// where for security reviews.
+///
+/// Every request is written as one line of JSON once its response body was received, see
+/// [`NetworkLogEntry`]. Entries are written by a background thread, use [`NetworkLog::flush`] to
+/// wait for them. Clones write to the same file, so one log can be shared by several packs. URLs
+/// are logged before credentials are added to them.
+#[derive(Debug, Clone)]
+pub struct NetworkLog {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    sender: Option<mpsc::Sender<Message>>,
+    writer: Option<JoinHandle<Option<String>>>,
+}
+
+#[derive(Debug)]
+enum Message {
+    Entry(NetworkLogEntry),
+    Flush(tokio::sync::oneshot::Sender<Option<String>>),
+}
+
+/// A request in a [`NetworkLog`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NetworkLogEntry {
+    /// When the request was sent, as unix timestamp.
+    pub timestamp: u64,
+    /// The HTTP method, e.g. `GET`.
+    pub method: String,
+    /// The requested URL.
+    pub url: String,
+    /// The URL that the response was received from, if one was received.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    /// The URLs of the redirects that were followed, in order. The last one is the final URL.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<String>,
+    /// The status code of the response, if one was received.
+    pub status: Option<u16>,
+    /// The number of bytes of the response body that were received, if a response was received.
+    pub bytes: Option<u64>,
+    /// The time until the response body was received completely, in milliseconds.
+    pub duration_ms: u64,
+    /// Why no response was received or the response body was not received completely.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl NetworkLog {
+    /// Create the log at `path`, replacing an existing file.
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = File::create(&path)
+            .map_err(|e| anyhow!("could not create network log {}: {}", path.display(), e))?;
+        let (sender, receiver) = mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("network-log".to_string())
+            .spawn(move || write_entries(file, receiver))
+            .map_err(|e| anyhow!("could not start network log writer: {}", e))?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                path,
+                sender: Some(sender),
+                writer: Some(writer),
+            }),
+        })
+    }
+
+    /// The path of the log.
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Append `entry` to the log. The entry is written in the background.
+    pub fn record(&self, entry: NetworkLogEntry) {
+        let sent = self
+            .inner
+            .sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(Message::Entry(entry)).is_ok());
+        if !sent {
+            tracing::warn!("network log writer of {} stopped", self.path().display());
+        }
+    }
+
+    /// Wait until all recorded entries are written.
+    pub async fn flush(&self) -> Result<()> {
+        let (reply, written) = tokio::sync::oneshot::channel();
+        if let Some(sender) = &self.inner.sender {
+            let _ = sender.send(Message::Flush(reply));
+        }
+        match written.await {
+            Ok(None) => Ok(()),
+            Ok(Some(e)) => Err(anyhow!(
+                "could not write network log {}: {}",
+                self.path().display(),
+                e
+            )),
+            Err(_) => Err(anyhow!(
+                "network log writer of {} stopped",
+                self.path().display()
+            )),
+        }
+    }
+
+    /// A redirect policy for clients that use this log, so that redirects are recorded.
+    ///
+    /// Follows up to 10 redirects like reqwest's default policy. Redirects are followed while
+    /// the middleware awaits the response, so the policy records them for the request of the
+    /// current task. This way, concurrent requests that end up at the same URL don't mix up
+    /// their redirects.
+    pub fn redirect_policy(&self) -> redirect::Policy {
+        redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            // The first URL is the requested one, which may contain credentials.
+            let redirects = attempt
+                .previous()
+                .iter()
+                .skip(1)
+                .chain([attempt.url()])
+                .cloned()
+                .collect();
+            // Requests that are not sent through the middleware are not recorded.
+            let _ = REDIRECTS.try_with(|current| *current.lock().unwrap() = redirects);
+            attempt.follow()
+        })
+    }
+
+    /// Read all entries of the log at `path`.
+    pub fn read(path: &Path) -> Result<Vec<NetworkLogEntry>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("could not read network log {}: {}", path.display(), e))?;
+        contents
+            .lines()
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| anyhow!("invalid entry in network log {}: {}", path.display(), e))
+            })
+            .collect()
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // Closing the channel stops the writer once all entries are written.
+        drop(self.sender.take());
+        let error = self
+            .writer
+            .take()
+            .and_then(|writer| writer.join().ok())
+            .flatten();
+        if let Some(e) = error {
+            tracing::warn!("could not write network log {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Write the entries received from `receiver` to `file` until all senders are dropped.
+///
+/// Returns the first error, after which no more entries are written.
+fn write_entries(file: File, receiver: mpsc::Receiver<Message>) -> Option<String> {
+    let mut writer = BufWriter::new(file);
+    let mut error = None;
+    for message in receiver {
+        match message {
+            Message::Entry(entry) => {
+                if error.is_none() {
+                    let result = serde_json::to_writer(&mut writer, &entry)
+                        .map_err(std::io::Error::from)
+                        .and_then(|()| writer.write_all(b"\n"));
+                    error = result.err().map(|e| e.to_string());
+                }
+            }
+            Message::Flush(reply) => {
+                if error.is_none() {
+                    error = writer.flush().err().map(|e| e.to_string());
+                }
+                let _ = reply.send(error.clone());
+            }
+        }
+    }
+    if error.is_none() {
+        error = writer.flush().err().map(|e| e.to_string());
+    }
+    error
+}
+
+/// An entry that is recorded once the response body was received.
+struct PendingEntry {
+    log: NetworkLog,
+    entry: NetworkLogEntry,
+    start: Instant,
+}
+
+impl PendingEntry {
+    fn record(mut self, error: Option<String>) {
+        self.entry.duration_ms = self.start.elapsed().as_millis() as u64;
+        self.entry.error = error;
+        self.log.record(self.entry);
+    }
+}
+
+/// A response body that counts the received bytes and records the entry when it is complete.
+struct LoggedBody {
+    inner: reqwest::Body,
+    pending: Option<PendingEntry>,
+}
+
+impl Body for LoggedBody {
+    type Data = <reqwest::Body as Body>::Data;
+    type Error = reqwest::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let result = Pin::new(&mut self.inner).poll_frame(cx);
+        match &result {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let (Some(data), Some(pending)) = (frame.data_ref(), &mut self.pending) {
+                    pending.entry.bytes =
+                        Some(pending.entry.bytes.unwrap_or(0) + data.len() as u64);
+                }
+            }
+            Poll::Ready(Some(Err(e))) => {
+                if let Some(pending) = self.pending.take() {
+                    pending.record(Some(e.to_string()));
+                }
+            }
+            Poll::Ready(None) => {
+                if let Some(pending) = self.pending.take() {
+                    pending.record(None);
+                }
+            }
+            Poll::Pending => {}
+        }
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            let error = (!self.inner.is_end_stream())
+                .then(|| "the response body was not read completely".to_string());
+            pending.record(error);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for NetworkLog {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let method = req.method().to_string();
+        let url = req.url().to_string();
+        let start = Instant::now();
+
+        let redirects = Arc::new(Mutex::new(Vec::new()));
+        let response = match REDIRECTS
+            .scope(redirects.clone(), next.run(req, extensions))
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                self.record(NetworkLogEntry {
+                    timestamp,
+                    method,
+                    url,
+                    final_url: None,
+                    redirects: Vec::new(),
+                    status: None,
+                    bytes: None,
+                    duration_ms: start.elapsed().as_millis() as u64,
+                    error: Some(e.to_string()),
+                });
+                return Err(e);
+            }
+        };
+
+        let response_url = response.url().clone();
+        let redirects = std::mem::take(&mut *redirects.lock().unwrap());
+        // Without redirects, the response URL is the requested one with credentials.
+        let final_url = if redirects.is_empty() {
+            url.clone()
+        } else {
+            response_url.to_string()
+        };
+        let pending = PendingEntry {
+            log: self.clone(),
+            entry: NetworkLogEntry {
+                timestamp,
+                method,
+                url,
+                final_url: Some(final_url),
+                redirects: redirects.iter().map(Url::to_string).collect(),
+                status: Some(response.status().as_u16()),
+                bytes: Some(0),
+                duration_ms: 0,
+                error: None,
+            },
+            start,
+        };
+
+        let (parts, body) = http::Response::<reqwest::Body>::from(response).into_parts();
+        let body = LoggedBody {
+            inner: body,
+            pending: Some(pending),
+        };
+        let mut builder = http::Response::builder()
+            .status(parts.status)
+            .version(parts.version)
+            .url(response_url);
+        if let Some(headers) = builder.headers_mut() {
+            *headers = parts.headers;
+        }
+        if let Some(response_extensions) = builder.extensions_mut() {
+            response_extensions.extend(parts.extensions);
+        }
+        let response = builder
+            .body(reqwest::Body::wrap(body))
+            .map_err(|e| reqwest_middleware::Error::Middleware(e.into()))?;
+        Ok(Response::from(response))
+    }
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_network_log() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            for response in [
+                "HTTP/1.1 302 Found\r\nlocation: /noarch/pkg-1.0-0.conda\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                // Chunked responses don't announce their size.
+                "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n3\r\ncon\r\n5\r\ntents\r\n0\r\n\r\n",
+            ] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut buf = [0; 1024];
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = NetworkLog::create(dir.path().join("network.jsonl")).unwrap();
+        let client = reqwest_middleware::ClientBuilder::new(
+            reqwest::Client::builder()
+                .redirect(log.redirect_policy())
+                .build()
+                .unwrap(),
+        )
+        .with(log.clone())
+        .build();
+        let url = format!("http://{}/redirect", address);
+        let final_url = format!("http://{}/noarch/pkg-1.0-0.conda", address);
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.url().as_str(), final_url);
+        // The entry is written once the body was received.
+        log.flush().await.unwrap();
+        assert!(NetworkLog::read(log.path()).unwrap().is_empty());
+        assert_eq!(response.text().await.unwrap(), "contents");
+        server.await.unwrap();
+        // Nothing listens on the port anymore.
+        assert!(client.get(&url).send().await.is_err());
+
+        log.flush().await.unwrap();
+        let entries = NetworkLog::read(log.path()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].method, "GET");
+        assert_eq!(entries[0].url, url);
+        assert_eq!(entries[0].final_url, Some(final_url.clone()));
+        assert_eq!(entries[0].redirects, vec![final_url]);
+        assert_eq!(entries[0].status, Some(200));
+        assert_eq!(entries[0].bytes, Some(8));
+        assert_eq!(entries[0].error, None);
+        assert_eq!(entries[1].status, None);
+        assert_eq!(entries[1].bytes, None);
+        assert!(entries[1].error.is_some());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_network_log_concurrent_redirects() {
+        /// Read a request and return its path.
+        async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                let mut buf = [0; 1024];
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            request.split(' ').nth(1).unwrap().to_string()
+        }
+        const OK: &[u8] = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (redirected, redirected_received) = tokio::sync::oneshot::channel();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            assert_eq!(read_request(&mut socket).await, "/redirect");
+            socket
+                .write_all(b"HTTP/1.1 302 Found\r\nlocation: /pkg\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            // The redirected request is answered after a direct request to the same URL.
+            let (mut redirected_socket, _) = listener.accept().await.unwrap();
+            assert_eq!(read_request(&mut redirected_socket).await, "/pkg");
+            redirected.send(()).unwrap();
+            let (mut socket, _) = listener.accept().await.unwrap();
+            assert_eq!(read_request(&mut socket).await, "/pkg");
+            socket.write_all(OK).await.unwrap();
+            drop(socket);
+            redirected_socket.write_all(OK).await.unwrap();
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = NetworkLog::create(dir.path().join("network.jsonl")).unwrap();
+        let client = reqwest_middleware::ClientBuilder::new(
+            reqwest::Client::builder()
+                .redirect(log.redirect_policy())
+                .build()
+                .unwrap(),
+        )
+        .with(log.clone())
+        .build();
+        let redirect_url = format!("http://{}/redirect", address);
+        let url = format!("http://{}/pkg", address);
+        let redirected_request = tokio::spawn({
+            let client = client.clone();
+            async move {
+                let response = client.get(&redirect_url).send().await.unwrap();
+                response.text().await.unwrap()
+            }
+        });
+        redirected_received.await.unwrap();
+        assert_eq!(
+            client.get(&url).send().await.unwrap().text().await.unwrap(),
+            "ok"
+        );
+        assert_eq!(redirected_request.await.unwrap(), "ok");
+        server.await.unwrap();
+
+        log.flush().await.unwrap();
+        let mut entries = NetworkLog::read(log.path()).unwrap();
+        entries.sort_by(|a, b| a.url.cmp(&b.url));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].url, url);
+        assert!(entries[0].redirects.is_empty());
+        assert_eq!(entries[1].url, format!("http://{}/redirect", address));
+        assert_eq!(entries[1].redirects, vec![url]);
+    }
+}
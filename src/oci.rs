@@ -0,0 +1,615 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::StreamExt;
+use rattler_digest::{compute_file_digest, Sha256};
+use rattler_networking::{Authentication, AuthenticationStorage};
+use reqwest::{header, Method, Response, StatusCode};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::{fs::File, io::AsyncWriteExt};
+use tokio_tar::{Archive, Builder};
+use url::Url;
+
+use crate::{
+    pack::write_archive_filtered, RetryPolicy, CHANNEL_DIRECTORY_NAME, PIXI_PACK_METADATA_PATH,
+};
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const ARTIFACT_TYPE: &str = "application/vnd.pixi-pack.v1";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.pixi-pack.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+/// A pack in an OCI registry, written as `oci://registry/repository[:tag]`, e.g.
+/// `oci://ghcr.io/org/env:tag`. The tag defaults to `latest`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OciReference {
+    /// The host of the registry, including the port, e.g. `ghcr.io` or `localhost:5000`.
+    pub registry: String,
+    pub repository: String,
+    pub tag: String,
+}
+
+impl OciReference {
+    /// Whether `path` is an `oci://` reference instead of a local file.
+    pub fn is_oci_reference(path: &Path) -> bool {
+        path.to_str().is_some_and(|path| path.starts_with("oci://"))
+    }
+
+    /// The base URL of the repository. Registries on localhost are accessed via plain HTTP.
+    fn url(&self, path: &str) -> Result<Url> {
+        let host = self.registry.split(':').next().unwrap_or_default();
+        let scheme = match host {
+            "localhost" | "127.0.0.1" => "http",
+            _ => "https",
+        };
+        Url::parse(&format!(
+            "{}://{}/v2/{}/{}",
+            scheme, self.registry, self.repository, path
+        ))
+        .map_err(|e| anyhow!("invalid OCI reference {}: {}", self, e))
+    }
+}
+
+impl FromStr for OciReference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rest = s
+            .strip_prefix("oci://")
+            .ok_or(anyhow!("expected an oci:// reference, got `{}`", s))?;
+        let (registry, name) = rest
+            .split_once('/')
+            .ok_or(anyhow!("missing repository in `{}`", s))?;
+        let (repository, tag) = match name.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+            _ => (name, "latest"),
+        };
+        if registry.is_empty() || repository.is_empty() || tag.is_empty() {
+            anyhow::bail!("expected `oci://registry/repository[:tag]`, got `{}`", s);
+        }
+        if repository != repository.to_lowercase() {
+            anyhow::bail!(
+                "OCI repository names must be lowercase, got `{}`",
+                repository
+            );
+        }
+        Ok(Self {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            tag: tag.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for OciReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "oci://{}/{}:{}",
+            self.registry, self.repository, self.tag
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+    size: u64,
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    annotations: std::collections::BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    schema_version: u32,
+    media_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artifact_type: Option<String>,
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+}
+
+/// A client for one repository of a registry, authenticated via the token flow of the
+/// distribution spec or HTTP basic authentication.
+struct Registry {
+    client: ClientWithMiddleware,
+    retry_policy: RetryPolicy,
+    reference: OciReference,
+    authorization: Option<String>,
+}
+
+impl Registry {
+    /// Authenticate with the credentials of the registry host in `auth_storage`, if any.
+    /// `actions` are the requested permissions, e.g. `pull` or `pull,push`.
+    async fn connect(
+        reference: &OciReference,
+        client: &ClientWithMiddleware,
+        retry_policy: RetryPolicy,
+        auth_storage: &AuthenticationStorage,
+        actions: &str,
+    ) -> Result<Self> {
+        let credentials = auth_storage.get(&reference.registry).map_err(|e| {
+            anyhow!(
+                "could not read credentials for {}: {}",
+                reference.registry,
+                e
+            )
+        })?;
+        let basic = match &credentials {
+            Some(Authentication::BasicHTTP { username, password }) => Some(format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", username, password))
+            )),
+            _ => None,
+        };
+
+        let mut base_url = reference.url("")?;
+        base_url.set_path("/v2/");
+        let response = send(client.get(base_url), retry_policy).await?;
+        let challenge = response
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let authorization = match (response.status(), challenge) {
+            (StatusCode::UNAUTHORIZED, Some(challenge))
+                if challenge.to_lowercase().starts_with("bearer ") =>
+            {
+                let token = match &credentials {
+                    Some(Authentication::BearerToken(token)) => token.clone(),
+                    _ => {
+                        let scope = format!("repository:{}:{}", reference.repository, actions);
+                        fetch_token(client, retry_policy, &challenge, &scope, basic.as_deref())
+                            .await?
+                    }
+                };
+                Some(format!("Bearer {}", token))
+            }
+            (StatusCode::UNAUTHORIZED, _) => Some(basic.ok_or(anyhow!(
+                "{} requires authentication, but there are no credentials for it",
+                reference.registry
+            ))?),
+            (status, _) if status.is_success() => basic,
+            (status, _) => anyhow::bail!(
+                "unexpected response from {}: {}",
+                reference.registry,
+                status
+            ),
+        };
+        Ok(Self {
+            client: client.clone(),
+            retry_policy,
+            reference: reference.clone(),
+            authorization,
+        })
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.authorization {
+            Some(authorization) => request.header(header::AUTHORIZATION, authorization),
+            None => request,
+        }
+    }
+
+    /// Send a request, retrying transient failures, see [`send`].
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        send(request, self.retry_policy).await
+    }
+
+    /// Upload a blob unless the registry already has it.
+    async fn push_blob(&self, path: &Path, descriptor: &Descriptor) -> Result<()> {
+        let blob_url = self
+            .reference
+            .url(&format!("blobs/{}", descriptor.digest))?;
+        let response = self.send(self.request(Method::HEAD, blob_url)).await?;
+        if response.status().is_success() {
+            tracing::debug!("{} already exists, skipping upload", descriptor.digest);
+            return Ok(());
+        }
+
+        let uploads_url = self.reference.url("blobs/uploads/")?;
+        let response = check(
+            self.send(self.request(Method::POST, uploads_url.clone()))
+                .await?,
+        )
+        .await?;
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(anyhow!("registry did not return an upload location"))?;
+        let mut upload_url = uploads_url.join(location)?;
+        upload_url
+            .query_pairs_mut()
+            .append_pair("digest", &descriptor.digest);
+        // Streamed bodies cannot be retried.
+        let file = File::open(path).await?;
+        check(
+            self.request(Method::PUT, upload_url)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .header(header::CONTENT_LENGTH, descriptor.size)
+                .body(file)
+                .send()
+                .await?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Download a blob to `destination` and verify its digest.
+    async fn pull_blob(&self, descriptor: &Descriptor, destination: &Path) -> Result<()> {
+        let url = self
+            .reference
+            .url(&format!("blobs/{}", descriptor.digest))?;
+        let response = check(self.send(self.request(Method::GET, url)).await?).await?;
+        let mut file = File::create(destination).await?;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+        let digest = sha256_digest(destination)?;
+        if digest != descriptor.digest {
+            anyhow::bail!(
+                "digest mismatch for blob {}: got {}",
+                descriptor.digest,
+                digest
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Send a request, retrying connection errors and server errors (`5xx`, `429`) according to
+/// `retry_policy`.
+async fn send(request: RequestBuilder, retry_policy: RetryPolicy) -> Result<Response> {
+    let mut retry = 0;
+    loop {
+        let attempt = request
+            .try_clone()
+            .expect("registry requests without a streamed body can be cloned");
+        let result = attempt.send().await;
+        let error = match &result {
+            Ok(response)
+                if response.status().is_server_error()
+                    || response.status() == StatusCode::TOO_MANY_REQUESTS =>
+            {
+                response.status().to_string()
+            }
+            Ok(_) => return Ok(result?),
+            Err(e) => e.to_string(),
+        };
+        if retry >= retry_policy.max_retries {
+            return Ok(result?);
+        }
+        let delay = retry_policy.delay(retry);
+        tracing::warn!(
+            "registry request failed: {}, retrying in {:.1}s",
+            error,
+            delay.as_secs_f64()
+        );
+        tokio::time::sleep(delay).await;
+        retry += 1;
+    }
+}
+
+/// Request a token from the realm of a `WWW-Authenticate: Bearer ...` challenge.
+async fn fetch_token(
+    client: &ClientWithMiddleware,
+    retry_policy: RetryPolicy,
+    challenge: &str,
+    scope: &str,
+    basic: Option<&str>,
+) -> Result<String> {
+    let parameters = parse_challenge(challenge);
+    let realm = parameters
+        .iter()
+        .find(|(key, _)| key == "realm")
+        .map(|(_, value)| value)
+        .ok_or(anyhow!("invalid authentication challenge: {}", challenge))?;
+    let mut url = Url::parse(realm)?;
+    if let Some((_, service)) = parameters.iter().find(|(key, _)| key == "service") {
+        url.query_pairs_mut().append_pair("service", service);
+    }
+    url.query_pairs_mut().append_pair("scope", scope);
+    let mut request = client.get(url);
+    if let Some(basic) = basic {
+        request = request.header(header::AUTHORIZATION, basic);
+    }
+    let response: serde_json::Value = check(send(request, retry_policy).await?)
+        .await?
+        .json()
+        .await?;
+    response
+        .get("token")
+        .or_else(|| response.get("access_token"))
+        .and_then(|token| token.as_str())
+        .map(str::to_string)
+        .ok_or(anyhow!("registry did not return a token"))
+}
+
+/// Parse the parameters of an authentication challenge like
+/// `Bearer realm="https://ghcr.io/token",service="ghcr.io"`.
+fn parse_challenge(challenge: &str) -> Vec<(String, String)> {
+    let parameters = challenge
+        .split_once(' ')
+        .map_or("", |(_, parameters)| parameters);
+    let mut result = Vec::new();
+    let mut rest = parameters.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        result.push((key, value.to_string()));
+        rest = remainder.trim_start_matches(',').trim();
+    }
+    result
+}
+
+async fn check(response: Response) -> Result<Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let url = response.url().clone();
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(anyhow!(
+        "request to {} failed with {}: {}",
+        url,
+        status,
+        body
+    ))
+}
+
+fn sha256_digest(path: &Path) -> Result<String> {
+    let digest = compute_file_digest::<Sha256>(path)
+        .map_err(|e| anyhow!("could not hash {}: {}", path.display(), e))?;
+    Ok(format!("sha256:{:x}", digest))
+}
+
+/// Which paths of a pack a layer contains, relative to the pack directory.
+type LayerFilter = Box<dyn Fn(&Path) -> bool>;
+
+/// The layers of a pack: one per subdirectory of the channel and one for all other files.
+/// Returns the title of each layer and which paths of the pack it contains.
+fn layers(pack_dir: &Path) -> Result<Vec<(String, LayerFilter)>> {
+    let channel = Path::new(CHANNEL_DIRECTORY_NAME);
+    let mut subdirs = std::fs::read_dir(pack_dir.join(CHANNEL_DIRECTORY_NAME))?
+        .map(|entry| Ok(entry?.file_name()))
+        .collect::<Result<Vec<_>>>()?;
+    subdirs.sort();
+
+    let mut layers: Vec<(String, LayerFilter)> = vec![(
+        "metadata".to_string(),
+        Box::new(move |path: &Path| path == channel || !path.starts_with(channel)),
+    )];
+    for subdir in subdirs {
+        let subdir = channel.join(subdir);
+        let title = subdir.to_string_lossy().replace('\\', "/");
+        layers.push((
+            title,
+            Box::new(move |path: &Path| path.starts_with(&subdir)),
+        ));
+    }
+    Ok(layers)
+}
+
+/// Push the contents of a pack in `pack_dir` to an OCI registry.
+///
+/// Every subdirectory of the channel becomes a layer, so unchanged subdirectories, e.g. `noarch`,
+/// are shared between the packs of different platforms or versions. The config of the artifact
+/// is the `pixi-pack.json` of the pack. Credentials for the registry host are read from
+/// `auth_storage`, so `client` must not authenticate requests itself, see
+/// [`crate::low_level::http_client`]. Failed requests are retried according to `retry_policy`, except for
+/// blob uploads.
+pub async fn push_to_registry(
+    pack_dir: &Path,
+    reference: &OciReference,
+    client: &ClientWithMiddleware,
+    retry_policy: RetryPolicy,
+    auth_storage: &AuthenticationStorage,
+) -> Result<()> {
+    let registry =
+        Registry::connect(reference, client, retry_policy, auth_storage, "pull,push").await?;
+    let staging_dir = tempfile::tempdir()?;
+
+    let config_path = pack_dir.join(PIXI_PACK_METADATA_PATH);
+    let config = Descriptor {
+        media_type: CONFIG_MEDIA_TYPE.to_string(),
+        digest: sha256_digest(&config_path)?,
+        size: std::fs::metadata(&config_path)?.len(),
+        annotations: Default::default(),
+    };
+    registry.push_blob(&config_path, &config).await?;
+
+    let mut descriptors = Vec::new();
+    for (index, (title, include)) in layers(pack_dir)?.into_iter().enumerate() {
+        let layer_path = staging_dir.path().join(format!("layer-{}.tar", index));
+        let writer = tokio::io::BufWriter::new(File::create(&layer_path).await?);
+        write_archive_filtered(Builder::new(writer), pack_dir, None, include).await?;
+        let descriptor = Descriptor {
+            media_type: LAYER_MEDIA_TYPE.to_string(),
+            digest: sha256_digest(&layer_path)?,
+            size: std::fs::metadata(&layer_path)?.len(),
+            annotations: [(TITLE_ANNOTATION.to_string(), title)].into(),
+        };
+        tracing::info!("Pushing layer {}", descriptor.digest);
+        registry.push_blob(&layer_path, &descriptor).await?;
+        descriptors.push(descriptor);
+    }
+
+    let manifest = Manifest {
+        schema_version: 2,
+        media_type: MANIFEST_MEDIA_TYPE.to_string(),
+        artifact_type: Some(ARTIFACT_TYPE.to_string()),
+        config,
+        layers: descriptors,
+    };
+    let manifest_url = reference.url(&format!("manifests/{}", reference.tag))?;
+    check(
+        registry
+            .send(
+                registry
+                    .request(Method::PUT, manifest_url)
+                    .header(header::CONTENT_TYPE, MANIFEST_MEDIA_TYPE)
+                    .body(serde_json::to_vec(&manifest)?),
+            )
+            .await?,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Pull a pack from an OCI registry and extract its contents into `target_dir`.
+///
+/// `client`, `retry_policy` and `auth_storage` are used like in [`push_to_registry`].
+pub async fn pull_from_registry(
+    reference: &OciReference,
+    target_dir: &Path,
+    client: &ClientWithMiddleware,
+    retry_policy: RetryPolicy,
+    auth_storage: &AuthenticationStorage,
+) -> Result<()> {
+    let registry = Registry::connect(reference, client, retry_policy, auth_storage, "pull").await?;
+    let manifest_url = reference.url(&format!("manifests/{}", reference.tag))?;
+    let manifest: Manifest = check(
+        registry
+            .send(
+                registry
+                    .request(Method::GET, manifest_url)
+                    .header(header::ACCEPT, MANIFEST_MEDIA_TYPE),
+            )
+            .await?,
+    )
+    .await?
+    .json()
+    .await
+    .map_err(|e| anyhow!("invalid manifest for {}: {}", reference, e))?;
+    if manifest.config.media_type != CONFIG_MEDIA_TYPE {
+        anyhow::bail!("{} is not a pixi-pack artifact", reference);
+    }
+
+    let staging_dir = tempfile::tempdir()?;
+    for layer in &manifest.layers {
+        if layer.media_type != LAYER_MEDIA_TYPE {
+            anyhow::bail!("unsupported layer media type: {}", layer.media_type);
+        }
+        tracing::info!("Pulling layer {}", layer.digest);
+        let layer_path: PathBuf = staging_dir.path().join("layer.tar");
+        registry.pull_blob(layer, &layer_path).await?;
+        Archive::new(File::open(&layer_path).await?)
+            .unpack(target_dir)
+            .await
+            .map_err(|e| anyhow!("could not extract layer {}: {}", layer.digest, e))?;
+    }
+    Ok(())
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("oci://ghcr.io/org/env:v1", "ghcr.io", "org/env", "v1")]
+    #[case("oci://ghcr.io/org/env", "ghcr.io", "org/env", "latest")]
+    #[case("oci://localhost:5000/env:2024.12", "localhost:5000", "env", "2024.12")]
+    fn test_oci_reference(
+        #[case] reference: &str,
+        #[case] registry: &str,
+        #[case] repository: &str,
+        #[case] tag: &str,
+    ) {
+        let parsed: OciReference = reference.parse().unwrap();
+        assert_eq!(parsed.registry, registry);
+        assert_eq!(parsed.repository, repository);
+        assert_eq!(parsed.tag, tag);
+        assert!(OciReference::is_oci_reference(Path::new(reference)));
+        assert_eq!(
+            parsed.url("manifests/latest").unwrap().scheme(),
+            if registry.starts_with("localhost") {
+                "http"
+            } else {
+                "https"
+            }
+        );
+    }
+
+    #[rstest]
+    #[case("ghcr.io/org/env")]
+    #[case("oci://ghcr.io")]
+    #[case("oci:///env")]
+    #[case("oci://ghcr.io/Org/env")]
+    fn test_invalid_oci_reference(#[case] reference: &str) {
+        assert!(reference.parse::<OciReference>().is_err());
+    }
+
+    #[rstest]
+    fn test_parse_challenge() {
+        assert_eq!(
+            parse_challenge(
+                r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:org/env:pull""#
+            ),
+            vec![
+                ("realm".to_string(), "https://ghcr.io/token".to_string()),
+                ("service".to_string(), "ghcr.io".to_string()),
+                ("scope".to_string(), "repository:org/env:pull".to_string()),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_layers() {
+        let pack_dir = tempfile::tempdir().unwrap();
+        for path in [
+            "pixi-pack.json",
+            "environment.yml",
+            "channel/noarch/repodata.json",
+            "channel/linux-64/repodata.json",
+            "channel/linux-64/a-1-0.conda",
+        ] {
+            let path = pack_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "{}").unwrap();
+        }
+
+        let layers = layers(pack_dir.path()).unwrap();
+        let titles: Vec<_> = layers.iter().map(|(title, _)| title.as_str()).collect();
+        assert_eq!(titles, ["metadata", "channel/linux-64", "channel/noarch"]);
+
+        // The layers together contain the whole pack.
+        let extracted = tempfile::tempdir().unwrap();
+        for (_, include) in layers {
+            let archive =
+                write_archive_filtered(Builder::new(Vec::new()), pack_dir.path(), None, include)
+                    .await
+                    .unwrap();
+            Archive::new(archive.as_slice())
+                .unpack(extracted.path())
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            crate::DirectoryChecksums::new(extracted.path()).unwrap(),
+            crate::DirectoryChecksums::new(pack_dir.path()).unwrap()
+        );
+    }
+}
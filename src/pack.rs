@@ -1,7 +1,13 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     path::{Path, PathBuf},
-    sync::Arc,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 #[cfg(not(target_os = "windows"))]
@@ -12,26 +18,233 @@ use indicatif::HumanBytes;
 use rattler_index::{package_record_from_conda, package_record_from_tar_bz2};
 use tokio::{
     fs::{self, create_dir_all, File},
-    io::AsyncWriteExt,
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::{OnceCell, Semaphore},
 };
 
 use anyhow::Result;
 use base64::engine::{general_purpose::STANDARD, Engine};
 use futures::{stream, StreamExt, TryFutureExt, TryStreamExt};
 use rattler_conda_types::{package::ArchiveType, ChannelInfo, PackageRecord, Platform, RepoData};
+use rattler_digest::{HashingWriter, Sha256, Sha256Hash};
 use rattler_lock::{CondaBinaryData, CondaPackageData, LockFile, LockedPackageRef, UrlOrPath};
 use rattler_networking::{AuthenticationMiddleware, AuthenticationStorage};
 use reqwest_middleware::ClientWithMiddleware;
 use tokio_tar::{Builder, HeaderMode};
+use url::Url;
 use walkdir::WalkDir;
 
 use crate::{
-    get_size, PixiPackMetadata, ProgressReporter, CHANNEL_DIRECTORY_NAME, PIXI_PACK_METADATA_PATH,
+    events::suppress_output,
+    get_size, list,
+    manifest::{requested_specs, workspace_manifest},
+    push_to_registry, signature_path,
+    unpack::{
+        collect_packages_in_subdir, open_archive, train_zstd_dictionary, BINARY_EXECUTABLE_MAGIC,
+    },
+    DirectoryChecksums, Event, FetchProgress, InjectedPackage, NetworkLog, OciReference, PackHook,
+    PackSources, PixiPackMetadata, ProgressReporter, RetryPolicy, SigningKey, Storage, Storages,
+    SystemRequirement, CHANNEL_DIRECTORY_NAME, CHECKSUMS_PATH, ENVIRONMENTS_DIRECTORY_NAME,
+    INSTRUCTIONS_PATH, PIXI_PACK_METADATA_PATH, PIXI_PACK_VERSION, SOURCES_PATH,
 };
 use anyhow::anyhow;
+use async_compression::{tokio::write::ZstdEncoder, zstd::CParameter};
+
+/// The format of the pack that is created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// A `.tar` archive.
+    #[default]
+    Tar,
+    /// A plain directory with the contents of the pack.
+    Dir,
+    /// A self-extracting executable (`.sh` on unix, `.ps1` on Windows), see
+    /// [`ExecutableFormat`].
+    Executable,
+    /// An artifact in an OCI registry, the output file is an `oci://registry/repository[:tag]`
+    /// reference. See [`crate::push_to_registry`].
+    Oci,
+}
+
+impl OutputFormat {
+    /// The default (relative) path of a pack in this format for the given platform.
+    pub fn default_output_file(
+        &self,
+        platform: Platform,
+        windows_flavor: WindowsFlavor,
+        executable_format: ExecutableFormat,
+        compression: Compression,
+    ) -> PathBuf {
+        match self {
+            OutputFormat::Tar => match compression {
+                Compression::None => PathBuf::from("environment.tar"),
+                Compression::Zstd(_) => PathBuf::from("environment.tar.zst"),
+            },
+            OutputFormat::Dir => PathBuf::from("environment"),
+            OutputFormat::Executable => PathBuf::from("environment").with_extension(
+                executable_extension(platform, windows_flavor, executable_format),
+            ),
+            // A local registry, e.g. `docker run -p 5000:5000 registry`.
+            OutputFormat::Oci => PathBuf::from("oci://localhost:5000/environment:latest"),
+        }
+    }
+}
+
+/// The compression of `tar` packs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// A plain `.tar` archive.
+    #[default]
+    None,
+    /// A zstd-compressed `.tar.zst` archive with the given compression level.
+    Zstd(i32),
+}
+
+impl Compression {
+    /// The default zstd compression level, a good tradeoff between speed and size.
+    pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+}
+
+/// The kind of self-extracting executable that is created for Windows platforms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum WindowsFlavor {
+    /// A PowerShell script (`.ps1`).
+    #[default]
+    Powershell,
+    /// A batch file (`.bat`) that only requires `cmd.exe`, `certutil` and `tar`.
+    Bat,
+}
+
+/// How self-extracting executables embed the pack.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExecutableFormat {
+    /// A script that contains the pack and the pixi-pack executable as base64, see
+    /// [`WindowsFlavor`].
+    #[default]
+    Script,
+    /// The native pixi-pack executable with the pack appended as tarball (`.exe` on Windows, no
+    /// extension otherwise). It is half the size of a script and starts unpacking immediately.
+    Binary,
+}
+
+/// The line endings of self-extracting executables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LineEndings {
+    /// `crlf` for Windows platforms, `lf` otherwise.
+    #[default]
+    Auto,
+    /// Windows line endings (`\r\n`).
+    Crlf,
+    /// Unix line endings (`\n`).
+    Lf,
+}
+
+impl LineEndings {
+    /// The line ending to use for `platform`.
+    fn as_str(&self, platform: Platform) -> &'static str {
+        match self {
+            LineEndings::Auto if platform.is_windows() => "\r\n",
+            LineEndings::Auto => "\n",
+            LineEndings::Crlf => "\r\n",
+            LineEndings::Lf => "\n",
+        }
+    }
+}
+
+/// Convert all line breaks in `text` to `line_ending`.
+fn normalize_line_endings(text: &str, line_ending: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', line_ending)
+}
+
+/// Check that all line breaks in `text` are `line_ending`.
+fn verify_line_endings(text: &str, line_ending: &str) -> Result<()> {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count();
+    let consistent = match line_ending {
+        "\r\n" => crlf == lf,
+        _ => crlf == 0,
+    };
+    if !consistent || text.replace("\r\n", "").contains('\r') {
+        anyhow::bail!(
+            "self-extracting executable has mixed line endings ({} of {} line breaks are crlf)",
+            crlf,
+            lf
+        );
+    }
+    Ok(())
+}
+
+/// The `channel_priority` to set in the generated `environment.yml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChannelPriority {
+    /// Only use packages from the highest-priority channel that contains a package name.
+    Strict,
+    /// Prefer packages from higher-priority channels but fall back to lower-priority ones.
+    Flexible,
+    /// Ignore the channel order and pick packages by version only.
+    Disabled,
+}
+
+impl ChannelPriority {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChannelPriority::Strict => "strict",
+            ChannelPriority::Flexible => "flexible",
+            ChannelPriority::Disabled => "disabled",
+        }
+    }
+}
+
+/// Settings of the HTTP client that downloads packages.
+///
+/// The defaults work for most channels; tune them if a mirror or proxy throttles connections or
+/// misbehaves with HTTP/2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpOptions {
+    /// The maximum number of idle connections to keep open per host.
+    pub pool_max_idle_per_host: usize,
+    /// How long idle connections are kept open (`None`: until the server closes them).
+    pub pool_idle_timeout: Option<Duration>,
+    /// Whether HTTP/2 may be negotiated. If disabled, only HTTP/1.1 is used.
+    pub http2: bool,
+    /// The interval of TCP keep-alive probes (`None`: disabled).
+    pub tcp_keepalive: Option<Duration>,
+    /// How failed downloads are retried.
+    pub retry_policy: RetryPolicy,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            pool_max_idle_per_host: 20,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            http2: true,
+            tcp_keepalive: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+}
+
+/// The file extension of self-extracting executables.
+fn executable_extension(
+    platform: Platform,
+    windows_flavor: WindowsFlavor,
+    executable_format: ExecutableFormat,
+) -> &'static str {
+    match (executable_format, platform.is_windows(), windows_flavor) {
+        (ExecutableFormat::Binary, true, _) => "exe",
+        (ExecutableFormat::Binary, false, _) => "",
+        (ExecutableFormat::Script, true, WindowsFlavor::Powershell) => "ps1",
+        (ExecutableFormat::Script, true, WindowsFlavor::Bat) => "bat",
+        (ExecutableFormat::Script, false, _) => "sh",
+    }
+}
 
 /// Options for packing a pixi environment.
+///
+/// Use [`PackOptions::builder`] to create options with sane defaults.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct PackOptions {
     pub environment: String,
     pub platform: Platform,
@@ -41,57 +254,1855 @@ pub struct PackOptions {
     pub metadata: PixiPackMetadata,
     pub injected_packages: Vec<PathBuf>,
     pub ignore_pypi_errors: bool,
-    pub create_executable: bool,
+    pub require_pip: bool,
+    pub output_format: OutputFormat,
+    pub reuse_from: Option<PathBuf>,
+    pub base_pack: Option<PathBuf>,
+    pub zstd_dictionary: bool,
+    pub cache_dir: Option<PathBuf>,
+    pub source_date_epoch: Option<u64>,
+    pub windows_flavor: WindowsFlavor,
+    pub executable_format: ExecutableFormat,
+    pub unpack_executable: Option<PathBuf>,
+    pub injected_channels: Vec<PathBuf>,
+    pub instructions: Option<PathBuf>,
+    pub extra_channels: Vec<String>,
+    pub channel_priority: Option<ChannelPriority>,
+    pub header_template: Option<PathBuf>,
+    pub line_endings: LineEndings,
+    pub http_options: HttpOptions,
+    pub download_order: DownloadOrder,
+    pub compression: Compression,
+    pub compression_threads: u32,
+    pub dry_run: bool,
+    pub storages: Vec<Arc<dyn Storage>>,
+    pub sign_key: Option<PathBuf>,
+    pub hooks: Vec<Arc<dyn PackHook>>,
+    pub network_log: Option<NetworkLog>,
+    pub digest_name: bool,
+}
+
+impl PackOptions {
+    /// Create a builder for [`PackOptions`].
+    pub fn builder() -> PackOptionsBuilder {
+        PackOptionsBuilder::default()
+    }
+}
+
+/// Builder for [`PackOptions`].
+///
+/// All options are optional, by default the `default` environment of `pixi.toml` in the current
+/// directory is packed for the current platform into `environment.tar`.
+#[derive(Debug, Clone, Default)]
+pub struct PackOptionsBuilder {
+    environment: Option<String>,
+    platform: Option<Platform>,
+    auth_file: Option<PathBuf>,
+    output_file: Option<PathBuf>,
+    manifest_path: Option<PathBuf>,
+    metadata: Option<PixiPackMetadata>,
+    extra_metadata: BTreeMap<String, String>,
+    injected_packages: Vec<PathBuf>,
+    ignore_pypi_errors: bool,
+    require_pip: bool,
+    output_format: OutputFormat,
+    reuse_from: Option<PathBuf>,
+    base_pack: Option<PathBuf>,
+    zstd_dictionary: bool,
+    cache_dir: Option<PathBuf>,
+    source_date_epoch: Option<u64>,
+    windows_flavor: WindowsFlavor,
+    executable_format: ExecutableFormat,
+    unpack_executable: Option<PathBuf>,
+    injected_channels: Vec<PathBuf>,
+    instructions: Option<PathBuf>,
+    extra_channels: Vec<String>,
+    channel_priority: Option<ChannelPriority>,
+    header_template: Option<PathBuf>,
+    line_endings: LineEndings,
+    http_options: HttpOptions,
+    download_order: DownloadOrder,
+    compression: Compression,
+    compression_threads: u32,
+    dry_run: bool,
+    storages: Vec<Arc<dyn Storage>>,
+    sign_key: Option<PathBuf>,
+    hooks: Vec<Arc<dyn PackHook>>,
+    network_log: Option<NetworkLog>,
+    digest_name: bool,
+}
+
+impl PackOptionsBuilder {
+    /// The environment to pack (default: `default`).
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// The platform to pack (default: the current platform).
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Authentication file for fetching packages.
+    pub fn auth_file(mut self, auth_file: impl Into<Option<PathBuf>>) -> Self {
+        self.auth_file = auth_file.into();
+        self
+    }
+
+    /// The file to write the pack to (default: depends on the output format).
+    pub fn output_file(mut self, output_file: impl Into<PathBuf>) -> Self {
+        self.output_file = Some(output_file.into());
+        self
+    }
+
+    /// The path to `pixi.toml` or `pyproject.toml` (default: `pixi.toml`).
+    pub fn manifest_path(mut self, manifest_path: impl Into<PathBuf>) -> Self {
+        self.manifest_path = Some(manifest_path.into());
+        self
+    }
+
+    /// The metadata to store in the pack (default: derived from the platform).
+    pub fn metadata(mut self, metadata: PixiPackMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Store a custom key/value pair in the metadata of the pack, see
+    /// [`PixiPackMetadata::extra`]. Can be called multiple times; later values replace earlier
+    /// ones with the same key.
+    pub fn extra_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extra_metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Additional conda packages to inject into the pack.
+    pub fn injected_packages(mut self, injected_packages: Vec<PathBuf>) -> Self {
+        self.injected_packages = injected_packages;
+        self
+    }
+
+    /// Local channels whose packages are added to the pack.
+    /// Packages from these channels replace packages with the same name from the lockfile.
+    pub fn injected_channels(mut self, injected_channels: Vec<PathBuf>) -> Self {
+        self.injected_channels = injected_channels;
+        self
+    }
+
+    /// A template for usage instructions that are bundled with the pack.
+    /// See [`render_instructions`] for the supported placeholders.
+    pub fn instructions(mut self, instructions: impl Into<Option<PathBuf>>) -> Self {
+        self.instructions = instructions.into();
+        self
+    }
+
+    /// Channels to list after the local channel in `environment.yml`, e.g. a mirror to install
+    /// additional packages from when using the pack with conda.
+    pub fn extra_channels(mut self, extra_channels: Vec<String>) -> Self {
+        self.extra_channels = extra_channels;
+        self
+    }
+
+    /// The `channel_priority` to set in `environment.yml`.
+    pub fn channel_priority(
+        mut self,
+        channel_priority: impl Into<Option<ChannelPriority>>,
+    ) -> Self {
+        self.channel_priority = channel_priority.into();
+        self
+    }
+
+    /// Whether to ignore PyPI packages instead of failing.
+    pub fn ignore_pypi_errors(mut self, ignore_pypi_errors: bool) -> Self {
+        self.ignore_pypi_errors = ignore_pypi_errors;
+        self
+    }
+
+    /// Fail instead of warning if the environment contains PyPI packages but no `pip` conda
+    /// package to install them with after unpacking (default: `false`).
+    pub fn require_pip(mut self, require_pip: bool) -> Self {
+        self.require_pip = require_pip;
+        self
+    }
+
+    /// The output format of the pack (default: [`OutputFormat::Tar`]).
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// A previous pack to reuse unchanged packages from.
+    pub fn reuse_from(mut self, reuse_from: impl Into<Option<PathBuf>>) -> Self {
+        self.reuse_from = reuse_from.into();
+        self
+    }
+
+    /// A previous pack to create a delta pack against.
+    /// Packages that are contained in the base pack with the same hash are left out of the pack
+    /// and listed in [`PixiPackMetadata::base_packages`] instead.
+    pub fn base_pack(mut self, base_pack: impl Into<Option<PathBuf>>) -> Self {
+        self.base_pack = base_pack.into();
+        self
+    }
+
+    /// Compress the delta pack with a zstd dictionary that is trained on the base pack
+    /// (default: `false`). Requires [`Self::base_pack`] and [`Compression::Zstd`]. The same base
+    /// pack is needed to unpack the pack.
+    pub fn zstd_dictionary(mut self, zstd_dictionary: bool) -> Self {
+        self.zstd_dictionary = zstd_dictionary;
+        self
+    }
+
+    /// A directory to cache downloaded packages in.
+    /// Packages that are already cached (and match the hash in the lockfile) are not downloaded.
+    pub fn cache_dir(mut self, cache_dir: impl Into<Option<PathBuf>>) -> Self {
+        self.cache_dir = cache_dir.into();
+        self
+    }
+
+    /// A unix timestamp to use as modification time of all archive entries and to record as
+    /// creation date in the metadata, see <https://reproducible-builds.org/specs/source-date-epoch/>.
+    /// Without it, all timestamps are zeroed out.
+    pub fn source_date_epoch(mut self, source_date_epoch: impl Into<Option<u64>>) -> Self {
+        self.source_date_epoch = source_date_epoch.into();
+        self
+    }
+
+    /// The kind of self-extracting executable to create for Windows platforms.
+    pub fn windows_flavor(mut self, windows_flavor: WindowsFlavor) -> Self {
+        self.windows_flavor = windows_flavor;
+        self
+    }
+
+    /// How self-extracting executables embed the pack (default: [`ExecutableFormat::Script`]).
+    pub fn executable_format(mut self, executable_format: ExecutableFormat) -> Self {
+        self.executable_format = executable_format;
+        self
+    }
+
+    /// A pixi-pack executable for the target platform to embed into self-extracting executables,
+    /// e.g. a local build (default: download the executable of this version from GitHub
+    /// releases). Downloaded executables are stored in [`Self::cache_dir`] and reused from there.
+    pub fn unpack_executable(mut self, unpack_executable: impl Into<Option<PathBuf>>) -> Self {
+        self.unpack_executable = unpack_executable.into();
+        self
+    }
+
+    /// A template that replaces the built-in header of self-extracting executables.
+    /// See [`render_header`] for the supported placeholders.
+    pub fn header_template(mut self, header_template: impl Into<Option<PathBuf>>) -> Self {
+        self.header_template = header_template.into();
+        self
+    }
+
+    /// The line endings of self-extracting executables (default: [`LineEndings::Auto`]).
+    pub fn line_endings(mut self, line_endings: LineEndings) -> Self {
+        self.line_endings = line_endings;
+        self
+    }
+
+    /// Settings of the HTTP client that downloads packages.
+    pub fn http_options(mut self, http_options: HttpOptions) -> Self {
+        self.http_options = http_options;
+        self
+    }
+
+    /// The order in which packages are downloaded (default: [`DownloadOrder::Size`]).
+    pub fn download_order(mut self, download_order: DownloadOrder) -> Self {
+        self.download_order = download_order;
+        self
+    }
+
+    /// The compression of `tar` packs (default: [`Compression::None`]).
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// The number of worker threads for zstd compression (default: `0`, i.e. compress on the
+    /// calling thread). The output is the same for any number of worker threads, but differs
+    /// from single-threaded compression.
+    pub fn compression_threads(mut self, compression_threads: u32) -> Self {
+        self.compression_threads = compression_threads;
+        self
+    }
+
+    /// Only report which packages would be downloaded and how large the pack would be, without
+    /// downloading or writing anything (default: `false`).
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// An additional [`Storage`] backend to fetch packages with, e.g. for an object store.
+    /// It takes precedence over the built-in backends for `http(s)://` and `file://` URLs.
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storages.push(storage);
+        self
+    }
+
+    /// A minisign secret key to sign the pack with (default: none).
+    /// The detached signature is written next to the pack, see [`signature_path`].
+    pub fn sign_key(mut self, sign_key: impl Into<Option<PathBuf>>) -> Self {
+        self.sign_key = sign_key.into();
+        self
+    }
+
+    /// A [`PackHook`] that checks the contents of the pack before it is archived, e.g. a virus
+    /// scanner. Hooks run in the order they are added and a failing hook aborts packing.
+    pub fn hook(mut self, hook: Arc<dyn PackHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Record all HTTP requests in a [`NetworkLog`] (default: none). Requests of custom
+    /// [`Storage`] backends are not recorded.
+    pub fn network_log(mut self, network_log: impl Into<Option<NetworkLog>>) -> Self {
+        self.network_log = network_log.into();
+        self
+    }
+
+    /// Add the first 12 hex characters of the sha256 digest of the pack to its file name, e.g.
+    /// `environment-3f2a9c81d0b4.tar` (default: `false`). The digest is computed while the pack
+    /// is written. Only supported for packs that are files.
+    pub fn digest_name(mut self, digest_name: bool) -> Self {
+        self.digest_name = digest_name;
+        self
+    }
+
+    /// Build the [`PackOptions`].
+    pub fn build(self) -> PackOptions {
+        let platform = self.platform.unwrap_or_else(Platform::current);
+        let mut metadata = self.metadata.unwrap_or_else(|| PixiPackMetadata {
+            platform,
+            ..PixiPackMetadata::default()
+        });
+        metadata.extra.extend(self.extra_metadata);
+        PackOptions {
+            environment: self.environment.unwrap_or_else(|| "default".to_string()),
+            platform,
+            auth_file: self.auth_file,
+            output_file: self.output_file.unwrap_or_else(|| {
+                self.output_format.default_output_file(
+                    platform,
+                    self.windows_flavor,
+                    self.executable_format,
+                    self.compression,
+                )
+            }),
+            manifest_path: self
+                .manifest_path
+                .unwrap_or_else(|| PathBuf::from("pixi.toml")),
+            metadata,
+            injected_packages: self.injected_packages,
+            ignore_pypi_errors: self.ignore_pypi_errors,
+            require_pip: self.require_pip,
+            output_format: self.output_format,
+            reuse_from: self.reuse_from,
+            base_pack: self.base_pack,
+            zstd_dictionary: self.zstd_dictionary,
+            cache_dir: self.cache_dir,
+            source_date_epoch: self.source_date_epoch,
+            windows_flavor: self.windows_flavor,
+            executable_format: self.executable_format,
+            unpack_executable: self.unpack_executable,
+            injected_channels: self.injected_channels,
+            instructions: self.instructions,
+            extra_channels: self.extra_channels,
+            channel_priority: self.channel_priority,
+            header_template: self.header_template,
+            line_endings: self.line_endings,
+            http_options: self.http_options,
+            download_order: self.download_order,
+            compression: self.compression,
+            compression_threads: self.compression_threads,
+            dry_run: self.dry_run,
+            storages: self.storages,
+            sign_key: self.sign_key,
+            hooks: self.hooks,
+            network_log: self.network_log,
+            digest_name: self.digest_name,
+        }
+    }
+}
+
+/// A non-fatal issue found while creating a pack.
+///
+/// Warnings are collected while packing and printed as a summary once the pack was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackWarning {
+    /// A PyPI package was left out because of `ignore_pypi_errors`.
+    IgnoredPypiPackage(String),
+    /// A directory or glob pattern passed as injected package did not match any packages.
+    NoPackagesToInject(PathBuf),
+    /// An injected channel does not contain any packages for the platform of the pack.
+    NoChannelPackages {
+        channel: PathBuf,
+        platform: Platform,
+    },
+    /// A package in the pack passed as `reuse_from` did not match the lockfile.
+    ReusedPackageHashMismatch(String),
+    /// The same package file was added more than once, e.g., via the lockfile and `--inject`.
+    DuplicatePackage(String),
+    /// The requested dependencies could not be read from the manifest.
+    NoRequestedSpecs(String),
+    /// The environment contains PyPI packages but no `pip` conda package.
+    MissingPip(String),
+}
+
+impl std::fmt::Display for PackWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackWarning::IgnoredPypiPackage(name) => write!(
+                f,
+                "Ignored PyPI package {} since PyPI packages are not supported by pixi-pack",
+                name
+            ),
+            PackWarning::NoPackagesToInject(path) => {
+                write!(f, "No packages to inject found in {}", path.display())
+            }
+            PackWarning::NoChannelPackages { channel, platform } => write!(
+                f,
+                "No packages for {} found in channel {}",
+                platform,
+                channel.display()
+            ),
+            PackWarning::ReusedPackageHashMismatch(file_name) => write!(
+                f,
+                "Hash of {} in previous pack does not match the lockfile, downloaded it again",
+                file_name
+            ),
+            PackWarning::DuplicatePackage(file_name) => write!(
+                f,
+                "Package {} was added more than once, it is only included once",
+                file_name
+            ),
+            PackWarning::NoRequestedSpecs(error) => write!(
+                f,
+                "Requested dependencies are not recorded in the pack: {}",
+                error
+            ),
+            PackWarning::MissingPip(environment) => write!(
+                f,
+                "Environment {} contains PyPI packages but no pip, they cannot be installed after unpacking",
+                environment
+            ),
+        }
+    }
+}
+
+/// The outcome of creating a pack, e.g. to log it or to upload the pack afterwards.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PackResult {
+    /// The created pack. This is the OCI reference for [`OutputFormat::Oci`] and includes the
+    /// digest for [`PackOptions::digest_name`].
+    pub output_file: PathBuf,
+    /// The size of the pack in bytes. `None` for OCI images and dry runs.
+    pub size_bytes: Option<u64>,
+    /// The sha256 digest of the pack, computed while it was written. `None` for directories, OCI
+    /// images and dry runs.
+    pub sha256: Option<Sha256Hash>,
+    /// The detached signature of the pack, if it was signed.
+    pub signature_file: Option<PathBuf>,
+    /// The number of zstd worker threads the pack was compressed with, see
+    /// [`PackOptionsBuilder::compression_threads`]. `0` if it was compressed on the calling
+    /// thread or not compressed at all.
+    pub compression_threads: u32,
+    /// The number of conda packages in the pack, including injected packages and the packages
+    /// of the base pack of delta packs.
+    pub conda_package_count: usize,
+    /// The number of PyPI packages that were left out of the pack, see
+    /// [`PackOptionsBuilder::ignore_pypi_errors`].
+    pub ignored_pypi_package_count: usize,
+    /// How long creating the pack took.
+    pub duration: Duration,
+    /// The warnings that occurred while packing.
+    pub warnings: Vec<PackWarning>,
+}
+
+impl PackResult {
+    fn new(output_file: PathBuf, start: Instant, warnings: Vec<PackWarning>) -> Self {
+        Self {
+            output_file,
+            size_bytes: None,
+            sha256: None,
+            signature_file: None,
+            compression_threads: 0,
+            conda_package_count: 0,
+            ignored_pypi_package_count: warnings
+                .iter()
+                .filter(|warning| matches!(warning, PackWarning::IgnoredPypiPackage(_)))
+                .count(),
+            duration: start.elapsed(),
+            warnings,
+        }
+    }
+
+    /// Emit [`Event::ArchiveCreated`] for this pack.
+    fn emit_archive_created(&self) {
+        Event::ArchiveCreated {
+            output_file: self.output_file.clone(),
+            size_bytes: self.size_bytes,
+            sha256: self.sha256.map(|digest| format!("{:x}", digest)),
+            compression_threads: self.compression_threads,
+        }
+        .emit();
+    }
 }
 
-/// Pack a pixi environment.
-pub async fn pack(options: PackOptions) -> Result<()> {
-    let lockfile_path = options
-        .manifest_path
-        .parent()
-        .ok_or(anyhow!("could not get parent directory"))?
-        .join("pixi.lock");
+/// Log a warning and record it for the summary at the end.
+fn warn(warnings: &mut Vec<PackWarning>, warning: PackWarning) {
+    tracing::warn!("{}", warning);
+    warnings.push(warning);
+}
+
+/// Print all warnings that were collected while packing.
+fn print_warnings(warnings: &[PackWarning]) {
+    if crate::json_output() {
+        for warning in warnings {
+            Event::Warning {
+                message: warning.to_string(),
+            }
+            .emit();
+        }
+        return;
+    }
+    if warnings.is_empty() {
+        return;
+    }
+    status!("⚠️  Packing finished with {} warning(s):", warnings.len());
+    for warning in warnings {
+        status!("  - {}", warning);
+    }
+}
+
+/// The maximum number of concurrent package downloads.
+const DOWNLOAD_CONCURRENCY: usize = 50;
+
+/// The order in which packages are downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DownloadOrder {
+    /// Largest packages first, so that no large download is started last and holds up the pack.
+    #[default]
+    Size,
+    /// Alphabetically by file name.
+    Name,
+    /// In the order of the lockfile.
+    Lockfile,
+}
+
+impl DownloadOrder {
+    /// Sort `packages` in this order. Packages without a known size are downloaded last.
+    fn sort(&self, packages: &mut [&CondaBinaryData]) {
+        match self {
+            DownloadOrder::Size => packages
+                .sort_by_key(|p| std::cmp::Reverse(p.package_record.size.unwrap_or_default())),
+            DownloadOrder::Name => packages.sort_by(|a, b| a.file_name.cmp(&b.file_name)),
+            DownloadOrder::Lockfile => {}
+        }
+    }
+}
+
+/// Where a package was obtained from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PackageSource {
+    /// The package was copied from the package cache.
+    Cache,
+    /// The package was downloaded.
+    Network,
+}
+
+/// Counts cache hits and misses while packages are being fetched concurrently.
+#[derive(Debug, Default)]
+struct CacheStatistics {
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl CacheStatistics {
+    fn record(&self, source: PackageSource) {
+        match source {
+            PackageSource::Cache => self.hits.fetch_add(1, Ordering::Relaxed),
+            PackageSource::Network => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+impl std::fmt::Display for CacheStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} cached, {} downloaded", self.hits(), self.misses())
+    }
+}
+
+/// The progress of downloading packages.
+///
+/// Progress is reported in bytes if the lockfile records the size of any package, and in
+/// packages otherwise. Packages without a recorded size add their `Content-Length` (or the bytes
+/// received so far if there is none) to the total once their download starts.
+struct DownloadProgress {
+    bar: ProgressReporter,
+    bytes: bool,
+}
+
+impl DownloadProgress {
+    fn new(packages: &[&CondaBinaryData]) -> Self {
+        let known_sizes: Vec<u64> = packages
+            .iter()
+            .filter_map(|p| p.package_record.size)
+            .collect();
+        if known_sizes.is_empty() {
+            return Self {
+                bar: ProgressReporter::new(packages.len() as u64),
+                bytes: false,
+            };
+        }
+        Self {
+            bar: ProgressReporter::new_bytes(known_sizes.iter().sum()),
+            bytes: true,
+        }
+    }
+
+    /// The progress callback for downloading `package`.
+    fn on_progress<'a>(
+        &'a self,
+        package: &CondaBinaryData,
+    ) -> impl Fn(FetchProgress) + Send + Sync + 'a {
+        let sized = AtomicBool::new(package.package_record.size.is_some());
+        move |progress| {
+            if !self.bytes {
+                return;
+            }
+            match progress {
+                FetchProgress::Size(size) => {
+                    if !sized.swap(true, Ordering::Relaxed) {
+                        self.bar.pb.inc_length(size);
+                    }
+                }
+                FetchProgress::Bytes(bytes) => {
+                    if !sized.load(Ordering::Relaxed) {
+                        self.bar.pb.inc_length(bytes);
+                    }
+                    self.bar.pb.inc(bytes);
+                }
+            }
+        }
+    }
+
+    /// Call after `package` was downloaded or copied from the cache.
+    fn package_done(&self, package: &CondaBinaryData, source: PackageSource) {
+        if !self.bytes {
+            self.bar.pb.inc(1);
+        }
+        Event::PackageDownloaded {
+            file_name: package.file_name.clone(),
+            subdir: package.package_record.subdir.clone(),
+            size: package.package_record.size,
+            cached: source == PackageSource::Cache,
+        }
+        .emit();
+    }
+}
+
+/// A `noarch` package that is downloaded once and then copied into every pack.
+type SharedPackage = Arc<OnceCell<(PathBuf, PackageSource)>>;
+
+/// Downloads that are shared between packs that are created concurrently.
+///
+/// Limits the total number of concurrent downloads and downloads `noarch` packages only once.
+struct SharedDownloads {
+    download_dir: tempfile::TempDir,
+    noarch_packages: Mutex<HashMap<String, SharedPackage>>,
+    permits: Semaphore,
+}
+
+impl SharedDownloads {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            download_dir: tempfile::tempdir()
+                .map_err(|e| anyhow!("could not create temporary directory: {}", e))?,
+            noarch_packages: Mutex::new(HashMap::new()),
+            permits: Semaphore::new(DOWNLOAD_CONCURRENCY),
+        })
+    }
+
+    /// Download a package into `output_dir`, reusing `noarch` packages that were already
+    /// downloaded for another pack.
+    async fn download(
+        &self,
+        storage: &dyn Storage,
+        package: &CondaBinaryData,
+        output_dir: &Path,
+        cache_dir: Option<&Path>,
+        on_progress: impl Fn(FetchProgress) + Send + Sync,
+    ) -> Result<PackageSource> {
+        if package.package_record.subdir != Platform::NoArch.as_str() {
+            let _permit = self.permits.acquire().await?;
+            return fetch_package(storage, package, output_dir, cache_dir, on_progress).await;
+        }
+
+        let cell = self
+            .noarch_packages
+            .lock()
+            .unwrap()
+            .entry(package.location.to_string())
+            .or_default()
+            .clone();
+        let mut downloaded = false;
+        let (path, source) = cell
+            .get_or_try_init(|| async {
+                let _permit = self.permits.acquire().await?;
+                // Every package gets its own directory as different channels may contain
+                // packages with the same file name.
+                let download_dir = tempfile::Builder::new()
+                    .prefix("noarch-")
+                    .tempdir_in(self.download_dir.path())
+                    .map_err(|e| anyhow!("could not create download directory: {}", e))?
+                    .into_path();
+                let source =
+                    fetch_package(storage, package, &download_dir, cache_dir, &on_progress).await?;
+                downloaded = true;
+                Ok::<_, anyhow::Error>((
+                    download_dir
+                        .join(&package.package_record.subdir)
+                        .join(&package.file_name),
+                    source,
+                ))
+            })
+            .await?;
+
+        let subdir_path = output_dir.join(&package.package_record.subdir);
+        create_dir_all(&subdir_path)
+            .await
+            .map_err(|e| anyhow!("could not create download directory: {}", e))?;
+        let size = fs::copy(path, subdir_path.join(&package.file_name)).await?;
+        if !downloaded {
+            on_progress(FetchProgress::Bytes(size));
+        }
+        Ok(*source)
+    }
+}
+
+/// Download a package to `<output_dir>/<subdir>/<file_name>`, going through `cache_dir` if given.
+async fn fetch_package(
+    storage: &dyn Storage,
+    package: &CondaBinaryData,
+    output_dir: &Path,
+    cache_dir: Option<&Path>,
+    on_progress: impl Fn(FetchProgress) + Send + Sync,
+) -> Result<PackageSource> {
+    let Some(cache_dir) = cache_dir else {
+        download_package(storage, package, output_dir, on_progress).await?;
+        return Ok(PackageSource::Network);
+    };
+    let (cached, source) = cache_package(storage, package, cache_dir, on_progress).await?;
+    let subdir_path = output_dir.join(&package.package_record.subdir);
+    create_dir_all(&subdir_path)
+        .await
+        .map_err(|e| anyhow!("could not create download directory: {}", e))?;
+    fs::copy(&cached, subdir_path.join(&package.file_name))
+        .await
+        .map_err(|e| anyhow!("could not copy {} from cache: {}", package.file_name, e))?;
+    Ok(source)
+}
+
+/// Make sure that a package is contained in `cache_dir` and return its path and whether it had to
+/// be downloaded.
+///
+/// Packages are stored as `<cache_dir>/<subdir>/<file_name>`. Cached packages whose hash does
+/// not match the lockfile are downloaded again. Downloads are moved into place atomically, so
+/// multiple processes can share a cache.
+async fn cache_package(
+    storage: &dyn Storage,
+    package: &CondaBinaryData,
+    cache_dir: &Path,
+    on_progress: impl Fn(FetchProgress) + Send + Sync,
+) -> Result<(PathBuf, PackageSource)> {
+    let cached = cache_dir
+        .join(&package.package_record.subdir)
+        .join(&package.file_name);
+    if cached.is_file() && matches_lockfile_hash(&cached, package)? {
+        tracing::debug!("Using cached package {}", cached.display());
+        on_progress(FetchProgress::Bytes(std::fs::metadata(&cached)?.len()));
+        return Ok((cached, PackageSource::Cache));
+    }
+
+    create_dir_all(cache_dir)
+        .await
+        .map_err(|e| anyhow!("could not create cache directory: {}", e))?;
+    let download_dir = tempfile::tempdir_in(cache_dir)
+        .map_err(|e| anyhow!("could not create temporary directory: {}", e))?;
+    download_package(storage, package, download_dir.path(), on_progress).await?;
+    create_dir_all(cached.parent().expect("cached package has a parent"))
+        .await
+        .map_err(|e| anyhow!("could not create cache directory: {}", e))?;
+    fs::rename(
+        download_dir
+            .path()
+            .join(&package.package_record.subdir)
+            .join(&package.file_name),
+        &cached,
+    )
+    .await
+    .map_err(|e| anyhow!("could not move {} into cache: {}", package.file_name, e))?;
+    Ok((cached, PackageSource::Network))
+}
+
+/// A summary of the packages that a pack would contain, see [`PackOptionsBuilder::dry_run`].
+fn dry_run_summary(
+    platform: Platform,
+    packages: &[&CondaBinaryData],
+    injected: &[(String, u64)],
+    warnings: &[PackWarning],
+) -> String {
+    let mut summary = format!(
+        "🔍 Dry run for {}, nothing was downloaded or written.\n",
+        platform
+    );
+    summary.push_str(&format!(
+        "Conda packages to download ({}):\n",
+        packages.len()
+    ));
+    for package in packages {
+        let size = package
+            .package_record
+            .size
+            .map(|size| HumanBytes(size).to_string())
+            .unwrap_or_else(|| "unknown size".to_string());
+        summary.push_str(&format!("  {}  {}\n", package.file_name, size));
+    }
+    if !injected.is_empty() {
+        summary.push_str(&format!("Packages to inject ({}):\n", injected.len()));
+        for (file_name, size) in injected {
+            summary.push_str(&format!("  {}  {}\n", file_name, HumanBytes(*size)));
+        }
+    }
+    let pypi_packages: Vec<&str> = warnings
+        .iter()
+        .filter_map(|warning| match warning {
+            PackWarning::IgnoredPypiPackage(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    if !pypi_packages.is_empty() {
+        summary.push_str(&format!(
+            "PyPI packages that are not supported and would be skipped ({}):\n",
+            pypi_packages.len()
+        ));
+        for name in pypi_packages {
+            summary.push_str(&format!("  {}\n", name));
+        }
+    }
+
+    let download_size: u64 = packages.iter().filter_map(|p| p.package_record.size).sum();
+    let unknown_sizes = packages
+        .iter()
+        .filter(|p| p.package_record.size.is_none())
+        .count();
+    summary.push_str(&format!("Download size: {}", HumanBytes(download_size)));
+    if unknown_sizes > 0 {
+        summary.push_str(&format!(
+            " (plus {} packages of unknown size)",
+            unknown_sizes
+        ));
+    }
+    let injected_size: u64 = injected.iter().map(|(_, size)| size).sum();
+    summary.push_str(&format!(
+        "\nEstimated pack size: {}\n",
+        HumanBytes(download_size + injected_size)
+    ));
+    summary
+}
+
+/// Whether the hash of a package file matches the lockfile. Packages without hashes always match.
+fn matches_lockfile_hash(path: &Path, package: &CondaBinaryData) -> Result<bool> {
+    let record = &package.package_record;
+    if let Some(sha256) = record.sha256 {
+        let hash = rattler_digest::compute_file_digest::<rattler_digest::Sha256>(path)?;
+        return Ok(hash == sha256);
+    }
+    if let Some(md5) = record.md5 {
+        let hash = rattler_digest::compute_file_digest::<rattler_digest::Md5>(path)?;
+        return Ok(hash == md5);
+    }
+    Ok(true)
+}
+
+/// Download all packages of an environment into [`PackOptions::cache_dir`] without creating a
+/// pack, e.g., to warm the cache before packing without network access.
+///
+/// Injected packages and channels as well as output options are ignored.
+pub async fn fetch(options: PackOptions) -> Result<Vec<PackWarning>> {
+    let cache_dir = options
+        .cache_dir
+        .clone()
+        .ok_or(anyhow!("fetching requires a cache directory"))?;
+    let mut warnings = Vec::new();
+    let packages = read_lockfile_packages(&options, &mut warnings)?;
+    let client = build_reqwest_client(
+        options.auth_file.clone(),
+        &options.http_options,
+        options.network_log.clone(),
+    )
+    .map_err(|e| anyhow!("could not create reqwest client from auth storage: {e}"))?;
+    let storage = Storages::new(client, options.http_options.retry_policy)
+        .with_backends(options.storages.clone());
+
+    tracing::info!("Fetching {} packages...", packages.len());
+    status!(
+        "⏳ Fetching {} packages into {}...",
+        packages.len(),
+        cache_dir.display()
+    );
+    let mut ordered_packages: Vec<&CondaBinaryData> = packages.iter().collect();
+    options.download_order.sort(&mut ordered_packages);
+    let progress = DownloadProgress::new(&ordered_packages);
+    let bar = &progress.bar;
+    bar.pb.set_message(format!("Fetching {}", options.platform));
+    let statistics = CacheStatistics::default();
+    stream::iter(ordered_packages)
+        .map(Ok)
+        .try_for_each_concurrent(DOWNLOAD_CONCURRENCY, |package| async {
+            let (_, source) =
+                cache_package(&storage, package, &cache_dir, progress.on_progress(package)).await?;
+            statistics.record(source);
+            bar.pb
+                .set_message(format!("Fetching {} ({})", options.platform, statistics));
+            progress.package_done(package, source);
+            Ok(())
+        })
+        .await
+        .map_err(|e: anyhow::Error| anyhow!("could not fetch package: {}", e))?;
+    bar.pb.finish_and_clear();
+
+    tracing::info!("Cache statistics: {}", statistics);
+    status!(
+        "📥 Fetched {} packages into {} ({}).",
+        packages.len(),
+        cache_dir.display(),
+        statistics
+    );
+    print_warnings(&warnings);
+    Ok(warnings)
+}
+
+/// Pack a pixi environment.
+///
+/// Returns where the pack was created along with the warnings that occurred while packing. The
+/// warnings are also printed at the end.
+pub async fn pack(options: PackOptions) -> Result<PackResult> {
+    let result = pack_with_shared_downloads(options, &SharedDownloads::new()?).await?;
+    print_warnings(&result.warnings);
+    Ok(result)
+}
+
+/// Create multiple packs concurrently, e.g., for several platforms.
+///
+/// `noarch` packages that are part of more than one pack are only downloaded once and the number
+/// of concurrent downloads is limited across all packs.
+pub async fn pack_many(options: impl IntoIterator<Item = PackOptions>) -> Result<Vec<PackResult>> {
+    // Check all options first so that no pack is downloaded if another one cannot be created.
+    let options: Vec<PackOptions> = options.into_iter().collect();
+    for options in &options {
+        validate_options(options)?;
+    }
+
+    let shared_downloads = SharedDownloads::new()?;
+    let results = futures::future::try_join_all(
+        options
+            .into_iter()
+            .map(|options| pack_with_shared_downloads(options, &shared_downloads)),
+    )
+    .await?;
+
+    print_warnings(&unique_warnings(&results));
+    Ok(results)
+}
+
+/// The warnings of all `results` without duplicates. Packs for different platforms or
+/// environments often produce the same warnings.
+fn unique_warnings(results: &[PackResult]) -> Vec<PackWarning> {
+    let mut warnings = Vec::new();
+    for warning in results.iter().flat_map(|result| &result.warnings) {
+        if !warnings.contains(warning) {
+            warnings.push(warning.clone());
+        }
+    }
+    warnings
+}
+
+/// Pack several environments of the same platform into one pack.
+///
+/// Every environment is a pack of its own in `environments/<name>` of the combined pack, see
+/// [`ENVIRONMENTS_DIRECTORY_NAME`]. The environments are listed in its `pixi-pack.json` and one of
+/// them is selected when unpacking. `options.environment` is ignored. Packages that are part of
+/// more than one environment are only downloaded once, but they are stored once per environment.
+pub async fn pack_environments(
+    options: PackOptions,
+    environments: Vec<String>,
+) -> Result<PackResult> {
+    let start = Instant::now();
+    if environments.is_empty() {
+        anyhow::bail!("no environments to pack");
+    }
+    let mut unique = HashSet::new();
+    if let Some(duplicate) = environments.iter().find(|e| !unique.insert(e.as_str())) {
+        anyhow::bail!("environment {} is given more than once", duplicate);
+    }
+    let environment_count = environments.len();
+    validate_options(&options)?;
+    if matches!(options.output_format, OutputFormat::Oci) {
+        anyhow::bail!("OCI images of multiple environments are not supported");
+    }
+    if options.output_format == OutputFormat::Executable
+        && options.executable_format == ExecutableFormat::Binary
+    {
+        anyhow::bail!("self-extracting binaries of multiple environments are not supported");
+    }
+    if options.base_pack.is_some() {
+        anyhow::bail!("delta packs of multiple environments are not supported");
+    }
+
+    // Read the signing key and header template first to fail before downloading anything.
+    let signing_key = options
+        .sign_key
+        .as_deref()
+        .map(SigningKey::from_file)
+        .transpose()?;
+    let header = match &options.header_template {
+        Some(template_path) => {
+            let template = fs::read_to_string(template_path).await.map_err(|e| {
+                anyhow!(
+                    "could not read header template {}: {}",
+                    template_path.display(),
+                    e
+                )
+            })?;
+            Some(render_header(
+                &template,
+                options.platform,
+                options.windows_flavor,
+            )?)
+        }
+        None => None,
+    };
+    let unpack_executable = match options.output_format {
+        OutputFormat::Executable if !options.dry_run => Some(unpack_executable(&options).await?),
+        _ => None,
+    };
+
+    let output_folder =
+        tempfile::tempdir().map_err(|e| anyhow!("could not create temporary directory: {}", e))?;
+    let environments_dir = output_folder.path().join(ENVIRONMENTS_DIRECTORY_NAME);
+    let shared_downloads = SharedDownloads::new()?;
+    status!(
+        "⏳ Packing {} environments for {}...",
+        environment_count,
+        options.platform
+    );
+    let results = futures::future::try_join_all(environments.iter().map(|environment| {
+        let options = PackOptions {
+            environment: environment.clone(),
+            output_file: environments_dir.join(environment),
+            output_format: OutputFormat::Dir,
+            compression: Compression::None,
+            compression_threads: 0,
+            header_template: None,
+            unpack_executable: None,
+            sign_key: None,
+            digest_name: false,
+            hooks: Vec::new(),
+            ..options.clone()
+        };
+        let shared_downloads = &shared_downloads;
+        // The combined pack is reported instead of the packs of the environments.
+        async move {
+            suppress_output(pack_with_shared_downloads(options, shared_downloads))
+                .await
+                .map_err(|e| anyhow!("could not pack environment {}: {}", environment, e))
+        }
+    }))
+    .await?;
+
+    let conda_package_count = results.iter().map(|r| r.conda_package_count).sum();
+    let warnings = unique_warnings(&results);
+    if options.dry_run {
+        print_warnings(&warnings);
+        let mut result = PackResult::new(options.output_file.clone(), start, warnings);
+        result.conda_package_count = conda_package_count;
+        return Ok(result);
+    }
 
-    let lockfile = LockFile::from_path(&lockfile_path).map_err(|e| {
-        anyhow!(
-            "could not read lockfile at {}: {}",
-            lockfile_path.display(),
-            e
+    tracing::info!("Creating pixi-pack.json file");
+    let metadata = PixiPackMetadata {
+        version: options.metadata.version.clone(),
+        pixi_pack_version: options.metadata.pixi_pack_version.clone(),
+        platform: options.platform,
+        created: options.source_date_epoch,
+        environments,
+        extra: options.metadata.extra.clone(),
+        ..Default::default()
+    };
+    let metadata = serde_json::to_string_pretty(&metadata)?;
+    fs::write(
+        output_folder.path().join(PIXI_PACK_METADATA_PATH),
+        metadata.as_bytes(),
+    )
+    .await?;
+
+    run_hooks(&options, output_folder.path()).await?;
+
+    tracing::info!("Creating pack at {}", options.output_file.display());
+    let digest = match options.output_format {
+        OutputFormat::Tar => create_tarball(
+            output_folder.path(),
+            &options.output_file,
+            options.source_date_epoch,
+            options.compression,
+            None,
+            options.compression_threads,
         )
-    })?;
+        .await
+        .map(Some),
+        OutputFormat::Executable => {
+            status!("📦 Creating self-extracting executable");
+            create_self_extracting_executable(
+                output_folder.path(),
+                &options.output_file,
+                unpack_executable
+                    .as_deref()
+                    .expect("the pixi-pack executable is fetched for self-extracting executables"),
+                options.platform,
+                options.windows_flavor,
+                options.source_date_epoch,
+                header.as_deref(),
+                options.line_endings,
+            )
+            .await
+            .map(Some)
+        }
+        OutputFormat::Dir => create_directory(output_folder.path(), &options.output_file)
+            .await
+            .map(|_| None),
+        OutputFormat::Oci => unreachable!("OCI images are rejected above"),
+    }
+    .map_err(|e| anyhow!("could not archive directory: {}", e))?;
+    let output_file = output_file_with_digest(&options, digest).await?;
 
-    let client = reqwest_client_from_auth_storage(options.auth_file)
-        .map_err(|e| anyhow!("could not create reqwest client from auth storage: {e}"))?;
+    let size_bytes = get_size(&output_file)?;
+    status!(
+        "📦 Created pack of {} environments at {} with size {}.",
+        environment_count,
+        output_file.display(),
+        HumanBytes(size_bytes)
+    );
 
-    let env = lockfile.environment(&options.environment).ok_or(anyhow!(
-        "environment not found in lockfile: {}",
-        options.environment
-    ))?;
+    let signature_file = match signing_key {
+        Some(signing_key) => {
+            let signature_file = sign_pack(&signing_key, &output_file, options.source_date_epoch)?;
+            status!(
+                "🔏 Signed pack with key {} at {}.",
+                signing_key.key_id(),
+                signature_file.display()
+            );
+            Some(signature_file)
+        }
+        None => None,
+    };
 
-    let packages = env.packages(options.platform).ok_or(anyhow!(
-        "platform not found in lockfile: {}",
-        options.platform.as_str()
-    ))?;
+    print_warnings(&warnings);
+    let mut result = PackResult::new(output_file, start, warnings);
+    result.size_bytes = Some(size_bytes);
+    result.sha256 = digest;
+    result.signature_file = signature_file;
+    result.compression_threads = options.compression_threads;
+    result.conda_package_count = conda_package_count;
+    result.emit_archive_created();
+    Ok(result)
+}
+
+async fn pack_with_shared_downloads(
+    options: PackOptions,
+    shared_downloads: &SharedDownloads,
+) -> Result<PackResult> {
+    let start = Instant::now();
+    let mut warnings = Vec::new();
+
+    validate_options(&options)?;
+
+    // Read the signing key first to fail before downloading anything.
+    let signing_key = options
+        .sign_key
+        .as_deref()
+        .map(SigningKey::from_file)
+        .transpose()?;
+
+    // Render the header template first to fail before downloading anything.
+    let header = match &options.header_template {
+        Some(_) if options.output_format != OutputFormat::Executable => {
+            anyhow::bail!("header templates are only supported for self-extracting executables")
+        }
+        Some(template_path) => {
+            let template = fs::read_to_string(template_path).await.map_err(|e| {
+                anyhow!(
+                    "could not read header template {}: {}",
+                    template_path.display(),
+                    e
+                )
+            })?;
+            Some(render_header(
+                &template,
+                options.platform,
+                options.windows_flavor,
+            )?)
+        }
+        None => None,
+    };
+
+    let mut conda_packages_from_lockfile = read_lockfile_packages(&options, &mut warnings)?;
+
+    let client = build_reqwest_client(
+        options.auth_file.clone(),
+        &options.http_options,
+        options.network_log.clone(),
+    )
+    .map_err(|e| anyhow!("could not create reqwest client from auth storage: {e}"))?;
+    let storage = Storages::new(client, options.http_options.retry_policy)
+        .with_backends(options.storages.clone());
 
     let output_folder =
         tempfile::tempdir().map_err(|e| anyhow!("could not create temporary directory: {}", e))?;
 
     let channel_dir = output_folder.path().join(CHANNEL_DIRECTORY_NAME);
 
-    let mut conda_packages_from_lockfile: Vec<CondaBinaryData> = Vec::new();
+    // Collect packages from injected channels. They take precedence over packages with the same
+    // name from the lockfile.
+    let mut channel_packages = Vec::new();
+    for channel in &options.injected_channels {
+        let packages = collect_channel_packages(channel, options.platform)
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "could not read injected channel {}: {}",
+                    channel.display(),
+                    e
+                )
+            })?;
+        if packages.is_empty() {
+            warn(
+                &mut warnings,
+                PackWarning::NoChannelPackages {
+                    channel: channel.clone(),
+                    platform: options.platform,
+                },
+            );
+        }
+        channel_packages.extend(packages);
+    }
+    let overridden_packages: HashSet<_> = channel_packages
+        .iter()
+        .map(|(_, _, p)| p.name.clone())
+        .collect();
+    conda_packages_from_lockfile.retain(|p| {
+        let overridden = overridden_packages.contains(&p.package_record.name);
+        if overridden {
+            tracing::info!(
+                "replacing {} from the lockfile with a package from an injected channel",
+                p.file_name
+            );
+        }
+        !overridden
+    });
+
+    // The same file may be locked from multiple channels. Only keep one copy of it.
+    let mut channel_files = ChannelFiles::default();
+    let mut lockfile_packages = Vec::with_capacity(conda_packages_from_lockfile.len());
+    for package in conda_packages_from_lockfile {
+        let record = &package.package_record;
+        if channel_files.insert(&record.subdir, &package.file_name, record.sha256)? {
+            lockfile_packages.push(package);
+        } else {
+            warn(
+                &mut warnings,
+                PackWarning::DuplicatePackage(package.file_name.clone()),
+            );
+        }
+    }
+    let conda_packages_from_lockfile = lockfile_packages;
+
+    if options.dry_run {
+        let mut injected: Vec<PathBuf> =
+            expand_injected_packages(&options.injected_packages, &mut warnings)?
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect();
+        injected.extend(channel_packages.into_iter().map(|(path, _, _)| path));
+        let injected = injected
+            .into_iter()
+            .map(|path| {
+                let size = std::fs::metadata(&path)
+                    .map_err(|e| anyhow!("could not read {}: {}", path.display(), e))?
+                    .len();
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                Ok((file_name.to_string(), size))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut packages: Vec<&CondaBinaryData> = conda_packages_from_lockfile.iter().collect();
+        options.download_order.sort(&mut packages);
+        if !crate::json_output() {
+            eprint!(
+                "{}",
+                dry_run_summary(options.platform, &packages, &injected, &warnings)
+            );
+        }
+        let conda_package_count = packages.len() + injected.len();
+        let mut result = PackResult::new(options.output_file.clone(), start, warnings);
+        result.conda_package_count = conda_package_count;
+        return Ok(result);
+    }
+
+    // Get the pixi-pack executable first to fail before downloading any packages.
+    let unpack_executable = match options.output_format {
+        OutputFormat::Executable => Some(unpack_executable(&options).await?),
+        _ => None,
+    };
+
+    // Packages that are contained in the base pack are neither downloaded nor packed.
+    let base_packages = match &options.base_pack {
+        Some(base_pack) => base_package_hashes(base_pack, options.platform)
+            .await
+            .map_err(|e| anyhow!("could not read base pack {}: {}", base_pack.display(), e))?,
+        None => HashMap::new(),
+    };
+    let in_base_pack = |path: String, sha256: Option<Sha256Hash>| {
+        sha256.is_some() && base_packages.get(&path) == sha256.as_ref()
+    };
+
+    // Copy packages that did not change from a previous pack.
+    let reused_packages = match &options.reuse_from {
+        Some(previous_pack) => {
+            let reused = reuse_packages_from_pack(
+                previous_pack,
+                &conda_packages_from_lockfile,
+                &channel_dir,
+                &mut warnings,
+            )
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "could not reuse packages from {}: {}",
+                    previous_pack.display(),
+                    e
+                )
+            })?;
+            tracing::info!(
+                "Reused {} packages from {}",
+                reused.len(),
+                previous_pack.display()
+            );
+            status!(
+                "♻️  Reused {} packages from {}",
+                reused.len(),
+                previous_pack.display()
+            );
+            reused
+        }
+        None => HashSet::new(),
+    };
+    let mut packages_to_download: Vec<&CondaBinaryData> = conda_packages_from_lockfile
+        .iter()
+        .filter(|p| !reused_packages.contains(&channel_path(p)))
+        .filter(|p| !in_base_pack(channel_path(p), p.package_record.sha256))
+        .collect();
+    options.download_order.sort(&mut packages_to_download);
+
+    // Download packages to temporary directory.
+    tracing::info!("Downloading {} packages...", packages_to_download.len());
+    status!("⏳ Downloading {} packages...", packages_to_download.len());
+    let progress = DownloadProgress::new(&packages_to_download);
+    let bar = &progress.bar;
+    bar.pb
+        .set_message(format!("Downloading {}", options.platform));
+    let statistics = CacheStatistics::default();
+    stream::iter(packages_to_download)
+        .map(Ok)
+        .try_for_each_concurrent(DOWNLOAD_CONCURRENCY, |package| async {
+            let source = shared_downloads
+                .download(
+                    &storage,
+                    package,
+                    &channel_dir,
+                    options.cache_dir.as_deref(),
+                    progress.on_progress(package),
+                )
+                .await?;
+            if options.cache_dir.is_some() {
+                statistics.record(source);
+                bar.pb
+                    .set_message(format!("Downloading {} ({})", options.platform, statistics));
+            }
+            progress.package_done(package, source);
+            Ok(())
+        })
+        .await
+        .map_err(|e: anyhow::Error| anyhow!("could not download package: {}", e))?;
+    bar.pb.finish_and_clear();
+    if let Some(cache_dir) = &options.cache_dir {
+        tracing::info!("Cache statistics: {}", statistics);
+        status!(
+            "🗃️  Used package cache {} for {}: {}",
+            cache_dir.display(),
+            options.platform,
+            statistics
+        );
+    }
+
+    let mut conda_packages: Vec<(String, PackageRecord)> = Vec::new();
+    let mut metadata = options.metadata.clone();
+    if options.source_date_epoch.is_some() {
+        metadata.created = options.source_date_epoch;
+    }
+    let manifest_path = workspace_manifest(&options.manifest_path)?;
+    if metadata.requested_specs.is_empty() && manifest_path.is_file() {
+        match requested_specs(&manifest_path, &options.environment, options.platform) {
+            Ok(specs) => metadata.requested_specs = specs,
+            Err(e) => warn(&mut warnings, PackWarning::NoRequestedSpecs(e.to_string())),
+        }
+    }
+
+    let sources = PackSources::new(&conda_packages_from_lockfile);
+    for package in conda_packages_from_lockfile {
+        let filename = package.file_name;
+        conda_packages.push((filename, package.package_record));
+    }
+
+    let injected_packages = expand_injected_packages(&options.injected_packages, &mut warnings)?;
+
+    tracing::info!("Injecting {} packages", injected_packages.len());
+    for (path, archive_type) in injected_packages.iter() {
+        // step 1: Derive PackageRecord from index.json inside the package
+        let mut package_record = match archive_type {
+            ArchiveType::TarBz2 => package_record_from_tar_bz2(path),
+            ArchiveType::Conda => package_record_from_conda(path),
+        }?;
+
+        // step 2: Copy file into channel dir
+        let subdir = &package_record.subdir;
+        let filename = path
+            .file_name()
+            .ok_or(anyhow!("could not get file name"))?
+            .to_str()
+            .ok_or(anyhow!("could not convert filename to string"))?
+            .to_string();
+
+        let sha256 = rattler_digest::compute_file_digest::<rattler_digest::Sha256>(path)
+            .map_err(|e| anyhow!("could not hash {}: {}", path.display(), e))?;
+        if !channel_files.insert(subdir, &filename, Some(sha256))? {
+            warn(&mut warnings, PackWarning::DuplicatePackage(filename));
+            continue;
+        }
+
+        fs::copy(&path, channel_dir.join(subdir).join(&filename))
+            .await
+            .map_err(|e| anyhow!("could not copy file to channel directory: {}", e))?;
+
+        // Record the hash in the repodata of the pack so that the package is verified on unpack.
+        package_record.sha256 = Some(sha256);
+        metadata
+            .injected
+            .push(injected_package(&filename, &package_record, sha256));
+        conda_packages.push((filename, package_record));
+    }
+
+    tracing::info!(
+        "Injecting {} packages from local channels",
+        channel_packages.len()
+    );
+    let injected_channel_packages = !channel_packages.is_empty();
+    for (path, filename, mut package_record) in channel_packages {
+        let sha256 = rattler_digest::compute_file_digest::<rattler_digest::Sha256>(&path)
+            .map_err(|e| anyhow!("could not hash {}: {}", path.display(), e))?;
+        if !channel_files.insert(&package_record.subdir, &filename, Some(sha256))? {
+            warn(&mut warnings, PackWarning::DuplicatePackage(filename));
+            continue;
+        }
+
+        let subdir_path = channel_dir.join(&package_record.subdir);
+        create_dir_all(&subdir_path)
+            .await
+            .map_err(|e| anyhow!("could not create channel directory: {}", e))?;
+        fs::copy(&path, subdir_path.join(&filename))
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "could not copy {} to channel directory: {}",
+                    path.display(),
+                    e
+                )
+            })?;
+
+        package_record.sha256 = Some(sha256);
+        metadata
+            .injected
+            .push(injected_package(&filename, &package_record, sha256));
+        conda_packages.push((filename, package_record));
+    }
+
+    // In case we injected packages, we need to validate that these packages are solvable with the
+    // environment (i.e., that each packages dependencies and run constraints are still satisfied).
+    if !injected_packages.is_empty() || injected_channel_packages {
+        PackageRecord::validate(conda_packages.iter().map(|(_, p)| p.clone()).collect())?;
+    }
+
+    metadata.system_requirements = system_requirements(conda_packages.iter().map(|(_, p)| p));
+    for requirement in &metadata.system_requirements {
+        tracing::info!(
+            "{} ({}): {}",
+            requirement,
+            requirement.host_requirement(),
+            requirement.packages.join(", ")
+        );
+    }
+
+    // Create `repodata.json` files.
+    tracing::info!("Creating repodata.json files");
+    create_repodata_files(conda_packages.iter(), &channel_dir).await?;
+
+    // The repodata lists all packages of the environment, but the packages of the base pack are
+    // removed from delta packs.
+    if let Some(base_pack) = &options.base_pack {
+        for (filename, package_record) in &conda_packages {
+            let path = format!(
+                "{}/{}/{}",
+                CHANNEL_DIRECTORY_NAME, package_record.subdir, filename
+            );
+            if in_base_pack(path.clone(), package_record.sha256) {
+                let package_path = channel_dir.join(&package_record.subdir).join(filename);
+                if package_path.exists() {
+                    fs::remove_file(&package_path).await?;
+                }
+                metadata.base_packages.push(path);
+            }
+        }
+        metadata.base_packages.sort();
+        tracing::info!(
+            "Left out {} packages that are contained in {}",
+            metadata.base_packages.len(),
+            base_pack.display()
+        );
+        status!(
+            "🧩 Creating a delta pack: {} of {} packages are taken from {}",
+            metadata.base_packages.len(),
+            conda_packages.len(),
+            base_pack.display()
+        );
+    }
+
+    // Add pixi-pack.json containing metadata.
+    tracing::info!("Creating pixi-pack.json file");
+    let metadata_path = output_folder.path().join(PIXI_PACK_METADATA_PATH);
+    let metadata = serde_json::to_string_pretty(&metadata)?;
+    fs::write(metadata_path, metadata.as_bytes()).await?;
+
+    // Add sources.json with the original location of every package.
+    tracing::info!("Creating {} file", SOURCES_PATH);
+    let sources = serde_json::to_string_pretty(&sources)?;
+    fs::write(output_folder.path().join(SOURCES_PATH), sources.as_bytes()).await?;
+
+    // Add usage instructions.
+    if let Some(template_path) = &options.instructions {
+        tracing::info!("Creating {} file", INSTRUCTIONS_PATH);
+        let template = fs::read_to_string(template_path).await.map_err(|e| {
+            anyhow!(
+                "could not read instructions template {}: {}",
+                template_path.display(),
+                e
+            )
+        })?;
+        let instructions = render_instructions(&template, &options.environment, options.platform);
+        fs::write(output_folder.path().join(INSTRUCTIONS_PATH), instructions).await?;
+    }
+
+    // Create environment file.
+    tracing::info!("Creating environment.yml file");
+    create_environment_file(
+        output_folder.path(),
+        conda_packages.iter().map(|(_, p)| p),
+        &options.extra_channels,
+        options.channel_priority,
+    )
+    .await?;
+
+    run_hooks(&options, output_folder.path()).await?;
+
+    // Pack = archive the contents.
+    tracing::info!("Creating pack at {}", options.output_file.display());
+    let digest = match options.output_format {
+        OutputFormat::Tar => {
+            let dictionary = match (&options.base_pack, options.zstd_dictionary) {
+                (Some(base_pack), true) => {
+                    status!("🗜️  Training zstd dictionary on {}", base_pack.display());
+                    Some(train_zstd_dictionary(base_pack).await.map_err(|e| {
+                        anyhow!("could not read base pack {}: {}", base_pack.display(), e)
+                    })?)
+                }
+                _ => None,
+            };
+            if options.compression_threads > 0 {
+                tracing::info!(
+                    "Compressing with {} worker threads",
+                    options.compression_threads
+                );
+            }
+            create_tarball(
+                output_folder.path(),
+                &options.output_file,
+                options.source_date_epoch,
+                options.compression,
+                dictionary.as_deref(),
+                options.compression_threads,
+            )
+            .await
+            .map(Some)
+        }
+        OutputFormat::Oci => {
+            let reference = oci_reference(&options.output_file)?;
+            status!("📤 Pushing pack to {}", reference);
+            let auth_storage = get_auth_store(options.auth_file.clone())?;
+            let client = http_client(&options.http_options, options.network_log.clone())?;
+            push_to_registry(
+                output_folder.path(),
+                &reference,
+                &client,
+                options.http_options.retry_policy,
+                &auth_storage,
+            )
+            .await
+            .map(|_| None)
+        }
+        OutputFormat::Executable => {
+            let unpack_executable = unpack_executable
+                .as_deref()
+                .expect("the pixi-pack executable is fetched for self-extracting executables");
+            match options.executable_format {
+                ExecutableFormat::Script => {
+                    status!("📦 Creating self-extracting executable");
+                    create_self_extracting_executable(
+                        output_folder.path(),
+                        &options.output_file,
+                        unpack_executable,
+                        options.platform,
+                        options.windows_flavor,
+                        options.source_date_epoch,
+                        header.as_deref(),
+                        options.line_endings,
+                    )
+                    .await
+                    .map(Some)
+                }
+                ExecutableFormat::Binary => {
+                    status!("📦 Creating self-extracting binary");
+                    create_binary_executable(
+                        output_folder.path(),
+                        &options.output_file,
+                        unpack_executable,
+                        options.platform,
+                        options.source_date_epoch,
+                    )
+                    .await
+                    .map(Some)
+                }
+            }
+        }
+        OutputFormat::Dir => create_directory(output_folder.path(), &options.output_file)
+            .await
+            .map(|_| None),
+    }
+    .map_err(|e| anyhow!("could not archive directory: {}", e))?;
+    let conda_package_count = conda_packages.len();
+
+    if options.output_format == OutputFormat::Oci {
+        tracing::info!("Pushed pack to {}", options.output_file.display());
+        status!("📦 Pushed pack to {}.", options.output_file.display());
+        let mut result = PackResult::new(options.output_file.clone(), start, warnings);
+        result.conda_package_count = conda_package_count;
+        result.emit_archive_created();
+        return Ok(result);
+    }
+
+    let output_file = output_file_with_digest(&options, digest).await?;
+    let size_bytes = get_size(&output_file)?;
+    tracing::info!(
+        "Created pack at {} with size {}.",
+        output_file.display(),
+        HumanBytes(size_bytes)
+    );
+    status!(
+        "📦 Created pack at {} with size {}.",
+        output_file.display(),
+        HumanBytes(size_bytes)
+    );
+
+    let signature_file = match signing_key {
+        Some(signing_key) => {
+            let signature_file = sign_pack(&signing_key, &output_file, options.source_date_epoch)?;
+            status!(
+                "🔏 Signed pack with key {} at {}.",
+                signing_key.key_id(),
+                signature_file.display()
+            );
+            Some(signature_file)
+        }
+        None => None,
+    };
+
+    let mut result = PackResult::new(output_file, start, warnings);
+    result.size_bytes = Some(size_bytes);
+    result.sha256 = digest;
+    result.signature_file = signature_file;
+    result.compression_threads = options.compression_threads;
+    result.conda_package_count = conda_package_count;
+    result.emit_archive_created();
+    Ok(result)
+}
+
+/// Run the [`PackOptions::hooks`] on the contents of the pack in `staging_dir`.
+async fn run_hooks(options: &PackOptions, staging_dir: &Path) -> Result<()> {
+    for hook in &options.hooks {
+        tracing::info!("Running pack hook {}", hook.name());
+        status!("🔍 Running pack hook {}", hook.name());
+        hook.run(staging_dir, options.platform)
+            .await
+            .map_err(|e| anyhow!("pack hook failed: {}", e))?;
+    }
+    Ok(())
+}
+
+/// The path of the pack created with `options`. With [`PackOptions::digest_name`], the pack is
+/// renamed to include the first 12 hex characters of its `digest`, see [`digest_file_name`].
+async fn output_file_with_digest(
+    options: &PackOptions,
+    digest: Option<Sha256Hash>,
+) -> Result<PathBuf> {
+    match digest {
+        Some(digest) if options.digest_name => {
+            let output_file = digest_file_name(&options.output_file, &digest);
+            fs::rename(&options.output_file, &output_file)
+                .await
+                .map_err(|e| {
+                    anyhow!("could not rename pack to {}: {}", output_file.display(), e)
+                })?;
+            Ok(output_file)
+        }
+        _ => Ok(options.output_file.clone()),
+    }
+}
+
+/// Insert the first 12 hex characters of `digest` into the file name of `output_file` before
+/// its extensions, e.g. `environment.tar.zst` becomes `environment-3f2a9c81d0b4.tar.zst`.
+fn digest_file_name(output_file: &Path, digest: &Sha256Hash) -> PathBuf {
+    let file_name = output_file
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    // A leading dot of hidden files is not an extension.
+    let (stem, extensions) = match file_name.get(1..).and_then(|name| name.find('.')) {
+        Some(index) => file_name.split_at(index + 1),
+        None => (file_name.as_ref(), ""),
+    };
+    let digest = format!("{:x}", digest);
+    output_file.with_file_name(format!("{}-{}{}", stem, &digest[..12], extensions))
+}
+
+/// Write a detached minisign signature of the pack next to it and return its path.
+/// The signature is reproducible if `timestamp` is set.
+fn sign_pack(
+    signing_key: &SigningKey,
+    pack_file: &Path,
+    timestamp: Option<u64>,
+) -> Result<PathBuf> {
+    let timestamp = timestamp.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
+    });
+    let file_name = pack_file
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let trusted_comment = format!("timestamp:{}\tfile:{}\thashed", timestamp, file_name);
+    let signature = signing_key
+        .sign_file(pack_file, &trusted_comment)
+        .map_err(|e| anyhow!("could not sign pack: {}", e))?;
+    let signature_file = signature_path(pack_file);
+    std::fs::write(&signature_file, signature)
+        .map_err(|e| anyhow!("could not write {}: {}", signature_file.display(), e))?;
+    Ok(signature_file)
+}
+
+/// Read the conda packages of the environment and platform to pack from the lockfile of the
+/// workspace of the manifest.
+fn read_lockfile_packages(
+    options: &PackOptions,
+    warnings: &mut Vec<PackWarning>,
+) -> Result<Vec<CondaBinaryData>> {
+    let lockfile = read_lockfile(&options.manifest_path)?;
+    let lockfile_path = lockfile_path(&options.manifest_path)?;
+    let lockfile_dir = lockfile_path
+        .parent()
+        .ok_or(anyhow!("could not get parent directory"))?;
+
+    let env = lockfile.environment(&options.environment).ok_or(anyhow!(
+        "environment not found in lockfile: {}",
+        options.environment
+    ))?;
+
+    let packages: Vec<LockedPackageRef> = if options.platform == Platform::NoArch {
+        noarch_packages(&env, &options.environment)?
+    } else {
+        env.packages(options.platform)
+            .ok_or_else(|| {
+                anyhow!(
+            "platform not found in lockfile: {} (available platforms for environment `{}`: {})",
+            options.platform.as_str(),
+            options.environment,
+            sorted_platforms(env.platforms())
+                .into_iter()
+                .map(Platform::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+            })?
+            .collect()
+    };
 
+    let mut conda_packages = Vec::new();
+    let mut has_pypi_packages = false;
     for package in packages {
         match package {
             LockedPackageRef::Conda(CondaPackageData::Binary(binary_data)) => {
-                conda_packages_from_lockfile.push(binary_data.clone())
+                let mut binary_data = binary_data.clone();
+                binary_data.location = resolve_location(&binary_data.location, lockfile_dir);
+                conda_packages.push(binary_data)
             }
             LockedPackageRef::Conda(CondaPackageData::Source(_)) => {
                 anyhow::bail!("Conda source packages are not yet supported by pixi-pack")
             }
-            LockedPackageRef::Pypi(_, _) => {
+            LockedPackageRef::Pypi(pypi_data, _) => {
+                has_pypi_packages = true;
                 if options.ignore_pypi_errors {
-                    tracing::warn!(
-                        "ignoring PyPI package since PyPI packages are not supported by pixi-pack"
+                    warn(
+                        warnings,
+                        PackWarning::IgnoredPypiPackage(pypi_data.name.to_string()),
                     );
                 } else {
                     anyhow::bail!("PyPI packages are not supported in pixi-pack");
@@ -100,111 +2111,380 @@ pub async fn pack(options: PackOptions) -> Result<()> {
         }
     }
 
-    // Download packages to temporary directory.
-    tracing::info!(
-        "Downloading {} packages...",
-        conda_packages_from_lockfile.len()
-    );
-    eprintln!(
-        "⏳ Downloading {} packages...",
-        conda_packages_from_lockfile.len()
-    );
-    let bar = ProgressReporter::new(conda_packages_from_lockfile.len() as u64);
-    stream::iter(conda_packages_from_lockfile.iter())
-        .map(Ok)
-        .try_for_each_concurrent(50, |package| async {
-            download_package(&client, package, &channel_dir).await?;
-            bar.pb.inc(1);
-            Ok(())
+    // Without pip, the environment silently ends up broken when the PyPI packages are installed
+    // afterwards, e.g., by `conda env create` with a pip section.
+    let has_pip = conda_packages
+        .iter()
+        .any(|p| p.package_record.name.as_normalized() == "pip");
+    if has_pypi_packages && !has_pip {
+        if options.require_pip {
+            anyhow::bail!(
+                "environment {} contains PyPI packages but no pip, add pip to the dependencies",
+                options.environment
+            );
+        }
+        warn(
+            warnings,
+            PackWarning::MissingPip(options.environment.clone()),
+        );
+    }
+    Ok(conda_packages)
+}
+
+/// The packages of an environment that only consists of `noarch` packages, e.g. to pack it for
+/// [`Platform::NoArch`] and install it on any platform.
+///
+/// The packages of all locked platforms need to be `noarch` and identical.
+fn noarch_packages<'lock>(
+    env: &rattler_lock::Environment<'lock>,
+    environment: &str,
+) -> Result<Vec<LockedPackageRef<'lock>>> {
+    let mut noarch_packages: Option<(Platform, Vec<LockedPackageRef<'lock>>)> = None;
+    for platform in sorted_platforms(env.platforms()) {
+        let packages: Vec<LockedPackageRef> =
+            env.packages(platform).into_iter().flatten().collect();
+        if let Some(package) = packages
+            .iter()
+            .filter_map(|p| p.as_conda())
+            .find(|p| p.record().subdir != Platform::NoArch.as_str())
+        {
+            anyhow::bail!(
+                "environment {} cannot be packed for noarch: {} is a {} package",
+                environment,
+                package.location(),
+                package.record().subdir
+            );
+        }
+        let locations = |packages: &[LockedPackageRef]| {
+            packages
+                .iter()
+                .map(|p| p.location().to_string())
+                .collect::<HashSet<_>>()
+        };
+        match &noarch_packages {
+            None => noarch_packages = Some((platform, packages)),
+            Some((first_platform, first_packages)) => {
+                if locations(first_packages) != locations(&packages) {
+                    anyhow::bail!(
+                        "environment {} cannot be packed for noarch: the packages for {} and {} differ",
+                        environment,
+                        first_platform,
+                        platform
+                    );
+                }
+            }
+        }
+    }
+    noarch_packages
+        .map(|(_, packages)| packages)
+        .ok_or_else(|| anyhow!("environment {} has no locked platforms", environment))
+}
+
+/// Resolve relative paths of packages from local channels against the directory of the lockfile.
+fn resolve_location(location: &UrlOrPath, lockfile_dir: &Path) -> UrlOrPath {
+    match location {
+        UrlOrPath::Path(path) if Path::new(path.as_str()).is_relative() => {
+            let absolute = lockfile_dir.join(path.as_str());
+            let absolute: &str = &absolute.to_string_lossy();
+            UrlOrPath::Path(absolute.into()).normalize().into_owned()
+        }
+        _ => location.clone(),
+    }
+}
+
+/// The `pixi.lock` file next to the workspace manifest, see [`workspace_manifest`].
+fn lockfile_path(manifest_path: &Path) -> Result<PathBuf> {
+    Ok(workspace_manifest(manifest_path)?
+        .parent()
+        .ok_or(anyhow!("could not get parent directory"))?
+        .join("pixi.lock"))
+}
+
+/// Read the `pixi.lock` file of the workspace of the manifest.
+fn read_lockfile(manifest_path: &Path) -> Result<LockFile> {
+    let lockfile_path = lockfile_path(manifest_path)?;
+
+    LockFile::from_path(&lockfile_path).map_err(|e| {
+        anyhow!(
+            "could not read lockfile at {}: {}",
+            lockfile_path.display(),
+            e
+        )
+    })
+}
+
+fn sorted_platforms(platforms: impl IntoIterator<Item = Platform>) -> Vec<Platform> {
+    let mut platforms = platforms.into_iter().collect::<Vec<_>>();
+    platforms.sort_by_key(|platform| platform.as_str());
+    platforms
+}
+
+/// The platforms that are locked for an environment in the lockfile of the workspace of
+/// `manifest_path`.
+pub fn lockfile_platforms(manifest_path: &Path, environment: &str) -> Result<Vec<Platform>> {
+    let lockfile = read_lockfile(manifest_path)?;
+    let env = lockfile.environment(environment).ok_or(anyhow!(
+        "environment not found in lockfile: {}",
+        environment
+    ))?;
+    Ok(sorted_platforms(env.platforms()))
+}
+
+/// An environment in a lockfile, see [`lockfile_environments`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockfileEnvironment {
+    /// The name of the environment.
+    pub name: String,
+    /// The locked platforms with the number of packages (conda and PyPI) for each platform.
+    pub platforms: Vec<(Platform, usize)>,
+}
+
+/// The environments in the lockfile of the workspace of `manifest_path`, sorted by name.
+pub fn lockfile_environments(manifest_path: &Path) -> Result<Vec<LockfileEnvironment>> {
+    let lockfile = read_lockfile(manifest_path)?;
+    let mut environments = lockfile
+        .environments()
+        .map(|(name, env)| LockfileEnvironment {
+            name: name.to_string(),
+            platforms: sorted_platforms(env.platforms())
+                .into_iter()
+                .map(|platform| {
+                    let packages = env.packages(platform).map_or(0, |p| p.count());
+                    (platform, packages)
+                })
+                .collect(),
         })
-        .await
-        .map_err(|e: anyhow::Error| anyhow!("could not download package: {}", e))?;
-    bar.pb.finish_and_clear();
+        .collect::<Vec<_>>();
+    environments.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(environments)
+}
 
-    let mut conda_packages: Vec<(String, PackageRecord)> = Vec::new();
+/// Resolve the paths of packages to inject.
+///
+/// Directories are scanned (non-recursively) for `.conda` and `.tar.bz2` files and glob patterns
+/// like `dist/*.conda` are expanded. Other paths are required to point to conda packages.
+fn expand_injected_packages(
+    paths: &[PathBuf],
+    warnings: &mut Vec<PackWarning>,
+) -> Result<Vec<(PathBuf, ArchiveType)>> {
+    let mut injected_packages = Vec::new();
+    for path in paths {
+        let path_str = path.to_string_lossy();
+        let candidates: Vec<PathBuf> = if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)
+                .map_err(|e| anyhow!("could not read directory {}: {}", path.display(), e))?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.retain(|p| p.is_file() && ArchiveType::try_from(p.as_path()).is_some());
+            entries.sort();
+            entries
+        } else if !path.exists() && path_str.contains(['*', '?', '[']) {
+            let mut entries = glob::glob(&path_str)
+                .map_err(|e| anyhow!("invalid glob pattern {}: {}", path_str, e))?
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.sort();
+            entries
+        } else {
+            vec![path.clone()]
+        };
 
-    for package in conda_packages_from_lockfile {
-        let filename = package.file_name;
-        conda_packages.push((filename, package.package_record));
+        if candidates.is_empty() {
+            warn(warnings, PackWarning::NoPackagesToInject(path.clone()));
+        }
+
+        for candidate in candidates {
+            let archive_type = ArchiveType::try_from(candidate.as_path()).ok_or(anyhow!(
+                "unsupported package format of injected package: {}",
+                candidate.display()
+            ))?;
+            injected_packages.push((candidate, archive_type));
+        }
     }
 
-    let injected_packages: Vec<(PathBuf, ArchiveType)> = options
-        .injected_packages
-        .iter()
-        .filter_map(|e| {
-            ArchiveType::split_str(e.as_path().to_string_lossy().as_ref())
-                .map(|(p, t)| (PathBuf::from(format!("{}{}", p, t.extension())), t))
-        })
-        .collect();
+    Ok(injected_packages)
+}
 
-    tracing::info!("Injecting {} packages", injected_packages.len());
-    for (path, archive_type) in injected_packages.iter() {
-        // step 1: Derive PackageRecord from index.json inside the package
-        let package_record = match archive_type {
-            ArchiveType::TarBz2 => package_record_from_tar_bz2(path),
-            ArchiveType::Conda => package_record_from_conda(path),
-        }?;
+/// Render a template for usage instructions.
+///
+/// The following placeholders are supported:
+/// - `{{ environment }}`: the name of the packed environment in the lockfile
+/// - `{{ platform }}`: the platform of the pack
+/// - `{{ env_name }}`: the default name of the unpacked environment directory
+/// - `{{ activation_command }}`: the command to activate the unpacked environment
+pub fn render_instructions(template: &str, environment: &str, platform: Platform) -> String {
+    let activation_command = if platform.is_windows() {
+        "activate.bat"
+    } else {
+        "source activate.sh"
+    };
+    let values = [
+        ("environment", environment),
+        ("platform", platform.as_str()),
+        ("env_name", "env"),
+        ("activation_command", activation_command),
+    ];
 
-        // step 2: Copy file into channel dir
-        let subdir = &package_record.subdir;
-        let filename = path
-            .file_name()
-            .ok_or(anyhow!("could not get file name"))?
-            .to_str()
-            .ok_or(anyhow!("could not convert filename to string"))?
-            .to_string();
+    render_template(template, &values)
+}
 
-        fs::copy(&path, channel_dir.join(subdir).join(&filename))
-            .await
-            .map_err(|e| anyhow!("could not copy file to channel directory: {}", e))?;
+/// Render a template for the header of self-extracting executables.
+///
+/// The header has to extract the pack and the pixi-pack executable that follow it and run
+/// `pixi-pack unpack`, see the built-in headers for reference. The following placeholders are
+/// supported:
+/// - `{{ end_header_marker }}`: the line that ends the header (required unless creating a batch
+///   file)
+/// - `{{ end_archive_marker }}`: the line between the base64-encoded pack and executable
+/// - `{{ platform }}`: the platform of the pack
+/// - `{{ env_name }}`: the default name of the unpacked environment directory
+/// - `{{ pixi_pack_version }}`: the version of pixi-pack that created the pack
+pub fn render_header(
+    template: &str,
+    platform: Platform,
+    windows_flavor: WindowsFlavor,
+) -> Result<String> {
+    let batch_file = platform.is_windows() && windows_flavor == WindowsFlavor::Bat;
+    let (end_header_marker, end_archive_marker) = if platform.is_windows() {
+        ("__END_HEADER__", "__END_ARCHIVE__")
+    } else {
+        ("@@END_HEADER@@", "@@END_ARCHIVE@@")
+    };
+    let values = [
+        ("end_header_marker", end_header_marker),
+        ("end_archive_marker", end_archive_marker),
+        ("platform", platform.as_str()),
+        ("env_name", "env"),
+        ("pixi_pack_version", PIXI_PACK_VERSION),
+    ];
 
-        conda_packages.push((filename, package_record));
+    let rendered = render_template(template, &values);
+    if !batch_file
+        && !rendered
+            .lines()
+            .any(|line| line.trim() == end_header_marker)
+    {
+        anyhow::bail!(
+            "header template must contain a line with the `{{{{ end_header_marker }}}}` placeholder"
+        );
     }
+    Ok(rendered)
+}
 
-    // In case we injected packages, we need to validate that these packages are solvable with the
-    // environment (i.e., that each packages dependencies and run constraints are still satisfied).
-    if !injected_packages.is_empty() {
-        PackageRecord::validate(conda_packages.iter().map(|(_, p)| p.clone()).collect())?;
+/// Replace `{{ name }}` and `{{name}}` placeholders in a template.
+fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut rendered = template.to_string();
+    for (name, value) in values {
+        rendered = rendered
+            .replace(&format!("{{{{ {} }}}}", name), value)
+            .replace(&format!("{{{{{}}}}}", name), value);
     }
+    rendered
+}
 
-    // Create `repodata.json` files.
-    tracing::info!("Creating repodata.json files");
-    create_repodata_files(conda_packages.iter(), &channel_dir).await?;
+/// Virtual packages whose constraints are recorded in the pack metadata.
+const SYSTEM_REQUIREMENT_PACKAGES: [&str; 2] = ["__cuda", "__glibc"];
 
-    // Add pixi-pack.json containing metadata.
-    tracing::info!("Creating pixi-pack.json file");
-    let metadata_path = output_folder.path().join(PIXI_PACK_METADATA_PATH);
-    let metadata = serde_json::to_string_pretty(&options.metadata)?;
-    fs::write(metadata_path, metadata.as_bytes()).await?;
+/// Collect the constraints of `packages` on the CUDA driver and glibc of the host, grouped by
+/// virtual package and constraint.
+fn system_requirements<'a>(
+    packages: impl IntoIterator<Item = &'a PackageRecord>,
+) -> Vec<SystemRequirement> {
+    let mut requirements: BTreeMap<(String, String), BTreeSet<String>> = BTreeMap::new();
+    for package in packages {
+        for dependency in &package.depends {
+            let (name, spec) = dependency
+                .trim()
+                .split_once(char::is_whitespace)
+                .unwrap_or((dependency.trim(), ""));
+            if SYSTEM_REQUIREMENT_PACKAGES.contains(&name) {
+                requirements
+                    .entry((name.to_string(), spec.trim().to_string()))
+                    .or_default()
+                    .insert(package.name.as_normalized().to_string());
+            }
+        }
+    }
+    requirements
+        .into_iter()
+        .map(|((name, spec), packages)| SystemRequirement {
+            name,
+            spec,
+            packages: packages.into_iter().collect(),
+        })
+        .collect()
+}
 
-    // Create environment file.
-    tracing::info!("Creating environment.yml file");
-    create_environment_file(output_folder.path(), conda_packages.iter().map(|(_, p)| p)).await?;
+/// Describe an injected package for the pack metadata.
+fn injected_package(
+    filename: &str,
+    package_record: &PackageRecord,
+    sha256: Sha256Hash,
+) -> InjectedPackage {
+    InjectedPackage {
+        name: package_record.name.as_normalized().to_string(),
+        version: package_record.version.to_string(),
+        build: package_record.build.clone(),
+        filename: filename.to_string(),
+        sha256: format!("{:x}", sha256),
+    }
+}
 
-    // Pack = archive the contents.
-    tracing::info!("Creating pack at {}", options.output_file.display());
-    archive_directory(
-        output_folder.path(),
-        &options.output_file,
-        options.create_executable,
-        options.platform,
-    )
-    .await
-    .map_err(|e| anyhow!("could not archive directory: {}", e))?;
+/// The package files in the channel directory of a pack, keyed by `<subdir>/<file_name>`.
+///
+/// Used to detect packages that are added more than once, e.g., via the lockfile and `--inject`.
+#[derive(Debug, Default)]
+struct ChannelFiles(HashMap<String, Option<Sha256Hash>>);
 
-    let output_size = HumanBytes(get_size(&options.output_file)?).to_string();
-    tracing::info!(
-        "Created pack at {} with size {}.",
-        options.output_file.display(),
-        output_size
-    );
-    eprintln!(
-        "📦 Created pack at {} with size {}.",
-        options.output_file.display(),
-        output_size
-    );
+impl ChannelFiles {
+    /// Register a package file. Returns `false` if the same package was already added and fails
+    /// if a different package (or one without hash) with the same file name was added.
+    fn insert(
+        &mut self,
+        subdir: &str,
+        file_name: &str,
+        sha256: Option<Sha256Hash>,
+    ) -> Result<bool> {
+        let key = format!("{}/{}", subdir, file_name);
+        match self.0.get(&key) {
+            None => {
+                self.0.insert(key, sha256);
+                Ok(true)
+            }
+            Some(Some(existing)) if Some(*existing) == sha256 => Ok(false),
+            Some(_) => anyhow::bail!(
+                "conflicting packages with the same file name {}: the pack can only contain one",
+                key
+            ),
+        }
+    }
+}
 
-    Ok(())
+/// Collect all packages of a local channel that are relevant for `platform`.
+///
+/// Returns the path to each package together with its file name and record.
+async fn collect_channel_packages(
+    channel: &Path,
+    platform: Platform,
+) -> Result<Vec<(PathBuf, String, PackageRecord)>> {
+    let mut packages = Vec::new();
+    for subdir in [platform, Platform::NoArch] {
+        let subdir_path = channel.join(subdir.as_str());
+        if !subdir_path.join("repodata.json").is_file() {
+            continue;
+        }
+        let mut subdir_packages: Vec<_> = collect_packages_in_subdir(subdir_path.clone())
+            .await?
+            .into_iter()
+            .collect();
+        subdir_packages.sort_by(|(a, _), (b, _)| a.cmp(b));
+        packages.extend(
+            subdir_packages
+                .into_iter()
+                .map(|(filename, record)| (subdir_path.join(&filename), filename, record)),
+        );
+    }
+
+    Ok(packages)
 }
 
 /// Get the authentication storage from the given auth file path.
@@ -216,29 +2496,182 @@ fn get_auth_store(auth_file: Option<PathBuf>) -> Result<AuthenticationStorage> {
 }
 
 /// Create a reqwest client (optionally including authentication middleware).
-fn reqwest_client_from_auth_storage(auth_file: Option<PathBuf>) -> Result<ClientWithMiddleware> {
+///
+/// If `auth_file` is `None`, the default authentication storage of rattler is used
+/// (keyring, `~/.rattler/credentials.json` and `RATTLER_AUTH_FILE`).
+pub fn reqwest_client_from_auth_storage(
+    auth_file: Option<PathBuf>,
+) -> Result<ClientWithMiddleware> {
+    reqwest_client(auth_file, &HttpOptions::default())
+}
+
+/// Create a reqwest client with authentication middleware and the given [`HttpOptions`].
+///
+/// See [`reqwest_client_from_auth_storage`] for how `auth_file` is used.
+pub fn reqwest_client(
+    auth_file: Option<PathBuf>,
+    http_options: &HttpOptions,
+) -> Result<ClientWithMiddleware> {
+    build_reqwest_client(auth_file, http_options, None)
+}
+
+/// Create a reqwest client with the given [`HttpOptions`] but without authentication
+/// middleware, e.g. for [`crate::push_to_registry`] which authenticates requests itself.
+pub fn http_client(
+    http_options: &HttpOptions,
+    network_log: Option<NetworkLog>,
+) -> Result<ClientWithMiddleware> {
+    Ok(http_client_builder(http_options, network_log)?.build())
+}
+
+/// Create a reqwest client like [`reqwest_client`] that records all requests in `network_log`.
+pub(crate) fn build_reqwest_client(
+    auth_file: Option<PathBuf>,
+    http_options: &HttpOptions,
+    network_log: Option<NetworkLog>,
+) -> Result<ClientWithMiddleware> {
     let auth_storage = get_auth_store(auth_file)?;
+    // The network log comes first so that it does not record credentials.
+    Ok(http_client_builder(http_options, network_log)?
+        .with_arc(Arc::new(AuthenticationMiddleware::new(auth_storage)))
+        .build())
+}
 
+/// The middleware stack of [`http_client`], see [`build_reqwest_client`].
+fn http_client_builder(
+    http_options: &HttpOptions,
+    network_log: Option<NetworkLog>,
+) -> Result<reqwest_middleware::ClientBuilder> {
     let timeout = 5 * 60;
-    let client = reqwest_middleware::ClientBuilder::new(
-        reqwest::Client::builder()
-            .no_gzip()
-            .pool_max_idle_per_host(20)
-            .user_agent("pixi-pack")
-            .timeout(std::time::Duration::from_secs(timeout))
+    // Packages are already compressed, so gzip would only cost time.
+    let mut builder = reqwest::Client::builder()
+        .no_gzip()
+        .pool_max_idle_per_host(http_options.pool_max_idle_per_host)
+        .pool_idle_timeout(http_options.pool_idle_timeout)
+        .tcp_keepalive(http_options.tcp_keepalive)
+        .user_agent("pixi-pack")
+        .timeout(Duration::from_secs(timeout));
+    if !http_options.http2 {
+        builder = builder.http1_only();
+    }
+    if let Some(network_log) = &network_log {
+        builder = builder.redirect(network_log.redirect_policy());
+    }
+    let mut client = reqwest_middleware::ClientBuilder::new(
+        builder
             .build()
             .map_err(|e| anyhow!("could not create download client: {}", e))?,
-    )
-    .with_arc(Arc::new(AuthenticationMiddleware::new(auth_storage)))
-    .build();
+    );
+    if let Some(network_log) = network_log {
+        client = client.with(network_log);
+    }
     Ok(client)
 }
 
+/// The path of a package inside the pack, e.g. `channel/linux-64/python-3.12.3-h2628c8c_0.conda`.
+fn channel_path(package: &CondaBinaryData) -> String {
+    format!(
+        "{}/{}/{}",
+        CHANNEL_DIRECTORY_NAME, package.package_record.subdir, package.file_name
+    )
+}
+
+/// The sha256 hashes of the packages in a base pack for delta packs, keyed by their path in the
+/// pack (see [`channel_path`]).
+///
+/// If the base pack is a delta pack itself, the packages it left out are not included since they
+/// cannot be restored from the base pack.
+async fn base_package_hashes(
+    base_pack: &Path,
+    platform: Platform,
+) -> Result<HashMap<String, Sha256Hash>> {
+    let contents = list(base_pack).await?;
+    if contents.metadata.platform != platform {
+        anyhow::bail!(
+            "the base pack is for {}, not for {}",
+            contents.metadata.platform,
+            platform
+        );
+    }
+    let base_packages: HashSet<&str> = contents
+        .metadata
+        .base_packages
+        .iter()
+        .map(String::as_str)
+        .collect();
+    Ok(contents
+        .packages
+        .iter()
+        .filter_map(|package| {
+            let path = format!(
+                "{}/{}/{}",
+                CHANNEL_DIRECTORY_NAME, package.record.subdir, package.file_name
+            );
+            if base_packages.contains(path.as_str()) {
+                return None;
+            }
+            Some((path, package.record.sha256?))
+        })
+        .collect())
+}
+
+/// Copy packages contained in a previous pack into the channel directory.
+/// A package is only reused if its sha256 hash matches the one recorded in the lockfile.
+/// Returns the paths (see [`channel_path`]) of all reused packages.
+async fn reuse_packages_from_pack(
+    pack_file: &Path,
+    packages: &[CondaBinaryData],
+    channel_dir: &Path,
+    warnings: &mut Vec<PackWarning>,
+) -> Result<HashSet<String>> {
+    let candidates: HashMap<String, &CondaBinaryData> = packages
+        .iter()
+        .filter(|p| p.package_record.sha256.is_some())
+        .map(|p| (channel_path(p), p))
+        .collect();
+
+    let mut archive = open_archive(pack_file).await?;
+    let mut entries = archive.entries()?;
+
+    let mut reused = HashSet::new();
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().replace('\\', "/");
+        let Some(package) = candidates.get(&path) else {
+            continue;
+        };
+
+        let output_dir = channel_dir.join(&package.package_record.subdir);
+        create_dir_all(&output_dir).await?;
+        let destination = output_dir.join(&package.file_name);
+        entry.unpack(&destination).await?;
+
+        let hash = rattler_digest::compute_file_digest::<rattler_digest::Sha256>(&destination)?;
+        if Some(hash) == package.package_record.sha256 {
+            reused.insert(path);
+        } else {
+            warn(
+                warnings,
+                PackWarning::ReusedPackageHashMismatch(package.file_name.clone()),
+            );
+            fs::remove_file(&destination).await?;
+        }
+    }
+
+    Ok(reused)
+}
+
 /// Download a conda package to a given output directory.
-async fn download_package(
-    client: &ClientWithMiddleware,
+///
+/// The package is written to `<output_dir>/<subdir>/<file_name>`, creating the subdirectory
+/// if necessary. The package is fetched with the [`Storage`] backend for the scheme of its URL,
+/// see [`Storages`]; packages that are referenced by a local path are copied.
+/// The progress of the download is reported to `on_progress`.
+pub async fn download_package(
+    storage: &dyn Storage,
     package: &CondaBinaryData,
     output_dir: &Path,
+    on_progress: impl Fn(FetchProgress) + Send + Sync,
 ) -> Result<()> {
     let output_dir = output_dir.join(&package.package_record.subdir);
     create_dir_all(&output_dir)
@@ -246,44 +2679,138 @@ async fn download_package(
         .map_err(|e| anyhow!("could not create download directory: {}", e))?;
 
     let file_name = &package.file_name;
-    let mut dest = File::create(output_dir.join(file_name)).await?;
 
     tracing::debug!("Fetching package {}", package.location);
-    let url = match &package.location {
-        UrlOrPath::Url(url) => url,
-        UrlOrPath::Path(path) => anyhow::bail!("Path not supported: {}", path),
+    let url = match package.location.normalize().as_ref() {
+        UrlOrPath::Url(url) => url.clone(),
+        UrlOrPath::Path(path) => Url::from_file_path(path.as_str())
+            .map_err(|_| anyhow!("invalid package path: {}", path))?,
     };
-    let mut response = client.get(url.clone()).send().await?;
-    if response.status().is_client_error() {
-        return Err(anyhow!(
-            "failed to download {}: {}",
-            url,
-            response.text().await?
-        ));
-    }
-
-    while let Some(chunk) = response.chunk().await? {
-        dest.write_all(&chunk).await?;
-    }
-
-    Ok(())
+    storage
+        .fetch(&url, &output_dir.join(file_name), &on_progress)
+        .await
 }
 
-async fn archive_directory(
+/// Archive a directory containing the contents of a pack into the given output format.
+///
+/// `input_dir` is expected to have the layout created by [`crate::pack`], i.e., a
+/// `pixi-pack.json` file, an `environment.yml` file and a `channel` directory.
+/// `platform` and `windows_flavor` are only used to select the header of self-extracting
+/// executables, `header` replaces the built-in one and `line_endings` are enforced in the whole
+/// script. Archive entries get `mtime` as modification time (default: zero); it is ignored for
+/// [`OutputFormat::Dir`]. [`OutputFormat::Oci`] packs are pushed to the registry of
+/// `archive_target` with the default credentials of rattler.
+#[allow(clippy::too_many_arguments)]
+pub async fn archive_directory(
     input_dir: &Path,
     archive_target: &Path,
-    create_executable: bool,
+    output_format: OutputFormat,
     platform: Platform,
+    windows_flavor: WindowsFlavor,
+    mtime: Option<u64>,
+    header: Option<&str>,
+    line_endings: LineEndings,
+    compression: Compression,
 ) -> Result<()> {
-    if create_executable {
-        eprintln!("📦 Creating self-extracting executable");
-        create_self_extracting_executable(input_dir, archive_target, platform).await
-    } else {
-        create_tarball(input_dir, archive_target).await
+    match output_format {
+        OutputFormat::Tar => create_tarball(input_dir, archive_target, mtime, compression, None, 0)
+            .await
+            .map(|_| ()),
+        OutputFormat::Dir => create_directory(input_dir, archive_target).await,
+        OutputFormat::Oci => {
+            let reference = oci_reference(archive_target)?;
+            status!("📤 Pushing pack to {}", reference);
+            let http_options = HttpOptions::default();
+            push_to_registry(
+                input_dir,
+                &reference,
+                &http_client(&http_options, None)?,
+                http_options.retry_policy,
+                &get_auth_store(None)?,
+            )
+            .await
+        }
+        OutputFormat::Executable => {
+            let unpack_executable = download_pixi_pack_executable(platform).await?;
+            status!("📦 Creating self-extracting executable");
+            create_self_extracting_executable(
+                input_dir,
+                archive_target,
+                &unpack_executable,
+                platform,
+                windows_flavor,
+                mtime,
+                header,
+                line_endings,
+            )
+            .await
+            .map(|_| ())
+        }
+    }
+}
+
+/// Copy the contents of the pack into a plain directory.
+///
+/// Writes a `checksums.json` file with the hashes of all files and a digest of the directory, see
+/// [`DirectoryChecksums`].
+async fn create_directory(input_dir: &Path, target_dir: &Path) -> Result<()> {
+    if target_dir.exists() && target_dir.read_dir()?.next().is_some() {
+        anyhow::bail!(
+            "output directory {} already exists and is not empty",
+            target_dir.display()
+        );
+    }
+
+    for entry in WalkDir::new(input_dir).sort_by_file_name() {
+        let entry = entry.map_err(|e| anyhow!("could not walk directory: {}", e))?;
+        let relative_path = entry
+            .path()
+            .strip_prefix(input_dir)
+            .map_err(|e| anyhow!("could not strip prefix: {}", e))?;
+        let destination = target_dir.join(relative_path);
+        if entry.file_type().is_dir() {
+            create_dir_all(&destination).await.map_err(|e| {
+                anyhow!(
+                    "could not create directory {}: {}",
+                    destination.display(),
+                    e
+                )
+            })?;
+        } else {
+            fs::copy(entry.path(), &destination)
+                .await
+                .map_err(|e| anyhow!("could not copy file to {}: {}", destination.display(), e))?;
+        }
     }
+
+    let checksums = DirectoryChecksums::new(target_dir)?;
+    tracing::info!("Digest of {}: {}", target_dir.display(), checksums.digest);
+    status!("🔏 Digest of the pack: sha256:{}", checksums.digest);
+    fs::write(
+        target_dir.join(CHECKSUMS_PATH),
+        serde_json::to_string_pretty(&checksums)?,
+    )
+    .await
+    .map_err(|e| anyhow!("could not write {}: {}", CHECKSUMS_PATH, e))?;
+
+    Ok(())
+}
+
+async fn write_archive<T>(archive: Builder<T>, input_dir: &Path, mtime: Option<u64>) -> Result<T>
+where
+    T: tokio::io::AsyncWrite + Unpin + Send,
+{
+    write_archive_filtered(archive, input_dir, mtime, |_| true).await
 }
 
-async fn write_archive<T>(mut archive: Builder<T>, input_dir: &Path) -> Result<T>
+/// Like [`write_archive`], but only archives the entries whose path relative to `input_dir`
+/// matches `include`. Parent directories of included entries are not added implicitly.
+pub(crate) async fn write_archive_filtered<T>(
+    mut archive: Builder<T>,
+    input_dir: &Path,
+    mtime: Option<u64>,
+    include: impl Fn(&Path) -> bool,
+) -> Result<T>
 where
     T: tokio::io::AsyncWrite + Unpin + Send,
 {
@@ -293,7 +2820,24 @@ where
         .sort_by_file_name()
         .into_iter()
         .collect::<Result<Vec<_>, walkdir::Error>>()
-        .map_err(|e| anyhow!("could not walk directory: {}", e))?;
+        .map_err(|e| anyhow!("could not walk directory: {}", e))?
+        .into_iter()
+        .filter(|file| {
+            file.path()
+                .strip_prefix(input_dir)
+                .is_ok_and(|path| path != Path::new("") && include(path))
+        })
+        .collect::<Vec<_>>();
+
+    let total_size = files
+        .iter()
+        .filter(|file| file.file_type().is_file())
+        .map(|file| file.metadata().map(|m| m.len()))
+        .sum::<Result<u64, walkdir::Error>>()
+        .map_err(|e| anyhow!("could not read file metadata: {}", e))?;
+    let bar = ProgressReporter::new_bytes(total_size);
+    bar.pb.set_message("Archiving");
+
     for file in files {
         let path = file.path();
         let relative_path = path
@@ -302,12 +2846,36 @@ where
         if relative_path == Path::new("") {
             continue;
         }
-        if path.is_dir() {
+        if let Some(mtime) = mtime {
+            let metadata = file
+                .metadata()
+                .map_err(|e| anyhow!("could not read file metadata: {}", e))?;
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_metadata_in_mode(&metadata, HeaderMode::Deterministic);
+            header.set_mtime(mtime);
+            if metadata.is_dir() {
+                header.set_size(0);
+                archive
+                    .append_data(&mut header, relative_path, tokio::io::empty())
+                    .await?;
+            } else {
+                archive
+                    .append_data(&mut header, relative_path, File::open(path).await?)
+                    .await?;
+                bar.pb.inc(metadata.len());
+            }
+        } else if path.is_dir() {
             archive.append_dir(relative_path, input_dir).await?;
         } else {
             archive.append_path_with_name(path, relative_path).await?;
+            bar.pb.inc(
+                file.metadata()
+                    .map_err(|e| anyhow!("could not read file metadata: {}", e))?
+                    .len(),
+            );
         }
     }
+    bar.pb.finish_and_clear();
 
     let mut compressor = archive
         .into_inner()
@@ -322,7 +2890,14 @@ where
     Ok(compressor)
 }
 
-async fn create_tarball(input_dir: &Path, archive_target: &Path) -> Result<()> {
+async fn create_tarball(
+    input_dir: &Path,
+    archive_target: &Path,
+    mtime: Option<u64>,
+    compression: Compression,
+    dictionary: Option<&[u8]>,
+    threads: u32,
+) -> Result<Sha256Hash> {
     let outfile = fs::File::create(archive_target).await.map_err(|e| {
         anyhow!(
             "could not create archive file at {}: {}",
@@ -331,62 +2906,392 @@ async fn create_tarball(input_dir: &Path, archive_target: &Path) -> Result<()> {
         )
     })?;
 
-    let writer = tokio::io::BufWriter::new(outfile);
-    let archive = Builder::new(writer);
+    let writer = tokio::io::BufWriter::new(HashingWriter::<_, Sha256>::new(outfile));
+    let writer = match compression {
+        Compression::None => write_archive(Builder::new(writer), input_dir, mtime).await?,
+        Compression::Zstd(level) => {
+            let level = async_compression::Level::Precise(level);
+            let encoder = match dictionary {
+                Some(dictionary) => ZstdEncoder::with_dict(writer, level, dictionary)
+                    .map_err(|e| anyhow!("invalid zstd dictionary: {}", e))?,
+                None => ZstdEncoder::with_quality_and_params(
+                    writer,
+                    level,
+                    &[CParameter::nb_workers(threads)],
+                ),
+            };
+            write_archive(Builder::new(IgnoreFlush(encoder)), input_dir, mtime)
+                .await?
+                .0
+                .into_inner()
+        }
+    };
+
+    let (_, digest) = writer.into_inner().finalize();
+    Ok(digest)
+}
+
+/// A writer that ignores flushes and only writes out buffered data on shutdown.
+///
+/// `tokio::io::copy` flushes whenever reading a file would block. Each flush ends a zstd block, so
+/// compressed packs would depend on the timing of file reads and not be reproducible.
+struct IgnoreFlush<W>(W);
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for IgnoreFlush<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// A writer that counts the bytes written to the inner writer.
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CountingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            self.written += written as u64;
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Create a binary self-extracting executable at `target`, see [`write_binary_executable`].
+async fn create_binary_executable(
+    input_dir: &Path,
+    target: &Path,
+    unpack_executable: &[u8],
+    platform: Platform,
+    mtime: Option<u64>,
+) -> Result<Sha256Hash> {
+    let executable_path = target.with_extension(executable_extension(
+        platform,
+        WindowsFlavor::default(),
+        ExecutableFormat::Binary,
+    ));
+    let final_executable = tokio::io::BufWriter::new(HashingWriter::<_, Sha256>::new(
+        File::create(&executable_path)
+            .await
+            .map_err(|e| anyhow!("could not create final executable file: {}", e))?,
+    ));
+    let mut final_executable =
+        write_binary_executable(final_executable, input_dir, unpack_executable, mtime).await?;
+    final_executable.shutdown().await?;
+    let (final_executable, digest) = final_executable.into_inner().finalize();
+
+    #[cfg(not(target_os = "windows"))]
+    if !platform.is_windows() {
+        let mut perms = final_executable.metadata().await?.permissions();
+        perms.set_mode(0o755);
+        final_executable.set_permissions(perms).await?;
+    }
+
+    Ok(digest)
+}
+
+/// Write a binary self-extracting executable: `unpack_executable` followed by the pack in
+/// `input_dir` as tarball and a trailer with the position of the tarball. Returns the writer.
+///
+/// When run, the pixi-pack executable finds the trailer in its own file and unpacks the embedded
+/// pack, see [`crate::low_level::embedded_pack`].
+pub async fn write_binary_executable<W>(
+    mut writer: W,
+    input_dir: &Path,
+    unpack_executable: &[u8],
+    mtime: Option<u64>,
+) -> Result<W>
+where
+    W: tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    writer.write_all(unpack_executable).await?;
+    let archive = write_archive(
+        Builder::new(CountingWriter {
+            inner: writer,
+            written: 0,
+        }),
+        input_dir,
+        mtime,
+    )
+    .await?;
+    let (mut writer, archive_len) = (archive.inner, archive.written);
+
+    writer
+        .write_all(&(unpack_executable.len() as u64).to_le_bytes())
+        .await?;
+    writer.write_all(&archive_len.to_le_bytes()).await?;
+    writer.write_all(&BINARY_EXECUTABLE_MAGIC).await?;
+    Ok(writer)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn create_self_extracting_executable(
+    input_dir: &Path,
+    target: &Path,
+    unpack_executable: &[u8],
+    platform: Platform,
+    windows_flavor: WindowsFlavor,
+    mtime: Option<u64>,
+    header: Option<&str>,
+    line_endings: LineEndings,
+) -> Result<Sha256Hash> {
+    let executable_path = target.with_extension(executable_extension(
+        platform,
+        windows_flavor,
+        ExecutableFormat::Script,
+    ));
+    let mut final_executable = tokio::io::BufWriter::new(HashingWriter::<_, Sha256>::new(
+        File::create(&executable_path)
+            .await
+            .map_err(|e| anyhow!("could not create final executable file: {}", e))?,
+    ));
+    write_self_extracting_executable(
+        &mut final_executable,
+        input_dir,
+        header.unwrap_or(default_executable_header(platform, windows_flavor)),
+        unpack_executable,
+        platform,
+        windows_flavor,
+        mtime,
+        line_endings,
+    )
+    .await?;
+    final_executable.shutdown().await?;
+    let (final_executable, digest) = final_executable.into_inner().finalize();
+
+    // Make the script executable
+    // This won't be executed when cross-packing due to Windows FS not supporting Unix permissions
+    #[cfg(not(target_os = "windows"))]
+    if !platform.is_windows() {
+        let mut perms = final_executable.metadata().await?.permissions();
+        perms.set_mode(0o755);
+        final_executable.set_permissions(perms).await?;
+    }
+
+    Ok(digest)
+}
+
+/// Check options that would otherwise only fail after all packages were downloaded.
+fn validate_options(options: &PackOptions) -> Result<()> {
+    if options.compression != Compression::None && options.output_format != OutputFormat::Tar {
+        anyhow::bail!("compression is only supported for tar archives");
+    }
+    if options.zstd_dictionary
+        && (options.base_pack.is_none() || options.compression == Compression::None)
+    {
+        anyhow::bail!("zstd dictionaries require a base pack and zstd compression");
+    }
+    if options.compression_threads > 0 && options.compression == Compression::None {
+        anyhow::bail!("compression threads require zstd compression");
+    }
+    if options.compression_threads > 0 && options.zstd_dictionary {
+        anyhow::bail!("zstd dictionaries do not support multithreaded compression");
+    }
+    if options.sign_key.is_some()
+        && matches!(options.output_format, OutputFormat::Dir | OutputFormat::Oci)
+    {
+        anyhow::bail!("signing is only supported for packs that are files");
+    }
+    if options.output_format == OutputFormat::Oci {
+        oci_reference(&options.output_file)?;
+    }
+    if options.digest_name && matches!(options.output_format, OutputFormat::Dir | OutputFormat::Oci)
+    {
+        anyhow::bail!("digest names are only supported for packs that are files");
+    }
+    if options.output_format != OutputFormat::Executable {
+        if options.unpack_executable.is_some() {
+            anyhow::bail!("an unpack executable is only used for self-extracting executables");
+        }
+        return Ok(());
+    }
+    if options.executable_format == ExecutableFormat::Binary && options.header_template.is_some() {
+        anyhow::bail!("custom headers are not supported for binary executables");
+    }
+    if executable_target(options.platform).is_none() {
+        anyhow::bail!(
+            "self-extracting executables are not supported for {}, supported platforms: {}",
+            options.platform,
+            executable_platforms()
+                .into_iter()
+                .map(Platform::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    if options.windows_flavor == WindowsFlavor::Bat && !options.platform.is_windows() {
+        anyhow::bail!(
+            "batch file executables are only supported for Windows platforms, not {}",
+            options.platform
+        );
+    }
+    Ok(())
+}
+
+/// Parse the output file of an [`OutputFormat::Oci`] pack.
+fn oci_reference(output_file: &Path) -> Result<OciReference> {
+    output_file
+        .to_str()
+        .ok_or(anyhow!("invalid OCI reference: {}", output_file.display()))?
+        .parse()
+}
 
-    write_archive(archive, input_dir).await?;
+/// The architecture and OS of the pixi-pack release executable for `platform`, if there is one.
+fn executable_target(platform: Platform) -> Option<(&'static str, &'static str)> {
+    match platform {
+        Platform::Linux64 => Some(("x86_64", "unknown-linux-musl")),
+        Platform::LinuxAarch64 => Some(("aarch64", "unknown-linux-musl")),
+        Platform::Osx64 => Some(("x86_64", "apple-darwin")),
+        Platform::OsxArm64 => Some(("aarch64", "apple-darwin")),
+        Platform::Win64 => Some(("x86_64", "pc-windows-msvc")),
+        Platform::WinArm64 => Some(("aarch64", "pc-windows-msvc")),
+        _ => None,
+    }
+}
 
-    Ok(())
+/// The platforms for which self-extracting executables can be created.
+pub fn executable_platforms() -> Vec<Platform> {
+    Platform::all()
+        .filter(|platform| executable_target(*platform).is_some())
+        .collect()
 }
 
-async fn create_self_extracting_executable(
-    input_dir: &Path,
-    target: &Path,
-    platform: Platform,
-) -> Result<()> {
-    let line_ending = if platform.is_windows() {
-        b"\r\n".to_vec()
-    } else {
-        b"\n".to_vec()
-    };
+/// The file name of the pixi-pack release executable for `platform`.
+fn executable_file_name(platform: Platform) -> Result<String> {
+    let (arch, os) =
+        executable_target(platform).ok_or(anyhow!("Unsupported platform: {}", platform))?;
+    let extension = if platform.is_windows() { ".exe" } else { "" };
+    Ok(format!("pixi-pack-{}-{}{}", arch, os, extension))
+}
 
-    let archive = Builder::new(Vec::new());
+/// The URL of the pixi-pack executable of the current version for `platform` on GitHub releases.
+fn executable_url(platform: Platform) -> Result<String> {
+    Ok(format!(
+        "https://github.com/Quantco/pixi-pack/releases/download/v{}/{}",
+        env!("CARGO_PKG_VERSION"),
+        executable_file_name(platform)?
+    ))
+}
 
-    let compressor = write_archive(archive, input_dir).await?;
+/// The path of the pixi-pack executable of the current version for `platform` in `cache_dir`.
+fn cached_executable_path(cache_dir: &Path, platform: Platform) -> Result<PathBuf> {
+    Ok(cache_dir
+        .join("pixi-pack")
+        .join(format!("v{}", env!("CARGO_PKG_VERSION")))
+        .join(executable_file_name(platform)?))
+}
 
-    let windows_header = include_str!("header.ps1");
-    let unix_header = include_str!("header.sh");
+/// Get the pixi-pack executable to embed into a self-extracting executable for
+/// [`PackOptions::platform`].
+///
+/// [`PackOptions::unpack_executable`] is used if given. Otherwise, the executable is taken from
+/// [`PackOptions::cache_dir`] or downloaded from GitHub releases and stored in the cache.
+async fn unpack_executable(options: &PackOptions) -> Result<Vec<u8>> {
+    if let Some(unpack_executable) = &options.unpack_executable {
+        tracing::info!("Using pixi-pack executable {}", unpack_executable.display());
+        return fs::read(unpack_executable).await.map_err(|e| {
+            anyhow!(
+                "could not read pixi-pack executable {}: {}",
+                unpack_executable.display(),
+                e
+            )
+        });
+    }
 
-    let header = if platform.is_windows() {
-        windows_header
-    } else {
-        unix_header
+    let cached = match &options.cache_dir {
+        Some(cache_dir) => Some(cached_executable_path(cache_dir, options.platform)?),
+        None => None,
     };
+    if let Some(cached) = cached.as_ref().filter(|cached| cached.is_file()) {
+        tracing::info!("Using cached pixi-pack executable {}", cached.display());
+        return fs::read(cached)
+            .await
+            .map_err(|e| anyhow!("could not read {}: {}", cached.display(), e));
+    }
 
-    let executable_path = target.with_extension(if platform.is_windows() { "ps1" } else { "sh" });
-
-    // Determine the target OS and architecture
-    let (os, arch) = match platform {
-        Platform::Linux64 => ("unknown-linux-musl", "x86_64"),
-        Platform::LinuxAarch64 => ("unknown-linux-musl", "aarch64"),
-        Platform::Osx64 => ("apple-darwin", "x86_64"),
-        Platform::OsxArm64 => ("apple-darwin", "aarch64"),
-        Platform::Win64 => ("pc-windows-msvc", "x86_64"),
-        Platform::WinArm64 => ("pc-windows-msvc", "aarch64"),
-        _ => return Err(anyhow!("Unsupported platform: {}", platform)),
+    let client = match &options.network_log {
+        Some(network_log) => reqwest_middleware::ClientBuilder::new(
+            reqwest::Client::builder()
+                .redirect(network_log.redirect_policy())
+                .build()
+                .map_err(|e| anyhow!("could not create download client: {}", e))?,
+        )
+        .with(network_log.clone()),
+        None => reqwest_middleware::ClientBuilder::new(reqwest::Client::new()),
     };
+    let executable = download_executable(&client.build(), options.platform)
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "could not download the pixi-pack executable: {}\n\
+                Download {} or build pixi-pack for {} and pass it with --unpack-executable, \
+                e.g. --unpack-executable target/release/pixi-pack",
+                e,
+                executable_url(options.platform).unwrap_or_default(),
+                options.platform
+            )
+        })?;
+    if let Some(cached) = &cached {
+        // A failure to cache the executable does not affect the pack.
+        if let Err(e) = cache_executable(cached, &executable) {
+            tracing::warn!("Could not cache pixi-pack executable: {}", e);
+        }
+    }
+    Ok(executable)
+}
 
-    let executable_name = format!("pixi-pack-{}-{}", arch, os);
-    let extension = if platform.is_windows() { ".exe" } else { "" };
+/// Store a downloaded pixi-pack executable at `cached`, see [`cached_executable_path`].
+fn cache_executable(cached: &Path, executable: &[u8]) -> Result<()> {
+    let parent = cached.parent().expect("cached executable has a parent");
+    std::fs::create_dir_all(parent)?;
+    // Write to a temporary file first so that concurrent packs never read a partial executable.
+    let mut file = tempfile::NamedTempFile::new_in(parent)?;
+    std::io::Write::write_all(&mut file, executable)?;
+    file.persist(cached)?;
+    Ok(())
+}
 
-    let version = env!("CARGO_PKG_VERSION");
-    let url = format!(
-        "https://github.com/Quantco/pixi-pack/releases/download/v{}/{}{}",
-        version, executable_name, extension
-    );
+/// Download the pixi-pack executable of the current version for `platform` from GitHub releases.
+///
+/// This is the executable that is embedded into self-extracting executables.
+pub async fn download_pixi_pack_executable(platform: Platform) -> Result<Vec<u8>> {
+    download_executable(
+        &reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build(),
+        platform,
+    )
+    .await
+}
+
+async fn download_executable(client: &ClientWithMiddleware, platform: Platform) -> Result<Vec<u8>> {
+    let url = executable_url(platform)?;
 
-    eprintln!("📥 Downloading pixi-pack executable...");
-    let client = reqwest::Client::new();
+    status!("📥 Downloading pixi-pack executable...");
     let response = client.get(&url).send().await?;
     if !response.status().is_success() {
         return Err(anyhow!(
@@ -399,7 +3304,7 @@ async fn create_self_extracting_executable(
         .content_length()
         .ok_or_else(|| anyhow!("Failed to get content length"))?;
 
-    let bar = ProgressReporter::new(total_size);
+    let bar = ProgressReporter::new_bytes(total_size);
     bar.pb.set_message("Downloading");
 
     let mut executable_bytes = Vec::new();
@@ -413,51 +3318,166 @@ async fn create_self_extracting_executable(
 
     bar.pb.finish_with_message("Download complete");
 
-    eprintln!("✅ Pixi-pack executable downloaded successfully");
+    status!("✅ Pixi-pack executable downloaded successfully");
 
-    let mut final_executable = File::create(&executable_path)
-        .await
-        .map_err(|e| anyhow!("could not create final executable file: {}", e))?;
+    Ok(executable_bytes)
+}
 
-    final_executable.write_all(header.as_bytes()).await?;
-    final_executable.write_all(&line_ending).await?; // Add a newline after the header
+/// The built-in header of self-extracting executables for `platform`.
+///
+/// The header is a shell (or PowerShell / batch) script that extracts the pack and the embedded
+/// pixi-pack executable and runs `pixi-pack unpack`.
+pub fn default_executable_header(
+    platform: Platform,
+    windows_flavor: WindowsFlavor,
+) -> &'static str {
+    match (platform.is_windows(), windows_flavor) {
+        (true, WindowsFlavor::Bat) => include_str!("header.bat"),
+        (true, WindowsFlavor::Powershell) => include_str!("header.ps1"),
+        (false, _) => include_str!("header.sh"),
+    }
+}
 
-    // Encode the archive to base64
-    let archive_base64 = STANDARD.encode(&compressor);
-    final_executable
-        .write_all(archive_base64.as_bytes())
-        .await?;
+/// Write a self-extracting executable of the pack in `input_dir` to `writer`.
+///
+/// `header` is the script that extracts the pack, see [`default_executable_header`]. It is
+/// followed by the base64-encoded pack and `unpack_executable`, the pixi-pack executable that
+/// the header runs to install the environment (see [`download_pixi_pack_executable`]).
+/// All line breaks of the header are converted to `line_endings`; the base64-encoded payloads
+/// don't contain any. The writer is not flushed.
+#[allow(clippy::too_many_arguments)]
+pub async fn write_self_extracting_executable<W>(
+    writer: &mut W,
+    input_dir: &Path,
+    header: &str,
+    unpack_executable: &[u8],
+    platform: Platform,
+    windows_flavor: WindowsFlavor,
+    mtime: Option<u64>,
+    line_endings: LineEndings,
+) -> Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let line_ending = line_endings.as_str(platform);
+    tracing::info!(
+        "Using {} line endings",
+        if line_ending == "\r\n" { "crlf" } else { "lf" }
+    );
+    let header = normalize_line_endings(header, line_ending);
+    verify_line_endings(&header, line_ending)?;
+
+    let archive = write_archive(Builder::new(Vec::new()), input_dir, mtime).await?;
+
+    if platform.is_windows() && windows_flavor == WindowsFlavor::Bat {
+        return write_batch_executable(
+            writer,
+            &header,
+            &archive,
+            unpack_executable,
+            mtime.unwrap_or(0),
+            line_ending,
+        )
+        .await;
+    }
+
+    let line_ending = line_ending.as_bytes();
+
+    writer.write_all(header.as_bytes()).await?;
+    writer.write_all(line_ending).await?; // Add a newline after the header
+
+    // Encode the archive to base64, wrapped into lines so that the header scripts can decode it
+    // line by line instead of holding the whole payload in memory
+    write_base64_lines(writer, &archive, BASE64_LINE_LENGTH, line_ending).await?;
 
-    final_executable.write_all(&line_ending).await?;
     if platform.is_windows() {
-        final_executable.write_all(b"__END_ARCHIVE__").await?;
+        writer.write_all(b"__END_ARCHIVE__").await?;
     } else {
-        final_executable.write_all(b"@@END_ARCHIVE@@").await?;
+        writer.write_all(b"@@END_ARCHIVE@@").await?;
     }
-    final_executable.write_all(&line_ending).await?;
+    writer.write_all(line_ending).await?;
 
     // Encode the executable to base64
-    let executable_base64 = STANDARD.encode(&executable_bytes);
-    final_executable
-        .write_all(executable_base64.as_bytes())
-        .await?;
+    write_base64_lines(writer, unpack_executable, BASE64_LINE_LENGTH, line_ending).await?;
 
-    // Make the script executable
-    // This won't be executed when cross-packing due to Windows FS not supporting Unix permissions
-    #[cfg(not(target_os = "windows"))]
-    if !platform.is_windows() {
-        let mut perms = final_executable.metadata().await?.permissions();
-        perms.set_mode(0o755);
-        final_executable.set_permissions(perms).await?;
+    Ok(())
+}
+
+/// The length of the base64 lines in self-extracting executables, see [`write_base64_lines`].
+const BASE64_LINE_LENGTH: usize = 76;
+
+/// Write `data` as base64 wrapped into lines of `line_length` characters, each followed by
+/// `line_ending`.
+///
+/// `line_length` must be a multiple of 4 so that every line can be decoded on its own.
+async fn write_base64_lines<W>(
+    writer: &mut W,
+    data: &[u8],
+    line_length: usize,
+    line_ending: &[u8],
+) -> Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    for chunk in data.chunks(line_length / 4 * 3) {
+        writer.write_all(STANDARD.encode(chunk).as_bytes()).await?;
+        writer.write_all(line_ending).await?;
+    }
+    Ok(())
+}
+
+/// Write a self-extracting batch file for `cmd.exe`.
+///
+/// The packed environment and the pixi-pack executable are bundled in a tar archive that is
+/// appended to the header as a base64 block which can be decoded with `certutil -decode`.
+async fn write_batch_executable<W>(
+    writer: &mut W,
+    header: &str,
+    archive: &[u8],
+    executable: &[u8],
+    mtime: u64,
+    line_ending: &str,
+) -> Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let line_ending = line_ending.as_bytes();
+    let mut payload = Builder::new(Vec::new());
+    for (name, data, mode) in [
+        ("environment.tar", archive, 0o644),
+        ("pixi-pack.exe", executable, 0o755),
+    ] {
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        payload.append_data(&mut header, name, data).await?;
     }
+    let payload = payload.into_inner().await?;
+
+    writer.write_all(header.as_bytes()).await?;
+    writer.write_all(b"-----BEGIN CERTIFICATE-----").await?;
+    writer.write_all(line_ending).await?;
+    // certutil expects the base64 content to be wrapped into lines of 64 characters
+    write_base64_lines(writer, &payload, 64, line_ending).await?;
+    writer.write_all(b"-----END CERTIFICATE-----").await?;
+    writer.write_all(line_ending).await?;
 
     Ok(())
 }
 
-/// Create an `environment.yml` file from the given packages.
-async fn create_environment_file(
+/// Create an `environment.yml` file from the given packages in `destination`.
+///
+/// The file references the channel at `./channel` relative to `destination`, so it can be used
+/// with `conda env create` or `micromamba create` after extracting the pack.
+/// `extra_channels` are listed after the local channel. Unless they contain `defaults`, the
+/// `defaults` channel is excluded with `nodefaults`.
+pub async fn create_environment_file(
     destination: &Path,
     packages: impl IntoIterator<Item = &PackageRecord>,
+    extra_channels: &[String],
+    channel_priority: Option<ChannelPriority>,
 ) -> Result<()> {
     let environment_path = destination.join("environment.yml");
 
@@ -465,7 +3485,24 @@ async fn create_environment_file(
 
     environment.push_str("channels:\n");
     environment.push_str(&format!("  - ./{CHANNEL_DIRECTORY_NAME}\n",));
-    environment.push_str("  - nodefaults\n");
+    let mut channels: Vec<&str> = Vec::new();
+    for channel in extra_channels {
+        if channel != "nodefaults" && !channels.contains(&channel.as_str()) {
+            channels.push(channel);
+        }
+    }
+    if !channels.contains(&"defaults") {
+        channels.push("nodefaults");
+    }
+    for channel in channels {
+        environment.push_str(&format!("  - {}\n", channel));
+    }
+    if let Some(channel_priority) = channel_priority {
+        environment.push_str(&format!(
+            "channel_priority: {}\n",
+            channel_priority.as_str()
+        ));
+    }
     environment.push_str("dependencies:\n");
 
     for package in packages {
@@ -487,7 +3524,11 @@ async fn create_environment_file(
 }
 
 /// Create `repodata.json` files for the given packages.
-async fn create_repodata_files(
+///
+/// `packages` contains the file names of the packages together with their records.
+/// A `repodata.json` file is written to `<channel_dir>/<subdir>` for every subdir that
+/// appears in the records, the subdirectories need to exist already.
+pub async fn create_repodata_files(
     packages: impl Iterator<Item = &(String, PackageRecord)>,
     channel_dir: &Path,
 ) -> Result<()> {
@@ -530,3 +3571,978 @@ async fn create_repodata_files(
 
     Ok(())
 }
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[fixture]
+    fn package_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "b-1.0-0.conda",
+            "a-1.0-0.tar.bz2",
+            "README.md",
+            "c-1.0-py3-none-any.whl",
+        ] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        dir
+    }
+
+    #[rstest]
+    fn test_expand_injected_packages_directory(package_dir: tempfile::TempDir) {
+        let packages =
+            expand_injected_packages(&[package_dir.path().to_path_buf()], &mut Vec::new()).unwrap();
+        assert_eq!(
+            packages,
+            vec![
+                (
+                    package_dir.path().join("a-1.0-0.tar.bz2"),
+                    ArchiveType::TarBz2
+                ),
+                (package_dir.path().join("b-1.0-0.conda"), ArchiveType::Conda),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_expand_injected_packages_glob(package_dir: tempfile::TempDir) {
+        let packages =
+            expand_injected_packages(&[package_dir.path().join("*.conda")], &mut Vec::new())
+                .unwrap();
+        assert_eq!(
+            packages,
+            vec![(package_dir.path().join("b-1.0-0.conda"), ArchiveType::Conda)]
+        );
+    }
+
+    #[rstest]
+    #[case(Platform::Linux64, "source activate.sh")]
+    #[case(Platform::Win64, "activate.bat")]
+    fn test_render_instructions(#[case] platform: Platform, #[case] activation_command: &str) {
+        let template = "# {{ environment }} ({{platform}})\n\nRun `{{ activation_command }}` to activate `{{ env_name }}`. {{ unknown }}";
+        assert_eq!(
+            render_instructions(template, "prod", platform),
+            format!(
+                "# prod ({})\n\nRun `{}` to activate `env`. {{{{ unknown }}}}",
+                platform, activation_command
+            )
+        );
+    }
+
+    #[rstest]
+    #[case(Platform::Linux64, WindowsFlavor::Powershell, "@@END_HEADER@@")]
+    #[case(Platform::Win64, WindowsFlavor::Powershell, "__END_HEADER__")]
+    fn test_render_header(
+        #[case] platform: Platform,
+        #[case] windows_flavor: WindowsFlavor,
+        #[case] marker: &str,
+    ) {
+        let template = "echo 'ACME installer for {{ platform }}, pixi-pack {{pixi_pack_version}}'\n{{ end_header_marker }}";
+        assert_eq!(
+            render_header(template, platform, windows_flavor).unwrap(),
+            format!(
+                "echo 'ACME installer for {}, pixi-pack {}'\n{}",
+                platform, PIXI_PACK_VERSION, marker
+            )
+        );
+    }
+
+    #[rstest]
+    fn test_render_header_missing_marker() {
+        let template = "echo 'ACME installer'";
+        assert!(render_header(template, Platform::Linux64, WindowsFlavor::Powershell).is_err());
+        assert!(render_header(template, Platform::Win64, WindowsFlavor::Bat).is_ok());
+    }
+
+    #[rstest]
+    #[case(Platform::Linux64, WindowsFlavor::Powershell)]
+    #[case(Platform::Win64, WindowsFlavor::Powershell)]
+    fn test_default_header_is_valid_template(
+        #[case] platform: Platform,
+        #[case] windows_flavor: WindowsFlavor,
+    ) {
+        let header = default_executable_header(platform, windows_flavor);
+        assert_eq!(
+            render_header(header, platform, windows_flavor).unwrap(),
+            header
+        );
+    }
+
+    #[rstest]
+    fn test_expand_injected_packages_unsupported(package_dir: tempfile::TempDir) {
+        let mut warnings = Vec::new();
+        assert!(
+            expand_injected_packages(&[package_dir.path().join("README.md")], &mut warnings)
+                .is_err()
+        );
+        assert!(
+            expand_injected_packages(&[package_dir.path().join("*.whl")], &mut warnings).is_err()
+        );
+    }
+
+    #[rstest]
+    #[case(&[], None, "  - ./channel\n  - nodefaults\ndependencies:\n")]
+    #[case(
+        &["https://mirror.example.com/conda-forge", "nodefaults"],
+        Some(ChannelPriority::Strict),
+        "  - ./channel\n  - https://mirror.example.com/conda-forge\n  - nodefaults\nchannel_priority: strict\ndependencies:\n"
+    )]
+    #[case(
+        &["defaults", "defaults"],
+        Some(ChannelPriority::Flexible),
+        "  - ./channel\n  - defaults\nchannel_priority: flexible\ndependencies:\n"
+    )]
+    #[tokio::test]
+    async fn test_create_environment_file_channels(
+        #[case] extra_channels: &[&str],
+        #[case] channel_priority: Option<ChannelPriority>,
+        #[case] expected: &str,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let extra_channels: Vec<String> = extra_channels.iter().map(|c| c.to_string()).collect();
+        create_environment_file(dir.path(), [], &extra_channels, channel_priority)
+            .await
+            .unwrap();
+        let environment = std::fs::read_to_string(dir.path().join("environment.yml")).unwrap();
+        assert_eq!(environment, format!("channels:\n{}", expected));
+    }
+
+    #[rstest]
+    #[case(None, 0)]
+    #[case(Some(1700000000), 1700000000)]
+    #[tokio::test]
+    async fn test_write_archive_mtime(#[case] mtime: Option<u64>, #[case] expected: u64) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("channel")).unwrap();
+        std::fs::write(dir.path().join("channel").join("repodata.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("pixi-pack.json"), "{}").unwrap();
+
+        let archive = write_archive(Builder::new(Vec::new()), dir.path(), mtime)
+            .await
+            .unwrap();
+        let mut entries = tokio_tar::Archive::new(archive.as_slice())
+            .entries()
+            .unwrap();
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry.unwrap();
+            if mtime.is_some() {
+                assert_eq!(entry.header().mtime().unwrap(), expected);
+            }
+            paths.push(entry.path().unwrap().to_string_lossy().into_owned());
+        }
+        assert_eq!(
+            paths,
+            vec!["channel", "channel/repodata.json", "pixi-pack.json"]
+        );
+    }
+
+    #[rstest]
+    #[case(Platform::Linux64, "\n", "@@END_ARCHIVE@@")]
+    #[case(Platform::Win64, "\r\n", "__END_ARCHIVE__")]
+    #[tokio::test]
+    async fn test_write_self_extracting_executable(
+        #[case] platform: Platform,
+        #[case] line_ending: &str,
+        #[case] end_archive_marker: &str,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("pixi-pack.json"), "{}").unwrap();
+
+        let mut executable = Vec::new();
+        write_self_extracting_executable(
+            &mut executable,
+            dir.path(),
+            "my custom header",
+            b"unpack executable",
+            platform,
+            WindowsFlavor::Powershell,
+            None,
+            LineEndings::Auto,
+        )
+        .await
+        .unwrap();
+
+        let executable = String::from_utf8(executable).unwrap();
+        let lines = executable
+            .strip_suffix(line_ending)
+            .unwrap()
+            .split(line_ending)
+            .collect::<Vec<_>>();
+        assert_eq!(lines[0], "my custom header");
+        let marker = lines.iter().position(|l| *l == end_archive_marker).unwrap();
+        // The archive spans multiple lines that can be decoded independently
+        assert!(marker > 2);
+        assert!(lines[1..marker]
+            .iter()
+            .chain(&lines[marker + 1..])
+            .all(|line| line.len() <= BASE64_LINE_LENGTH));
+        let decode = |lines: &[&str]| -> Vec<u8> {
+            lines
+                .iter()
+                .flat_map(|line| STANDARD.decode(line).unwrap())
+                .collect()
+        };
+        assert_eq!(
+            decode(&lines[1..marker]),
+            write_archive(Builder::new(Vec::new()), dir.path(), None)
+                .await
+                .unwrap()
+        );
+        assert_eq!(decode(&lines[marker + 1..]), b"unpack executable");
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_write_binary_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("pixi-pack.json"), "{}").unwrap();
+
+        let executable =
+            write_binary_executable(Vec::new(), dir.path(), b"unpack executable", None)
+                .await
+                .unwrap();
+        let archive = write_archive(Builder::new(Vec::new()), dir.path(), None)
+            .await
+            .unwrap();
+        assert!(executable.starts_with(b"unpack executable"));
+        assert!(executable.ends_with(&BINARY_EXECUTABLE_MAGIC));
+
+        let executable_path = dir.path().join("environment");
+        std::fs::write(&executable_path, &executable).unwrap();
+        let range = crate::low_level::embedded_pack(&executable_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            &executable[range.start as usize..range.end as usize],
+            archive.as_slice()
+        );
+
+        // Plain files and self-extracting scripts have no embedded pack.
+        assert!(
+            crate::low_level::embedded_pack(&dir.path().join("pixi-pack.json"))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[rstest]
+    fn test_lockfile_platforms() {
+        let manifest_path = Path::new("examples/simple-python/pixi.toml");
+        let platforms = lockfile_platforms(manifest_path, "default").unwrap();
+        assert!(platforms.contains(&Platform::Linux64));
+        assert!(!platforms.contains(&Platform::WinArm64));
+        assert!(lockfile_platforms(manifest_path, "does-not-exist").is_err());
+
+        let environments = lockfile_environments(manifest_path).unwrap();
+        let default = environments.iter().find(|e| e.name == "default").unwrap();
+        assert_eq!(
+            default
+                .platforms
+                .iter()
+                .map(|(p, _)| *p)
+                .collect::<Vec<_>>(),
+            platforms
+        );
+        assert!(default.platforms.iter().all(|(_, packages)| *packages > 0));
+
+        let options = PackOptions::builder()
+            .manifest_path(manifest_path)
+            .platform(Platform::WinArm64)
+            .build();
+        let error = read_lockfile_packages(&options, &mut Vec::new()).unwrap_err();
+        assert!(
+            error.to_string().contains("linux-64, linux-aarch64"),
+            "{}",
+            error
+        );
+    }
+
+    #[rstest]
+    fn test_lockfile_of_nested_pyproject() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("pyproject.toml"),
+            "[project]\nname = \"workspace\"\n\n[tool.pixi.workspace]\nchannels = [\"conda-forge\"]\nplatforms = [\"linux-64\"]\n",
+        )
+        .unwrap();
+        std::fs::copy(
+            "examples/simple-python/pixi.lock",
+            dir.path().join("pixi.lock"),
+        )
+        .unwrap();
+        let member = dir.path().join("packages").join("member");
+        std::fs::create_dir_all(&member).unwrap();
+        std::fs::write(
+            member.join("pyproject.toml"),
+            "[project]\nname = \"member\"\n",
+        )
+        .unwrap();
+
+        for manifest_path in [
+            member.join("pyproject.toml"),
+            member.clone(),
+            dir.path().join("pixi.toml"),
+        ] {
+            let environments = lockfile_environments(&manifest_path).unwrap();
+            assert!(
+                environments.iter().any(|e| e.name == "default"),
+                "{}",
+                manifest_path.display()
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case(
+        "https://conda.anaconda.org/conda-forge/noarch/a-1-0.conda",
+        "https://conda.anaconda.org/conda-forge/noarch/a-1-0.conda"
+    )]
+    #[case("../channel/noarch/a-1-0.conda", "/project/channel/noarch/a-1-0.conda")]
+    #[case(
+        "./channel/noarch/a-1-0.conda",
+        "/project/sub/channel/noarch/a-1-0.conda"
+    )]
+    #[case("/channel/noarch/a-1-0.conda", "/channel/noarch/a-1-0.conda")]
+    #[case("file:///channel/noarch/a-1-0.conda", "/channel/noarch/a-1-0.conda")]
+    fn test_resolve_location(#[case] location: &str, #[case] expected: &str) {
+        let location = location.parse::<UrlOrPath>().unwrap();
+        let resolved = resolve_location(&location, Path::new("/project/sub"));
+        assert_eq!(resolved.as_str(), expected);
+    }
+
+    #[rstest]
+    #[case(Platform::Linux64, WindowsFlavor::Powershell, true)]
+    #[case(Platform::Win64, WindowsFlavor::Bat, true)]
+    #[case(Platform::Linux64, WindowsFlavor::Bat, false)]
+    #[case(Platform::LinuxPpc64le, WindowsFlavor::Powershell, false)]
+    fn test_validate_options(
+        #[case] platform: Platform,
+        #[case] windows_flavor: WindowsFlavor,
+        #[case] valid: bool,
+    ) {
+        let options = PackOptions::builder()
+            .platform(platform)
+            .windows_flavor(windows_flavor)
+            .output_format(OutputFormat::Executable)
+            .build();
+        assert_eq!(validate_options(&options).is_ok(), valid);
+        let options = PackOptions::builder().platform(platform).build();
+        assert!(validate_options(&options).is_ok());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_unpack_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_build = dir.path().join("pixi-pack");
+        std::fs::write(&local_build, b"local build").unwrap();
+        let options = PackOptions::builder()
+            .platform(Platform::Linux64)
+            .output_format(OutputFormat::Executable)
+            .unpack_executable(local_build.clone())
+            .build();
+        assert_eq!(unpack_executable(&options).await.unwrap(), b"local build");
+
+        // A previously downloaded executable is taken from the cache.
+        let cache_dir = dir.path().join("cache");
+        let cached = cached_executable_path(&cache_dir, Platform::Win64).unwrap();
+        assert!(cached.ends_with(format!(
+            "pixi-pack/v{}/pixi-pack-x86_64-pc-windows-msvc.exe",
+            env!("CARGO_PKG_VERSION")
+        )));
+        cache_executable(&cached, b"cached release").unwrap();
+        let options = PackOptions::builder()
+            .platform(Platform::Win64)
+            .output_format(OutputFormat::Executable)
+            .cache_dir(cache_dir)
+            .build();
+        assert_eq!(
+            unpack_executable(&options).await.unwrap(),
+            b"cached release"
+        );
+
+        let options = PackOptions::builder()
+            .unpack_executable(local_build)
+            .build();
+        assert!(validate_options(&options).is_err());
+    }
+
+    #[rstest]
+    fn test_extra_metadata() {
+        let options = PackOptions::builder()
+            .platform(Platform::Linux64)
+            .extra_metadata("git-sha", "abc123")
+            .extra_metadata("build-id", "1")
+            .extra_metadata("build-id", "2")
+            .build();
+        assert_eq!(options.metadata.platform, Platform::Linux64);
+        assert_eq!(
+            options.metadata.extra,
+            BTreeMap::from([
+                ("build-id".to_string(), "2".to_string()),
+                ("git-sha".to_string(), "abc123".to_string()),
+            ])
+        );
+    }
+
+    #[rstest]
+    fn test_executable_platforms() {
+        let platforms = executable_platforms();
+        assert!(platforms.contains(&Platform::OsxArm64));
+        assert!(!platforms.contains(&Platform::LinuxPpc64le));
+    }
+
+    #[rstest]
+    #[case("environment.tar", "environment-9f86d081884c.tar")]
+    #[case("packs/environment.tar.zst", "packs/environment-9f86d081884c.tar.zst")]
+    #[case("environment", "environment-9f86d081884c")]
+    #[case(".pack.tar", ".pack-9f86d081884c.tar")]
+    fn test_digest_file_name(#[case] output_file: &str, #[case] expected: &str) {
+        let digest = rattler_digest::compute_bytes_digest::<Sha256>("test");
+        assert_eq!(
+            digest_file_name(Path::new(output_file), &digest),
+            PathBuf::from(expected)
+        );
+    }
+
+    #[rstest]
+    #[case(Compression::None, 0)]
+    #[case(Compression::Zstd(Compression::DEFAULT_ZSTD_LEVEL), 0)]
+    #[case(Compression::Zstd(19), 0)]
+    #[case(Compression::Zstd(Compression::DEFAULT_ZSTD_LEVEL), 4)]
+    #[tokio::test]
+    async fn test_compressed_tarball(#[case] compression: Compression, #[case] threads: u32) {
+        let input_dir = tempfile::tempdir().unwrap();
+        std::fs::write(input_dir.path().join(PIXI_PACK_METADATA_PATH), "{}").unwrap();
+        std::fs::create_dir(input_dir.path().join(CHANNEL_DIRECTORY_NAME)).unwrap();
+        std::fs::write(
+            input_dir
+                .path()
+                .join(CHANNEL_DIRECTORY_NAME)
+                .join("a-1-0.conda"),
+            "a".repeat(10_000),
+        )
+        .unwrap();
+
+        let output_dir = tempfile::tempdir().unwrap();
+        let pack_file = output_dir
+            .path()
+            .join(OutputFormat::Tar.default_output_file(
+                Platform::Linux64,
+                WindowsFlavor::default(),
+                ExecutableFormat::default(),
+                compression,
+            ));
+        let digest = create_tarball(
+            input_dir.path(),
+            &pack_file,
+            None,
+            compression,
+            None,
+            threads,
+        )
+        .await
+        .unwrap();
+        let compressed = std::fs::read(&pack_file).unwrap();
+        assert_eq!(
+            compressed.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]),
+            compression != Compression::None
+        );
+        // The digest is computed while writing the pack.
+        assert_eq!(
+            digest,
+            rattler_digest::compute_bytes_digest::<Sha256>(&compressed)
+        );
+
+        let unpacked = tempfile::tempdir().unwrap();
+        crate::unarchive(&pack_file, unpacked.path()).await.unwrap();
+        assert_eq!(
+            DirectoryChecksums::new(unpacked.path()).unwrap(),
+            DirectoryChecksums::new(input_dir.path()).unwrap()
+        );
+
+        // Compressed packs are reproducible as well, regardless of the number of worker threads.
+        let threads = if threads > 0 { threads + 1 } else { 0 };
+        create_tarball(
+            input_dir.path(),
+            &pack_file,
+            None,
+            compression,
+            None,
+            threads,
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read(&pack_file).unwrap(), compressed);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_zstd_dictionary() {
+        let base_pack = tempfile::tempdir().unwrap();
+        for i in 0..200 {
+            std::fs::write(
+                base_pack.path().join(format!("{}.json", i)),
+                format!("{{\"name\": \"package-{}\", \"version\": \"1.{}.0\", \"build\": \"h{:x}_0\", \"depends\": [\"python >=3.9\", \"libzlib >=1.3.1,<2.0a0\"]}}", i, i % 7, i * 7919),
+            )
+            .unwrap();
+        }
+        let dictionary = train_zstd_dictionary(base_pack.path()).await.unwrap();
+
+        let output_dir = tempfile::tempdir().unwrap();
+        let pack_file = output_dir.path().join("pack.tar.zst");
+        create_tarball(
+            base_pack.path(),
+            &pack_file,
+            None,
+            Compression::Zstd(19),
+            Some(&dictionary),
+            0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            crate::unpack::zstd_dictionary_id(&pack_file).unwrap(),
+            zstd::zstd_safe::get_dict_id_from_dict(&dictionary).map(|id| id.get())
+        );
+        assert!(
+            crate::unarchive(&pack_file, output_dir.path().join("unpacked").as_path())
+                .await
+                .is_err()
+        );
+
+        let unpacked = tempfile::tempdir().unwrap();
+        crate::unpack::open_archive_with_dictionary(&pack_file, Some(&dictionary))
+            .await
+            .unwrap()
+            .unpack(unpacked.path())
+            .await
+            .unwrap();
+        assert_eq!(
+            DirectoryChecksums::new(unpacked.path()).unwrap(),
+            DirectoryChecksums::new(base_pack.path()).unwrap()
+        );
+    }
+
+    #[rstest]
+    #[case(&["linux-64", "osx-arm64"], &["linux-64", "osx-arm64"], None)]
+    #[case(&["linux-64"], &[], Some("the packages for linux-64 and osx-arm64 differ"))]
+    fn test_noarch_packages(
+        #[case] tzdata_platforms: &[&str],
+        #[case] pip_platforms: &[&str],
+        #[case] error: Option<&str>,
+    ) {
+        let tzdata = "https://conda.anaconda.org/conda-forge/noarch/tzdata-2024b-hc8b5060_0.conda";
+        let pip = "https://conda.anaconda.org/conda-forge/noarch/pip-24.3.1-pyh8b19718_0.conda";
+        let mut lockfile = String::from(
+            "version: 6\nenvironments:\n  default:\n    channels:\n    - url: https://conda.anaconda.org/conda-forge/\n    packages:\n",
+        );
+        for platform in ["linux-64", "osx-arm64"] {
+            lockfile.push_str(&format!("      {}:\n", platform));
+            if tzdata_platforms.contains(&platform) {
+                lockfile.push_str(&format!("      - conda: {}\n", tzdata));
+            }
+            if pip_platforms.contains(&platform) || !tzdata_platforms.contains(&platform) {
+                lockfile.push_str(&format!("      - conda: {}\n", pip));
+            }
+        }
+        lockfile.push_str(&format!(
+            "packages:\n- conda: {}\n  sha256: 4fde5c3008bf5d2db82f2b50204464314cc3c91c1d953652f7bd01d9e52aefdf\n  md5: 8ac3367aafb1cc0a068483c580af8015\n  size: 122354\n",
+            tzdata
+        ));
+        lockfile.push_str(&format!(
+            "- conda: {}\n  sha256: 499313e72e20225f84c2e9690bbaf5b952c8d7e0bf34b728278538f766b81628\n  md5: 5dd546fe99b44fda83963d15f84263b7\n  size: 1243168\n",
+            pip
+        ));
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("pixi.lock"), lockfile).unwrap();
+
+        let options = PackOptions::builder()
+            .manifest_path(dir.path().join("pixi.toml"))
+            .platform(Platform::NoArch)
+            .build();
+        match (read_lockfile_packages(&options, &mut Vec::new()), error) {
+            (Ok(packages), None) => {
+                let mut file_names: Vec<&str> =
+                    packages.iter().map(|p| p.file_name.as_str()).collect();
+                file_names.sort();
+                assert_eq!(
+                    file_names,
+                    vec![
+                        "pip-24.3.1-pyh8b19718_0.conda",
+                        "tzdata-2024b-hc8b5060_0.conda"
+                    ]
+                );
+            }
+            (Err(e), Some(error)) => assert!(e.to_string().ends_with(error), "{}", e),
+            (result, _) => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        let options = PackOptions::builder()
+            .manifest_path("examples/simple-python/pixi.toml")
+            .platform(Platform::NoArch)
+            .build();
+        let error = read_lockfile_packages(&options, &mut Vec::new()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("environment default cannot be packed for noarch"));
+    }
+
+    #[rstest]
+    fn test_missing_pip() {
+        let builder = PackOptions::builder()
+            .manifest_path("examples/pypi-packages/pixi.toml")
+            .platform(Platform::Linux64)
+            .ignore_pypi_errors(true);
+
+        let mut warnings = Vec::new();
+        read_lockfile_packages(&builder.clone().build(), &mut warnings).unwrap();
+        assert!(warnings.contains(&PackWarning::MissingPip("default".to_string())));
+
+        let error = read_lockfile_packages(&builder.require_pip(true).build(), &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "environment default contains PyPI packages but no pip, add pip to the dependencies"
+        );
+
+        // Environments without PyPI packages do not need pip.
+        let options = PackOptions::builder()
+            .manifest_path("examples/simple-python/pixi.toml")
+            .platform(Platform::Linux64)
+            .require_pip(true)
+            .build();
+        let mut warnings = Vec::new();
+        read_lockfile_packages(&options, &mut warnings).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_base_package_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let subdir = dir.path().join(CHANNEL_DIRECTORY_NAME).join("noarch");
+        std::fs::create_dir_all(&subdir).unwrap();
+        let sha256 = "4fde5c3008bf5d2db82f2b50204464314cc3c91c1d953652f7bd01d9e52aefdf";
+        let package = |name: &str, sha256: Option<&str>| {
+            let mut package = serde_json::json!({
+                "name": name,
+                "version": "1.0",
+                "build": "0",
+                "build_number": 0,
+                "depends": [],
+                "subdir": "noarch",
+            });
+            if let Some(sha256) = sha256 {
+                package["sha256"] = sha256.into();
+            }
+            package
+        };
+        let repodata = serde_json::json!({
+            "info": {"subdir": "noarch"},
+            "packages": {},
+            "packages.conda": {
+                "a-1.0-0.conda": package("a", Some(sha256)),
+                "b-1.0-0.conda": package("b", None),
+                "c-1.0-0.conda": package("c", Some(sha256)),
+            },
+        });
+        std::fs::write(subdir.join("repodata.json"), repodata.to_string()).unwrap();
+        // The base pack is a delta pack itself that does not contain `c`.
+        let metadata = PixiPackMetadata {
+            platform: Platform::Linux64,
+            base_packages: vec!["channel/noarch/c-1.0-0.conda".to_string()],
+            ..PixiPackMetadata::default()
+        };
+        std::fs::write(
+            dir.path().join(PIXI_PACK_METADATA_PATH),
+            serde_json::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+
+        let hashes = base_package_hashes(dir.path(), Platform::Linux64)
+            .await
+            .unwrap();
+        assert_eq!(
+            hashes,
+            HashMap::from([(
+                "channel/noarch/a-1.0-0.conda".to_string(),
+                rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(sha256).unwrap()
+            )])
+        );
+        let error = base_package_hashes(dir.path(), Platform::Win64)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the base pack is for linux-64, not for win-64"
+        );
+    }
+
+    #[rstest]
+    fn test_dry_run_summary() {
+        let options = PackOptions::builder()
+            .manifest_path("examples/simple-python/pixi.toml")
+            .platform(Platform::Linux64)
+            .build();
+        let mut packages = read_lockfile_packages(&options, &mut Vec::new()).unwrap();
+        packages.truncate(2);
+        packages[1].package_record.size = None;
+        let warnings = vec![PackWarning::IgnoredPypiPackage("requests".to_string())];
+        let summary = dry_run_summary(
+            Platform::Linux64,
+            &packages.iter().collect::<Vec<_>>(),
+            &[("my-pkg-1.0-0.conda".to_string(), 1024)],
+            &warnings,
+        );
+
+        let size = packages[0].package_record.size.unwrap();
+        let expected = [
+            "🔍 Dry run for linux-64, nothing was downloaded or written.".to_string(),
+            "Conda packages to download (2):".to_string(),
+            format!("  {}  {}", packages[0].file_name, HumanBytes(size)),
+            format!("  {}  unknown size", packages[1].file_name),
+            "Packages to inject (1):".to_string(),
+            "  my-pkg-1.0-0.conda  1.00 KiB".to_string(),
+            "PyPI packages that are not supported and would be skipped (1):".to_string(),
+            "  requests".to_string(),
+            format!(
+                "Download size: {} (plus 1 packages of unknown size)",
+                HumanBytes(size)
+            ),
+            format!("Estimated pack size: {}", HumanBytes(size + 1024)),
+            "".to_string(),
+        ]
+        .join("\n");
+        assert_eq!(summary, expected);
+    }
+
+    #[rstest]
+    fn test_download_order() {
+        let options = PackOptions::builder()
+            .manifest_path("examples/simple-python/pixi.toml")
+            .platform(Platform::Linux64)
+            .build();
+        let packages = read_lockfile_packages(&options, &mut Vec::new()).unwrap();
+        let lockfile: Vec<&CondaBinaryData> = packages.iter().collect();
+
+        let mut by_size = lockfile.clone();
+        DownloadOrder::Size.sort(&mut by_size);
+        assert!(by_size
+            .windows(2)
+            .all(|w| w[0].package_record.size >= w[1].package_record.size));
+
+        let mut by_name = lockfile.clone();
+        DownloadOrder::Name.sort(&mut by_name);
+        assert!(by_name.windows(2).all(|w| w[0].file_name <= w[1].file_name));
+
+        let mut unchanged = lockfile.clone();
+        DownloadOrder::Lockfile.sort(&mut unchanged);
+        assert_eq!(unchanged, lockfile);
+    }
+
+    #[rstest]
+    fn test_download_progress() {
+        let options = PackOptions::builder()
+            .manifest_path("examples/simple-python/pixi.toml")
+            .platform(Platform::Linux64)
+            .build();
+        let mut packages = read_lockfile_packages(&options, &mut Vec::new()).unwrap();
+        let total_size: u64 = packages
+            .iter()
+            .map(|p| p.package_record.size.unwrap())
+            .sum();
+        let unknown_size = packages[0].package_record.size.take().unwrap();
+
+        let progress = DownloadProgress::new(&packages.iter().collect::<Vec<_>>());
+        assert_eq!(progress.bar.pb.length(), Some(total_size - unknown_size));
+        // Packages without a size in the lockfile add their `Content-Length` once.
+        let on_progress = progress.on_progress(&packages[0]);
+        on_progress(FetchProgress::Size(unknown_size));
+        on_progress(FetchProgress::Size(unknown_size));
+        on_progress(FetchProgress::Bytes(unknown_size));
+        progress.package_done(&packages[0], PackageSource::Network);
+        assert_eq!(progress.bar.pb.length(), Some(total_size));
+        assert_eq!(progress.bar.pb.position(), unknown_size);
+        let on_progress = progress.on_progress(&packages[1]);
+        on_progress(FetchProgress::Size(10));
+        on_progress(FetchProgress::Bytes(10));
+        assert_eq!(progress.bar.pb.length(), Some(total_size));
+        assert_eq!(progress.bar.pb.position(), unknown_size + 10);
+
+        // Without any sizes, packages are counted instead.
+        for package in &mut packages {
+            package.package_record.size = None;
+        }
+        let progress = DownloadProgress::new(&packages.iter().collect::<Vec<_>>());
+        progress.on_progress(&packages[0])(FetchProgress::Bytes(10));
+        progress.package_done(&packages[0], PackageSource::Cache);
+        assert_eq!(progress.bar.pb.length(), Some(packages.len() as u64));
+        assert_eq!(progress.bar.pb.position(), 1);
+    }
+
+    #[rstest]
+    fn test_system_requirements() {
+        let package = |name: &str, depends: &[&str]| {
+            let mut record = PackageRecord::new(
+                name.parse().unwrap(),
+                "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+                "0".to_string(),
+            );
+            record.depends = depends.iter().map(|d| d.to_string()).collect();
+            record
+        };
+        let packages = [
+            package(
+                "pytorch",
+                &["__cuda >=12.2", "__glibc >=2.17,<3.0.a0", "python"],
+            ),
+            package("cudnn", &["__cuda >=12.2"]),
+            package("nccl", &["__cuda"]),
+            package("libzlib", &["__glibc >=2.17,<3.0.a0", "__unix"]),
+        ];
+        assert_eq!(
+            system_requirements(&packages),
+            vec![
+                SystemRequirement {
+                    name: "__cuda".into(),
+                    spec: "".into(),
+                    packages: vec!["nccl".into()],
+                },
+                SystemRequirement {
+                    name: "__cuda".into(),
+                    spec: ">=12.2".into(),
+                    packages: vec!["cudnn".into(), "pytorch".into()],
+                },
+                SystemRequirement {
+                    name: "__glibc".into(),
+                    spec: ">=2.17,<3.0.a0".into(),
+                    packages: vec!["libzlib".into(), "pytorch".into()],
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_channel_files() {
+        let a = rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(
+            "0000000000000000000000000000000000000000000000000000000000000000",
+        );
+        let b = rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(
+            "1111111111111111111111111111111111111111111111111111111111111111",
+        );
+        let mut files = ChannelFiles::default();
+        assert!(files.insert("noarch", "a-1-0.conda", a).unwrap());
+        assert!(files.insert("linux-64", "a-1-0.conda", b).unwrap());
+        assert!(files.insert("noarch", "b-1-0.conda", None).unwrap());
+        assert!(!files.insert("noarch", "a-1-0.conda", a).unwrap());
+        assert!(files.insert("noarch", "a-1-0.conda", b).is_err());
+        assert!(files.insert("noarch", "a-1-0.conda", None).is_err());
+        assert!(files.insert("noarch", "b-1-0.conda", None).is_err());
+    }
+
+    #[rstest]
+    #[case("a\nb\r\nc\n", "\r\n", "a\r\nb\r\nc\r\n")]
+    #[case("a\nb\r\nc\n", "\n", "a\nb\nc\n")]
+    fn test_normalize_line_endings(
+        #[case] text: &str,
+        #[case] line_ending: &str,
+        #[case] expected: &str,
+    ) {
+        let normalized = normalize_line_endings(text, line_ending);
+        assert_eq!(normalized, expected);
+        assert!(verify_line_endings(&normalized, line_ending).is_ok());
+    }
+
+    #[rstest]
+    #[case("a\nb\r\n", "\r\n")]
+    #[case("a\nb\r\n", "\n")]
+    #[case("a\rb\n", "\n")]
+    fn test_verify_line_endings_mixed(#[case] text: &str, #[case] line_ending: &str) {
+        assert!(verify_line_endings(text, line_ending).is_err());
+    }
+
+    #[rstest]
+    #[case(Platform::Win64, WindowsFlavor::Powershell, LineEndings::Auto, "\r\n")]
+    #[case(Platform::Win64, WindowsFlavor::Bat, LineEndings::Auto, "\r\n")]
+    #[case(Platform::Linux64, WindowsFlavor::Powershell, LineEndings::Auto, "\n")]
+    #[case(
+        Platform::Linux64,
+        WindowsFlavor::Powershell,
+        LineEndings::Crlf,
+        "\r\n"
+    )]
+    #[tokio::test]
+    async fn test_self_extracting_executable_line_endings(
+        #[case] platform: Platform,
+        #[case] windows_flavor: WindowsFlavor,
+        #[case] line_endings: LineEndings,
+        #[case] line_ending: &str,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("pixi-pack.json"), "{}").unwrap();
+        // A header with unix line endings, e.g., from a checkout without .gitattributes
+        let header = default_executable_header(platform, windows_flavor).replace("\r\n", "\n");
+
+        let mut executable = Vec::new();
+        write_self_extracting_executable(
+            &mut executable,
+            dir.path(),
+            &header,
+            b"unpack executable",
+            platform,
+            windows_flavor,
+            None,
+            line_endings,
+        )
+        .await
+        .unwrap();
+        verify_line_endings(&String::from_utf8(executable).unwrap(), line_ending).unwrap();
+    }
+
+    #[rstest]
+    #[case(HttpOptions::default())]
+    #[case(HttpOptions {
+        pool_max_idle_per_host: 0,
+        pool_idle_timeout: None,
+        http2: false,
+        tcp_keepalive: Some(Duration::from_secs(30)),
+        retry_policy: RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        },
+    })]
+    fn test_reqwest_client(#[case] http_options: HttpOptions) {
+        assert!(reqwest_client(None, &http_options).is_ok());
+    }
+
+    #[rstest]
+    fn test_cache_statistics() {
+        let statistics = CacheStatistics::default();
+        statistics.record(PackageSource::Cache);
+        statistics.record(PackageSource::Network);
+        statistics.record(PackageSource::Cache);
+        assert_eq!(statistics.hits(), 2);
+        assert_eq!(statistics.misses(), 1);
+        assert_eq!(statistics.to_string(), "2 cached, 1 downloaded");
+    }
+
+    #[rstest]
+    fn test_expand_injected_packages_no_match(package_dir: tempfile::TempDir) {
+        let pattern = package_dir.path().join("*.nothing");
+        let mut warnings = Vec::new();
+        let packages =
+            expand_injected_packages(std::slice::from_ref(&pattern), &mut warnings).unwrap();
+        assert!(packages.is_empty());
+        assert_eq!(warnings, vec![PackWarning::NoPackagesToInject(pattern)]);
+    }
+}
@@ -0,0 +1,288 @@
+use std::{path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use walkdir::WalkDir;
+
+/// The owner and (optionally) the group to assign to unpacked files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ownership {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl FromStr for Ownership {
+    type Err = anyhow::Error;
+
+    /// Parse `user[:group]` or `:group` where user and group can be names or numeric ids.
+    fn from_str(s: &str) -> Result<Self> {
+        let (user, group) = match s.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (s, None),
+        };
+        if user.is_empty() && matches!(group, None | Some("")) {
+            anyhow::bail!("expected `user[:group]` or `:group`, got `{}`", s);
+        }
+        Ok(Self {
+            uid: Some(user)
+                .filter(|user| !user.is_empty())
+                .map(resolve_user)
+                .transpose()?,
+            gid: group.map(resolve_group).transpose()?,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn resolve_user(user: &str) -> Result<u32> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    nix::unistd::User::from_name(user)?
+        .map(|u| u.uid.as_raw())
+        .ok_or(anyhow!("unknown user: {}", user))
+}
+
+#[cfg(unix)]
+fn resolve_group(group: &str) -> Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    nix::unistd::Group::from_name(group)?
+        .map(|g| g.gid.as_raw())
+        .ok_or(anyhow!("unknown group: {}", group))
+}
+
+#[cfg(not(unix))]
+fn resolve_user(_user: &str) -> Result<u32> {
+    anyhow::bail!("changing the ownership of files is only supported on unix")
+}
+
+#[cfg(not(unix))]
+fn resolve_group(_group: &str) -> Result<u32> {
+    anyhow::bail!("changing the ownership of files is only supported on unix")
+}
+
+/// Ownership and permissions to apply to an unpacked environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PermissionPolicy {
+    /// The owner of all files and directories.
+    pub owner: Option<Ownership>,
+    /// The mode of regular files.
+    /// Files that are executable by their owner keep execute permissions for every class
+    /// (user, group, other) that is granted read access by this mode.
+    pub file_mode: Option<u32>,
+    /// The mode of directories.
+    pub dir_mode: Option<u32>,
+    /// Make everything readable (and directories and executables executable) by the group,
+    /// regardless of the umask. Applied on top of `file_mode` and `dir_mode`.
+    pub group_shared: bool,
+    /// Set the setgid bit on directories so that files created later inherit their group.
+    pub setgid_dirs: bool,
+}
+
+impl PermissionPolicy {
+    /// Whether the policy leaves ownership and permissions untouched.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// The mode of a regular file after applying `policy`, keeping it executable if it was before.
+fn file_mode(current_mode: u32, policy: &PermissionPolicy) -> u32 {
+    let executable = current_mode & 0o100 != 0;
+    let mut mode = match policy.file_mode {
+        // Add an execute bit for every read bit.
+        Some(mode) if executable => mode | ((mode & 0o444) >> 2),
+        Some(mode) => mode,
+        None => current_mode & 0o7777,
+    };
+    if policy.group_shared {
+        mode |= if executable { 0o050 } else { 0o040 };
+    }
+    mode
+}
+
+/// The mode of a directory after applying `policy`.
+fn dir_mode(current_mode: u32, policy: &PermissionPolicy) -> u32 {
+    let mut mode = policy.dir_mode.unwrap_or(current_mode & 0o7777);
+    if policy.group_shared {
+        mode |= 0o050;
+    }
+    if policy.setgid_dirs {
+        mode |= 0o2000;
+    }
+    mode
+}
+
+/// Apply a permission policy to `path` and (if it is a directory) everything inside it.
+/// Symbolic links are not followed.
+#[cfg(unix)]
+pub(crate) fn apply_permission_policy(path: &Path, policy: &PermissionPolicy) -> Result<()> {
+    use nix::{
+        fcntl::AtFlags,
+        unistd::{fchownat, Gid, Uid},
+    };
+    use std::os::unix::fs::PermissionsExt;
+
+    if policy.is_empty() {
+        return Ok(());
+    }
+
+    for entry in WalkDir::new(path) {
+        let entry = entry.map_err(|e| anyhow!("could not walk {}: {}", path.display(), e))?;
+
+        // Change the owner first as this may clear the setgid bit.
+        if let Some(owner) = policy.owner {
+            fchownat(
+                None,
+                entry.path(),
+                owner.uid.map(Uid::from_raw),
+                owner.gid.map(Gid::from_raw),
+                AtFlags::AT_SYMLINK_NOFOLLOW,
+            )
+            .map_err(|e| {
+                anyhow!(
+                    "could not change owner of {}: {}",
+                    entry.path().display(),
+                    e
+                )
+            })?;
+        }
+
+        let metadata = entry
+            .metadata()
+            .map_err(|e| anyhow!("could not read metadata: {}", e))?;
+        let current_mode = metadata.permissions().mode();
+        let mode = if metadata.is_dir() {
+            dir_mode(current_mode, policy)
+        } else if metadata.is_file() {
+            file_mode(current_mode, policy)
+        } else {
+            continue;
+        };
+        if mode != current_mode & 0o7777 {
+            std::fs::set_permissions(entry.path(), std::fs::Permissions::from_mode(mode)).map_err(
+                |e| {
+                    anyhow!(
+                        "could not set permissions of {}: {}",
+                        entry.path().display(),
+                        e
+                    )
+                },
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn apply_permission_policy(_path: &Path, policy: &PermissionPolicy) -> Result<()> {
+    if policy.is_empty() {
+        return Ok(());
+    }
+    anyhow::bail!("changing ownership and permissions is only supported on unix")
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case(0o644, 0o640, 0o640)]
+    #[case(0o755, 0o640, 0o750)]
+    #[case(0o700, 0o644, 0o755)]
+    #[case(0o744, 0o600, 0o700)]
+    fn test_file_mode(#[case] current: u32, #[case] requested: u32, #[case] expected: u32) {
+        let policy = PermissionPolicy {
+            file_mode: Some(requested),
+            ..Default::default()
+        };
+        assert_eq!(file_mode(current, &policy), expected);
+    }
+
+    #[rstest]
+    #[case(0o600, None, 0o640)]
+    #[case(0o700, None, 0o750)]
+    #[case(0o700, Some(0o600), 0o750)]
+    fn test_file_mode_group_shared(
+        #[case] current: u32,
+        #[case] requested: Option<u32>,
+        #[case] expected: u32,
+    ) {
+        let policy = PermissionPolicy {
+            file_mode: requested,
+            group_shared: true,
+            ..Default::default()
+        };
+        assert_eq!(file_mode(current, &policy), expected);
+    }
+
+    #[rstest]
+    #[case(0o40700, None, false, 0o750)]
+    #[case(0o40700, None, true, 0o2750)]
+    #[case(0o40755, Some(0o700), true, 0o2750)]
+    fn test_dir_mode_group_shared(
+        #[case] current: u32,
+        #[case] requested: Option<u32>,
+        #[case] setgid_dirs: bool,
+        #[case] expected: u32,
+    ) {
+        let policy = PermissionPolicy {
+            dir_mode: requested,
+            group_shared: true,
+            setgid_dirs,
+            ..Default::default()
+        };
+        assert_eq!(dir_mode(current, &policy), expected);
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case("1000", Ownership { uid: Some(1000), gid: None })]
+    #[case("1000:100", Ownership { uid: Some(1000), gid: Some(100) })]
+    #[case("root:0", Ownership { uid: Some(0), gid: Some(0) })]
+    #[case(":100", Ownership { uid: None, gid: Some(100) })]
+    fn test_parse_ownership(#[case] input: &str, #[case] expected: Ownership) {
+        assert_eq!(input.parse::<Ownership>().unwrap(), expected);
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn test_parse_ownership_unknown_user() {
+        assert!("this-user-does-not-exist".parse::<Ownership>().is_err());
+        assert!(":".parse::<Ownership>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn test_apply_file_and_dir_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("bin");
+        std::fs::create_dir(&bin).unwrap();
+        std::fs::write(bin.join("python"), "").unwrap();
+        std::fs::set_permissions(bin.join("python"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        std::fs::write(dir.path().join("README"), "").unwrap();
+
+        let policy = PermissionPolicy {
+            file_mode: Some(0o640),
+            dir_mode: Some(0o750),
+            ..Default::default()
+        };
+        apply_permission_policy(dir.path(), &policy).unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(dir.path()), 0o750);
+        assert_eq!(mode(&bin), 0o750);
+        assert_eq!(mode(&bin.join("python")), 0o750);
+        assert_eq!(mode(&dir.path().join("README")), 0o640);
+    }
+}
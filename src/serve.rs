@@ -0,0 +1,372 @@
+use std::{
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use rattler_shell::shell::ShellEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    signal::unix::{signal, SignalKind},
+};
+
+use crate::{unpack, util::CurrentProgress, UnpackOptions};
+
+/// The state of an unpack job started via the socket API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+enum JobState {
+    Running,
+    Finished,
+    Failed { error: String },
+}
+
+/// The progress of the current step of a running job, e.g. extracting packages.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct JobProgress {
+    message: String,
+    position: u64,
+    length: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Job {
+    job: usize,
+    pack_file: PathBuf,
+    output_directory: PathBuf,
+    #[serde(flatten)]
+    state: JobState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<JobProgress>,
+    #[serde(skip)]
+    current_progress: CurrentProgress,
+}
+
+impl Job {
+    /// The job with the progress of its current step if it is running.
+    fn with_progress(&self) -> Self {
+        let mut job = self.clone();
+        if job.state == JobState::Running {
+            job.progress = self
+                .current_progress
+                .get()
+                .map(|(message, position, length)| JobProgress {
+                    message,
+                    position,
+                    length,
+                });
+        }
+        job
+    }
+}
+
+type Jobs = Arc<Mutex<Vec<Job>>>;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct UnpackParams {
+    pack_file: PathBuf,
+    output_directory: PathBuf,
+    env_name: Option<String>,
+    shell: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StatusParams {
+    job: usize,
+}
+
+/// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const UNKNOWN_JOB: i64 = -32000;
+
+/// The permissions of the socket: only the user running the server may connect.
+const SOCKET_MODE: u32 = 0o600;
+
+/// Removes the socket when the server stops.
+struct SocketGuard<'a>(&'a Path);
+
+impl Drop for SocketGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(self.0) {
+            tracing::warn!("could not remove socket {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Serve the unpack API on a unix socket until the process is interrupted or terminated.
+///
+/// The socket is only accessible to the user running the server. A socket that is left over
+/// from a previous server is replaced, and the socket is removed when the server stops.
+///
+/// Clients send one [JSON-RPC 2.0](https://www.jsonrpc.org/specification) request per line and
+/// receive one response per line. Supported methods:
+/// - `unpack` with `pack-file`, `output-directory` and optionally `env-name` and `shell`:
+///   starts unpacking in the background and returns the id of the job.
+/// - `status` with `job`: returns the state of a job (`running`, `finished` or `failed`) and,
+///   while it is running, the `progress` of its current step with `message`, `position` and
+///   `length`.
+/// - `list`: returns the state of all jobs.
+pub async fn serve(socket: &Path) -> Result<()> {
+    remove_stale_socket(socket).await?;
+    let listener = bind(socket)?;
+    let _guard = SocketGuard(socket);
+    tracing::info!("Listening on {}", socket.display());
+    status!("🛰️  Listening on {}", socket.display());
+
+    let mut terminate = signal(SignalKind::terminate())
+        .map_err(|e| anyhow!("could not listen for SIGTERM: {}", e))?;
+    let jobs = Jobs::default();
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => accepted?.0,
+            _ = terminate.recv() => {
+                tracing::info!("Received SIGTERM, stopping server");
+                return Ok(());
+            }
+        };
+        let jobs = jobs.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, jobs).await {
+                tracing::warn!("connection closed with error: {}", e);
+            }
+        });
+    }
+}
+
+/// Remove `socket` if it is left over from a server that is no longer running.
+async fn remove_stale_socket(socket: &Path) -> Result<()> {
+    let Ok(metadata) = std::fs::symlink_metadata(socket) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        anyhow::bail!("{} already exists and is not a socket", socket.display());
+    }
+    if UnixStream::connect(socket).await.is_ok() {
+        anyhow::bail!(
+            "another server is already listening on {}",
+            socket.display()
+        );
+    }
+    tracing::info!("Removing stale socket {}", socket.display());
+    std::fs::remove_file(socket)
+        .map_err(|e| anyhow!("could not remove stale socket {}: {}", socket.display(), e))
+}
+
+/// Bind to `socket` with [`SOCKET_MODE`].
+///
+/// The socket is created in a private temporary directory and moved into place once its
+/// permissions are set, so it is never accessible with the permissions of the umask.
+fn bind(socket: &Path) -> Result<UnixListener> {
+    let parent = match socket.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let tmp_dir = tempfile::Builder::new()
+        .prefix(".pixi-pack-socket")
+        .tempdir_in(parent)
+        .map_err(|e| anyhow!("could not create temporary directory: {}", e))?;
+    let tmp_socket = tmp_dir.path().join("socket");
+    let listener = UnixListener::bind(&tmp_socket)
+        .map_err(|e| anyhow!("could not bind to {}: {}", socket.display(), e))?;
+    std::fs::set_permissions(&tmp_socket, std::fs::Permissions::from_mode(SOCKET_MODE))
+        .map_err(|e| anyhow!("could not set permissions of {}: {}", socket.display(), e))?;
+    std::fs::rename(&tmp_socket, socket)
+        .map_err(|e| anyhow!("could not move socket to {}: {}", socket.display(), e))?;
+    Ok(listener)
+}
+
+async fn handle_connection(stream: UnixStream, jobs: Jobs) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut response = handle_request(&line, &jobs).to_string();
+        response.push('\n');
+        writer.write_all(response.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Handle a single JSON-RPC request and return the response.
+fn handle_request(line: &str, jobs: &Jobs) -> Value {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return error_response(Value::Null, PARSE_ERROR, e.to_string()),
+    };
+
+    let result = match request.method.as_str() {
+        "unpack" => serde_json::from_value(request.params)
+            .map_err(|e| (INVALID_PARAMS, e.to_string()))
+            .and_then(|params| start_unpack(params, jobs))
+            .map(|job| json!({ "job": job })),
+        "status" => serde_json::from_value::<StatusParams>(request.params)
+            .map_err(|e| (INVALID_PARAMS, e.to_string()))
+            .and_then(|params| {
+                jobs.lock()
+                    .unwrap()
+                    .get(params.job)
+                    .map(|job| json!(job.with_progress()))
+                    .ok_or((UNKNOWN_JOB, format!("unknown job: {}", params.job)))
+            }),
+        "list" => Ok(json!(jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(Job::with_progress)
+            .collect::<Vec<_>>())),
+        method => Err((METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+    };
+
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+        Err((code, message)) => error_response(request.id, code, message),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Start unpacking in the background and return the id of the job.
+fn start_unpack(params: UnpackParams, jobs: &Jobs) -> Result<usize, (i64, String)> {
+    let shell = params
+        .shell
+        .map(|shell| shell.parse::<ShellEnum>())
+        .transpose()
+        .map_err(|_| (INVALID_PARAMS, "unknown shell".to_string()))?;
+    let mut builder = UnpackOptions::builder()
+        .pack_file(&params.pack_file)
+        .output_directory(&params.output_directory)
+        .shell(shell);
+    if let Some(env_name) = params.env_name {
+        builder = builder.env_name(env_name);
+    }
+    let options = builder.build();
+
+    let current_progress = CurrentProgress::default();
+    let job = {
+        let mut jobs = jobs.lock().unwrap();
+        let job = jobs.len();
+        jobs.push(Job {
+            job,
+            pack_file: params.pack_file,
+            output_directory: params.output_directory,
+            state: JobState::Running,
+            progress: None,
+            current_progress: current_progress.clone(),
+        });
+        job
+    };
+    tracing::info!("Starting job {}: {:?}", job, options);
+
+    let jobs = jobs.clone();
+    tokio::spawn(async move {
+        let state = match current_progress.track(unpack(options)).await {
+            Ok(_) => JobState::Finished,
+            Err(e) => JobState::Failed {
+                error: e.to_string(),
+            },
+        };
+        tracing::info!("Job {} finished: {:?}", job, state);
+        jobs.lock().unwrap()[job].state = state;
+    });
+    Ok(job)
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("not json", PARSE_ERROR)]
+    #[case(r#"{"id": 1, "method": "install"}"#, METHOD_NOT_FOUND)]
+    #[case(r#"{"id": 1, "method": "status", "params": {"job": 0}}"#, UNKNOWN_JOB)]
+    #[case(r#"{"id": 1, "method": "unpack", "params": {}}"#, INVALID_PARAMS)]
+    fn test_invalid_requests(#[case] request: &str, #[case] code: i64) {
+        let response = handle_request(request, &Jobs::default());
+        assert_eq!(response["error"]["code"], code);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_serve_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("pixi-pack.sock");
+        std::fs::write(&socket, "").unwrap();
+        assert!(serve(&socket).await.is_err());
+        std::fs::remove_file(&socket).unwrap();
+
+        // A socket without a server is replaced.
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        let server = tokio::spawn({
+            let socket = socket.clone();
+            async move { serve(&socket).await }
+        });
+        while UnixStream::connect(&socket).await.is_err() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, SOCKET_MODE);
+        assert!(serve(&socket).await.is_err());
+
+        server.abort();
+        assert!(server.await.unwrap_err().is_cancelled());
+        assert!(!socket.exists());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_unpack_job() {
+        let jobs = Jobs::default();
+        let dir = tempfile::tempdir().unwrap();
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": "a",
+            "method": "unpack",
+            "params": {
+                "pack-file": dir.path().join("does-not-exist.tar"),
+                "output-directory": dir.path(),
+            },
+        });
+        let response = handle_request(&request.to_string(), &jobs);
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["result"]["job"], 0);
+
+        let status = r#"{"id": 2, "method": "status", "params": {"job": 0}}"#;
+        let mut response = handle_request(status, &jobs);
+        while response["result"]["state"] == "running" {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            response = handle_request(status, &jobs);
+        }
+        assert_eq!(response["result"]["state"], "failed");
+        assert!(response["result"]["error"].is_string());
+
+        let response = handle_request(r#"{"id": 3, "method": "list"}"#, &jobs);
+        assert_eq!(response["result"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    fmt,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2::{digest::consts::U32, Blake2b, Blake2b512, Digest};
+use ring::signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519};
+
+/// The file extension of detached signatures, appended to the path of the signed pack.
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+const SIGNATURE_ALGORITHM: &[u8; 2] = b"Ed";
+const PREHASHED_SIGNATURE_ALGORITHM: &[u8; 2] = b"ED";
+const UNTRUSTED_COMMENT: &str = "untrusted comment: ";
+const TRUSTED_COMMENT: &str = "trusted comment: ";
+
+/// The path of the detached signature of `pack_file`, e.g. `environment.tar.minisig`.
+pub fn signature_path(pack_file: &Path) -> PathBuf {
+    let mut path = pack_file.as_os_str().to_owned();
+    path.push(".");
+    path.push(SIGNATURE_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// A [minisign](https://jedisct1.github.io/minisign/) secret key to sign packs with.
+///
+/// Only unencrypted keys are supported since packs are usually signed non-interactively in CI,
+/// create them with `minisign -G -W`.
+pub struct SigningKey {
+    key_id: [u8; 8],
+    key_pair: Ed25519KeyPair,
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("key_id", &key_id_hex(&self.key_id))
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigningKey {
+    /// Read a minisign secret key file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("could not read secret key {}: {}", path.display(), e))?;
+        contents
+            .parse()
+            .map_err(|e| anyhow!("invalid secret key {}: {}", path.display(), e))
+    }
+
+    /// The ID of the key, as printed by minisign.
+    pub fn key_id(&self) -> String {
+        key_id_hex(&self.key_id)
+    }
+
+    /// Create a detached signature of the file at `path` in the minisign format.
+    /// `trusted_comment` is signed as well and shown when verifying the signature.
+    pub fn sign_file(&self, path: &Path, trusted_comment: &str) -> Result<String> {
+        if trusted_comment.contains('\n') {
+            anyhow::bail!("the trusted comment must be a single line");
+        }
+        let signature = self.key_pair.sign(&blake2b512_file(path)?);
+        let mut signature_block = Vec::with_capacity(74);
+        signature_block.extend_from_slice(PREHASHED_SIGNATURE_ALGORITHM);
+        signature_block.extend_from_slice(&self.key_id);
+        signature_block.extend_from_slice(signature.as_ref());
+        let global_signature = self
+            .key_pair
+            .sign(&[signature.as_ref(), trusted_comment.as_bytes()].concat());
+        Ok(format!(
+            "{}signature from pixi-pack secret key {}\n{}\n{}{}\n{}\n",
+            UNTRUSTED_COMMENT,
+            self.key_id(),
+            STANDARD.encode(signature_block),
+            TRUSTED_COMMENT,
+            trusted_comment,
+            STANDARD.encode(global_signature)
+        ))
+    }
+}
+
+impl std::str::FromStr for SigningKey {
+    type Err = anyhow::Error;
+
+    fn from_str(contents: &str) -> Result<Self> {
+        let key = decode_payload(contents)?;
+        // signature algorithm (2) | kdf algorithm (2) | checksum algorithm (2) | kdf salt (32) |
+        // kdf opslimit (8) | kdf memlimit (8) | key id (8) | secret key (64) | checksum (32)
+        if key.len() != 158 || &key[..2] != SIGNATURE_ALGORITHM || &key[4..6] != b"B2" {
+            anyhow::bail!("not a minisign secret key");
+        }
+        if key[2..4] != [0, 0] {
+            anyhow::bail!(
+                "encrypted secret keys are not supported, create the key with `minisign -G -W`"
+            );
+        }
+        let (key_id, secret_key, checksum) = (&key[54..62], &key[62..126], &key[126..]);
+        let expected_checksum = Blake2b::<U32>::new()
+            .chain_update(SIGNATURE_ALGORITHM)
+            .chain_update(key_id)
+            .chain_update(secret_key)
+            .finalize();
+        if checksum != expected_checksum.as_slice() {
+            anyhow::bail!("checksum mismatch");
+        }
+        let key_pair =
+            Ed25519KeyPair::from_seed_and_public_key(&secret_key[..32], &secret_key[32..])
+                .map_err(|e| anyhow!("{}", e))?;
+        Ok(Self {
+            key_id: key_id.try_into()?,
+            key_pair,
+        })
+    }
+}
+
+/// A minisign public key to verify the signatures of packs with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyingKey {
+    key_id: [u8; 8],
+    public_key: [u8; 32],
+}
+
+impl VerifyingKey {
+    /// Read a minisign public key file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("could not read public key {}: {}", path.display(), e))?;
+        contents
+            .parse()
+            .map_err(|e| anyhow!("invalid public key {}: {}", path.display(), e))
+    }
+
+    /// The ID of the key, as printed by minisign.
+    pub fn key_id(&self) -> String {
+        key_id_hex(&self.key_id)
+    }
+
+    /// Verify a detached minisign signature of the file at `path`.
+    /// Returns the trusted comment of the signature.
+    pub fn verify_file(&self, path: &Path, signature: &str) -> Result<String> {
+        let mut lines = signature.lines();
+        let (Some(_), Some(signature_block), Some(trusted_comment), Some(global_signature)) =
+            (lines.next(), lines.next(), lines.next(), lines.next())
+        else {
+            anyhow::bail!("malformed signature");
+        };
+        let signature_block = STANDARD
+            .decode(signature_block.trim())
+            .map_err(|_| anyhow!("malformed signature"))?;
+        if signature_block.len() != 74 {
+            anyhow::bail!("malformed signature");
+        }
+        let (algorithm, key_id, signature) = (
+            &signature_block[..2],
+            &signature_block[2..10],
+            &signature_block[10..],
+        );
+        if key_id != self.key_id {
+            anyhow::bail!(
+                "the pack was signed with key {}, not with {}",
+                key_id_hex(key_id),
+                self.key_id()
+            );
+        }
+        let public_key = UnparsedPublicKey::new(&ED25519, &self.public_key);
+        let message = match algorithm {
+            a if a == PREHASHED_SIGNATURE_ALGORITHM => blake2b512_file(path)?.to_vec(),
+            a if a == SIGNATURE_ALGORITHM => std::fs::read(path)?,
+            _ => anyhow::bail!("unsupported signature algorithm"),
+        };
+        public_key
+            .verify(&message, signature)
+            .map_err(|_| anyhow!("invalid signature for {}", path.display()))?;
+
+        let trusted_comment = trusted_comment
+            .strip_prefix(TRUSTED_COMMENT)
+            .ok_or_else(|| anyhow!("malformed signature"))?;
+        let global_signature = STANDARD
+            .decode(global_signature.trim())
+            .map_err(|_| anyhow!("malformed signature"))?;
+        public_key
+            .verify(
+                &[signature, trusted_comment.as_bytes()].concat(),
+                &global_signature,
+            )
+            .map_err(|_| anyhow!("invalid trusted comment in the signature"))?;
+        Ok(trusted_comment.to_string())
+    }
+}
+
+impl std::str::FromStr for VerifyingKey {
+    type Err = anyhow::Error;
+
+    /// Parse a public key file or the base64-encoded key alone, as passed to `minisign -P`.
+    fn from_str(contents: &str) -> Result<Self> {
+        let key = decode_payload(contents)?;
+        // signature algorithm (2) | key id (8) | public key (32)
+        if key.len() != 42 || &key[..2] != SIGNATURE_ALGORITHM {
+            anyhow::bail!("not a minisign public key");
+        }
+        Ok(Self {
+            key_id: key[2..10].try_into()?,
+            public_key: key[10..].try_into()?,
+        })
+    }
+}
+
+/// Decode the base64 payload of a minisign key, skipping the untrusted comment.
+fn decode_payload(contents: &str) -> Result<Vec<u8>> {
+    let payload = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with(UNTRUSTED_COMMENT))
+        .ok_or_else(|| anyhow!("empty key"))?;
+    STANDARD
+        .decode(payload)
+        .map_err(|e| anyhow!("invalid base64: {}", e))
+}
+
+fn blake2b512_file(path: &Path) -> Result<[u8; 64]> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| anyhow!("could not open {}: {}", path.display(), e))?;
+    let mut hasher = Blake2b512::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(anyhow!("could not read {}: {}", path.display(), e)),
+        };
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// minisign prints key IDs as little-endian hex numbers.
+fn key_id_hex(key_id: &[u8]) -> String {
+    key_id.iter().rev().map(|b| format!("{:02X}", b)).collect()
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::KeyPair;
+    use rstest::*;
+
+    /// An unencrypted minisign key pair, encoded like `minisign -G -W` does.
+    fn key_pair(seed: u8) -> (String, String) {
+        let key_id = [seed; 8];
+        let seed = [seed; 32];
+        let public_key = Ed25519KeyPair::from_seed_unchecked(&seed)
+            .unwrap()
+            .public_key()
+            .as_ref()
+            .to_vec();
+        let secret_key = [seed.as_slice(), &public_key].concat();
+        let checksum = Blake2b::<U32>::new()
+            .chain_update(SIGNATURE_ALGORITHM)
+            .chain_update(key_id)
+            .chain_update(&secret_key)
+            .finalize();
+        let secret = [
+            SIGNATURE_ALGORITHM.as_slice(),
+            &[0, 0],
+            b"B2",
+            &[0; 48],
+            &key_id,
+            &secret_key,
+            &checksum,
+        ]
+        .concat();
+        let public = [SIGNATURE_ALGORITHM.as_slice(), &key_id, &public_key].concat();
+        (
+            format!(
+                "untrusted comment: minisign secret key\n{}\n",
+                STANDARD.encode(secret)
+            ),
+            format!(
+                "untrusted comment: minisign public key\n{}\n",
+                STANDARD.encode(public)
+            ),
+        )
+    }
+
+    #[rstest]
+    fn test_sign_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_file = dir.path().join("environment.tar");
+        std::fs::write(&pack_file, "pack").unwrap();
+        assert_eq!(
+            signature_path(&pack_file),
+            dir.path().join("environment.tar.minisig")
+        );
+
+        let (secret, public) = key_pair(1);
+        let signing_key: SigningKey = secret.parse().unwrap();
+        let verifying_key: VerifyingKey = public.parse().unwrap();
+        assert_eq!(signing_key.key_id(), "0101010101010101");
+        // The base64-encoded key alone is accepted as well.
+        assert_eq!(
+            public
+                .lines()
+                .nth(1)
+                .unwrap()
+                .parse::<VerifyingKey>()
+                .unwrap(),
+            verifying_key
+        );
+
+        let signature = signing_key
+            .sign_file(&pack_file, "timestamp:0\tfile:environment.tar\thashed")
+            .unwrap();
+        assert_eq!(
+            verifying_key.verify_file(&pack_file, &signature).unwrap(),
+            "timestamp:0\tfile:environment.tar\thashed"
+        );
+
+        // Tampered trusted comment.
+        let tampered = signature.replace("timestamp:0", "timestamp:1");
+        assert_eq!(
+            verifying_key
+                .verify_file(&pack_file, &tampered)
+                .unwrap_err()
+                .to_string(),
+            "invalid trusted comment in the signature"
+        );
+
+        // Wrong key.
+        let (_, other_public) = key_pair(2);
+        let other_key: VerifyingKey = other_public.parse().unwrap();
+        assert_eq!(
+            other_key
+                .verify_file(&pack_file, &signature)
+                .unwrap_err()
+                .to_string(),
+            "the pack was signed with key 0101010101010101, not with 0202020202020202"
+        );
+
+        // Tampered pack.
+        std::fs::write(&pack_file, "tampered").unwrap();
+        assert!(verifying_key
+            .verify_file(&pack_file, &signature)
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid signature for"));
+    }
+
+    #[rstest]
+    fn test_encrypted_secret_key() {
+        let (secret, _) = key_pair(1);
+        let mut key = decode_payload(&secret).unwrap();
+        key[2..4].copy_from_slice(b"Sc");
+        let error = STANDARD.encode(key).parse::<SigningKey>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "encrypted secret keys are not supported, create the key with `minisign -G -W`"
+        );
+    }
+}
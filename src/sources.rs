@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{anyhow, Result};
+use rattler_lock::CondaBinaryData;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{CHANNEL_DIRECTORY_NAME, SOURCES_PATH};
+
+/// The original download locations of the packages in a pack, written to `sources.json`.
+///
+/// With these, single packages can be fetched again or updated from the pack alone, e.g. by
+/// recipients with partial connectivity. Injected packages and packages from local paths have no
+/// source and are not listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackSources {
+    /// The source of every package, keyed by its `/`-separated path in the pack, e.g.
+    /// `channel/linux-64/python-3.12.3-h2628c8c_0_cpython.conda`.
+    pub packages: BTreeMap<String, PackageSource>,
+}
+
+/// Where a package of a pack was downloaded from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageSource {
+    pub url: Url,
+    /// The hex-encoded sha256 hash of the package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The hex-encoded md5 hash of the package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    /// The size of the package in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+impl PackSources {
+    /// Collect the sources of the given packages from the lockfile.
+    pub fn new<'a>(packages: impl IntoIterator<Item = &'a CondaBinaryData>) -> Self {
+        let packages = packages
+            .into_iter()
+            .filter_map(|package| {
+                let url = package.location.as_url()?.clone();
+                let record = &package.package_record;
+                let path = format!(
+                    "{}/{}/{}",
+                    CHANNEL_DIRECTORY_NAME, record.subdir, package.file_name
+                );
+                let source = PackageSource {
+                    url,
+                    sha256: record.sha256.map(|hash| format!("{:x}", hash)),
+                    md5: record.md5.map(|hash| format!("{:x}", hash)),
+                    size: record.size,
+                };
+                Some((path, source))
+            })
+            .collect();
+        Self { packages }
+    }
+
+    /// Read `sources.json` of an extracted pack, or `None` if the pack was created without it.
+    pub fn read(pack_dir: &Path) -> Result<Option<Self>> {
+        let path = pack_dir.join(SOURCES_PATH);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("could not read {}: {}", SOURCES_PATH, e))?;
+        let sources = serde_json::from_str(&contents)
+            .map_err(|e| anyhow!("could not parse {}: {}", SOURCES_PATH, e))?;
+        Ok(Some(sources))
+    }
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rattler_conda_types::{PackageName, PackageRecord, Version};
+    use rattler_lock::UrlOrPath;
+    use rstest::*;
+
+    fn package(location: &str, sha256: Option<&str>) -> CondaBinaryData {
+        let mut record = PackageRecord::new(
+            PackageName::new_unchecked("pip"),
+            "24.3.1".parse::<Version>().unwrap(),
+            "pyh8b19718_0".to_string(),
+        );
+        record.subdir = "noarch".to_string();
+        record.sha256 = sha256.map(|hash| {
+            rattler_digest::parse_digest_from_hex::<rattler_digest::Sha256>(hash).unwrap()
+        });
+        record.size = Some(1243168);
+        CondaBinaryData {
+            package_record: record,
+            location: location.parse::<UrlOrPath>().unwrap(),
+            file_name: "pip-24.3.1-pyh8b19718_0.conda".to_string(),
+            channel: None,
+        }
+    }
+
+    #[rstest]
+    fn test_pack_sources() {
+        let sha256 = "499313e72e20225f84c2e9690bbaf5b952c8d7e0bf34b728278538f766b81628";
+        let url = "https://conda.anaconda.org/conda-forge/noarch/pip-24.3.1-pyh8b19718_0.conda";
+        let packages = [
+            package(url, Some(sha256)),
+            package("/local/pip-24.3.1-pyh8b19718_0.conda", None),
+        ];
+        let sources = PackSources::new(&packages);
+        assert_eq!(
+            sources.packages,
+            BTreeMap::from([(
+                "channel/noarch/pip-24.3.1-pyh8b19718_0.conda".to_string(),
+                PackageSource {
+                    url: url.parse().unwrap(),
+                    sha256: Some(sha256.to_string()),
+                    md5: None,
+                    size: Some(1243168),
+                }
+            )])
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(PackSources::read(dir.path()).unwrap(), None);
+        std::fs::write(
+            dir.path().join(SOURCES_PATH),
+            serde_json::to_string(&sources).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(PackSources::read(dir.path()).unwrap(), Some(sources));
+    }
+}
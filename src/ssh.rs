@@ -0,0 +1,180 @@
+use std::{path::Path, process::Stdio, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use tokio::process::Command;
+
+/// A file on a remote host that is reachable via `ssh`.
+///
+/// Written as `ssh://[user@]host[:port]/path`. Paths are absolute, use `ssh://host/~/path` for
+/// paths relative to the home directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshDestination {
+    /// The destination as passed to `ssh`, i.e. `[user@]host`.
+    pub host: String,
+    pub port: Option<u16>,
+    pub path: String,
+}
+
+impl FromStr for SshDestination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rest = s
+            .strip_prefix("ssh://")
+            .ok_or(anyhow!("expected an ssh:// URL, got `{}`", s))?;
+        let (authority, path) = rest
+            .split_once('/')
+            .ok_or(anyhow!("missing remote path in `{}`", s))?;
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                Some(
+                    port.parse()
+                        .map_err(|_| anyhow!("invalid port in `{}`", s))?,
+                ),
+            ),
+            None => (authority, None),
+        };
+        if host.is_empty() || path.is_empty() {
+            anyhow::bail!("expected `ssh://[user@]host[:port]/path`, got `{}`", s);
+        }
+        let path = match path.strip_prefix("~/") {
+            Some(relative) => relative.to_string(),
+            None => format!("/{}", path),
+        };
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+impl std::fmt::Display for SshDestination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ssh://{}", self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        match self.path.strip_prefix('/') {
+            Some(absolute) => write!(f, "/{}", absolute),
+            None => write!(f, "/~/{}", self.path),
+        }
+    }
+}
+
+impl SshDestination {
+    fn command(&self, remote_command: &str) -> Command {
+        let mut command = Command::new("ssh");
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        command.arg(&self.host).arg("--").arg(remote_command);
+        command
+    }
+
+    /// Stream a local file to the destination.
+    pub async fn upload(&self, local_file: &Path) -> Result<()> {
+        let mut file = tokio::fs::File::open(local_file)
+            .await
+            .map_err(|e| anyhow!("could not open {}: {}", local_file.display(), e))?;
+
+        let mut child = self
+            .command(&format!("cat > {}", shell_quote(&self.path)))
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("could not run ssh: {}", e))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        tokio::io::copy(&mut file, &mut stdin)
+            .await
+            .map_err(|e| anyhow!("could not send pack to {}: {}", self.host, e))?;
+        drop(stdin);
+
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| anyhow!("could not run ssh: {}", e))?;
+        if !status.success() {
+            anyhow::bail!("uploading to {} failed with {}", self, status);
+        }
+        Ok(())
+    }
+
+    /// Run `program` with `args` on the remote host.
+    pub async fn run(&self, program: &str, args: &[&str]) -> Result<()> {
+        let remote_command = std::iter::once(program)
+            .chain(args.iter().copied())
+            .map(shell_quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let status = self
+            .command(&remote_command)
+            .status()
+            .await
+            .map_err(|e| anyhow!("could not run ssh: {}", e))?;
+        if !status.success() {
+            anyhow::bail!(
+                "`{}` on {} failed with {}",
+                remote_command,
+                self.host,
+                status
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Quote a string for a POSIX shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("ssh://host/tmp/env.tar", "host", None, "/tmp/env.tar")]
+    #[case(
+        "ssh://user@host:2222/tmp/env.tar",
+        "user@host",
+        Some(2222),
+        "/tmp/env.tar"
+    )]
+    #[case("ssh://user@host/~/env.tar", "user@host", None, "env.tar")]
+    fn test_parse_ssh_destination(
+        #[case] input: &str,
+        #[case] host: &str,
+        #[case] port: Option<u16>,
+        #[case] path: &str,
+    ) {
+        let destination = input.parse::<SshDestination>().unwrap();
+        assert_eq!(destination.host, host);
+        assert_eq!(destination.port, port);
+        assert_eq!(destination.path, path);
+        assert_eq!(destination.to_string(), input);
+    }
+
+    #[rstest]
+    #[case("/tmp/env.tar")]
+    #[case("ssh://host")]
+    #[case("ssh://host/")]
+    #[case("ssh:///tmp/env.tar")]
+    #[case("ssh://host:port/tmp/env.tar")]
+    fn test_parse_ssh_destination_invalid(#[case] input: &str) {
+        assert!(input.parse::<SshDestination>().is_err());
+    }
+
+    #[rstest]
+    #[case("env.tar", "'env.tar'")]
+    #[case("my env.tar", "'my env.tar'")]
+    #[case("it's.tar", r"'it'\''s.tar'")]
+    fn test_shell_quote(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(shell_quote(input), expected);
+    }
+}
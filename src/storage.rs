@@ -0,0 +1,498 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use reqwest::{header, StatusCode};
+use reqwest_middleware::ClientWithMiddleware;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use url::Url;
+
+/// Progress of a [`Storage::fetch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchProgress {
+    /// The size of the file in bytes, reported before the first chunk if the backend knows it,
+    /// e.g. from the `Content-Length` of a response.
+    Size(u64),
+    /// The number of bytes of a chunk that was written.
+    Bytes(u64),
+}
+
+/// A backend that files can be fetched from, e.g. an HTTP server or the local filesystem.
+///
+/// Backends are selected by the scheme of the URL, see [`Storages`]. Additional backends, e.g.
+/// for object stores, can be added with [`crate::PackOptionsBuilder::storage`].
+pub trait Storage: fmt::Debug + Send + Sync {
+    /// The URL schemes handled by this backend, e.g. `["http", "https"]`.
+    fn schemes(&self) -> &[&str];
+
+    /// Write the file at `url` to `destination`, reporting the progress to `on_progress`.
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        destination: &'a Path,
+        on_progress: &'a (dyn Fn(FetchProgress) + Send + Sync),
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+/// How often and how fast failed HTTP downloads are retried.
+///
+/// Connection errors, timeouts and server errors (`5xx`, `429`) are retried with exponential
+/// backoff and full jitter; other client errors fail immediately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt (`0`: no retries).
+    pub max_retries: u32,
+    /// The upper bound of the delay before the first retry, doubled for every further retry.
+    pub initial_backoff: Duration,
+    /// The upper bound of the delay before any retry.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The upper bound of the delay before retry number `retry` (starting at zero).
+    fn max_delay(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+
+    /// A random delay between zero and [`Self::max_delay`], so that concurrent downloads that
+    /// failed at the same time don't hit the server at the same time again.
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        let random = RandomState::new().build_hasher().finish();
+        self.max_delay(retry)
+            .mul_f64(random as f64 / u64::MAX as f64)
+    }
+}
+
+/// A failed download attempt.
+enum FetchError {
+    /// The error may go away when retrying, e.g. a timeout.
+    Transient(anyhow::Error),
+    Permanent(anyhow::Error),
+}
+
+/// Fetches files via HTTP(S) with the given client, including its authentication middleware.
+///
+/// Failed downloads are retried according to the [`RetryPolicy`]. Retries continue a partially
+/// downloaded file with a range request if the server supports it.
+#[derive(Debug, Clone)]
+pub struct HttpStorage {
+    client: ClientWithMiddleware,
+    retry_policy: RetryPolicy,
+}
+
+impl HttpStorage {
+    pub fn new(client: ClientWithMiddleware, retry_policy: RetryPolicy) -> Self {
+        Self {
+            client,
+            retry_policy,
+        }
+    }
+
+    /// Download `url` to `destination`, continuing after the first `offset` bytes if the server
+    /// supports range requests. `reported` is the number of bytes already reported as progress,
+    /// so that restarted downloads are not counted twice.
+    async fn fetch_once(
+        &self,
+        url: &Url,
+        destination: &Path,
+        offset: u64,
+        reported: &mut u64,
+        on_progress: &(dyn Fn(FetchProgress) + Send + Sync),
+    ) -> Result<(), FetchError> {
+        let mut request = self.client.get(url.clone());
+        if offset > 0 {
+            request = request.header(header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request
+            .send()
+            .await
+            .map_err(|e| FetchError::Transient(anyhow!("failed to download {}: {}", url, e)))?;
+        let status = response.status();
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(FetchError::Transient(anyhow!(
+                "failed to download {}: {}",
+                url,
+                status
+            )));
+        }
+        if status.is_client_error() {
+            let reason = match response.text().await {
+                Ok(body) if !body.trim().is_empty() => format!("{}: {}", status, body.trim()),
+                Ok(_) => status.to_string(),
+                Err(e) => format!("{}: {}", status, e),
+            };
+            return Err(FetchError::Permanent(anyhow!(
+                "failed to download {}: {}",
+                url,
+                reason
+            )));
+        }
+
+        // Servers without support for range requests send the whole file again.
+        let resumed = status == StatusCode::PARTIAL_CONTENT;
+        let mut position = if resumed { offset } else { 0 };
+        let mut dest = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(destination)
+            .await
+            .map_err(|e| FetchError::Permanent(e.into()))?;
+        if *reported == 0 {
+            if let Some(size) = response.content_length() {
+                on_progress(FetchProgress::Size(position + size));
+            }
+        }
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| FetchError::Transient(anyhow!("failed to download {}: {}", url, e)))?
+        {
+            dest.write_all(&chunk)
+                .await
+                .map_err(|e| FetchError::Permanent(e.into()))?;
+            position += chunk.len() as u64;
+            if position > *reported {
+                on_progress(FetchProgress::Bytes(position - *reported));
+                *reported = position;
+            }
+        }
+        dest.flush()
+            .await
+            .map_err(|e| FetchError::Permanent(e.into()))?;
+        Ok(())
+    }
+}
+
+impl Storage for HttpStorage {
+    fn schemes(&self) -> &[&str] {
+        &["http", "https"]
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        destination: &'a Path,
+        on_progress: &'a (dyn Fn(FetchProgress) + Send + Sync),
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            File::create(destination).await?;
+            let mut reported = 0;
+            let mut retry = 0;
+            loop {
+                let offset = tokio::fs::metadata(destination).await?.len();
+                match self
+                    .fetch_once(url, destination, offset, &mut reported, on_progress)
+                    .await
+                {
+                    Ok(()) => return Ok(()),
+                    Err(FetchError::Transient(e)) if retry < self.retry_policy.max_retries => {
+                        let delay = self.retry_policy.delay(retry);
+                        tracing::warn!("{}, retrying in {:.1}s", e, delay.as_secs_f64());
+                        tokio::time::sleep(delay).await;
+                        retry += 1;
+                    }
+                    Err(FetchError::Transient(e) | FetchError::Permanent(e)) => return Err(e),
+                }
+            }
+        })
+    }
+}
+
+/// Copies files from the local filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileStorage;
+
+impl Storage for FileStorage {
+    fn schemes(&self) -> &[&str] {
+        &["file"]
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        destination: &'a Path,
+        on_progress: &'a (dyn Fn(FetchProgress) + Send + Sync),
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = url
+                .to_file_path()
+                .map_err(|_| anyhow!("invalid file url: {}", url))?;
+            let size = tokio::fs::copy(&path, destination)
+                .await
+                .map_err(|e| anyhow!("could not copy {}: {}", path.display(), e))?;
+            on_progress(FetchProgress::Size(size));
+            on_progress(FetchProgress::Bytes(size));
+            Ok(())
+        })
+    }
+}
+
+/// All available storage backends, dispatching to the first one that handles the scheme of a URL.
+#[derive(Debug, Clone)]
+pub struct Storages {
+    backends: Vec<Arc<dyn Storage>>,
+}
+
+impl Storages {
+    /// The built-in backends for `http(s)://` and `file://` URLs.
+    pub fn new(client: ClientWithMiddleware, retry_policy: RetryPolicy) -> Self {
+        Self {
+            backends: vec![
+                Arc::new(HttpStorage::new(client, retry_policy)),
+                Arc::new(FileStorage),
+            ],
+        }
+    }
+
+    /// Add backends that take precedence over the already registered ones.
+    pub fn with_backends(mut self, backends: impl IntoIterator<Item = Arc<dyn Storage>>) -> Self {
+        let mut backends: Vec<_> = backends.into_iter().collect();
+        backends.append(&mut self.backends);
+        self.backends = backends;
+        self
+    }
+
+    fn backend(&self, url: &Url) -> Result<&dyn Storage> {
+        self.backends
+            .iter()
+            .find(|backend| backend.schemes().contains(&url.scheme()))
+            .map(Arc::as_ref)
+            .ok_or_else(|| anyhow!("no storage backend for {} urls: {}", url.scheme(), url))
+    }
+}
+
+impl Storage for Storages {
+    fn schemes(&self) -> &[&str] {
+        &[]
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        url: &'a Url,
+        destination: &'a Path,
+        on_progress: &'a (dyn Fn(FetchProgress) + Send + Sync),
+    ) -> BoxFuture<'a, Result<()>> {
+        match self.backend(url) {
+            Ok(backend) => backend.fetch(url, destination, on_progress),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// A backend that serves the path of the url as file contents.
+    #[derive(Debug)]
+    struct EchoStorage;
+
+    impl Storage for EchoStorage {
+        fn schemes(&self) -> &[&str] {
+            &["echo", "file"]
+        }
+
+        fn fetch<'a>(
+            &'a self,
+            url: &'a Url,
+            destination: &'a Path,
+            on_progress: &'a (dyn Fn(FetchProgress) + Send + Sync),
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                tokio::fs::write(destination, url.path()).await?;
+                on_progress(FetchProgress::Bytes(url.path().len() as u64));
+                Ok(())
+            })
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_storages() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::write(&source, "contents").unwrap();
+        let source_url = Url::from_file_path(&source).unwrap();
+        let destination = dir.path().join("destination");
+        let progress = AtomicU64::new(0);
+        let size = AtomicU64::new(0);
+        let on_progress = |progress_event| match progress_event {
+            FetchProgress::Size(bytes) => size.store(bytes, Ordering::Relaxed),
+            FetchProgress::Bytes(bytes) => {
+                progress.fetch_add(bytes, Ordering::Relaxed);
+            }
+        };
+
+        let storages = Storages::new(
+            ClientWithMiddleware::from(reqwest::Client::new()),
+            RetryPolicy::default(),
+        );
+        storages
+            .fetch(&source_url, &destination, &on_progress)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "contents");
+        assert_eq!(progress.load(Ordering::Relaxed), 8);
+        assert_eq!(size.load(Ordering::Relaxed), 8);
+
+        let echo_url = Url::parse("echo:///hello").unwrap();
+        let error = storages
+            .fetch(&echo_url, &destination, &on_progress)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no storage backend for echo urls: echo:///hello"
+        );
+
+        // Added backends take precedence over the built-in ones.
+        let storages = storages.with_backends([Arc::new(EchoStorage) as Arc<dyn Storage>]);
+        for url in [&echo_url, &source_url] {
+            storages
+                .fetch(url, &destination, &on_progress)
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read_to_string(&destination).unwrap(), url.path());
+        }
+    }
+
+    #[rstest]
+    #[case(0, Duration::from_secs(1))]
+    #[case(1, Duration::from_secs(2))]
+    #[case(4, Duration::from_secs(16))]
+    #[case(5, Duration::from_secs(30))]
+    #[case(u32::MAX, Duration::from_secs(30))]
+    fn test_retry_delay(#[case] retry: u32, #[case] expected: Duration) {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.max_delay(retry), expected);
+        assert!(policy.delay(retry) <= expected);
+    }
+
+    /// Serve the given raw HTTP responses, one per connection, and return the URL of the server
+    /// and the received request headers.
+    async fn serve_responses(
+        responses: Vec<&'static str>,
+    ) -> (Url, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/file", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut buf = [0; 1024];
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                requests.push(String::from_utf8(request).unwrap().to_lowercase());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn no_backoff(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_http_retry_resumes_download() {
+        let (url, server) = serve_responses(vec![
+            "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\ncontent-length: 8\r\nconnection: close\r\n\r\ncont",
+            "HTTP/1.1 206 Partial Content\r\ncontent-length: 4\r\nconnection: close\r\n\r\nents",
+        ])
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("destination");
+        let progress = AtomicU64::new(0);
+        let on_progress = |progress_event| {
+            if let FetchProgress::Bytes(bytes) = progress_event {
+                progress.fetch_add(bytes, Ordering::Relaxed);
+            }
+        };
+
+        let storage = HttpStorage::new(
+            ClientWithMiddleware::from(reqwest::Client::new()),
+            no_backoff(2),
+        );
+        storage
+            .fetch(&url, &destination, &on_progress)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "contents");
+        assert_eq!(progress.load(Ordering::Relaxed), 8);
+
+        let requests = server.await.unwrap();
+        assert!(!requests[1].contains("range:"));
+        assert!(requests[2].contains("range: bytes=4-"));
+    }
+
+    #[rstest]
+    #[case("HTTP/1.1 503 Service Unavailable", 2, 3)]
+    #[case("HTTP/1.1 404 Not Found", 2, 1)]
+    #[case("HTTP/1.1 429 Too Many Requests", 0, 1)]
+    #[tokio::test]
+    async fn test_http_retry_gives_up(
+        #[case] status_line: &'static str,
+        #[case] max_retries: u32,
+        #[case] expected_requests: usize,
+    ) {
+        let response: &'static str = format!(
+            "{}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+            status_line
+        )
+        .leak();
+        let (url, server) = serve_responses(vec![response; expected_requests]).await;
+        let dir = tempfile::tempdir().unwrap();
+
+        let storage = HttpStorage::new(
+            ClientWithMiddleware::from(reqwest::Client::new()),
+            no_backoff(max_retries),
+        );
+        assert!(storage
+            .fetch(&url, &dir.path().join("destination"), &|_| {})
+            .await
+            .is_err());
+        assert_eq!(server.await.unwrap().len(), expected_requests);
+    }
+}
@@ -1,4 +1,10 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashSet},
+    num::NonZeroUsize,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use anyhow::{anyhow, Result};
 use futures::{
@@ -6,67 +12,882 @@ use futures::{
     TryFutureExt, TryStreamExt,
 };
 use fxhash::FxHashMap;
+use indicatif::HumanBytes;
 use rattler::{
-    install::Installer,
+    install::{
+        link_script::{run_link_scripts, LinkScriptType},
+        Installer, Reporter, Transaction,
+    },
     package_cache::{CacheKey, PackageCache},
 };
-use rattler_conda_types::{PackageRecord, Platform, RepoData, RepoDataRecord};
-use rattler_package_streaming::fs::extract;
+use rattler_conda_types::{
+    PackageName, PackageRecord, Platform, PrefixRecord, RepoData, RepoDataRecord,
+};
+use rattler_networking::AuthenticationStorage;
+use rattler_package_streaming::{fs::extract, ExtractError};
 use rattler_shell::{
     activation::{ActivationVariables, Activator, PathModificationBehavior},
     shell::{Shell, ShellEnum},
 };
+use serde::{Deserialize, Serialize};
 
-use tokio::fs;
+use async_compression::tokio::bufread::ZstdDecoder;
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt},
+};
 use tokio_stream::wrappers::ReadDirStream;
 use tokio_tar::Archive;
 use url::Url;
 
 use crate::{
-    PixiPackMetadata, ProgressReporter, CHANNEL_DIRECTORY_NAME, DEFAULT_PIXI_PACK_VERSION,
+    get_size,
+    pack::{build_reqwest_client, http_client, reqwest_client_from_auth_storage},
+    permissions::apply_permission_policy,
+    pull_from_registry, signature_path,
+    util::{available_space, same_filesystem, ProgressReader},
+    DirectoryChecksums, Event, FetchProgress, HostVirtualPackages, HttpOptions, HttpStorage,
+    NetworkLog, OciReference, PermissionPolicy, PixiPackMetadata, ProgressReporter, RetryPolicy,
+    Storage, SystemRequirement, SystemRequirementCheck, VerifyingKey, CHANNEL_DIRECTORY_NAME,
+    CHECKSUMS_PATH, DEFAULT_PIXI_PACK_VERSION, ENVIRONMENTS_DIRECTORY_NAME, INSTRUCTIONS_PATH,
     PIXI_PACK_METADATA_PATH, PIXI_PACK_VERSION,
 };
 
+/// How the activation script modifies the `PATH` variable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PathModification {
+    /// Put the directories of the environment in front of the existing `PATH`.
+    #[default]
+    Prepend,
+    /// Put the directories of the environment after the existing `PATH`.
+    Append,
+    /// Replace the existing `PATH` with the directories of the environment.
+    Replace,
+}
+
+impl From<PathModification> for PathModificationBehavior {
+    fn from(path_modification: PathModification) -> Self {
+        match path_modification {
+            PathModification::Prepend => PathModificationBehavior::Prepend,
+            PathModification::Append => PathModificationBehavior::Append,
+            PathModification::Replace => PathModificationBehavior::Replace,
+        }
+    }
+}
+
 /// Options for unpacking a pixi environment.
+///
+/// Use [`UnpackOptions::builder`] to create options with sane defaults.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct UnpackOptions {
     pub pack_file: PathBuf,
     pub output_directory: PathBuf,
     pub env_name: String,
     pub shell: Option<ShellEnum>,
+    pub permissions: PermissionPolicy,
+    pub idempotent: bool,
+    pub skip_disk_space_check: bool,
+    pub allow_link_scripts: Vec<PackageName>,
+    pub extract_concurrency: Option<NonZeroUsize>,
+    pub base_pack: Option<PathBuf>,
+    pub target_prefix: Option<PathBuf>,
+    pub verify_key: Option<PathBuf>,
+    pub require_signature: bool,
+    pub path_modification: PathModification,
+    pub skip_activation_script: bool,
+    pub activation_script: Option<PathBuf>,
+    pub environment: Option<String>,
+    pub verify_only: bool,
+    pub http_options: HttpOptions,
+    pub network_log: Option<NetworkLog>,
+}
+
+impl UnpackOptions {
+    /// Create a builder for [`UnpackOptions`].
+    pub fn builder() -> UnpackOptionsBuilder {
+        UnpackOptionsBuilder::default()
+    }
+
+    /// The prefix that the environment is installed into.
+    pub fn prefix(&self) -> PathBuf {
+        match &self.target_prefix {
+            Some(target_prefix) => target_prefix.clone(),
+            None => self.output_directory.join(&self.env_name),
+        }
+    }
+
+    /// The path of the activation script for `shell`, or `None` if no activation script is
+    /// written.
+    pub fn activation_script_path(&self, shell: &ShellEnum) -> Option<PathBuf> {
+        if self.skip_activation_script {
+            return None;
+        }
+        Some(match &self.activation_script {
+            Some(activation_script) => self.output_directory.join(activation_script),
+            None => default_activation_script_path(&self.output_directory, shell),
+        })
+    }
+}
+
+/// Builder for [`UnpackOptions`].
+///
+/// By default, `environment.tar` is unpacked into `env` in the current directory.
+#[derive(Debug, Clone, Default)]
+pub struct UnpackOptionsBuilder {
+    pack_file: Option<PathBuf>,
+    output_directory: Option<PathBuf>,
+    env_name: Option<String>,
+    shell: Option<ShellEnum>,
+    permissions: PermissionPolicy,
+    idempotent: bool,
+    skip_disk_space_check: bool,
+    allow_link_scripts: Vec<PackageName>,
+    extract_concurrency: Option<NonZeroUsize>,
+    base_pack: Option<PathBuf>,
+    target_prefix: Option<PathBuf>,
+    verify_key: Option<PathBuf>,
+    require_signature: bool,
+    path_modification: PathModification,
+    skip_activation_script: bool,
+    activation_script: Option<PathBuf>,
+    environment: Option<String>,
+    verify_only: bool,
+    http_options: HttpOptions,
+    network_log: Option<NetworkLog>,
+}
+
+impl UnpackOptionsBuilder {
+    /// The pack to unpack (default: `environment.tar`).
+    /// `-` reads a tarball from stdin, an `http://` or `https://` URL is downloaded first and
+    /// an `oci://` reference pulls the pack from a registry, see [`crate::OciReference`].
+    pub fn pack_file(mut self, pack_file: impl Into<PathBuf>) -> Self {
+        self.pack_file = Some(pack_file.into());
+        self
+    }
+
+    /// The directory to unpack the environment into (default: the current directory).
+    pub fn output_directory(mut self, output_directory: impl Into<PathBuf>) -> Self {
+        self.output_directory = Some(output_directory.into());
+        self
+    }
+
+    /// The name of the environment directory (default: `env`).
+    pub fn env_name(mut self, env_name: impl Into<String>) -> Self {
+        self.env_name = Some(env_name.into());
+        self
+    }
+
+    /// The shell to create the activation script for (default: the default shell).
+    pub fn shell(mut self, shell: impl Into<Option<ShellEnum>>) -> Self {
+        self.shell = shell.into();
+        self
+    }
+
+    /// Ownership and permissions to apply to the prefix and the activation script
+    /// (default: leave them as created).
+    pub fn permissions(mut self, permissions: PermissionPolicy) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Skip unpacking if the prefix already contains this pack (default: `false`).
+    /// After unpacking, a marker with the hash of the pack is written to the prefix.
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+
+    /// Don't check whether there is enough disk space before unpacking (default: `false`).
+    /// The check is based on an estimate of the size of the extracted packages.
+    pub fn skip_disk_space_check(mut self, skip_disk_space_check: bool) -> Self {
+        self.skip_disk_space_check = skip_disk_space_check;
+        self
+    }
+
+    /// Packages whose post-link scripts may run after installing them (default: none).
+    /// Post-link scripts of all other packages are skipped and recorded in
+    /// `conda-meta/pixi-pack-link-scripts.json`, see [`LinkScriptsReport`].
+    pub fn allow_link_scripts(
+        mut self,
+        allow_link_scripts: impl IntoIterator<Item = PackageName>,
+    ) -> Self {
+        self.allow_link_scripts = allow_link_scripts.into_iter().collect();
+        self
+    }
+
+    /// The number of packages that are extracted at the same time (default: the number of CPUs).
+    /// Lower values reduce the memory usage while unpacking.
+    pub fn extract_concurrency(mut self, extract_concurrency: Option<NonZeroUsize>) -> Self {
+        self.extract_concurrency = extract_concurrency;
+        self
+    }
+
+    /// The pack that a delta pack was created against (default: none).
+    /// Without it, the packages that are left out of a delta pack need to be installed in the
+    /// environment already, e.g. because it was unpacked from the base pack before.
+    pub fn base_pack(mut self, base_pack: impl Into<Option<PathBuf>>) -> Self {
+        self.base_pack = base_pack.into();
+        self
+    }
+
+    /// An absolute path to install the environment into instead of `<output_directory>/<env_name>`
+    /// (default: none). Missing parent directories are created. The activation script is still
+    /// written to the output directory.
+    pub fn target_prefix(mut self, target_prefix: impl Into<Option<PathBuf>>) -> Self {
+        self.target_prefix = target_prefix.into();
+        self
+    }
+
+    /// A minisign public key to verify the detached signature of the pack with (default: none).
+    /// Packs with an invalid signature are rejected, see [`crate::signature_path`] for where the
+    /// signature is expected.
+    pub fn verify_key(mut self, verify_key: impl Into<Option<PathBuf>>) -> Self {
+        self.verify_key = verify_key.into();
+        self
+    }
+
+    /// Reject packs without a signature (default: `false`). Requires [`Self::verify_key`].
+    pub fn require_signature(mut self, require_signature: bool) -> Self {
+        self.require_signature = require_signature;
+        self
+    }
+
+    /// How the activation script modifies `PATH` (default: [`PathModification::Prepend`]).
+    pub fn path_modification(mut self, path_modification: PathModification) -> Self {
+        self.path_modification = path_modification;
+        self
+    }
+
+    /// Don't write an activation script, e.g. if the environment is activated by other means
+    /// (default: `false`).
+    pub fn skip_activation_script(mut self, skip_activation_script: bool) -> Self {
+        self.skip_activation_script = skip_activation_script;
+        self
+    }
+
+    /// Where to write the activation script (default: `activate.<extension>` in the output
+    /// directory). Relative paths are resolved against the output directory and missing parent
+    /// directories are created.
+    pub fn activation_script(mut self, activation_script: impl Into<Option<PathBuf>>) -> Self {
+        self.activation_script = activation_script.into();
+        self
+    }
+
+    /// The environment to unpack from a pack of multiple environments (default: none).
+    /// Required for such packs, see [`crate::pack_environments`].
+    pub fn environment(mut self, environment: impl Into<Option<String>>) -> Self {
+        self.environment = environment.into();
+        self
+    }
+
+    /// Only check whether the pack can be unpacked and print what would be installed, without
+    /// touching the prefix (default: `false`). The packages themselves are not extracted.
+    pub fn verify_only(mut self, verify_only: bool) -> Self {
+        self.verify_only = verify_only;
+        self
+    }
+
+    /// Settings of the HTTP client that downloads packs from URLs and registries.
+    pub fn http_options(mut self, http_options: HttpOptions) -> Self {
+        self.http_options = http_options;
+        self
+    }
+
+    /// Record all HTTP requests in a [`NetworkLog`] (default: none).
+    pub fn network_log(mut self, network_log: impl Into<Option<NetworkLog>>) -> Self {
+        self.network_log = network_log.into();
+        self
+    }
+
+    /// Build the [`UnpackOptions`].
+    pub fn build(self) -> UnpackOptions {
+        UnpackOptions {
+            pack_file: self
+                .pack_file
+                .unwrap_or_else(|| PathBuf::from("environment.tar")),
+            output_directory: self.output_directory.unwrap_or_else(|| PathBuf::from(".")),
+            env_name: self.env_name.unwrap_or_else(|| "env".to_string()),
+            shell: self.shell,
+            permissions: self.permissions,
+            idempotent: self.idempotent,
+            skip_disk_space_check: self.skip_disk_space_check,
+            allow_link_scripts: self.allow_link_scripts,
+            extract_concurrency: self.extract_concurrency,
+            base_pack: self.base_pack,
+            target_prefix: self.target_prefix,
+            verify_key: self.verify_key,
+            require_signature: self.require_signature,
+            path_modification: self.path_modification,
+            skip_activation_script: self.skip_activation_script,
+            activation_script: self.activation_script,
+            environment: self.environment,
+            verify_only: self.verify_only,
+            http_options: self.http_options,
+            network_log: self.network_log,
+        }
+    }
+}
+
+/// The result of a successful [`unpack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnpackOutcome {
+    /// The environment was unpacked.
+    Installed,
+    /// The prefix already contained the pack, see [`UnpackOptionsBuilder::idempotent`].
+    AlreadyInstalled,
+    /// The pack can be unpacked, nothing was installed, see [`UnpackOptionsBuilder::verify_only`].
+    Verified,
+}
+
+/// Record of the post-link scripts that were run while unpacking, relative to the prefix.
+const LINK_SCRIPTS_REPORT_PATH: &str = "conda-meta/pixi-pack-link-scripts.json";
+
+/// The post-link scripts of an unpacked environment, see
+/// [`UnpackOptionsBuilder::allow_link_scripts`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LinkScriptsReport {
+    /// Packages whose post-link script ran successfully.
+    pub succeeded: Vec<String>,
+    /// Packages whose post-link script failed.
+    pub failed: Vec<String>,
+    /// Packages whose post-link script was not allowed to run.
+    pub skipped: Vec<String>,
+}
+
+impl LinkScriptsReport {
+    /// Read the report of the environment in `prefix`.
+    /// Returns `None` if none of its packages contain a post-link script.
+    pub fn read(prefix: &Path) -> Result<Option<Self>> {
+        let path = prefix.join(LINK_SCRIPTS_REPORT_PATH);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| anyhow!("could not parse {}: {}", path.display(), e))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.succeeded.is_empty() && self.failed.is_empty() && self.skipped.is_empty()
+    }
+}
+
+/// Run the post-link scripts of the packages in `allow` that are installed in `prefix`, in
+/// topological order. The scripts of all other packages are skipped.
+fn run_allowed_link_scripts(prefix: &Path, allow: &[PackageName]) -> Result<LinkScriptsReport> {
+    let records = PrefixRecord::collect_from_prefix(prefix)
+        .map_err(|e| anyhow!("could not read installed packages: {}", e))?;
+    let records = PackageRecord::sort_topologically(records.iter().collect::<Vec<_>>());
+    let platform = Platform::current();
+
+    let mut report = LinkScriptsReport::default();
+    let mut allowed = Vec::new();
+    for record in records {
+        let package = &record.repodata_record.package_record;
+        if !prefix
+            .join(LinkScriptType::PostLink.get_path(package, &platform))
+            .is_file()
+        {
+            continue;
+        }
+        if allow.contains(&package.name) {
+            allowed.push(record);
+        } else {
+            tracing::warn!(
+                "Skipping post-link script of {} as it is not allowed",
+                package.name.as_normalized()
+            );
+            report
+                .skipped
+                .push(package.name.as_normalized().to_string());
+        }
+    }
+
+    let result = run_link_scripts(
+        LinkScriptType::PostLink,
+        allowed.iter().copied(),
+        prefix,
+        &platform,
+    )
+    .map_err(|e| anyhow!("could not run post-link scripts: {}", e))?;
+    for record in allowed {
+        let name = &record.repodata_record.package_record.name;
+        if result.failed_packages.contains(name) {
+            report.failed.push(name.as_normalized().to_string());
+        } else {
+            report.succeeded.push(name.as_normalized().to_string());
+        }
+    }
+    Ok(report)
+}
+
+/// Record the dependencies that were requested in the manifest in `conda-meta` so that conda
+/// respects them when modifying the environment later on.
+///
+/// The specs are stored as `requested_spec` of the installed packages, and specs with a version
+/// or build constraint are written to `conda-meta/pinned`.
+fn record_requested_specs(prefix: &Path, specs: &[String]) -> Result<()> {
+    if specs.is_empty() {
+        return Ok(());
+    }
+    let conda_meta = prefix.join("conda-meta");
+    let spec_name = |spec: &str| {
+        spec.split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    for mut record in PrefixRecord::collect_from_prefix(prefix)? {
+        let package = &record.repodata_record.package_record;
+        let Some(spec) = specs
+            .iter()
+            .find(|spec| spec_name(spec) == package.name.as_normalized())
+        else {
+            continue;
+        };
+        record.requested_spec = Some(spec.clone());
+        let file_name = format!(
+            "{}-{}-{}.json",
+            package.name.as_normalized(),
+            package.version,
+            package.build
+        );
+        record.write_to_path(conda_meta.join(file_name), true)?;
+    }
+
+    let pinned: String = specs
+        .iter()
+        .filter(|spec| spec.contains(char::is_whitespace))
+        .map(|spec| format!("{}\n", spec))
+        .collect();
+    if !pinned.is_empty() {
+        std::fs::write(conda_meta.join("pinned"), pinned)?;
+    }
+    Ok(())
+}
+
+/// Marker written to idempotently unpacked prefixes, relative to the prefix.
+const INSTALL_MARKER_PATH: &str = "conda-meta/pixi-pack-installed.json";
+
+/// Contents of the install marker.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct InstallMarker {
+    pack_sha256: String,
+    pixi_pack_version: String,
+}
+
+/// The install marker for a pack.
+///
+/// For directory packs, the layout digest of [`DirectoryChecksums`] is used as hash.
+fn install_marker(pack_file: &Path) -> Result<InstallMarker> {
+    let pack_sha256 = if pack_file.is_dir() {
+        DirectoryChecksums::new(pack_file)?.digest
+    } else {
+        let digest = rattler_digest::compute_file_digest::<rattler_digest::Sha256>(pack_file)
+            .map_err(|e| anyhow!("could not compute hash of {}: {}", pack_file.display(), e))?;
+        format!("{:x}", digest)
+    };
+    Ok(InstallMarker {
+        pack_sha256,
+        pixi_pack_version: PIXI_PACK_VERSION.to_string(),
+    })
+}
+
+/// Whether `prefix` contains a marker for the same pack. The version of pixi-pack is ignored.
+async fn is_installed(prefix: &Path, marker: &InstallMarker) -> bool {
+    match fs::read_to_string(prefix.join(INSTALL_MARKER_PATH)).await {
+        Ok(contents) => serde_json::from_str::<InstallMarker>(&contents)
+            .is_ok_and(|existing| existing.pack_sha256 == marker.pack_sha256),
+        Err(_) => false,
+    }
+}
+
+/// Rough upper bound of the ratio between the extracted and the compressed size of packages.
+const EXTRACTED_SIZE_FACTOR: u64 = 4;
+
+/// The estimated disk space in bytes needed to unpack a pack of `pack_size` bytes as
+/// `(temporary directory, prefix)`.
+///
+/// Unless the pack is a directory (`in_place`), it is unarchived into the temporary directory.
+/// All packages are extracted into the temporary directory before they are installed into the
+/// prefix. Installed files are hard links to the extracted packages if both are on the same
+/// filesystem and copies otherwise.
+fn required_disk_space(pack_size: u64, same_filesystem: bool, in_place: bool) -> (u64, u64) {
+    let extracted_size = pack_size.saturating_mul(EXTRACTED_SIZE_FACTOR);
+    let temporary = if in_place {
+        extracted_size
+    } else {
+        pack_size.saturating_add(extracted_size)
+    };
+    if same_filesystem {
+        (temporary, temporary)
+    } else {
+        (temporary, extracted_size)
+    }
+}
+
+/// Fail if there is not enough disk space to unpack a pack of `pack_size` bytes using `tmp_dir`.
+fn check_disk_space(
+    pack_size: u64,
+    in_place: bool,
+    tmp_dir: &Path,
+    target_prefix: &Path,
+) -> Result<()> {
+    // The prefix does not need to exist yet.
+    let Some(target_dir) = target_prefix.ancestors().find(|path| path.exists()) else {
+        return Ok(());
+    };
+    let same_filesystem = same_filesystem(tmp_dir, target_dir);
+    let (temporary, prefix) = required_disk_space(pack_size, same_filesystem, in_place);
+    let mut requirements = vec![(tmp_dir, temporary)];
+    if !same_filesystem {
+        requirements.push((target_dir, prefix));
+    }
+
+    for (path, required) in requirements {
+        let Some(available) = available_space(path) else {
+            tracing::debug!("Could not determine free disk space of {}", path.display());
+            continue;
+        };
+        tracing::debug!(
+            "Unpacking requires about {} in {}, {} are available",
+            HumanBytes(required),
+            path.display(),
+            HumanBytes(available)
+        );
+        if available < required {
+            anyhow::bail!(
+                "Not enough disk space: unpacking requires about {} in {} but only {} are available. \
+                Free up space, set TMPDIR to a larger filesystem or skip this check with \
+                `--skip-disk-space-check`",
+                HumanBytes(required),
+                path.display(),
+                HumanBytes(available)
+            );
+        }
+    }
+    Ok(())
 }
 
 /// Unpack a pixi environment.
-pub async fn unpack(options: UnpackOptions) -> Result<()> {
+pub async fn unpack(options: UnpackOptions) -> Result<UnpackOutcome> {
+    if let Some(target_prefix) = &options.target_prefix {
+        if !target_prefix.is_absolute() {
+            anyhow::bail!(
+                "The target prefix must be an absolute path: {}",
+                target_prefix.display()
+            );
+        }
+    }
+    let target_prefix = options.prefix();
+    let from_stdin = is_stdin(&options.pack_file);
+    if from_stdin && options.idempotent {
+        anyhow::bail!("--idempotent is not supported when reading the pack from stdin");
+    }
+    let from_registry = OciReference::is_oci_reference(&options.pack_file);
+    if from_registry && options.idempotent {
+        anyhow::bail!("--idempotent is not supported when pulling the pack from a registry");
+    }
+    let from_url = is_url(&options.pack_file);
+    if from_url && options.idempotent {
+        anyhow::bail!("--idempotent is not supported when downloading the pack from a URL");
+    }
+    if options.verify_only && options.idempotent {
+        anyhow::bail!("--idempotent cannot be combined with --verify-only");
+    }
+    let marker = if options.idempotent {
+        let marker = install_marker(&options.pack_file)?;
+        if is_installed(&target_prefix, &marker).await {
+            tracing::info!("{} is already installed", target_prefix.display());
+            status!("✅ {} is already up to date.", target_prefix.display());
+            Event::PrefixUpToDate {
+                prefix: target_prefix.clone(),
+            }
+            .emit();
+            return Ok(UnpackOutcome::AlreadyInstalled);
+        }
+        Some(marker)
+    } else {
+        None
+    };
+
+    verify_signature(
+        &options.pack_file,
+        options.verify_key.as_deref(),
+        options.require_signature,
+    )?;
+
     let tmp_dir =
         tempfile::tempdir().map_err(|e| anyhow!("Could not create temporary directory: {}", e))?;
-    let unpack_dir = tmp_dir.path();
+    // Directory packs are used in place instead of copying them into the temporary directory.
+    let in_place = options.pack_file.is_dir();
 
-    tracing::info!("Unarchiving pack to {}", unpack_dir.display());
+    // Packs are downloaded before unarchiving them, so that interrupted downloads are resumed.
+    let download_dir = match from_url {
+        true => Some(
+            tempfile::tempdir()
+                .map_err(|e| anyhow!("Could not create temporary directory: {}", e))?,
+        ),
+        false => None,
+    };
+    let pack_file = match &download_dir {
+        Some(download_dir) => {
+            let pack_file = download_dir.path().join("environment.tar");
+            tracing::info!("Downloading pack from {}", options.pack_file.display());
+            let client =
+                build_reqwest_client(None, &options.http_options, options.network_log.clone())?;
+            let storage = HttpStorage::new(client, options.http_options.retry_policy);
+            download_archive(&options.pack_file, &pack_file, &storage).await?;
+            pack_file
+        }
+        None => options.pack_file.clone(),
+    };
 
-    unarchive(&options.pack_file, unpack_dir)
+    // The size of a pack streamed from stdin or a registry is not known in advance.
+    if !options.skip_disk_space_check && !from_stdin && !from_registry {
+        let pack_size = get_size(&pack_file)
+            .map_err(|e| anyhow!("Could not read {}: {}", options.pack_file.display(), e))?;
+        check_disk_space(pack_size, in_place, tmp_dir.path(), &target_prefix)?;
+    }
+
+    let unpack_dir = if in_place {
+        tracing::info!("Using extracted pack at {}", options.pack_file.display());
+        options.pack_file.as_path()
+    } else if from_registry {
+        let reference: OciReference = options.pack_file.to_string_lossy().parse()?;
+        tracing::info!("Pulling pack from {}", reference);
+        status!("📥 Pulling pack from {}", reference);
+        pull_from_registry(
+            &reference,
+            tmp_dir.path(),
+            &http_client(&options.http_options, options.network_log.clone())?,
+            options.http_options.retry_policy,
+            &AuthenticationStorage::default(),
+        )
         .await
+        .map_err(|e| anyhow!("Could not pull {}: {}", reference, e))?;
+        tmp_dir.path()
+    } else {
+        tracing::info!("Unarchiving pack to {}", tmp_dir.path().display());
+        // Delta packs can be compressed with a zstd dictionary that is trained on the base pack.
+        let dictionary_id = if from_stdin {
+            None
+        } else {
+            zstd_dictionary_id(&pack_file)
+                .map_err(|e| anyhow!("Could not read {}: {}", options.pack_file.display(), e))?
+        };
+        let dictionary = match (dictionary_id, &options.base_pack) {
+            (None, _) => None,
+            (Some(_), None) => anyhow::bail!(
+                "{} is compressed with a zstd dictionary, pass the pack it was created against \
+                with --base-pack",
+                options.pack_file.display()
+            ),
+            (Some(_), Some(base_pack)) => {
+                tracing::info!("Training zstd dictionary on {}", base_pack.display());
+                Some(train_zstd_dictionary(base_pack).await.map_err(|e| {
+                    anyhow!("Could not read base pack {}: {}", base_pack.display(), e)
+                })?)
+            }
+        };
+        let archive = open_archive_with_dictionary(&pack_file, dictionary.as_deref()).await?;
+        if options.verify_only {
+            unpack_without_packages(archive, tmp_dir.path()).await
+        } else {
+            let mut archive = archive;
+            archive.unpack(tmp_dir.path()).await
+        }
         .map_err(|e| anyhow!("Could not unarchive: {}", e))?;
+        tmp_dir.path()
+    };
 
-    validate_metadata_file(unpack_dir.join(PIXI_PACK_METADATA_PATH)).await?;
+    let metadata = validate_metadata_file(unpack_dir.join(PIXI_PACK_METADATA_PATH)).await?;
 
-    let target_prefix = options.output_directory.join(options.env_name);
+    // Packs of multiple environments contain a pack for each of them.
+    let (unpack_dir, metadata) = if metadata.environments.is_empty() {
+        if let Some(environment) = &options.environment {
+            tracing::warn!(
+                "{} contains a single environment, ignoring --environment {}",
+                options.pack_file.display(),
+                environment
+            );
+        }
+        (unpack_dir.to_path_buf(), metadata)
+    } else {
+        let environment =
+            select_environment(&metadata.environments, options.environment.as_deref())?;
+        tracing::info!("Selected environment {}", environment);
+        status!("🌐 Unpacking environment {}", environment);
+        let environment_dir = unpack_dir
+            .join(ENVIRONMENTS_DIRECTORY_NAME)
+            .join(environment);
+        let metadata =
+            validate_metadata_file(environment_dir.join(PIXI_PACK_METADATA_PATH)).await?;
+        (environment_dir, metadata)
+    };
+    let unpack_dir = unpack_dir.as_path();
 
-    tracing::info!("Creating prefix at {}", target_prefix.display());
-    let channel_directory = unpack_dir.join(CHANNEL_DIRECTORY_NAME);
-    let cache_dir = unpack_dir.join("cache");
-    create_prefix(&channel_directory, &target_prefix, &cache_dir)
+    if !metadata.base_packages.is_empty() {
+        prepare_delta_pack(
+            &metadata.base_packages,
+            options.base_pack.as_deref(),
+            unpack_dir,
+            in_place,
+            &target_prefix,
+        )
         .await
-        .map_err(|e| anyhow!("Could not create prefix: {}", e))?;
+        .map_err(|e| anyhow!("Could not unpack delta pack: {}", e))?;
+    } else if let Some(base_pack) = &options.base_pack {
+        tracing::warn!(
+            "{} is not a delta pack, ignoring base pack {}",
+            options.pack_file.display(),
+            base_pack.display()
+        );
+    }
 
-    tracing::info!("Generating activation script");
-    create_activation_script(
-        &options.output_directory,
+    if options.verify_only {
+        let packages = collect_packages(&unpack_dir.join(CHANNEL_DIRECTORY_NAME))
+            .await
+            .map_err(|e| anyhow!("Could not read packages: {}", e))?;
+        tmp_dir
+            .close()
+            .map_err(|e| anyhow!("Could not remove temporary directory: {}", e))?;
+        let host = tokio::task::spawn_blocking(HostVirtualPackages::detect)
+            .await
+            .map_err(|e| anyhow!("Could not detect virtual packages: {}", e))?;
+        status!(
+            "{}",
+            verify_summary(packages.values(), &metadata.system_requirements, &host).trim_end()
+        );
+        Event::SystemRequirementsChecked {
+            pack_file: options.pack_file.clone(),
+            requirements: metadata
+                .system_requirements
+                .iter()
+                .map(|requirement| {
+                    let result = host.check(requirement);
+                    SystemRequirementCheck {
+                        name: requirement.name.clone(),
+                        spec: requirement.spec.clone(),
+                        packages: requirement.packages.clone(),
+                        met: result.as_ref().map(Result::is_ok),
+                        reason: result.and_then(Result::err),
+                    }
+                })
+                .collect(),
+        }
+        .emit();
+        let unmet: Vec<String> = metadata
+            .system_requirements
+            .iter()
+            .filter_map(|requirement| match host.check(requirement) {
+                Some(Err(reason)) => Some(format!(
+                    "{} ({}): {}",
+                    requirement.host_requirement(),
+                    requirement.packages.join(", "),
+                    reason
+                )),
+                _ => None,
+            })
+            .collect();
+        if !unmet.is_empty() {
+            anyhow::bail!(
+                "The host does not meet the requirements of {}:\n  {}",
+                options.pack_file.display(),
+                unmet.join("\n  ")
+            );
+        }
+        status!(
+            "✅ {} can be unpacked into {}.",
+            options.pack_file.display(),
+            target_prefix.display()
+        );
+        Event::PackVerified {
+            pack_file: options.pack_file.clone(),
+            prefix: target_prefix.clone(),
+            package_count: packages.len(),
+        }
+        .emit();
+        return Ok(UnpackOutcome::Verified);
+    }
+
+    tracing::info!("Creating prefix at {}", target_prefix.display());
+    if let Some(parent) = target_prefix.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| anyhow!("Could not create directory {}: {}", parent.display(), e))?;
+    }
+    let channel_directory = unpack_dir.join(CHANNEL_DIRECTORY_NAME);
+    let cache_dir = tmp_dir.path().join("cache");
+    create_prefix(
+        &channel_directory,
         &target_prefix,
-        options.shell.unwrap_or_default(),
+        &cache_dir,
+        options.extract_concurrency,
     )
     .await
-    .map_err(|e| anyhow!("Could not create activation script: {}", e))?;
+    .map_err(|e| anyhow!("Could not create prefix: {}", e))?;
+    record_requested_specs(&target_prefix, &metadata.requested_specs)
+        .map_err(|e| anyhow!("Could not record requested specs: {}", e))?;
+
+    let link_scripts = run_allowed_link_scripts(&target_prefix, &options.allow_link_scripts)?;
+    if !link_scripts.is_empty() {
+        fs::write(
+            target_prefix.join(LINK_SCRIPTS_REPORT_PATH),
+            serde_json::to_string_pretty(&link_scripts)?,
+        )
+        .await
+        .map_err(|e| anyhow!("Could not write link script report: {}", e))?;
+    }
+
+    let shell = options.shell.clone().unwrap_or_default();
+    let activation_script = match options.activation_script_path(&shell) {
+        Some(activation_script) => {
+            tracing::info!("Generating activation script");
+            Some(
+                create_activation_script(
+                    &activation_script,
+                    &target_prefix,
+                    shell.clone(),
+                    options.path_modification,
+                )
+                .await
+                .map_err(|e| anyhow!("Could not create activation script: {}", e))?,
+            )
+        }
+        None => None,
+    };
+
+    if !options.permissions.is_empty() {
+        tracing::info!("Applying ownership and permissions");
+        apply_permission_policy(&target_prefix, &options.permissions)
+            .and_then(|_| match &activation_script {
+                Some(activation_script) => {
+                    apply_permission_policy(activation_script, &options.permissions)
+                }
+                None => Ok(()),
+            })
+            .map_err(|e| anyhow!("Could not apply permissions: {}", e))?;
+    }
+
+    // The marker is written last so that installations that were interrupted or failed to
+    // apply permissions are not considered complete.
+    if let Some(marker) = marker {
+        let marker_path = target_prefix.join(INSTALL_MARKER_PATH);
+        fs::write(&marker_path, serde_json::to_string_pretty(&marker)?)
+            .await
+            .map_err(|e| anyhow!("Could not write install marker: {}", e))?;
+        if !options.permissions.is_empty() {
+            apply_permission_policy(&marker_path, &options.permissions)
+                .map_err(|e| anyhow!("Could not apply permissions: {}", e))?;
+        }
+    }
 
     tmp_dir
         .close()
@@ -76,15 +897,230 @@ pub async fn unpack(options: UnpackOptions) -> Result<()> {
         "Finished unpacking to {}.",
         options.output_directory.display(),
     );
-    eprintln!(
+    status!(
         "💫 Finished unpacking to {}.",
         options.output_directory.display()
     );
 
+    if let Err(e) = print_install_summary(&target_prefix, activation_script.as_deref(), &shell) {
+        tracing::warn!("Could not create install summary: {}", e);
+    }
+
+    Ok(UnpackOutcome::Installed)
+}
+
+/// Select `environment` from the environments of a pack of multiple environments.
+fn select_environment<'a>(
+    environments: &'a [String],
+    environment: Option<&str>,
+) -> Result<&'a str> {
+    let Some(environment) = environment else {
+        anyhow::bail!(
+            "The pack contains multiple environments, select one with --environment: {}",
+            environments.join(", ")
+        );
+    };
+    environments
+        .iter()
+        .find(|e| *e == environment)
+        .map(String::as_str)
+        .ok_or_else(|| {
+            anyhow!(
+                "The pack does not contain environment {}, available environments: {}",
+                environment,
+                environments.join(", ")
+            )
+        })
+}
+
+/// Verify the detached signature of the pack with `verify_key`, if any.
+/// Unsigned packs are only rejected if `require_signature` is set.
+fn verify_signature(
+    pack_file: &Path,
+    verify_key: Option<&Path>,
+    require_signature: bool,
+) -> Result<()> {
+    let Some(verify_key) = verify_key else {
+        if require_signature {
+            anyhow::bail!("Requiring a signature needs a key to verify it with");
+        }
+        return Ok(());
+    };
+    let verify_key = VerifyingKey::from_file(verify_key)?;
+    let signature_file = signature_path(pack_file);
+    let signature = if is_stdin(pack_file)
+        || OciReference::is_oci_reference(pack_file)
+        || is_url(pack_file)
+        || pack_file.is_dir()
+    {
+        None
+    } else {
+        match std::fs::read_to_string(&signature_file) {
+            Ok(signature) => Some(signature),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => anyhow::bail!("Could not read {}: {}", signature_file.display(), e),
+        }
+    };
+    let Some(signature) = signature else {
+        if require_signature {
+            anyhow::bail!(
+                "{} is not signed, expected a signature at {}",
+                pack_file.display(),
+                signature_file.display()
+            );
+        }
+        tracing::warn!("{} is not signed", pack_file.display());
+        return Ok(());
+    };
+    let trusted_comment = verify_key
+        .verify_file(pack_file, &signature)
+        .map_err(|e| anyhow!("Could not verify the signature of the pack: {}", e))?;
+    tracing::info!(
+        "Verified signature of {} with key {} ({})",
+        pack_file.display(),
+        verify_key.key_id(),
+        trusted_comment
+    );
+    status!(
+        "🔏 Verified signature of {} with key {}.",
+        pack_file.display(),
+        verify_key.key_id()
+    );
     Ok(())
 }
 
-async fn collect_packages_in_subdir(subdir: PathBuf) -> Result<FxHashMap<String, PackageRecord>> {
+/// Print the packages installed into `prefix` and how to activate the environment, or emit
+/// [`Event::PrefixInstalled`] if JSON output is enabled.
+fn print_install_summary(
+    prefix: &Path,
+    activation_script: Option<&Path>,
+    shell: &ShellEnum,
+) -> Result<()> {
+    let records = PrefixRecord::collect_from_prefix(prefix)
+        .map_err(|e| anyhow!("could not read installed packages: {}", e))?;
+    let prefix_size = get_size(prefix)?;
+    if crate::json_output() {
+        Event::PrefixInstalled {
+            prefix: prefix.to_path_buf(),
+            package_count: records.len(),
+            size_bytes: prefix_size,
+        }
+        .emit();
+        return Ok(());
+    }
+    let activation_command = match activation_script {
+        Some(activation_script) => {
+            let mut activation_command = String::new();
+            shell.run_script(&mut activation_command, activation_script)?;
+            Some(activation_command)
+        }
+        None => None,
+    };
+    eprint!(
+        "{}",
+        install_summary(
+            records.iter().map(|r| &r.repodata_record.package_record),
+            prefix_size,
+            activation_command.as_deref()
+        )
+    );
+    Ok(())
+}
+
+/// A human-readable summary of the installed packages and how to activate the environment, if an
+/// activation script was written.
+fn install_summary<'a>(
+    packages: impl IntoIterator<Item = &'a PackageRecord>,
+    prefix_size: u64,
+    activation_command: Option<&str>,
+) -> String {
+    let (mut summary, package_count) = package_table(packages);
+    summary.push_str(&format!(
+        "Installed {} packages ({}).\n",
+        package_count,
+        HumanBytes(prefix_size)
+    ));
+    if let Some(activation_command) = activation_command {
+        summary.push_str("To activate the environment, run:\n");
+        summary.push_str(&format!("  {}", activation_command));
+    }
+    summary
+}
+
+/// A human-readable summary of the packages that would be installed from a pack and whether
+/// `host` meets the requirements of the pack.
+fn verify_summary<'a>(
+    packages: impl IntoIterator<Item = &'a PackageRecord>,
+    system_requirements: &[SystemRequirement],
+    host: &HostVirtualPackages,
+) -> String {
+    let packages: Vec<&PackageRecord> = packages.into_iter().collect();
+    let download_size: u64 = packages.iter().filter_map(|p| p.size).sum();
+    let (mut summary, package_count) = package_table(packages);
+    summary.push_str(&format!(
+        "Would install {} packages ({} compressed).\n",
+        package_count,
+        HumanBytes(download_size)
+    ));
+    if !system_requirements.is_empty() {
+        summary.push_str("System requirements:\n");
+        for requirement in system_requirements {
+            let (icon, reason) = match host.check(requirement) {
+                Some(Ok(())) => ("✅", String::new()),
+                Some(Err(reason)) => ("❌", format!(": {}", reason)),
+                None => ("❔", ": cannot be checked".to_string()),
+            };
+            summary.push_str(&format!(
+                "  {} {} ({}){}\n",
+                icon,
+                requirement.host_requirement(),
+                requirement.packages.join(", "),
+                reason
+            ));
+        }
+    }
+    summary
+}
+
+/// A table of the name, version and build of `packages`, sorted by name, and the number of
+/// packages.
+fn package_table<'a>(packages: impl IntoIterator<Item = &'a PackageRecord>) -> (String, usize) {
+    let mut rows: Vec<[String; 3]> = packages
+        .into_iter()
+        .map(|p| {
+            [
+                p.name.as_normalized().to_string(),
+                p.version.to_string(),
+                p.build.clone(),
+            ]
+        })
+        .collect();
+    rows.sort();
+    let package_count = rows.len();
+    rows.insert(0, ["Package", "Version", "Build"].map(String::from));
+
+    let widths: Vec<usize> = (0..3)
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+
+    let mut summary = String::new();
+    for row in rows {
+        summary.push_str(&format!(
+            "  {:<w0$}  {:<w1$}  {}\n",
+            row[0],
+            row[1],
+            row[2],
+            w0 = widths[0],
+            w1 = widths[1],
+        ));
+    }
+    (summary, package_count)
+}
+
+/// Collect all packages listed in the `repodata.json` of a channel subdir.
+pub(crate) async fn collect_packages_in_subdir(
+    subdir: PathBuf,
+) -> Result<FxHashMap<String, PackageRecord>> {
     let repodata = subdir.join("repodata.json");
 
     let raw_repodata_json = fs::read_to_string(repodata)
@@ -105,28 +1141,208 @@ async fn collect_packages_in_subdir(subdir: PathBuf) -> Result<FxHashMap<String,
     Ok(conda_packages)
 }
 
-async fn validate_metadata_file(metadata_file: PathBuf) -> Result<()> {
+async fn validate_metadata_file(metadata_file: PathBuf) -> Result<PixiPackMetadata> {
     let metadata_contents = fs::read_to_string(&metadata_file)
         .await
         .map_err(|e| anyhow!("Could not read metadata file: {}", e))?;
 
-    let metadata: PixiPackMetadata = serde_json::from_str(&metadata_contents)?;
+    let (metadata, warnings) = PixiPackMetadata::from_json(&metadata_contents)?;
 
+    validate_compatibility(&metadata, Platform::current())?;
+
+    tracing::debug!("pack metadata: {:?}", metadata);
+    for warning in warnings {
+        tracing::warn!("{}", warning);
+    }
+
+    Ok(metadata)
+}
+
+/// Check whether a pack with the given metadata can be installed on `platform`.
+pub fn validate_compatibility(metadata: &PixiPackMetadata, platform: Platform) -> Result<()> {
     if metadata.version != DEFAULT_PIXI_PACK_VERSION {
         anyhow::bail!("Unsupported pixi-pack version: {}", metadata.version);
     }
-    if metadata.platform != Platform::current() {
+    // noarch packs can be installed on any platform.
+    if metadata.platform != platform && metadata.platform != Platform::NoArch {
         anyhow::bail!("The pack was created for a different platform");
     }
 
-    tracing::debug!("pack metadata: {:?}", metadata);
-    if metadata.pixi_pack_version != Some(PIXI_PACK_VERSION.to_string()) {
-        tracing::warn!(
-            "The pack was created with a different version of pixi-pack: {:?}",
-            metadata.pixi_pack_version
+    Ok(())
+}
+
+/// Read the metadata of a pack without unpacking it.
+///
+/// `pack_file` can either be a tarball or a directory created with `--format dir`.
+pub async fn read_metadata(pack_file: &Path) -> Result<PixiPackMetadata> {
+    let metadata_contents = if pack_file.is_dir() {
+        fs::read_to_string(pack_file.join(PIXI_PACK_METADATA_PATH))
+            .await
+            .map_err(|e| anyhow!("Could not read metadata file: {}", e))?
+    } else {
+        read_file_from_archive(pack_file, Path::new(PIXI_PACK_METADATA_PATH))
+            .await?
+            .ok_or(anyhow!(
+                "{} not found in {}",
+                PIXI_PACK_METADATA_PATH,
+                pack_file.display()
+            ))?
+    };
+
+    let (metadata, warnings) = PixiPackMetadata::from_json(&metadata_contents)?;
+    for warning in warnings {
+        tracing::warn!("{}", warning);
+    }
+
+    Ok(metadata)
+}
+
+/// Read the usage instructions that were bundled with a pack, if any.
+///
+/// `pack_file` can either be a tarball or a directory created with `--format dir`.
+pub async fn read_instructions(pack_file: &Path) -> Result<Option<String>> {
+    if pack_file.is_dir() {
+        let instructions_path = pack_file.join(INSTRUCTIONS_PATH);
+        if !instructions_path.is_file() {
+            return Ok(None);
+        }
+        let instructions = fs::read_to_string(instructions_path)
+            .await
+            .map_err(|e| anyhow!("Could not read instructions: {}", e))?;
+        Ok(Some(instructions))
+    } else {
+        read_file_from_archive(pack_file, Path::new(INSTRUCTIONS_PATH)).await
+    }
+}
+
+/// Read a single file from a tarball into a string.
+/// Returns `None` if the file does not exist in the tarball.
+async fn read_file_from_archive(archive_path: &Path, file_path: &Path) -> Result<Option<String>> {
+    let mut archive = open_archive(archive_path).await?;
+    let mut entries = archive
+        .entries()
+        .map_err(|e| anyhow!("could not read archive: {}", e))?;
+
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry.map_err(|e| anyhow!("could not read archive entry: {}", e))?;
+        if entry.path()? == file_path {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).await?;
+            return Ok(Some(contents));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Provide the packages that a delta pack leaves out.
+///
+/// They are either extracted from `base_pack` into the channel of the pack, or they need to be
+/// installed in `target_prefix` already, e.g. because it was unpacked from the base pack before.
+/// Packages from the base pack are verified against the repodata of the delta pack when they are
+/// installed.
+async fn prepare_delta_pack(
+    base_packages: &[String],
+    base_pack: Option<&Path>,
+    unpack_dir: &Path,
+    in_place: bool,
+    target_prefix: &Path,
+) -> Result<()> {
+    let Some(base_pack) = base_pack else {
+        let installed: HashSet<String> = if target_prefix.join("conda-meta").is_dir() {
+            PrefixRecord::collect_from_prefix(target_prefix)
+                .map_err(|e| anyhow!("could not read installed packages: {}", e))?
+                .into_iter()
+                .map(|record| {
+                    format!(
+                        "{}/{}/{}",
+                        CHANNEL_DIRECTORY_NAME,
+                        record.repodata_record.package_record.subdir,
+                        record.repodata_record.file_name
+                    )
+                })
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        let missing = base_packages
+            .iter()
+            .filter(|path| !installed.contains(*path))
+            .count();
+        if missing > 0 {
+            anyhow::bail!(
+                "{} packages are neither installed in {} nor provided by a base pack, pass the \
+                pack it was created against with --base-pack",
+                missing,
+                target_prefix.display()
+            );
+        }
+        tracing::info!(
+            "Using {} packages that are installed in {} already",
+            base_packages.len(),
+            target_prefix.display()
         );
+        return Ok(());
+    };
+
+    // Directory packs are used in place and must not be modified.
+    if in_place {
+        anyhow::bail!("--base-pack is not supported for directory packs");
     }
+    tracing::info!(
+        "Extracting {} packages from base pack {}",
+        base_packages.len(),
+        base_pack.display()
+    );
+    let mut missing: HashSet<&str> = base_packages.iter().map(String::as_str).collect();
+    let destination = |path: &str| -> Result<PathBuf> {
+        let destination = unpack_dir.join(path);
+        std::fs::create_dir_all(destination.parent().unwrap_or(unpack_dir))?;
+        Ok(destination)
+    };
+    if base_pack.is_dir() {
+        for path in base_packages {
+            let source = base_pack.join(path);
+            if source.is_file() {
+                fs::copy(&source, destination(path)?).await?;
+                missing.remove(path.as_str());
+            }
+        }
+    } else {
+        let mut archive = open_archive(base_pack).await?;
+        let mut entries = archive
+            .entries()
+            .map_err(|e| anyhow!("could not read base pack: {}", e))?;
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry.map_err(|e| anyhow!("could not read base pack: {}", e))?;
+            let path = entry.path()?.to_string_lossy().replace('\\', "/");
+            if missing.remove(path.as_str()) {
+                entry.unpack(destination(&path)?).await?;
+            }
+        }
+    }
+    if let Some(path) = missing.iter().min() {
+        anyhow::bail!(
+            "{} is not contained in base pack {}",
+            path,
+            base_pack.display()
+        );
+    }
+    Ok(())
+}
 
+/// Unpack everything but the package files of `archive` into `dir`, e.g. to read the metadata and
+/// repodata of a pack.
+async fn unpack_without_packages(mut archive: FileArchive, dir: &Path) -> std::io::Result<()> {
+    let mut entries = archive.entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        if path.ends_with(".conda") || path.ends_with(".tar.bz2") {
+            continue;
+        }
+        entry.unpack_in(dir).await?;
+    }
     Ok(())
 }
 
@@ -157,46 +1373,380 @@ async fn collect_packages(channel_dir: &Path) -> Result<FxHashMap<String, Packag
     Ok(packages)
 }
 
-/// Unarchive a tarball.
+/// The magic number at the start of zstd frames.
+pub(crate) const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// The magic bytes at the end of binary self-extracting executables, see [`embedded_pack`].
+pub(crate) const BINARY_EXECUTABLE_MAGIC: [u8; 8] = *b"PIXIPACK";
+
+/// The length of the trailer of binary self-extracting executables: the offset and the length of
+/// the embedded tarball as little-endian `u64`s, followed by [`BINARY_EXECUTABLE_MAGIC`].
+pub(crate) const BINARY_EXECUTABLE_TRAILER_LEN: u64 = 24;
+
+/// The byte range of the tarball embedded in a binary self-extracting executable, or `None` if
+/// `path` is not such an executable.
+///
+/// Binary executables are the pixi-pack executable followed by the tarball of the pack and a
+/// trailer that records where the tarball starts.
+pub fn embedded_pack(path: &Path) -> Result<Option<Range<u64>>> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| anyhow!("could not open {}: {}", path.display(), e))?;
+    let file_len = file.metadata()?.len();
+    if file_len < BINARY_EXECUTABLE_TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut trailer = [0; BINARY_EXECUTABLE_TRAILER_LEN as usize];
+    std::io::Seek::seek(
+        &mut file,
+        std::io::SeekFrom::End(-(BINARY_EXECUTABLE_TRAILER_LEN as i64)),
+    )?;
+    std::io::Read::read_exact(&mut file, &mut trailer)?;
+    if trailer[16..] != BINARY_EXECUTABLE_MAGIC {
+        return Ok(None);
+    }
+
+    let start = u64::from_le_bytes(trailer[..8].try_into()?);
+    let length = u64::from_le_bytes(trailer[8..16].try_into()?);
+    match start.checked_add(length) {
+        Some(end) if end <= file_len - BINARY_EXECUTABLE_TRAILER_LEN => Ok(Some(start..end)),
+        _ => anyhow::bail!("the trailer of {} is corrupted", path.display()),
+    }
+}
+
+/// A tarball that is read from a file, decompressing it on the fly if needed.
+pub(crate) type FileArchive = Archive<Box<dyn AsyncRead + Unpin + Send + Sync>>;
+
+/// Whether the pack should be read from stdin instead of a file.
+pub(crate) fn is_stdin(pack_file: &Path) -> bool {
+    pack_file == Path::new("-")
+}
+
+/// Whether the pack should be downloaded from a URL instead of read from a file.
+pub(crate) fn is_url(pack_file: &Path) -> bool {
+    pack_file.to_str().is_some_and(|pack_file| {
+        ["http://", "https://"]
+            .iter()
+            .any(|scheme| pack_file.starts_with(scheme))
+    })
+}
+
+/// Download the pack at `url` to `destination` with `storage`, which retries failed downloads
+/// and resumes them with range requests if the server supports them.
+async fn download_archive(url: &Path, destination: &Path, storage: &HttpStorage) -> Result<()> {
+    let url = url.to_string_lossy();
+    let url = Url::parse(&url).map_err(|e| anyhow!("invalid URL {}: {}", url, e))?;
+    let reporter = ProgressReporter::new_bytes(0);
+    reporter.pb.set_message("Downloading pack");
+    let sized = AtomicBool::new(false);
+    let on_progress = |progress| match progress {
+        FetchProgress::Size(size) => {
+            sized.store(true, Ordering::Relaxed);
+            reporter.pb.set_length(size);
+        }
+        FetchProgress::Bytes(bytes) => {
+            if !sized.load(Ordering::Relaxed) {
+                reporter.pb.inc_length(bytes);
+            }
+            reporter.pb.inc(bytes);
+        }
+    };
+    storage
+        .fetch(&url, destination, &on_progress)
+        .await
+        .map_err(|e| anyhow!("could not download {}: {}", url, e))?;
+    reporter.pb.finish_and_clear();
+    Ok(())
+}
+
+/// Open a tarball. zstd-compressed tarballs are detected by their content, not by their name.
+/// The tarball of binary self-extracting executables is read directly, see [`embedded_pack`].
+///
+/// If `archive_path` is `-`, the tarball is streamed from stdin with bounded memory and the
+/// number of bytes read is reported as progress. `http://` and `https://` URLs are downloaded to
+/// a temporary file first with the default authentication storage of rattler.
+pub(crate) async fn open_archive(archive_path: &Path) -> Result<FileArchive> {
+    open_archive_with_dictionary(archive_path, None).await
+}
+
+/// Open a tarball like [`open_archive`], decompressing it with `dictionary` if it is compressed
+/// with zstd, see [`train_zstd_dictionary`].
+pub(crate) async fn open_archive_with_dictionary(
+    archive_path: &Path,
+    dictionary: Option<&[u8]>,
+) -> Result<FileArchive> {
+    let file: Box<dyn AsyncRead + Unpin + Send + Sync> = if is_stdin(archive_path) {
+        let pb = ProgressReporter::new_stream().pb;
+        pb.set_message("Reading pack from stdin");
+        Box::new(ProgressReader::new(tokio::io::stdin(), pb))
+    } else if is_url(archive_path) {
+        // The download is removed once the archive is closed.
+        let download = tempfile::NamedTempFile::new()
+            .map_err(|e| anyhow!("could not create temporary file: {}", e))?;
+        let storage = HttpStorage::new(
+            reqwest_client_from_auth_storage(None)?,
+            RetryPolicy::default(),
+        );
+        download_archive(archive_path, download.path(), &storage).await?;
+        Box::new(fs::File::from_std(download.reopen()?))
+    } else {
+        let mut file = fs::File::open(archive_path)
+            .await
+            .map_err(|e| anyhow!("could not open archive {:#?}: {}", archive_path, e))?;
+        match embedded_pack(archive_path)? {
+            Some(range) => {
+                file.seek(std::io::SeekFrom::Start(range.start)).await?;
+                Box::new(file.take(range.end - range.start))
+            }
+            None => Box::new(file),
+        }
+    };
+
+    let mut reader = tokio::io::BufReader::new(file);
+    let compressed = reader
+        .fill_buf()
+        .await
+        .map_err(|e| anyhow!("could not read archive {:#?}: {}", archive_path, e))?
+        .starts_with(&ZSTD_MAGIC);
+    let reader: Box<dyn AsyncRead + Unpin + Send + Sync> = if compressed {
+        let mut decoder = match dictionary {
+            Some(dictionary) => ZstdDecoder::with_dict(reader, dictionary)
+                .map_err(|e| anyhow!("invalid zstd dictionary: {}", e))?,
+            None => ZstdDecoder::new(reader),
+        };
+        decoder.multiple_members(true);
+        Box::new(decoder)
+    } else {
+        Box::new(reader)
+    };
+    Ok(Archive::new(reader))
+}
+
+/// The maximum size of zstd dictionaries for delta packs.
+const ZSTD_DICTIONARY_SIZE: usize = 112 * 1024;
+
+/// The number of bytes at the start of every file of a base pack that are used to train the zstd
+/// dictionary of delta packs.
+const ZSTD_DICTIONARY_SAMPLE_SIZE: u64 = 128 * 1024;
+
+/// Train a zstd dictionary on the contents of `base_pack` to compress delta packs with.
+///
+/// The dictionary is not stored in the delta pack but trained again from the base pack when
+/// unpacking. Training is deterministic and only depends on the files of the base pack, so
+/// tarballs and directory packs with the same contents result in the same dictionary.
+pub(crate) async fn train_zstd_dictionary(base_pack: &Path) -> Result<Vec<u8>> {
+    // Samples are sorted by path so that the order of archive entries does not matter.
+    let mut samples = BTreeMap::new();
+    if base_pack.is_dir() {
+        for entry in walkdir::WalkDir::new(base_pack) {
+            let entry = entry?;
+            let relative_path = entry.path().strip_prefix(base_pack)?;
+            if !entry.file_type().is_file() || relative_path == Path::new(CHECKSUMS_PATH) {
+                continue;
+            }
+            let mut sample = Vec::new();
+            fs::File::open(entry.path())
+                .await?
+                .take(ZSTD_DICTIONARY_SAMPLE_SIZE)
+                .read_to_end(&mut sample)
+                .await?;
+            samples.insert(relative_path.to_string_lossy().replace('\\', "/"), sample);
+        }
+    } else {
+        let mut archive = open_archive(base_pack).await?;
+        let mut entries = archive
+            .entries()
+            .map_err(|e| anyhow!("could not read base pack: {}", e))?;
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry.map_err(|e| anyhow!("could not read base pack: {}", e))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.to_string_lossy().replace('\\', "/");
+            let mut sample = Vec::new();
+            (&mut entry)
+                .take(ZSTD_DICTIONARY_SAMPLE_SIZE)
+                .read_to_end(&mut sample)
+                .await?;
+            samples.insert(path, sample);
+        }
+    }
+
+    let samples: Vec<Vec<u8>> = samples
+        .into_values()
+        .filter(|sample| !sample.is_empty())
+        .collect();
+    zstd::dict::from_samples(&samples, ZSTD_DICTIONARY_SIZE)
+        .map_err(|e| anyhow!("could not train zstd dictionary on the base pack: {}", e))
+}
+
+/// The ID of the zstd dictionary that the pack at `path` was compressed with, if any.
+pub(crate) fn zstd_dictionary_id(path: &Path) -> Result<Option<u32>> {
+    use std::io::Read;
+
+    // The frame header is at most 18 bytes long.
+    let mut header = Vec::new();
+    std::fs::File::open(path)?
+        .take(18)
+        .read_to_end(&mut header)?;
+    Ok(zstd::zstd_safe::get_dict_id_from_frame(&header).map(|id| id.get()))
+}
+
+/// Unarchive a tarball, which may be compressed with zstd.
 pub async fn unarchive(archive_path: &Path, target_dir: &Path) -> Result<()> {
-    let file = fs::File::open(archive_path)
+    let mut archive = open_archive(archive_path).await?;
+
+    archive
+        .unpack(target_dir)
         .await
-        .map_err(|e| anyhow!("could not open archive {:#?}: {}", archive_path, e))?;
+        .map_err(|e| anyhow!("could not unpack archive: {}", e))?;
+
+    Ok(())
+}
+
+/// Reports the progress of linking packages into the prefix, which can take longer than
+/// extracting them, e.g. on network filesystems.
+struct InstallReporter {
+    reporter: ProgressReporter,
+}
+
+impl InstallReporter {
+    fn new() -> Self {
+        let reporter = ProgressReporter::new(0);
+        reporter.pb.set_message("Linking packages");
+        Self { reporter }
+    }
+}
+
+impl Reporter for InstallReporter {
+    fn on_transaction_start(&self, transaction: &Transaction<PrefixRecord, RepoDataRecord>) {
+        self.reporter
+            .pb
+            .set_length(transaction.operations.len() as u64);
+    }
+
+    fn on_transaction_operation_start(&self, _operation: usize) {}
+
+    fn on_populate_cache_start(&self, operation: usize, _record: &RepoDataRecord) -> usize {
+        operation
+    }
+
+    fn on_validate_start(&self, cache_entry: usize) -> usize {
+        cache_entry
+    }
+
+    fn on_validate_complete(&self, _validate_idx: usize) {}
+
+    fn on_download_start(&self, cache_entry: usize) -> usize {
+        cache_entry
+    }
+
+    fn on_download_progress(&self, _download_idx: usize, _progress: u64, _total: Option<u64>) {}
+
+    fn on_download_completed(&self, _download_idx: usize) {}
+
+    fn on_populate_cache_complete(&self, _cache_entry: usize) {}
+
+    fn on_unlink_start(&self, operation: usize, record: &PrefixRecord) -> usize {
+        self.reporter.pb.set_message(format!(
+            "Unlinking {}",
+            record.repodata_record.package_record.name.as_normalized()
+        ));
+        operation
+    }
+
+    fn on_unlink_complete(&self, _index: usize) {}
 
-    let reader = tokio::io::BufReader::new(file);
-    let mut archive = Archive::new(reader);
+    fn on_link_start(&self, operation: usize, record: &RepoDataRecord) -> usize {
+        self.reporter.pb.set_message(format!(
+            "Linking {}",
+            record.package_record.name.as_normalized()
+        ));
+        operation
+    }
 
-    archive
-        .unpack(target_dir)
-        .await
-        .map_err(|e| anyhow!("could not unpack archive: {}", e))?;
+    fn on_link_complete(&self, _index: usize) {}
 
-    Ok(())
+    fn on_transaction_operation_complete(&self, _operation: usize) {
+        self.reporter.pb.inc(1);
+    }
+
+    fn on_transaction_complete(&self) {
+        self.reporter.pb.finish_and_clear();
+    }
 }
 
-async fn create_prefix(channel_dir: &Path, target_prefix: &Path, cache_dir: &Path) -> Result<()> {
+/// Install all packages of a local channel into `target_prefix`.
+///
+/// `channel_dir` needs to contain one directory per subdir with a `repodata.json` file and the
+/// referenced packages. The packages are extracted into `cache_dir` before being linked into the
+/// prefix, so `cache_dir` should be on the same filesystem as `target_prefix`.
+/// Up to `extract_concurrency` packages are extracted at the same time, by default one per CPU.
+///
+/// Packages that are listed in the repodata but missing from `channel_dir` need to be installed in
+/// `target_prefix` already, e.g. the packages that a delta pack leaves out.
+pub async fn create_prefix(
+    channel_dir: &Path,
+    target_prefix: &Path,
+    cache_dir: &Path,
+    extract_concurrency: Option<NonZeroUsize>,
+) -> Result<()> {
     let packages = collect_packages(channel_dir)
         .await
         .map_err(|e| anyhow!("could not collect packages: {}", e))?;
 
-    eprintln!(
+    status!(
         "⏳ Extracting and installing {} packages to {}...",
         packages.len(),
         cache_dir.display()
     );
-    let reporter = ProgressReporter::new(packages.len() as u64);
 
     // extract packages to cache
     tracing::info!("Creating cache with {} packages", packages.len());
     let package_cache = PackageCache::new(cache_dir);
 
-    let repodata_records: Vec<RepoDataRecord> = stream::iter(packages)
-        .map(|(file_name, package_record)| {
-            let cache_key = CacheKey::from(&package_record);
+    // Decompressing a single package cannot be parallelized, so we extract several packages at
+    // once on the blocking thread pool. Starting with the largest packages avoids ending up with a
+    // single large package being extracted while all other threads are idle.
+    let (packages, installed_packages): (Vec<_>, Vec<_>) =
+        packages
+            .into_iter()
+            .partition(|(file_name, package_record)| {
+                channel_dir
+                    .join(&package_record.subdir)
+                    .join(file_name)
+                    .exists()
+            });
+    if !installed_packages.is_empty() {
+        tracing::info!(
+            "{} packages are not contained in the pack and expected to be installed already",
+            installed_packages.len()
+        );
+    }
 
+    // The progress is reported in bytes of the package files to show the extraction throughput.
+    let mut packages = packages
+        .into_iter()
+        .map(|(file_name, package_record)| {
             let package_path = channel_dir.join(&package_record.subdir).join(&file_name);
+            let size = std::fs::metadata(&package_path)
+                .map_err(|e| anyhow!("could not read {}: {}", package_path.display(), e))?
+                .len();
+            Ok((file_name, package_record, package_path, size))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    packages.sort_by_key(|(_, _, _, size)| std::cmp::Reverse(*size));
+    let reporter = ProgressReporter::new_bytes(packages.iter().map(|(_, _, _, size)| size).sum());
+    let extract_concurrency = extract_concurrency
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    tracing::info!("Extracting up to {} packages at once", extract_concurrency);
+    let (package_cache_ref, reporter_ref) = (&package_cache, &reporter);
 
-            let url = Url::parse(&format!("file:///{}", file_name)).unwrap();
+    let mut repodata_records: Vec<RepoDataRecord> = stream::iter(packages)
+        .map(|(file_name, package_record, package_path, size)| {
+            let cache_key = CacheKey::from(&package_record);
+
+            let url = package_url(&file_name);
 
             let repodata_record = RepoDataRecord {
                 package_record,
@@ -205,7 +1755,18 @@ async fn create_prefix(channel_dir: &Path, target_prefix: &Path, cache_dir: &Pat
                 channel: None,
             };
 
-            async {
+            let verify_path = package_path.clone();
+            let verify_record = repodata_record.clone();
+            async move {
+                let (package_cache, reporter) = (package_cache_ref, reporter_ref);
+                // Packages are verified before extracting them so that corrupted or tampered
+                // packages are never extracted.
+                tokio::task::spawn_blocking(move || {
+                    verify_package_hash(&verify_path, &verify_record)
+                })
+                .await
+                .map_err(|e| anyhow!("could not verify package: {}", e))??;
+
                 // We have to prepare the package cache by inserting all packages into it.
                 // We can only do so by calling `get_or_fetch` on each package, which will
                 // use the provided closure to fetch the package and insert it into the cache.
@@ -214,7 +1775,13 @@ async fn create_prefix(channel_dir: &Path, target_prefix: &Path, cache_dir: &Pat
                         cache_key,
                         move |destination| {
                             let value = package_path.clone();
-                            async move { extract(&value, &destination).map(|_| ()) }
+                            async move {
+                                tokio::task::spawn_blocking(move || {
+                                    extract(&value, &destination).map(|_| ())
+                                })
+                                .await
+                                .unwrap_or(Err(ExtractError::Cancelled))
+                            }
                         },
                         None,
                     )
@@ -226,20 +1793,31 @@ async fn create_prefix(channel_dir: &Path, target_prefix: &Path, cache_dir: &Pat
                             e
                         )
                     })?;
-                reporter.pb.inc(1);
+                reporter.pb.inc(size);
 
                 Ok::<RepoDataRecord, anyhow::Error>(repodata_record)
             }
         })
-        .buffer_unordered(50)
+        .buffer_unordered(extract_concurrency)
         .try_collect()
         .await?;
+    repodata_records.extend(
+        installed_packages
+            .into_iter()
+            .map(|(file_name, package_record)| RepoDataRecord {
+                url: package_url(&file_name),
+                package_record,
+                file_name,
+                channel: None,
+            }),
+    );
 
-    // Invariant: all packages are in the cache
+    // Invariant: all packages are in the cache or installed already
     tracing::info!("Installing {} packages", repodata_records.len());
     let installer = Installer::default();
     installer
         .with_package_cache(package_cache)
+        .with_reporter(InstallReporter::new())
         .install(&target_prefix, repodata_records)
         .await
         .map_err(|e| anyhow!("could not install packages: {}", e))?;
@@ -256,19 +1834,97 @@ async fn create_prefix(channel_dir: &Path, target_prefix: &Path, cache_dir: &Pat
     Ok(())
 }
 
-async fn create_activation_script(
+/// Check that a package file of the pack matches the sha256 (or md5) hash in the repodata.
+/// Packages without a recorded hash are not verified.
+fn verify_package_hash(path: &Path, record: &RepoDataRecord) -> Result<()> {
+    let package = &record.package_record;
+    if let Some(expected) = package.sha256 {
+        let actual = rattler_digest::compute_file_digest::<rattler_digest::Sha256>(path)
+            .map_err(|e| anyhow!("could not hash {}: {}", record.file_name, e))?;
+        if actual != expected {
+            anyhow::bail!(
+                "{} is corrupt or was tampered with: sha256 is {:x}, expected {:x}",
+                record.file_name,
+                actual,
+                expected
+            );
+        }
+    } else if let Some(expected) = package.md5 {
+        let actual = rattler_digest::compute_file_digest::<rattler_digest::Md5>(path)
+            .map_err(|e| anyhow!("could not hash {}: {}", record.file_name, e))?;
+        if actual != expected {
+            anyhow::bail!(
+                "{} is corrupt or was tampered with: md5 is {:x}, expected {:x}",
+                record.file_name,
+                actual,
+                expected
+            );
+        }
+    } else {
+        tracing::warn!(
+            "No hash recorded for {}, skipping verification",
+            record.file_name
+        );
+    }
+    Ok(())
+}
+
+/// The URL that is recorded for a package from the pack in `conda-meta`, e.g. `file:///a-1-0.conda`.
+///
+/// The file name is percent-encoded so that spaces, unicode characters, `#` or `?` are preserved.
+fn package_url(file_name: &str) -> Url {
+    let mut url = Url::parse("file:///").expect("valid url");
+    url.path_segments_mut()
+        .expect("file urls have a path")
+        .pop_if_empty()
+        .push(file_name);
+    url
+}
+
+/// Regenerate the activation script of an environment that was already unpacked to `prefix`.
+///
+/// The script for `shell` is written to `destination`, next to the scripts of other shells,
+/// without touching the environment itself. Returns the path of the activation script.
+pub async fn write_activation_script(
+    prefix: &Path,
     destination: &Path,
+    shell: ShellEnum,
+    path_modification: PathModification,
+) -> Result<PathBuf> {
+    if !prefix.join("conda-meta").is_dir() {
+        anyhow::bail!("{} is not a conda environment", prefix.display());
+    }
+    let prefix = std::path::absolute(prefix)
+        .map_err(|e| anyhow!("Could not resolve {}: {}", prefix.display(), e))?;
+
+    let activate_path = default_activation_script_path(destination, &shell);
+    create_activation_script(&activate_path, &prefix, shell, path_modification)
+        .await
+        .map_err(|e| anyhow!("Could not create activation script: {}", e))
+}
+
+/// The default path of the activation script for `shell` in `destination`.
+fn default_activation_script_path(destination: &Path, shell: &ShellEnum) -> PathBuf {
+    destination.join(format!("activate.{}", shell.extension()))
+}
+
+async fn create_activation_script(
+    activate_path: &Path,
     prefix: &Path,
     shell: ShellEnum,
-) -> Result<()> {
-    let file_extension = shell.extension();
-    let activate_path = destination.join(format!("activate.{}", file_extension));
+    path_modification: PathModification,
+) -> Result<PathBuf> {
+    if let Some(parent) = activate_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| anyhow!("Could not create directory {}: {}", parent.display(), e))?;
+    }
     let activator = Activator::from_path(prefix, shell, Platform::current())?;
 
     let result = activator.activation(ActivationVariables {
         conda_prefix: None,
         path: None,
-        path_modification_behavior: PathModificationBehavior::Prepend,
+        path_modification_behavior: path_modification.into(),
     })?;
 
     let contents = result.script.contents()?;
@@ -276,7 +1932,7 @@ async fn create_activation_script(
         .await
         .map_err(|e| anyhow!("Could not write activate script: {}", e))?;
 
-    Ok(())
+    Ok(activate_path.to_path_buf())
 }
 
 /* --------------------------------------------------------------------------------------------- */
@@ -285,14 +1941,149 @@ async fn create_activation_script(
 
 #[cfg(test)]
 mod tests {
-    use crate::PIXI_PACK_VERSION;
-
     use super::*;
     use rstest::*;
     use serde_json::json;
+    use std::collections::BTreeMap;
     use std::io::Write;
     use tempfile::NamedTempFile;
 
+    #[rstest]
+    #[case("numpy-2.0.0-py312_0.conda", "file:///numpy-2.0.0-py312_0.conda")]
+    #[case("my pkg-1.0-0.conda", "file:///my%20pkg-1.0-0.conda")]
+    #[case("pkg#1?-ü-0.conda", "file:///pkg%231%3F-%C3%BC-0.conda")]
+    fn test_package_url(#[case] file_name: &str, #[case] expected: &str) {
+        assert_eq!(package_url(file_name).as_str(), expected);
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[tokio::test]
+    async fn test_activation_script_exotic_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("with spaces ünïcødé 'quoted'");
+        let prefix = destination.join("my env");
+        std::fs::create_dir_all(prefix.join("bin")).unwrap();
+
+        let activation_script = create_activation_script(
+            &destination.join("activate.sh"),
+            &prefix,
+            ShellEnum::Bash(Default::default()),
+            PathModification::Prepend,
+        )
+        .await
+        .unwrap();
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(r#". "$1" && printf '%s\n%s' "$CONDA_PREFIX" "${PATH%%:*}""#)
+            .arg("bash")
+            .arg(&activation_script)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!("{}\n{}", prefix.display(), prefix.join("bin").display())
+        );
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case(PathModification::Prepend, "{bin}:/usr/bin:/bin")]
+    #[case(PathModification::Append, "/usr/bin:/bin:{bin}")]
+    #[case(PathModification::Replace, "{bin}")]
+    #[tokio::test]
+    async fn test_activation_script_path_modification(
+        #[case] path_modification: PathModification,
+        #[case] expected: &str,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("env");
+        std::fs::create_dir_all(prefix.join("bin")).unwrap();
+
+        let activation_script = create_activation_script(
+            &dir.path().join("activate.sh"),
+            &prefix,
+            ShellEnum::Bash(Default::default()),
+            path_modification,
+        )
+        .await
+        .unwrap();
+        let output = std::process::Command::new("/bin/bash")
+            .arg("-c")
+            .arg(r#". "$1" && printf '%s' "$PATH""#)
+            .arg("bash")
+            .arg(&activation_script)
+            .env("PATH", "/usr/bin:/bin")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected.replace("{bin}", prefix.join("bin").to_str().unwrap())
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_write_activation_script() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("env");
+        assert!(write_activation_script(
+            &prefix,
+            dir.path(),
+            ShellEnum::Bash(Default::default()),
+            PathModification::default()
+        )
+        .await
+        .is_err());
+
+        std::fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        let activation_script = write_activation_script(
+            &prefix,
+            dir.path(),
+            ShellEnum::Fish(Default::default()),
+            PathModification::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(activation_script, dir.path().join("activate.fish"));
+        let contents = std::fs::read_to_string(activation_script).unwrap();
+        assert!(contents.contains(prefix.to_str().unwrap()));
+    }
+
+    #[rstest]
+    #[case(100, true, false, (500, 500))]
+    #[case(100, false, false, (500, 400))]
+    #[case(100, true, true, (400, 400))]
+    #[case(100, false, true, (400, 400))]
+    #[case(u64::MAX, false, false, (u64::MAX, u64::MAX))]
+    fn test_required_disk_space(
+        #[case] pack_size: u64,
+        #[case] same_filesystem: bool,
+        #[case] in_place: bool,
+        #[case] expected: (u64, u64),
+    ) {
+        assert_eq!(
+            required_disk_space(pack_size, same_filesystem, in_place),
+            expected
+        );
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn test_check_disk_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("does-not-exist").join("env");
+        assert!(check_disk_space(1, false, dir.path(), &prefix).is_ok());
+        let error = check_disk_space(u64::MAX / 8, false, dir.path(), &prefix).unwrap_err();
+        assert!(
+            error.to_string().contains("Not enough disk space"),
+            "{}",
+            error
+        );
+    }
+
     fn other_platform() -> Platform {
         match Platform::current() {
             Platform::Linux64 => Platform::Win64,
@@ -308,8 +2099,8 @@ mod tests {
         let mut metadata_file = NamedTempFile::new().unwrap();
         let metadata = PixiPackMetadata {
             version,
-            pixi_pack_version: Some(PIXI_PACK_VERSION.to_string()),
             platform,
+            ..Default::default()
         };
         let buffer = metadata_file.as_file_mut();
         buffer
@@ -352,6 +2143,190 @@ mod tests {
         assert_eq!(error.to_string(), "Unsupported pixi-pack version: v0");
     }
 
+    #[rstest]
+    fn test_install_summary() {
+        let package = |name: &str, version: &str, build: &str| {
+            PackageRecord::new(
+                name.parse().unwrap(),
+                version.parse::<rattler_conda_types::Version>().unwrap(),
+                build.to_string(),
+            )
+        };
+        let packages = [
+            package("python", "3.12.3", "h2628c8c_0_cpython"),
+            package("ca-certificates", "2024.7.4", "hbcca054_0"),
+        ];
+        let summary = install_summary(&packages, 2048, Some(". \"/tmp/activate.sh\"\n"));
+        assert_eq!(
+            summary,
+            [
+                "  Package          Version   Build",
+                "  ca-certificates  2024.7.4  hbcca054_0",
+                "  python           3.12.3    h2628c8c_0_cpython",
+                "Installed 2 packages (2.00 KiB).",
+                "To activate the environment, run:",
+                "  . \"/tmp/activate.sh\"",
+                "",
+            ]
+            .join("\n")
+        );
+        assert!(
+            install_summary(&packages, 2048, None).ends_with("Installed 2 packages (2.00 KiB).\n")
+        );
+    }
+
+    #[rstest]
+    fn test_activation_script_path() {
+        let shell = ShellEnum::Bash(Default::default());
+        let options = UnpackOptions::builder().output_directory("/opt").build();
+        assert_eq!(
+            options.activation_script_path(&shell),
+            Some(PathBuf::from("/opt/activate.sh"))
+        );
+        let options = UnpackOptions::builder()
+            .output_directory("/opt")
+            .activation_script(PathBuf::from("scripts/activate-env.sh"))
+            .build();
+        assert_eq!(
+            options.activation_script_path(&shell),
+            Some(PathBuf::from("/opt/scripts/activate-env.sh"))
+        );
+        let options = UnpackOptions::builder()
+            .output_directory("/opt")
+            .activation_script(PathBuf::from("/etc/profile.d/env.sh"))
+            .build();
+        assert_eq!(
+            options.activation_script_path(&shell),
+            Some(PathBuf::from("/etc/profile.d/env.sh"))
+        );
+        let options = UnpackOptions::builder()
+            .skip_activation_script(true)
+            .build();
+        assert_eq!(options.activation_script_path(&shell), None);
+    }
+
+    #[rstest]
+    fn test_select_environment() {
+        let environments = vec!["default".to_string(), "gpu".to_string()];
+        assert_eq!(
+            select_environment(&environments, Some("gpu")).unwrap(),
+            "gpu"
+        );
+        let error = select_environment(&environments, None).unwrap_err();
+        assert!(error.to_string().contains("default, gpu"), "{}", error);
+        let error = select_environment(&environments, Some("cpu")).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("does not contain environment cpu"),
+            "{}",
+            error
+        );
+    }
+
+    #[rstest]
+    fn test_validate_compatibility() {
+        let metadata = PixiPackMetadata {
+            platform: Platform::Linux64,
+            ..Default::default()
+        };
+        assert!(validate_compatibility(&metadata, Platform::Linux64).is_ok());
+        assert!(validate_compatibility(&metadata, Platform::Win64).is_err());
+
+        let metadata = PixiPackMetadata {
+            platform: Platform::NoArch,
+            ..metadata
+        };
+        assert!(validate_compatibility(&metadata, Platform::Linux64).is_ok());
+        assert!(validate_compatibility(&metadata, Platform::Win64).is_ok());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_read_metadata(metadata_file: NamedTempFile) {
+        let pack_dir = tempfile::tempdir().unwrap();
+        let pack_file = pack_dir.path().join("environment.tar");
+        let mut builder = tokio_tar::Builder::new(fs::File::create(&pack_file).await.unwrap());
+        builder
+            .append_path_with_name(metadata_file.path(), PIXI_PACK_METADATA_PATH)
+            .await
+            .unwrap();
+        builder.finish().await.unwrap();
+
+        let metadata = read_metadata(&pack_file).await.unwrap();
+        assert_eq!(metadata.platform, Platform::current());
+        assert_eq!(metadata.version, DEFAULT_PIXI_PACK_VERSION);
+
+        std::fs::copy(
+            metadata_file.path(),
+            pack_dir.path().join(PIXI_PACK_METADATA_PATH),
+        )
+        .unwrap();
+        assert_eq!(read_metadata(pack_dir.path()).await.unwrap(), metadata);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_read_metadata_from_url(metadata_file: NamedTempFile) {
+        use tokio::io::AsyncWriteExt;
+
+        let pack_dir = tempfile::tempdir().unwrap();
+        let pack_file = pack_dir.path().join("environment.tar");
+        let mut builder = tokio_tar::Builder::new(fs::File::create(&pack_file).await.unwrap());
+        builder
+            .append_path_with_name(metadata_file.path(), PIXI_PACK_METADATA_PATH)
+            .await
+            .unwrap();
+        builder.finish().await.unwrap();
+        let pack = std::fs::read(&pack_file).unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/environment.tar", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let responses = [
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    pack.len()
+                )
+                .into_bytes()
+                .into_iter()
+                .chain(pack)
+                .collect::<Vec<_>>(),
+                b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                    .to_vec(),
+            ];
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut buf = [0; 1024];
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                socket.write_all(&response).await.unwrap();
+            }
+        });
+
+        assert!(is_url(Path::new(&url)));
+        assert!(!is_url(Path::new("s3://bucket/environment.tar")));
+        let metadata = read_metadata(Path::new(&url)).await.unwrap();
+        assert_eq!(metadata.platform, Platform::current());
+        let error = read_metadata(Path::new(&url)).await.unwrap_err();
+        assert!(error.to_string().contains("404"), "{}", error);
+        server.await.unwrap();
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_read_metadata_missing() {
+        let pack_dir = tempfile::tempdir().unwrap();
+        let pack_file = pack_dir.path().join("environment.tar");
+        let builder = tokio_tar::Builder::new(fs::File::create(&pack_file).await.unwrap());
+        builder.into_inner().await.unwrap();
+
+        assert!(read_metadata(&pack_file).await.is_err());
+    }
+
     #[rstest]
     #[tokio::test]
     async fn test_metadata_file_wrong_platform(
@@ -365,4 +2340,451 @@ mod tests {
             "The pack was created for a different platform"
         );
     }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_install_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_file = dir.path().join("environment.tar");
+        std::fs::write(&pack_file, "pack").unwrap();
+        let prefix = dir.path().join("env");
+        std::fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+
+        let marker = install_marker(&pack_file).unwrap();
+        assert!(!is_installed(&prefix, &marker).await);
+
+        // Markers from other versions of pixi-pack are still valid.
+        let written = InstallMarker {
+            pack_sha256: marker.pack_sha256.clone(),
+            pixi_pack_version: "0.0.1".to_string(),
+        };
+        std::fs::write(
+            prefix.join(INSTALL_MARKER_PATH),
+            serde_json::to_string(&written).unwrap(),
+        )
+        .unwrap();
+        assert!(is_installed(&prefix, &marker).await);
+
+        std::fs::write(&pack_file, "other pack").unwrap();
+        assert!(!is_installed(&prefix, &install_marker(&pack_file).unwrap()).await);
+    }
+
+    #[rstest]
+    fn test_install_marker_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(PIXI_PACK_METADATA_PATH), "{}").unwrap();
+        let marker = install_marker(dir.path()).unwrap();
+        assert_eq!(
+            marker.pack_sha256,
+            DirectoryChecksums::new(dir.path()).unwrap().digest
+        );
+
+        std::fs::write(dir.path().join(PIXI_PACK_METADATA_PATH), "[]").unwrap();
+        assert_ne!(install_marker(dir.path()).unwrap(), marker);
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn test_run_allowed_link_scripts() {
+        let prefix = tempfile::tempdir().unwrap();
+        let prefix = prefix.path();
+        std::fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        std::fs::create_dir_all(prefix.join("bin")).unwrap();
+        for (name, script) in [
+            ("allowed", "touch \"$PREFIX/allowed-ran\""),
+            ("failing", "exit 1"),
+            ("forbidden", "touch \"$PREFIX/forbidden-ran\""),
+            ("no-script", ""),
+        ] {
+            let file_name = format!("{}-1.0-0.conda", name);
+            let record = RepoDataRecord {
+                package_record: PackageRecord::new(
+                    name.parse().unwrap(),
+                    "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+                    "0".to_string(),
+                ),
+                url: package_url(&file_name),
+                file_name,
+                channel: None,
+            };
+            PrefixRecord::from_repodata_record(record, None, None, vec![], None, None)
+                .write_to_path(prefix.join(format!("conda-meta/{}-1.0-0.json", name)), true)
+                .unwrap();
+            if !script.is_empty() {
+                std::fs::write(prefix.join(format!("bin/.{}-post-link.sh", name)), script).unwrap();
+            }
+        }
+
+        let allow = ["allowed".parse().unwrap(), "failing".parse().unwrap()];
+        let report = run_allowed_link_scripts(prefix, &allow).unwrap();
+        assert_eq!(
+            report,
+            LinkScriptsReport {
+                succeeded: vec!["allowed".into()],
+                failed: vec!["failing".into()],
+                skipped: vec!["forbidden".into()],
+            }
+        );
+        assert!(prefix.join("allowed-ran").exists());
+        assert!(!prefix.join("forbidden-ran").exists());
+    }
+
+    #[rstest]
+    fn test_verify_package_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pkg-1.0-0.conda");
+        std::fs::write(&path, b"package").unwrap();
+        let mut record = RepoDataRecord {
+            package_record: PackageRecord::new(
+                "pkg".parse().unwrap(),
+                "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+                "0".to_string(),
+            ),
+            url: package_url("pkg-1.0-0.conda"),
+            file_name: "pkg-1.0-0.conda".to_string(),
+            channel: None,
+        };
+        // Packages without hashes are not verified.
+        assert!(verify_package_hash(&path, &record).is_ok());
+
+        record.package_record.md5 = Some(
+            rattler_digest::compute_bytes_digest::<rattler_digest::Md5>(b"package"),
+        );
+        assert!(verify_package_hash(&path, &record).is_ok());
+        record.package_record.sha256 =
+            Some(rattler_digest::compute_bytes_digest::<rattler_digest::Sha256>(b"package"));
+        assert!(verify_package_hash(&path, &record).is_ok());
+
+        std::fs::write(&path, b"tampered").unwrap();
+        let error = verify_package_hash(&path, &record).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("pkg-1.0-0.conda is corrupt or was tampered with: sha256 is "));
+        record.package_record.sha256 = None;
+        let error = verify_package_hash(&path, &record).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("pkg-1.0-0.conda is corrupt or was tampered with: md5 is "));
+    }
+
+    #[rstest]
+    fn test_record_requested_specs() {
+        let prefix = tempfile::tempdir().unwrap();
+        let prefix = prefix.path();
+        std::fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        for name in ["python", "pip", "openssl"] {
+            let file_name = format!("{}-1.0-0.conda", name);
+            let record = RepoDataRecord {
+                package_record: PackageRecord::new(
+                    name.parse().unwrap(),
+                    "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+                    "0".to_string(),
+                ),
+                url: package_url(&file_name),
+                file_name,
+                channel: None,
+            };
+            PrefixRecord::from_repodata_record(record, None, None, vec![], None, None)
+                .write_to_path(prefix.join(format!("conda-meta/{}-1.0-0.json", name)), true)
+                .unwrap();
+        }
+
+        let specs = ["pip".to_string(), "python >=3.12".to_string()];
+        record_requested_specs(prefix, &specs).unwrap();
+
+        let requested: BTreeMap<String, Option<String>> = PrefixRecord::collect_from_prefix(prefix)
+            .unwrap()
+            .into_iter()
+            .map(|r| {
+                (
+                    r.repodata_record
+                        .package_record
+                        .name
+                        .as_normalized()
+                        .to_string(),
+                    r.requested_spec,
+                )
+            })
+            .collect();
+        assert_eq!(requested["python"].as_deref(), Some("python >=3.12"));
+        assert_eq!(requested["pip"].as_deref(), Some("pip"));
+        assert_eq!(requested["openssl"], None);
+        assert_eq!(
+            std::fs::read_to_string(prefix.join("conda-meta/pinned")).unwrap(),
+            "python >=3.12\n"
+        );
+    }
+
+    #[rstest]
+    fn test_verify_signature_unsigned() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_file = dir.path().join("environment.tar");
+        std::fs::write(&pack_file, "pack").unwrap();
+        let verify_key = dir.path().join("key.pub");
+        std::fs::write(
+            &verify_key,
+            "untrusted comment: minisign public key\nRWQBAQEBAQEBAYqJ5A3Vh1wbPGzCTAmjRkW1lHZaeXR1EWUivl5ssuEj\n",
+        )
+        .unwrap();
+
+        assert!(verify_signature(&pack_file, None, false).is_ok());
+        assert!(verify_signature(&pack_file, Some(&verify_key), false).is_ok());
+        assert_eq!(
+            verify_signature(&pack_file, None, true)
+                .unwrap_err()
+                .to_string(),
+            "Requiring a signature needs a key to verify it with"
+        );
+        assert!(verify_signature(&pack_file, Some(&verify_key), true)
+            .unwrap_err()
+            .to_string()
+            .contains("environment.tar is not signed"));
+
+        std::fs::write(signature_path(&pack_file), "not a signature").unwrap();
+        assert_eq!(
+            verify_signature(&pack_file, Some(&verify_key), false)
+                .unwrap_err()
+                .to_string(),
+            "Could not verify the signature of the pack: malformed signature"
+        );
+    }
+
+    #[rstest]
+    fn test_install_reporter() {
+        let reporter = InstallReporter::new();
+        let record = RepoDataRecord {
+            package_record: PackageRecord::new(
+                PackageName::new_unchecked("numpy"),
+                "2.0.0".parse::<rattler_conda_types::Version>().unwrap(),
+                "py312_0".to_string(),
+            ),
+            file_name: "numpy-2.0.0-py312_0.conda".to_string(),
+            url: package_url("numpy-2.0.0-py312_0.conda"),
+            channel: None,
+        };
+        assert_eq!(reporter.on_link_start(3, &record), 3);
+        assert_eq!(reporter.reporter.pb.message(), "Linking numpy");
+        reporter.on_link_complete(3);
+        reporter.on_transaction_operation_complete(3);
+        assert_eq!(reporter.reporter.pb.position(), 1);
+        reporter.on_transaction_complete();
+        assert!(reporter.reporter.pb.is_finished());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_target_prefix() {
+        let options = UnpackOptions::builder().output_directory("/out").build();
+        assert_eq!(options.prefix(), Path::new("/out/env"));
+        let target_prefix = std::path::absolute("opt/myapp/env").unwrap();
+        let options = UnpackOptions::builder()
+            .output_directory("/out")
+            .target_prefix(target_prefix.clone())
+            .build();
+        assert_eq!(options.prefix(), target_prefix);
+
+        let options = UnpackOptions::builder()
+            .target_prefix(PathBuf::from("relative/env"))
+            .build();
+        let error = unpack(options).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The target prefix must be an absolute path: relative/env"
+        );
+    }
+
+    #[rstest]
+    #[case::no_requirements(None)]
+    #[case::unmet_requirement(Some(if cfg!(windows) { "__unix" } else { "__win" }))]
+    #[tokio::test]
+    async fn test_verify_only(#[case] requirement: Option<&str>) {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_dir = dir.path().join("pack");
+        let subdir = pack_dir.join(CHANNEL_DIRECTORY_NAME).join("noarch");
+        std::fs::create_dir_all(&subdir).unwrap();
+        std::fs::write(
+            subdir.join("pkg-1.0-0.conda"),
+            b"not really a conda package",
+        )
+        .unwrap();
+        let repodata = serde_json::json!({
+            "info": {"subdir": "noarch"},
+            "packages": {},
+            "packages.conda": {
+                "pkg-1.0-0.conda": {
+                    "name": "pkg",
+                    "version": "1.0",
+                    "build": "0",
+                    "build_number": 0,
+                    "depends": [],
+                    "subdir": "noarch",
+                    "size": 26,
+                }
+            }
+        });
+        std::fs::write(subdir.join("repodata.json"), repodata.to_string()).unwrap();
+        let metadata = PixiPackMetadata {
+            system_requirements: requirement
+                .map(|name| SystemRequirement {
+                    name: name.to_string(),
+                    spec: String::new(),
+                    packages: vec!["pkg".to_string()],
+                })
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        std::fs::write(
+            pack_dir.join(PIXI_PACK_METADATA_PATH),
+            serde_json::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+        let pack_file = dir.path().join("environment.tar");
+        crate::low_level::archive_directory(
+            &pack_dir,
+            &pack_file,
+            crate::OutputFormat::Tar,
+            Platform::current(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+            crate::Compression::None,
+        )
+        .await
+        .unwrap();
+
+        let output_directory = dir.path().join("out");
+        let options = UnpackOptions::builder()
+            .pack_file(pack_file)
+            .output_directory(&output_directory)
+            .verify_only(true)
+            .build();
+        match requirement {
+            None => assert_eq!(unpack(options).await.unwrap(), UnpackOutcome::Verified),
+            Some(name) => {
+                let error = unpack(options).await.unwrap_err().to_string();
+                assert!(
+                    error.contains(&format!("{} is not available", name)),
+                    "{}",
+                    error
+                );
+            }
+        }
+        assert!(!output_directory.exists());
+    }
+
+    #[rstest]
+    fn test_verify_summary() {
+        let mut package = PackageRecord::new(
+            "cuda-version".parse().unwrap(),
+            "12.4".parse::<rattler_conda_types::Version>().unwrap(),
+            "h3060b56_3".to_string(),
+        );
+        package.size = Some(2048);
+        let requirements = [
+            SystemRequirement {
+                name: "__cuda".to_string(),
+                spec: ">=12.4".to_string(),
+                packages: vec!["cuda-version".to_string()],
+            },
+            SystemRequirement {
+                name: "__unix".to_string(),
+                spec: String::new(),
+                packages: vec!["cuda-version".to_string()],
+            },
+        ];
+        let host = HostVirtualPackages::from_packages([("__unix", None)]);
+        assert_eq!(
+            verify_summary([&package], &requirements, &host),
+            [
+                "  Package       Version  Build",
+                "  cuda-version  12.4     h3060b56_3",
+                "Would install 1 packages (2.00 KiB compressed).",
+                "System requirements:",
+                "  ❌ requires NVIDIA driver >= 550.54.14 (cuda-version): __cuda is not available",
+                "  ✅ requires __unix (cuda-version)",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_prepare_delta_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_packages = vec!["channel/noarch/pkg-1.0-0.conda".to_string()];
+        let base_dir = dir.path().join("base");
+        std::fs::create_dir_all(base_dir.join("channel/noarch")).unwrap();
+        std::fs::write(base_dir.join(&base_packages[0]), b"package").unwrap();
+        let base_pack = dir.path().join("base.tar");
+        crate::low_level::archive_directory(
+            &base_dir,
+            &base_pack,
+            crate::OutputFormat::Tar,
+            Platform::current(),
+            Default::default(),
+            None,
+            None,
+            Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        let prefix = dir.path().join("env");
+
+        // The packages are extracted from the base pack, either a tarball or a directory.
+        for base_pack in [&base_pack, &base_dir] {
+            let unpack_dir = tempfile::tempdir().unwrap();
+            prepare_delta_pack(
+                &base_packages,
+                Some(base_pack),
+                unpack_dir.path(),
+                false,
+                &prefix,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                std::fs::read(unpack_dir.path().join(&base_packages[0])).unwrap(),
+                b"package"
+            );
+        }
+
+        let missing = vec!["channel/noarch/other-1.0-0.conda".to_string()];
+        let error = prepare_delta_pack(&missing, Some(&base_pack), dir.path(), false, &prefix)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("channel/noarch/other-1.0-0.conda is not contained in base pack"));
+
+        // Without a base pack, the packages need to be installed in the prefix already.
+        let error = prepare_delta_pack(&base_packages, None, dir.path(), false, &prefix)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("1 packages are neither installed in"));
+        let mut package_record = PackageRecord::new(
+            "pkg".parse().unwrap(),
+            "1.0".parse::<rattler_conda_types::Version>().unwrap(),
+            "0".to_string(),
+        );
+        package_record.subdir = "noarch".to_string();
+        let record = RepoDataRecord {
+            package_record,
+            url: package_url("pkg-1.0-0.conda"),
+            file_name: "pkg-1.0-0.conda".to_string(),
+            channel: None,
+        };
+        std::fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        PrefixRecord::from_repodata_record(record, None, None, vec![], None, None)
+            .write_to_path(prefix.join("conda-meta/pkg-1.0-0.json"), true)
+            .unwrap();
+        prepare_delta_pack(&base_packages, None, dir.path(), false, &prefix)
+            .await
+            .unwrap();
+    }
 }
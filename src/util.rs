@@ -1,6 +1,51 @@
-use std::{path::Path, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, LazyLock, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
 
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rattler_conda_types::{package::ArchiveType, PrefixRecord};
+use tokio::io::{AsyncRead, ReadBuf};
+use walkdir::WalkDir;
+
+use crate::CHANNEL_DIRECTORY_NAME;
+
+/// All progress bars are drawn together so that concurrent packs don't overwrite each other.
+static PROGRESS_BARS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+/// Hide all progress bars, e.g. when events are printed as JSON.
+pub(crate) fn hide_progress_bars() {
+    PROGRESS_BARS.set_draw_target(ProgressDrawTarget::hidden());
+}
+
+tokio::task_local! {
+    static CURRENT_PROGRESS: CurrentProgress;
+}
+
+/// The progress bar that a task created most recently, e.g. to report the progress of unpack jobs
+/// of the socket API.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CurrentProgress(Arc<Mutex<Option<ProgressBar>>>);
+
+#[cfg_attr(not(unix), allow(dead_code))]
+impl CurrentProgress {
+    /// Run `future` and track the progress bars it creates.
+    pub async fn track<F: Future>(&self, future: F) -> F::Output {
+        CURRENT_PROGRESS.scope(self.clone(), future).await
+    }
+
+    /// The message, position and length of the current progress bar, if there is one.
+    pub fn get(&self) -> Option<(String, u64, Option<u64>)> {
+        let pb = self.0.lock().unwrap();
+        let pb = pb.as_ref()?;
+        Some((pb.message(), pb.position(), pb.length()))
+    }
+}
 
 /// Progress reporter that wraps a progress bar with default styles.
 pub struct ProgressReporter {
@@ -9,24 +54,277 @@ pub struct ProgressReporter {
 
 impl ProgressReporter {
     pub fn new(length: u64) -> Self {
-        let pb = ProgressBar::new(length).with_style(
-            ProgressStyle::with_template("[{elapsed_precise}] {bar:40.cyan/blue} {msg}")
+        Self::with_template(length, "[{elapsed_precise}] {bar:40.cyan/blue} {msg}")
+    }
+
+    /// Create a progress reporter that tracks a number of bytes instead of items.
+    pub fn new_bytes(length: u64) -> Self {
+        Self::with_template(
+            length,
+            "[{elapsed_precise}] {bar:40.cyan/blue} {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}",
+        )
+    }
+
+    /// Create a progress reporter for a stream of bytes of unknown length.
+    pub fn new_stream() -> Self {
+        Self::with_bar(
+            ProgressBar::no_length(),
+            "[{elapsed_precise}] {bytes} ({bytes_per_sec}) {msg}",
+        )
+    }
+
+    fn with_template(length: u64, template: &str) -> Self {
+        Self::with_bar(ProgressBar::new(length), template)
+    }
+
+    fn with_bar(pb: ProgressBar, template: &str) -> Self {
+        let pb = PROGRESS_BARS.add(pb).with_style(
+            ProgressStyle::with_template(template)
                 .expect("could not set progress style")
                 .progress_chars("##-"),
         );
         pb.enable_steady_tick(Duration::from_millis(500));
+        // Outside of `CurrentProgress::track`, progress bars are not tracked.
+        let _ = CURRENT_PROGRESS.try_with(|current| *current.0.lock().unwrap() = Some(pb.clone()));
         Self { pb }
     }
 }
 
+/// A reader that reports the number of bytes read from the inner reader.
+pub(crate) struct ProgressReader<R> {
+    inner: R,
+    pb: ProgressBar,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn new(inner: R, pb: ProgressBar) -> Self {
+        Self { inner, pb }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            self.pb.inc((buf.filled().len() - filled) as u64);
+        }
+        result
+    }
+}
+
+impl<R> Drop for ProgressReader<R> {
+    fn drop(&mut self) {
+        self.pb.finish_and_clear();
+    }
+}
+
 /// Get the size of a file or directory in bytes.
+///
+/// Symbolic links are not followed and hard links are only counted once.
 pub fn get_size<P: AsRef<Path>>(path: P) -> std::io::Result<u64> {
-    let metadata = std::fs::metadata(&path)?;
-    let mut size = metadata.len();
-    if metadata.is_dir() {
-        for entry in std::fs::read_dir(&path)? {
-            size += get_size(entry?.path())?;
+    Ok(SizeReport::new(path)?.total)
+}
+
+/// A breakdown of the disk usage of a pack directory or a prefix, similar to `du`.
+///
+/// Symbolic links are not followed and files with multiple hard links are only counted once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SizeReport {
+    /// The total size in bytes.
+    pub total: u64,
+    /// The size of every top-level entry, e.g., `channel` or `lib`.
+    pub top_level: BTreeMap<String, u64>,
+    /// The size of every package.
+    /// For packs, these are the package files in the channel directory keyed by their file name.
+    /// For prefixes, these are the files of the installed packages keyed by
+    /// `name-version-build`.
+    pub packages: BTreeMap<String, u64>,
+}
+
+impl SizeReport {
+    /// Walk a pack directory, a prefix or any other file or directory.
+    pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let mut report = Self::default();
+        let mut seen_inodes = HashSet::new();
+        // The counted size of every file, relative to `path`.
+        let mut sizes: HashMap<PathBuf, u64> = HashMap::new();
+
+        for entry in WalkDir::new(path) {
+            let entry = entry?;
+            let metadata = entry.path().symlink_metadata()?;
+            if !metadata.is_dir() && !first_hard_link(&metadata, &mut seen_inodes) {
+                continue;
+            }
+            let size = metadata.len();
+            report.total += size;
+
+            let relative_path = entry
+                .path()
+                .strip_prefix(path)
+                .expect("walked path is inside the root");
+            if let Some(top_level) = relative_path.components().next() {
+                *report
+                    .top_level
+                    .entry(top_level.as_os_str().to_string_lossy().into_owned())
+                    .or_default() += size;
+            }
+            sizes.insert(relative_path.to_path_buf(), size);
+        }
+
+        let channel_dir = Path::new(CHANNEL_DIRECTORY_NAME);
+        for (relative_path, size) in &sizes {
+            let in_channel = relative_path.parent().and_then(Path::parent) == Some(channel_dir);
+            if in_channel && ArchiveType::try_from(relative_path.as_path()).is_some() {
+                let file_name = relative_path.file_name().expect("package has a file name");
+                report
+                    .packages
+                    .insert(file_name.to_string_lossy().into_owned(), *size);
+            }
         }
+
+        if path.join("conda-meta").is_dir() {
+            for record in PrefixRecord::collect_from_prefix(path)? {
+                let package = &record.repodata_record.package_record;
+                let size = record.files.iter().filter_map(|file| sizes.get(file)).sum();
+                report.packages.insert(
+                    format!(
+                        "{}-{}-{}",
+                        package.name.as_normalized(),
+                        package.version,
+                        package.build
+                    ),
+                    size,
+                );
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// The number of bytes that are available to unprivileged users on the filesystem containing
+/// `path`, or `None` if it cannot be determined.
+#[cfg(unix)]
+#[allow(clippy::useless_conversion)] // the field types differ between platforms
+pub(crate) fn available_space(path: &Path) -> Option<u64> {
+    let stat = nix::sys::statvfs::statvfs(path).ok()?;
+    Some(u64::from(stat.blocks_available()) * u64::from(stat.fragment_size()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Whether two existing paths are on the same filesystem. Assumes they are if unknown.
+#[cfg(unix)]
+pub(crate) fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn same_filesystem(_a: &Path, _b: &Path) -> bool {
+    true
+}
+
+/// Whether this is the first hard link of a file that we encounter.
+#[cfg(unix)]
+fn first_hard_link(metadata: &std::fs::Metadata, seen_inodes: &mut HashSet<(u64, u64)>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() <= 1 || seen_inodes.insert((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn first_hard_link(_metadata: &std::fs::Metadata, _seen_inodes: &mut HashSet<(u64, u64)>) -> bool {
+    true
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+    use tokio::io::AsyncReadExt;
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_progress_reader() {
+        let data = vec![0u8; 100_000];
+        let pb = ProgressBar::hidden();
+        let mut reader = ProgressReader::new(data.as_slice(), pb.clone());
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).await.unwrap();
+        assert_eq!(contents, data);
+        assert_eq!(pb.position(), data.len() as u64);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_current_progress() {
+        let current = CurrentProgress::default();
+        assert_eq!(current.get(), None);
+        let reporter = current
+            .track(async {
+                ProgressReporter::new(1);
+                let reporter = ProgressReporter::new(10);
+                reporter.pb.set_message("Extracting");
+                reporter.pb.inc(3);
+                reporter
+            })
+            .await;
+        assert_eq!(current.get(), Some(("Extracting".to_string(), 3, Some(10))));
+        // Progress bars created outside of `track` are not tracked.
+        ProgressReporter::new(5);
+        reporter.pb.inc(1);
+        assert_eq!(current.get(), Some(("Extracting".to_string(), 4, Some(10))));
+    }
+
+    #[rstest]
+    fn test_size_report_pack_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let subdir = dir.path().join("channel").join("noarch");
+        std::fs::create_dir_all(&subdir).unwrap();
+        std::fs::write(subdir.join("pkg-1.0-0.conda"), [0; 100]).unwrap();
+        std::fs::write(subdir.join("repodata.json"), [0; 10]).unwrap();
+        std::fs::write(dir.path().join("pixi-pack.json"), [0; 5]).unwrap();
+
+        let report = SizeReport::new(dir.path()).unwrap();
+        assert_eq!(
+            report.packages,
+            BTreeMap::from([("pkg-1.0-0.conda".to_string(), 100)])
+        );
+        assert_eq!(report.top_level["pixi-pack.json"], 5);
+        assert!(report.top_level["channel"] >= 110);
+        assert_eq!(report.total, get_size(dir.path()).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    fn test_size_report_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("large"), [0; 1000]).unwrap();
+        std::fs::write(dir.path().join("file"), [0; 100]).unwrap();
+        std::fs::hard_link(dir.path().join("file"), dir.path().join("hard-link")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("large"), dir.path().join("symlink"))
+            .unwrap();
+
+        let report = SizeReport::new(dir.path()).unwrap();
+        let files: u64 = report.top_level.values().sum();
+        assert!(files < 200, "{:?}", report);
+        assert_eq!(report.top_level.len(), 2);
     }
-    Ok(size)
 }
@@ -0,0 +1,612 @@
+use std::{
+    collections::HashSet,
+    io::{BufRead, Read, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use base64::engine::{general_purpose::STANDARD, Engine};
+use futures::StreamExt;
+use rattler_conda_types::{package::ArchiveType, RepoData};
+use rattler_digest::Sha256;
+
+use crate::{
+    unarchive,
+    unpack::{embedded_pack, is_stdin, is_url, ZSTD_MAGIC},
+    DirectoryChecksums, PixiPackMetadata, CHANNEL_DIRECTORY_NAME, CHECKSUMS_PATH,
+    DEFAULT_PIXI_PACK_VERSION, ENVIRONMENTS_DIRECTORY_NAME, PIXI_PACK_METADATA_PATH,
+};
+
+/// The result of [`validate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// The number of packages listed in the channel of the pack.
+    pub packages: usize,
+    /// The problems found in the pack.
+    pub problems: Vec<String>,
+}
+
+impl ValidationReport {
+    /// Whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check the structural integrity of a pack without installing it.
+///
+/// `pack_file` can either be a tarball, a self-extracting executable or a directory created with
+/// `--format dir`. The pack is valid if `pixi-pack.json` can be read by this version of
+/// pixi-pack, every package listed in the repodata of the channel exists with the recorded size
+/// and sha256 hash, and the channel contains no other files. Directory packs are checked against their `checksums.json`
+/// as well. An error is only returned if the pack cannot be read at all.
+pub async fn validate(pack_file: &Path) -> Result<ValidationReport> {
+    if pack_file.is_dir() {
+        return Ok(validate_directory(pack_file));
+    }
+
+    let tmp_dir =
+        tempfile::tempdir().map_err(|e| anyhow!("could not create temporary directory: {}", e))?;
+    let extract_dir = tmp_dir.path().join("pack");
+    if !is_stdin(pack_file) && !is_url(pack_file) && !is_archive(pack_file)? {
+        let archive_path = tmp_dir.path().join("environment.tar");
+        extract_embedded_archive(pack_file, &archive_path).await?;
+        unarchive(&archive_path, &extract_dir).await?;
+    } else {
+        unarchive(pack_file, &extract_dir).await?;
+    }
+    Ok(validate_directory(&extract_dir))
+}
+
+/// Whether `pack_file` can be read as a (possibly zstd compressed) tarball, i.e. it is not a
+/// self-extracting script. Binary self-extracting executables are read like tarballs.
+fn is_archive(pack_file: &Path) -> Result<bool> {
+    if embedded_pack(pack_file)?.is_some() {
+        return Ok(true);
+    }
+    let mut start = Vec::with_capacity(512);
+    std::fs::File::open(pack_file)
+        .and_then(|file| Read::read_to_end(&mut file.take(512), &mut start))
+        .map_err(|e| anyhow!("could not read {}: {}", pack_file.display(), e))?;
+    Ok(start.starts_with(&ZSTD_MAGIC) || start.get(257..262) == Some(b"ustar"))
+}
+
+/// The end of batch scripts, see [`EXECUTABLE_MARKERS`].
+const BATCH_END_MARKER: &[u8] = b"-----END CERTIFICATE-----";
+
+/// The lines before and after the base64 encoded payload of self-extracting executables.
+const EXECUTABLE_MARKERS: [(&[u8], &[u8]); 3] = [
+    (b"@@END_HEADER@@", b"@@END_ARCHIVE@@"),
+    (b"__END_HEADER__", b"__END_ARCHIVE__"),
+    (b"-----BEGIN CERTIFICATE-----", BATCH_END_MARKER),
+];
+
+/// Extract the tarball embedded in a self-extracting executable to `archive_path`.
+///
+/// Shell and PowerShell scripts contain the base64 encoded tarball between their header and the
+/// end-of-archive marker. Batch scripts contain a base64 encoded certificate block with a tarball
+/// of the pack (`environment.tar`) and the unpack executable. The payload is decoded line by line
+/// so that large executables are not read into memory.
+async fn extract_embedded_archive(executable: &Path, archive_path: &Path) -> Result<()> {
+    let file = std::fs::File::open(executable)
+        .map_err(|e| anyhow!("could not read {}: {}", executable.display(), e))?;
+    let mut lines = std::io::BufReader::new(file).split(b'\n').map(|line| {
+        line.map(|mut line| {
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            line
+        })
+        .map_err(|e| anyhow!("could not read {}: {}", executable.display(), e))
+    });
+    let mut end_marker = None;
+    for line in lines.by_ref() {
+        let line = line?;
+        end_marker = EXECUTABLE_MARKERS
+            .iter()
+            .find(|(start_marker, _)| *start_marker == line.as_slice())
+            .map(|(_, end_marker)| *end_marker);
+        if end_marker.is_some() {
+            break;
+        }
+    }
+    let end_marker = end_marker.ok_or(anyhow!(
+        "not a tarball or a self-extracting executable created by pixi-pack"
+    ))?;
+
+    let payload_path = if end_marker == BATCH_END_MARKER {
+        archive_path.with_extension("payload")
+    } else {
+        archive_path.to_path_buf()
+    };
+    let mut payload = std::io::BufWriter::new(
+        std::fs::File::create(&payload_path)
+            .map_err(|e| anyhow!("could not write embedded archive: {}", e))?,
+    );
+    // Lines are not necessarily a multiple of four characters long, so undecoded characters are
+    // carried over to the next line.
+    let mut encoded = Vec::new();
+    let mut complete = false;
+    for line in lines {
+        let line = line?;
+        if line == end_marker {
+            complete = true;
+            break;
+        }
+        encoded.extend_from_slice(&line);
+        let decodable = encoded.len() - encoded.len() % 4;
+        decode_into(&encoded[..decodable], &mut payload)?;
+        encoded.drain(..decodable);
+    }
+    if !complete {
+        anyhow::bail!("the embedded archive is truncated");
+    }
+    decode_into(&encoded, &mut payload)?;
+    payload
+        .into_inner()
+        .map_err(|e| anyhow!("could not write embedded archive: {}", e.error()))?;
+    if end_marker != BATCH_END_MARKER {
+        return Ok(());
+    }
+
+    let mut archive = tokio_tar::Archive::new(
+        tokio::fs::File::open(&payload_path)
+            .await
+            .map_err(|e| anyhow!("could not read the embedded archive: {}", e))?,
+    );
+    let mut entries = archive
+        .entries()
+        .map_err(|e| anyhow!("could not read the embedded archive: {}", e))?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry.map_err(|e| anyhow!("could not read the embedded archive: {}", e))?;
+        if entry.path()?.as_ref() == Path::new("environment.tar") {
+            let mut output = tokio::fs::File::create(archive_path)
+                .await
+                .map_err(|e| anyhow!("could not write embedded archive: {}", e))?;
+            tokio::io::copy(&mut entry, &mut output).await?;
+            return Ok(());
+        }
+    }
+    anyhow::bail!("the embedded archive does not contain environment.tar")
+}
+
+/// Decode the base64 encoded `encoded` and write it to `output`.
+fn decode_into(encoded: &[u8], output: &mut impl Write) -> Result<()> {
+    let decoded = STANDARD
+        .decode(encoded)
+        .map_err(|e| anyhow!("could not decode the embedded archive: {}", e))?;
+    output
+        .write_all(&decoded)
+        .map_err(|e| anyhow!("could not write embedded archive: {}", e))
+}
+
+fn validate_directory(dir: &Path) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    let metadata = std::fs::read_to_string(dir.join(PIXI_PACK_METADATA_PATH))
+        .map_err(|e| anyhow!("could not read {}: {}", PIXI_PACK_METADATA_PATH, e))
+        .and_then(|contents| PixiPackMetadata::from_json(&contents));
+    let metadata = match metadata {
+        Ok((metadata, _)) if metadata.version != DEFAULT_PIXI_PACK_VERSION => {
+            report
+                .problems
+                .push(format!("unsupported pack version: {}", metadata.version));
+            None
+        }
+        Ok((metadata, _)) => Some(metadata),
+        Err(e) => {
+            report.problems.push(e.to_string());
+            None
+        }
+    };
+
+    // Packs of multiple environments contain a pack for each of them.
+    let environments = metadata
+        .as_ref()
+        .map(|metadata| metadata.environments.as_slice())
+        .unwrap_or_default();
+    for environment in environments {
+        let environment_report =
+            validate_directory(&dir.join(ENVIRONMENTS_DIRECTORY_NAME).join(environment));
+        report.packages += environment_report.packages;
+        report.problems.extend(
+            environment_report
+                .problems
+                .into_iter()
+                .map(|problem| format!("environment {}: {}", environment, problem)),
+        );
+    }
+    if !environments.is_empty() {
+        if dir.join(CHECKSUMS_PATH).is_file() {
+            if let Err(e) = validate_checksums(dir, &mut report) {
+                report.problems.push(e.to_string());
+            }
+        }
+        return report;
+    }
+
+    if !dir.join("environment.yml").is_file() {
+        report.problems.push("environment.yml is missing".into());
+    }
+
+    // Delta packs leave out the packages of their base pack.
+    let base_packages: HashSet<&str> = metadata
+        .iter()
+        .flat_map(|metadata| &metadata.base_packages)
+        .map(String::as_str)
+        .collect();
+    let channel_dir = dir.join(CHANNEL_DIRECTORY_NAME);
+    let mut package_files = HashSet::new();
+    match std::fs::read_dir(&channel_dir)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+    {
+        Ok(entries) => {
+            for entry in entries {
+                if entry.path().is_dir() {
+                    validate_subdir(
+                        &entry.path(),
+                        &base_packages,
+                        &mut package_files,
+                        &mut report,
+                    );
+                }
+            }
+        }
+        Err(e) => report
+            .problems
+            .push(format!("could not read channel directory: {}", e)),
+    }
+
+    for injected in metadata.iter().flat_map(|metadata| &metadata.injected) {
+        if !package_files.contains(&injected.filename) {
+            report.problems.push(format!(
+                "injected package {} is not part of the channel",
+                injected.filename
+            ));
+        }
+    }
+
+    if dir.join(CHECKSUMS_PATH).is_file() {
+        if let Err(e) = validate_checksums(dir, &mut report) {
+            report.problems.push(e.to_string());
+        }
+    }
+
+    report
+}
+
+/// Check that every package in the repodata of `subdir` exists with the recorded size and hash.
+/// Packages in `base_packages` may be missing.
+fn validate_subdir(
+    subdir: &Path,
+    base_packages: &HashSet<&str>,
+    package_files: &mut HashSet<String>,
+    report: &mut ValidationReport,
+) {
+    let name = subdir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let repodata = std::fs::read_to_string(subdir.join("repodata.json"))
+        .map_err(|e| anyhow!("could not read repodata of {}: {}", name, e))
+        .and_then(|contents| {
+            serde_json::from_str::<RepoData>(&contents)
+                .map_err(|e| anyhow!("could not parse repodata of {}: {}", name, e))
+        });
+    let repodata = match repodata {
+        Ok(repodata) => repodata,
+        Err(e) => {
+            report.problems.push(e.to_string());
+            return;
+        }
+    };
+
+    let mut listed = HashSet::new();
+    for (file_name, record) in repodata.packages.iter().chain(&repodata.conda_packages) {
+        report.packages += 1;
+        listed.insert(file_name.as_str());
+        package_files.insert(file_name.clone());
+        let relative_path = format!("{}/{}", name, file_name);
+        let path = subdir.join(file_name);
+
+        if ArchiveType::try_from(&path).is_none() {
+            report
+                .problems
+                .push(format!("{} is not a conda package", relative_path));
+        }
+        let size = match path.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_)
+                if base_packages
+                    .contains(format!("{}/{}", CHANNEL_DIRECTORY_NAME, relative_path).as_str()) =>
+            {
+                continue
+            }
+            Err(_) => {
+                report.problems.push(format!(
+                    "{} is listed in the repodata but missing",
+                    relative_path
+                ));
+                continue;
+            }
+        };
+        if let Some(expected) = record.size.filter(|expected| *expected != size) {
+            report.problems.push(format!(
+                "{} has a size of {} bytes, expected {}",
+                relative_path, size, expected
+            ));
+        }
+        if let Some(expected) = record.sha256 {
+            match rattler_digest::compute_file_digest::<Sha256>(&path) {
+                Ok(sha256) if sha256 == expected => {}
+                Ok(sha256) => report.problems.push(format!(
+                    "{} has sha256 {:x}, expected {:x}",
+                    relative_path, sha256, expected
+                )),
+                Err(e) => report
+                    .problems
+                    .push(format!("could not hash {}: {}", relative_path, e)),
+            }
+        }
+    }
+
+    let entries = std::fs::read_dir(subdir)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .unwrap_or_default();
+    for entry in entries {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name != "repodata.json" && !listed.contains(file_name.as_str()) {
+            report.problems.push(format!(
+                "{}/{} is not listed in the repodata",
+                name, file_name
+            ));
+        }
+    }
+}
+
+/// Compare the files of a directory pack with its `checksums.json`.
+fn validate_checksums(dir: &Path, report: &mut ValidationReport) -> Result<()> {
+    let contents = std::fs::read_to_string(dir.join(CHECKSUMS_PATH))
+        .map_err(|e| anyhow!("could not read {}: {}", CHECKSUMS_PATH, e))?;
+    let expected: DirectoryChecksums = serde_json::from_str(&contents)
+        .map_err(|e| anyhow!("could not parse {}: {}", CHECKSUMS_PATH, e))?;
+    let actual = DirectoryChecksums::new(dir)?;
+    if actual.digest == expected.digest {
+        return Ok(());
+    }
+
+    for (path, sha256) in &expected.files {
+        match actual.files.get(path) {
+            None => report.problems.push(format!(
+                "{} is listed in {} but missing",
+                path, CHECKSUMS_PATH
+            )),
+            Some(actual) if actual != sha256 => report
+                .problems
+                .push(format!("{} does not match {}", path, CHECKSUMS_PATH)),
+            Some(_) => {}
+        }
+    }
+    for path in actual.files.keys() {
+        if !expected.files.contains_key(path) {
+            report
+                .problems
+                .push(format!("{} is not listed in {}", path, CHECKSUMS_PATH));
+        }
+    }
+    report.problems.push(format!(
+        "the layout digest {} does not match {} in {}",
+        actual.digest, expected.digest, CHECKSUMS_PATH
+    ));
+
+    Ok(())
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        low_level::{
+            default_executable_header, write_binary_executable, write_self_extracting_executable,
+        },
+        LineEndings, WindowsFlavor,
+    };
+    use rattler_conda_types::Platform;
+    use rattler_digest::digest::Digest;
+    use rstest::*;
+    use serde_json::json;
+
+    fn create_pack(dir: &Path) {
+        let package = b"not really a conda package";
+        let subdir = dir.join(CHANNEL_DIRECTORY_NAME).join("noarch");
+        std::fs::create_dir_all(&subdir).unwrap();
+        std::fs::write(subdir.join("pkg-1.0-0.conda"), package).unwrap();
+        let repodata = json!({
+            "info": {"subdir": "noarch"},
+            "packages": {},
+            "packages.conda": {
+                "pkg-1.0-0.conda": {
+                    "name": "pkg",
+                    "version": "1.0",
+                    "build": "0",
+                    "build_number": 0,
+                    "depends": [],
+                    "subdir": "noarch",
+                    "size": package.len(),
+                    "sha256": format!("{:x}", Sha256::digest(package)),
+                }
+            }
+        });
+        std::fs::write(subdir.join("repodata.json"), repodata.to_string()).unwrap();
+        std::fs::write(
+            dir.join(PIXI_PACK_METADATA_PATH),
+            serde_json::to_string(&PixiPackMetadata::default()).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.join("environment.yml"), "channels: []\n").unwrap();
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_validate() {
+        let dir = tempfile::tempdir().unwrap();
+        create_pack(dir.path());
+        let report = validate(dir.path()).await.unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.packages, 1);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_validate_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        create_pack(dir.path());
+        let subdir = dir.path().join(CHANNEL_DIRECTORY_NAME).join("noarch");
+        std::fs::write(subdir.join("pkg-1.0-0.conda"), b"tampered").unwrap();
+        std::fs::write(subdir.join("unknown-1.0-0.conda"), b"").unwrap();
+        std::fs::remove_file(dir.path().join("environment.yml")).unwrap();
+
+        let report = validate(dir.path()).await.unwrap();
+        assert_eq!(report.problems.len(), 4, "{:?}", report.problems);
+        assert!(report.problems[0].starts_with("environment.yml"));
+        assert!(report.problems[1].starts_with("noarch/pkg-1.0-0.conda has a size"));
+        assert!(report.problems[2].starts_with("noarch/pkg-1.0-0.conda has sha256"));
+        assert!(report.problems[3].starts_with("noarch/unknown-1.0-0.conda is not listed"));
+    }
+
+    #[rstest]
+    #[case(Platform::Linux64, WindowsFlavor::Powershell)]
+    #[case(Platform::Win64, WindowsFlavor::Powershell)]
+    #[case(Platform::Win64, WindowsFlavor::Bat)]
+    #[tokio::test]
+    async fn test_validate_executable(
+        #[case] platform: Platform,
+        #[case] windows_flavor: WindowsFlavor,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_dir = dir.path().join("pack");
+        create_pack(&pack_dir);
+        let mut executable = Vec::new();
+        write_self_extracting_executable(
+            &mut executable,
+            &pack_dir,
+            default_executable_header(platform, windows_flavor),
+            b"not really pixi-pack",
+            platform,
+            windows_flavor,
+            None,
+            LineEndings::default(),
+        )
+        .await
+        .unwrap();
+        let executable_path = dir.path().join("environment");
+        std::fs::write(&executable_path, &executable).unwrap();
+
+        let report = validate(&executable_path).await.unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.packages, 1);
+
+        // Truncated executables are missing the end of the archive.
+        std::fs::write(&executable_path, &executable[..executable.len() / 2]).unwrap();
+        assert!(validate(&executable_path).await.is_err());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_validate_binary_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_dir = dir.path().join("pack");
+        create_pack(&pack_dir);
+        let executable =
+            write_binary_executable(Vec::new(), &pack_dir, b"not really pixi-pack", None)
+                .await
+                .unwrap();
+        let executable_path = dir.path().join("environment");
+        std::fs::write(&executable_path, &executable).unwrap();
+
+        let report = validate(&executable_path).await.unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.packages, 1);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_validate_delta_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        create_pack(dir.path());
+        let package = "channel/noarch/pkg-1.0-0.conda";
+        std::fs::remove_file(dir.path().join(package)).unwrap();
+        assert!(!validate(dir.path()).await.unwrap().is_valid());
+
+        // Delta packs may leave out the packages of their base pack.
+        let metadata = PixiPackMetadata {
+            base_packages: vec![package.to_string()],
+            ..PixiPackMetadata::default()
+        };
+        std::fs::write(
+            dir.path().join(PIXI_PACK_METADATA_PATH),
+            serde_json::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+        let report = validate(dir.path()).await.unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_validate_environments() {
+        let dir = tempfile::tempdir().unwrap();
+        let environments_dir = dir.path().join(ENVIRONMENTS_DIRECTORY_NAME);
+        create_pack(&environments_dir.join("cpu"));
+        create_pack(&environments_dir.join("gpu"));
+        let metadata = PixiPackMetadata {
+            environments: vec!["cpu".to_string(), "gpu".to_string()],
+            ..PixiPackMetadata::default()
+        };
+        std::fs::write(
+            dir.path().join(PIXI_PACK_METADATA_PATH),
+            serde_json::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+        let report = validate(dir.path()).await.unwrap();
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.packages, 2);
+
+        std::fs::remove_file(environments_dir.join("gpu").join("environment.yml")).unwrap();
+        let report = validate(dir.path()).await.unwrap();
+        assert_eq!(
+            report.problems,
+            vec!["environment gpu: environment.yml is missing"]
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_validate_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        create_pack(dir.path());
+        let checksums = DirectoryChecksums::new(dir.path()).unwrap();
+        std::fs::write(
+            dir.path().join(CHECKSUMS_PATH),
+            serde_json::to_string(&checksums).unwrap(),
+        )
+        .unwrap();
+        assert!(validate(dir.path()).await.unwrap().is_valid());
+
+        std::fs::write(
+            dir.path().join("environment.yml"),
+            "channels: [conda-forge]\n",
+        )
+        .unwrap();
+        let report = validate(dir.path()).await.unwrap();
+        assert_eq!(report.problems.len(), 2, "{:?}", report.problems);
+        assert_eq!(
+            report.problems[0],
+            "environment.yml does not match checksums.json"
+        );
+    }
+}
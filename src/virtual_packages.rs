@@ -0,0 +1,191 @@
+use std::{collections::BTreeMap, process::Command};
+
+use rattler_conda_types::{ParseStrictness, Version, VersionSpec};
+
+use crate::SystemRequirement;
+
+/// The virtual packages that are detected on the host. Requirements on other virtual packages,
+/// e.g. `__archspec`, cannot be checked.
+const DETECTED_VIRTUAL_PACKAGES: [&str; 6] =
+    ["__cuda", "__glibc", "__linux", "__osx", "__unix", "__win"];
+
+/// The virtual packages of the host, like conda and pixi detect them.
+///
+/// Like in conda, `CONDA_OVERRIDE_CUDA`, `CONDA_OVERRIDE_GLIBC` and `CONDA_OVERRIDE_OSX`
+/// override the detected versions, an empty value means that the virtual package is missing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostVirtualPackages {
+    /// The available virtual packages with their version, if they have one.
+    packages: BTreeMap<String, Option<Version>>,
+}
+
+impl HostVirtualPackages {
+    /// Detect the virtual packages of the host. This runs `ldd`, `nvidia-smi` and `sw_vers`.
+    pub fn detect() -> Self {
+        let mut packages = BTreeMap::new();
+        if cfg!(unix) {
+            packages.insert("__unix".to_string(), None);
+        }
+        if cfg!(windows) {
+            packages.insert("__win".to_string(), None);
+        }
+        if cfg!(target_os = "linux") {
+            let kernel = std::fs::read_to_string("/proc/sys/kernel/osrelease").ok();
+            packages.insert(
+                "__linux".to_string(),
+                kernel.as_deref().and_then(parse_version),
+            );
+        }
+        let detected = [
+            (
+                "__glibc",
+                "CONDA_OVERRIDE_GLIBC",
+                detect_glibc as fn() -> Option<Version>,
+            ),
+            ("__cuda", "CONDA_OVERRIDE_CUDA", detect_cuda),
+            ("__osx", "CONDA_OVERRIDE_OSX", detect_osx),
+        ];
+        for (name, override_variable, detect) in detected {
+            let version = match std::env::var(override_variable) {
+                Ok(version) if version.is_empty() => None,
+                Ok(version) => parse_version(&version),
+                Err(_) => detect(),
+            };
+            if let Some(version) = version {
+                packages.insert(name.to_string(), Some(version));
+            }
+        }
+        tracing::debug!("Detected virtual packages: {:?}", packages);
+        Self { packages }
+    }
+
+    /// Create the virtual packages from their names and versions, e.g. `("__glibc", "2.28")`.
+    pub fn from_packages<'a>(
+        packages: impl IntoIterator<Item = (&'a str, Option<&'a str>)>,
+    ) -> Self {
+        let packages = packages
+            .into_iter()
+            .map(|(name, version)| (name.to_string(), version.and_then(parse_version)))
+            .collect();
+        Self { packages }
+    }
+
+    /// Check whether the host meets `requirement`.
+    ///
+    /// Returns `None` if the requirement cannot be checked and otherwise why it is not met, if
+    /// it is not met.
+    pub fn check(&self, requirement: &SystemRequirement) -> Option<Result<(), String>> {
+        if !DETECTED_VIRTUAL_PACKAGES.contains(&requirement.name.as_str()) {
+            return None;
+        }
+        let Some(version) = self.packages.get(&requirement.name) else {
+            return Some(Err(format!("{} is not available", requirement.name)));
+        };
+        if requirement.spec.is_empty() {
+            return Some(Ok(()));
+        }
+        let Ok(spec) = VersionSpec::from_str(&requirement.spec, ParseStrictness::Lenient) else {
+            return None;
+        };
+        Some(match version {
+            Some(version) if spec.matches(version) => Ok(()),
+            Some(version) => Err(format!("{} is {}", requirement.name, version)),
+            None => Err(format!("the version of {} is unknown", requirement.name)),
+        })
+    }
+}
+
+/// Parse the leading version of `text`, e.g. `5.15.0` of `5.15.0-105-generic`.
+fn parse_version(text: &str) -> Option<Version> {
+    let version: String = text
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    version.trim_end_matches('.').parse().ok()
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The glibc version from `ldd --version`, e.g. `ldd (GNU libc) 2.39`. musl prints no version.
+fn detect_glibc() -> Option<Version> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let output = command_output("ldd", &["--version"])?;
+    let first_line = output.lines().next()?;
+    if !first_line.contains("GLIBC") && !first_line.contains("GNU libc") {
+        return None;
+    }
+    parse_version(first_line.split_whitespace().last()?)
+}
+
+/// The CUDA version supported by the driver from `nvidia-smi`, e.g. `CUDA Version: 12.4`.
+fn detect_cuda() -> Option<Version> {
+    if cfg!(target_os = "macos") {
+        return None;
+    }
+    let output = command_output("nvidia-smi", &[])?;
+    let (_, version) = output.split_once("CUDA Version:")?;
+    parse_version(version)
+}
+
+/// The macOS version from `sw_vers -productVersion`, e.g. `14.5`.
+fn detect_osx() -> Option<Version> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+    parse_version(&command_output("sw_vers", &["-productVersion"])?)
+}
+
+/* --------------------------------------------------------------------------------------------- */
+/*                                             TESTS                                             */
+/* --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("__glibc", ">=2.17", Some(Ok(())))]
+    #[case("__glibc", ">=2.34", Some(Err("__glibc is 2.28".to_string())))]
+    #[case("__cuda", ">=12", Some(Err("__cuda is not available".to_string())))]
+    #[case("__unix", "", Some(Ok(())))]
+    #[case("__linux", ">=4.18", Some(Err("the version of __linux is unknown".to_string())))]
+    #[case("__archspec", "1 x86_64_v3", None)]
+    fn test_check(
+        #[case] name: &str,
+        #[case] spec: &str,
+        #[case] expected: Option<Result<(), String>>,
+    ) {
+        let host = HostVirtualPackages::from_packages([
+            ("__glibc", Some("2.28")),
+            ("__unix", None),
+            ("__linux", None),
+        ]);
+        let requirement = SystemRequirement {
+            name: name.to_string(),
+            spec: spec.to_string(),
+            packages: vec!["pkg".to_string()],
+        };
+        assert_eq!(host.check(&requirement), expected);
+    }
+
+    #[rstest]
+    #[case("5.15.0-105-generic\n", Some("5.15.0"))]
+    #[case("12.4", Some("12.4"))]
+    #[case("unknown", None)]
+    fn test_parse_version(#[case] text: &str, #[case] expected: Option<&str>) {
+        assert_eq!(
+            parse_version(text),
+            expected.map(|version| version.parse().unwrap())
+        );
+    }
+}
@@ -5,8 +5,8 @@ use std::{fs, io};
 use std::{path::PathBuf, process::Command};
 
 use pixi_pack::{
-    unarchive, PackOptions, PixiPackMetadata, UnpackOptions, DEFAULT_PIXI_PACK_VERSION,
-    PIXI_PACK_VERSION,
+    unarchive, Compression, DirectoryChecksums, OutputFormat, PackOptions, PackWarning,
+    UnpackOptions, UnpackOutcome, WindowsFlavor,
 };
 use rattler_conda_types::Platform;
 use rattler_conda_types::RepoData;
@@ -32,42 +32,36 @@ fn options(
     #[default(Some(ShellEnum::Bash(Bash)))] shell: Option<ShellEnum>,
     #[default(false)] ignore_pypi_errors: bool,
     #[default("env")] env_name: String,
-    #[default(false)] create_executable: bool,
+    #[default(OutputFormat::Tar)] output_format: OutputFormat,
 ) -> Options {
     let output_dir = tempdir().expect("Couldn't create a temp dir for tests");
-    let pack_file = if create_executable {
-        output_dir.path().join(if platform.is_windows() {
+    let pack_file = match output_format {
+        OutputFormat::Tar => output_dir.path().join("environment.tar"),
+        OutputFormat::Dir => output_dir.path().join("environment"),
+        OutputFormat::Executable => output_dir.path().join(if platform.is_windows() {
             "environment.ps1"
         } else {
             "environment.sh"
-        })
-    } else {
-        output_dir.path().join("environment.tar")
-    };
-    let metadata = PixiPackMetadata {
-        version: DEFAULT_PIXI_PACK_VERSION.to_string(),
-        pixi_pack_version: Some(PIXI_PACK_VERSION.to_string()),
-        platform,
+        }),
+        OutputFormat::Oci => PathBuf::from("oci://localhost:5000/environment:latest"),
     };
 
     Options {
-        pack_options: PackOptions {
-            environment,
-            platform,
-            auth_file,
-            output_file: pack_file.clone(),
-            manifest_path,
-            metadata,
-            injected_packages: vec![],
-            ignore_pypi_errors,
-            create_executable,
-        },
-        unpack_options: UnpackOptions {
-            pack_file,
-            output_directory: output_dir.path().to_path_buf(),
-            env_name,
-            shell,
-        },
+        pack_options: PackOptions::builder()
+            .environment(environment)
+            .platform(platform)
+            .auth_file(auth_file)
+            .output_file(pack_file.clone())
+            .manifest_path(manifest_path)
+            .ignore_pypi_errors(ignore_pypi_errors)
+            .output_format(output_format)
+            .build(),
+        unpack_options: UnpackOptions::builder()
+            .pack_file(pack_file)
+            .output_directory(output_dir.path())
+            .env_name(env_name)
+            .shell(shell)
+            .build(),
         output_dir,
     }
 }
@@ -132,6 +126,43 @@ async fn test_simple_python(options: Options, required_fs_objects: Vec<&'static
         });
 }
 
+#[rstest]
+#[tokio::test]
+async fn test_pack_result(options: Options) {
+    let mut pack_options = options.pack_options;
+    pack_options.digest_name = true;
+    let result = pixi_pack::pack(pack_options).await.unwrap();
+
+    let file_name = result.output_file.file_name().unwrap().to_string_lossy();
+    assert!(file_name.starts_with("environment-"), "{}", file_name);
+    assert!(!options.unpack_options.pack_file.exists());
+    assert_eq!(
+        result.size_bytes,
+        Some(fs::metadata(&result.output_file).unwrap().len())
+    );
+    let digest = sha256_digest_bytes(&result.output_file).to_lowercase();
+    assert_eq!(
+        result.sha256.map(|digest| format!("{:x}", digest)),
+        Some(digest.clone())
+    );
+    assert!(file_name.contains(&digest[..12]), "{}", file_name);
+    assert!(result.conda_package_count > 0);
+    assert_eq!(result.ignored_pypi_package_count, 0);
+    assert_eq!(result.signature_file, None);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_pack_result_dry_run(options: Options) {
+    let mut pack_options = options.pack_options;
+    pack_options.dry_run = true;
+    let result = pixi_pack::pack(pack_options).await.unwrap();
+    assert!(!result.output_file.exists());
+    assert_eq!(result.size_bytes, None);
+    assert_eq!(result.sha256, None);
+    assert!(result.conda_package_count > 0);
+}
+
 #[rstest]
 #[case("conda")]
 #[case("tar.bz2")]
@@ -155,6 +186,14 @@ async fn test_inject(
     assert!(pack_result.is_ok(), "{:?}", pack_result);
     assert!(pack_file.is_file());
 
+    let metadata = pixi_pack::read_metadata(&pack_file).await.unwrap();
+    assert_eq!(metadata.injected.len(), 1);
+    assert_eq!(metadata.injected[0].name, "my-webserver");
+    assert_eq!(
+        metadata.injected[0].filename,
+        format!("my-webserver-0.1.0-pyh4616a5c_0.{package_format}")
+    );
+
     let env_dir = unpack_options.output_directory.join("env");
     let activate_file = unpack_options.output_directory.join("activate.sh");
     let unpack_result = pixi_pack::unpack(unpack_options).await;
@@ -172,6 +211,77 @@ async fn test_inject(
         });
 }
 
+#[rstest]
+#[tokio::test]
+async fn test_inject_channel(options: Options, mut required_fs_objects: Vec<&'static str>) {
+    let channel = tempdir().expect("Couldn't create a temp dir for tests");
+    let filename = "my-webserver-0.1.0-pyh4616a5c_0.conda";
+    fs::create_dir(channel.path().join("noarch")).unwrap();
+    fs::copy(
+        PathBuf::from("examples/webserver").join(filename),
+        channel.path().join("noarch").join(filename),
+    )
+    .unwrap();
+    let record = rattler_index::package_record_from_conda(
+        &PathBuf::from("examples/webserver").join(filename),
+    )
+    .unwrap();
+    pixi_pack::low_level::create_repodata_files(
+        [(filename.to_string(), record)].iter(),
+        channel.path(),
+    )
+    .await
+    .unwrap();
+
+    let mut pack_options = options.pack_options;
+    let unpack_options = options.unpack_options;
+    pack_options.manifest_path = PathBuf::from("examples/webserver/pixi.toml");
+    pack_options
+        .injected_channels
+        .push(channel.path().to_path_buf());
+
+    let pack_result = pixi_pack::pack(pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let env_dir = unpack_options.output_directory.join("env");
+    let unpack_result = pixi_pack::unpack(unpack_options).await;
+    assert!(unpack_result.is_ok(), "{:?}", unpack_result);
+
+    required_fs_objects.push("conda-meta/my-webserver-0.1.0-pyh4616a5c_0.json");
+    required_fs_objects
+        .iter()
+        .map(|dir| env_dir.join(dir))
+        .for_each(|dir| {
+            assert!(dir.exists(), "{:?} does not exist", dir);
+        });
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_inject_duplicate(options: Options) {
+    let mut pack_options = options.pack_options;
+    let filename = "my-webserver-0.1.0-pyh4616a5c_0.conda";
+    pack_options.injected_packages = vec![
+        PathBuf::from("examples/webserver").join(filename),
+        PathBuf::from("examples/webserver/my-webserver-0.1.0-*.conda"),
+    ];
+    pack_options.manifest_path = PathBuf::from("examples/webserver/pixi.toml");
+    let pack_file = options.unpack_options.pack_file.clone();
+
+    let warnings = pixi_pack::pack(pack_options).await.unwrap().warnings;
+    assert!(warnings.contains(&PackWarning::DuplicatePackage(filename.to_string())));
+
+    let metadata = pixi_pack::read_metadata(&pack_file).await.unwrap();
+    assert_eq!(
+        metadata
+            .injected
+            .iter()
+            .filter(|p| p.filename == filename)
+            .count(),
+        1
+    );
+}
+
 #[rstest]
 #[tokio::test]
 async fn test_inject_failure(options: Options) {
@@ -308,6 +418,13 @@ async fn test_pypi_ignore(
     pack_options.ignore_pypi_errors = ignore_pypi_errors;
     let pack_result = pixi_pack::pack(pack_options).await;
     assert_eq!(pack_result.is_err(), should_fail);
+    if let Ok(result) = pack_result {
+        assert!(result
+            .warnings
+            .iter()
+            .any(|w| matches!(w, PackWarning::IgnoredPypiPackage(_))));
+        assert!(result.ignored_pypi_package_count > 0);
+    }
 }
 
 fn sha256_digest_bytes(path: &PathBuf) -> String {
@@ -351,7 +468,7 @@ async fn test_reproducible_shasum(
     });
 
     let mut pack_options = options.pack_options.clone();
-    pack_options.create_executable = true;
+    pack_options.output_format = OutputFormat::Executable;
     pack_options.output_file = output_file.clone();
     let pack_result = pixi_pack::pack(pack_options).await;
     assert!(pack_result.is_ok(), "{:?}", pack_result);
@@ -366,7 +483,7 @@ async fn test_reproducible_shasum(
 #[tokio::test]
 async fn test_line_endings(
     #[case] platform: Platform,
-    #[with(PathBuf::from("examples/simple-python/pixi.toml"), "default".to_string(), platform, None, None, false, "env".to_string(), true)]
+    #[with(PathBuf::from("examples/simple-python/pixi.toml"), "default".to_string(), platform, None, None, false, "env".to_string(), OutputFormat::Executable)]
     options: Options,
 ) {
     let pack_result = pixi_pack::pack(options.pack_options.clone()).await;
@@ -384,6 +501,228 @@ async fn test_line_endings(
     }
 }
 
+#[rstest]
+#[tokio::test]
+async fn test_directory_output(
+    #[with(PathBuf::from("examples/simple-python/pixi.toml"), "default".to_string(), Platform::current(), None, None, false, "env".to_string(), OutputFormat::Dir)]
+    options: Options,
+) {
+    let pack_file = options.pack_options.output_file.clone();
+    let pack_result = pixi_pack::pack(options.pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    assert!(pack_file.is_dir());
+    assert!(pack_file.join("pixi-pack.json").is_file());
+    assert!(pack_file.join("environment.yml").is_file());
+    assert!(pack_file.join("channel").is_dir());
+
+    let checksums: DirectoryChecksums =
+        serde_json::from_str(&fs::read_to_string(pack_file.join("checksums.json")).unwrap())
+            .unwrap();
+    assert!(checksums.files.contains_key("pixi-pack.json"));
+    assert_eq!(checksums, DirectoryChecksums::new(&pack_file).unwrap());
+}
+
+#[rstest]
+#[case(OutputFormat::Tar)]
+#[case(OutputFormat::Dir)]
+#[tokio::test]
+async fn test_validate(options: Options, #[case] output_format: OutputFormat) {
+    let mut pack_options = options.pack_options;
+    pack_options.output_format = output_format;
+    pack_options.output_file = options.output_dir.path().join("environment");
+    let pack_file = pack_options.output_file.clone();
+    let pack_result = pixi_pack::pack(pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let report = pixi_pack::validate(&pack_file).await.unwrap();
+    assert!(report.is_valid(), "{:?}", report.problems);
+    assert!(report.packages > 0);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_reuse_from_previous_pack(options: Options) {
+    let pack_result = pixi_pack::pack(options.pack_options.clone()).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let mut pack_options = options.pack_options.clone();
+    pack_options.reuse_from = Some(options.pack_options.output_file.clone());
+    pack_options.output_file = options.output_dir.path().join("environment-reused.tar");
+    let pack_result = pixi_pack::pack(pack_options.clone()).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    assert_eq!(
+        sha256_digest_bytes(&options.pack_options.output_file),
+        sha256_digest_bytes(&pack_options.output_file)
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_fetch_into_cache(options: Options) {
+    let cache_dir = options.output_dir.path().join("cache");
+    let mut pack_options = options.pack_options.clone();
+    pack_options.cache_dir = Some(cache_dir.clone());
+
+    let fetch_result = pixi_pack::fetch(pack_options.clone()).await;
+    assert!(fetch_result.is_ok(), "{:?}", fetch_result);
+    let cached_packages = fs::read_dir(cache_dir.join(Platform::current().as_str()))
+        .unwrap()
+        .count();
+    assert!(cached_packages > 0);
+
+    let pack_result = pixi_pack::pack(pack_options.clone()).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+    let cached_pack = options.output_dir.path().join("environment-cached.tar");
+    fs::rename(&pack_options.output_file, &cached_pack).unwrap();
+
+    let pack_result = pixi_pack::pack(options.pack_options.clone()).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+    assert_eq!(
+        sha256_digest_bytes(&cached_pack),
+        sha256_digest_bytes(&options.pack_options.output_file)
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_pack_many_platforms(options: Options) {
+    let platforms = [Platform::Linux64, Platform::OsxArm64, Platform::Win64];
+    let pack_options: Vec<_> = platforms
+        .iter()
+        .map(|&platform| {
+            let mut pack_options = options.pack_options.clone();
+            pack_options.platform = platform;
+            pack_options.metadata.platform = platform;
+            pack_options.output_file = options
+                .output_dir
+                .path()
+                .join(format!("environment-{}.tar", platform));
+            pack_options
+        })
+        .collect();
+    let pack_result = pixi_pack::pack_many(pack_options.clone()).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    // Packs created concurrently are identical to packs created one by one.
+    for pack_options in pack_options {
+        let concurrent_pack = pack_options.output_file.clone();
+        let mut single_pack_options = pack_options;
+        single_pack_options.output_file = options.output_dir.path().join("environment.tar");
+        let pack_result = pixi_pack::pack(single_pack_options.clone()).await;
+        assert!(pack_result.is_ok(), "{:?}", pack_result);
+        assert_eq!(
+            sha256_digest_bytes(&concurrent_pack),
+            sha256_digest_bytes(&single_pack_options.output_file)
+        );
+    }
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_pack_environments(
+    #[with(PathBuf::from("examples/webserver/pixi.toml"))] options: Options,
+) {
+    let environments = vec!["default".to_string(), "prod".to_string()];
+    let pack_result = pixi_pack::pack_environments(options.pack_options, environments).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let metadata = pixi_pack::read_metadata(&options.unpack_options.pack_file)
+        .await
+        .unwrap();
+    assert_eq!(metadata.environments, vec!["default", "prod"]);
+
+    // One of the environments has to be selected.
+    let unpack_result = pixi_pack::unpack(options.unpack_options.clone()).await;
+    assert!(unpack_result.is_err());
+    let mut unpack_options = options.unpack_options.clone();
+    unpack_options.environment = Some("dev".to_string());
+    assert!(pixi_pack::unpack(unpack_options).await.is_err());
+
+    let mut unpack_options = options.unpack_options;
+    unpack_options.environment = Some("prod".to_string());
+    let env_dir = unpack_options.output_directory.join("env");
+    let unpack_result = pixi_pack::unpack(unpack_options).await;
+    assert!(unpack_result.is_ok(), "{:?}", unpack_result);
+    assert!(env_dir.join("conda-meta").is_dir());
+    // The dev dependencies are only part of the default environment.
+    assert!(!fs::read_dir(env_dir.join("conda-meta"))
+        .unwrap()
+        .any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with("pytest-")));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_pack_environments_digest_name(
+    #[with(PathBuf::from("examples/webserver/pixi.toml"))] options: Options,
+) {
+    let hook_log = options.output_dir.path().join("hook.log");
+    let mut pack_options = options.pack_options;
+    pack_options.digest_name = true;
+    pack_options.hooks = vec![std::sync::Arc::new(pixi_pack::CommandHook::new(format!(
+        "echo >> \"{}\"",
+        hook_log.display()
+    )))];
+    let environments = vec!["default".to_string(), "prod".to_string()];
+    let result = pixi_pack::pack_environments(pack_options, environments)
+        .await
+        .unwrap();
+
+    // The digest is added to the name of the combined pack only.
+    let file_name = result.output_file.file_name().unwrap().to_string_lossy();
+    let digest = sha256_digest_bytes(&result.output_file).to_lowercase();
+    assert!(file_name.contains(&digest[..12]), "{}", file_name);
+    assert!(!options.unpack_options.pack_file.exists());
+    let metadata = pixi_pack::read_metadata(&result.output_file).await.unwrap();
+    assert_eq!(metadata.environments, vec!["default", "prod"]);
+    // Hooks run once on the combined pack.
+    assert_eq!(fs::read_to_string(&hook_log).unwrap().lines().count(), 1);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_batch_executable(
+    #[with(PathBuf::from("examples/simple-python/pixi.toml"), "default".to_string(), Platform::Win64, None, None, false, "env".to_string(), OutputFormat::Executable)]
+    options: Options,
+) {
+    let mut pack_options = options.pack_options;
+    pack_options.windows_flavor = WindowsFlavor::Bat;
+    pack_options.output_file = options.output_dir.path().join("environment.bat");
+    let pack_file = pack_options.output_file.clone();
+    let pack_result = pixi_pack::pack(pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let output = fs::read_to_string(&pack_file).unwrap();
+    assert_eq!(output.matches("\r\n").count(), output.matches("\n").count());
+
+    let payload = output
+        .split_once("-----BEGIN CERTIFICATE-----\r\n")
+        .expect("Could not find payload start marker")
+        .1
+        .split_once("-----END CERTIFICATE-----")
+        .expect("Could not find payload end marker")
+        .0;
+    assert!(!payload.is_empty());
+    assert!(payload.lines().all(|line| line.trim_end().len() <= 64));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_batch_executable_non_windows(
+    #[with(PathBuf::from("examples/simple-python/pixi.toml"), "default".to_string(), Platform::Linux64, None, None, false, "env".to_string(), OutputFormat::Executable)]
+    options: Options,
+) {
+    let mut pack_options = options.pack_options;
+    pack_options.windows_flavor = WindowsFlavor::Bat;
+    let pack_result = pixi_pack::pack(pack_options).await;
+    assert!(pack_result.is_err());
+}
+
 #[rstest]
 #[tokio::test]
 async fn test_non_authenticated(
@@ -426,12 +765,70 @@ async fn test_custom_env_name(options: Options) {
     assert!(env_dir.is_dir());
 }
 
+#[rstest]
+#[tokio::test]
+async fn test_zstd_compression(options: Options, required_fs_objects: Vec<&'static str>) {
+    let pack_file = options.output_dir.path().join("environment.tar.zst");
+    let mut pack_options = options.pack_options;
+    pack_options.output_file = pack_file.clone();
+    pack_options.compression = Compression::Zstd(Compression::DEFAULT_ZSTD_LEVEL);
+    let pack_result = pixi_pack::pack(pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let mut unpack_options = options.unpack_options;
+    unpack_options.pack_file = pack_file;
+    let env_dir = unpack_options
+        .output_directory
+        .join(&unpack_options.env_name);
+    let unpack_result = pixi_pack::unpack(unpack_options).await;
+    assert!(unpack_result.is_ok(), "{:?}", unpack_result);
+    for path in required_fs_objects {
+        assert!(env_dir.join(path).exists(), "{} does not exist", path);
+    }
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_unpack_directory_pack(
+    #[with(PathBuf::from("examples/simple-python/pixi.toml"), "default".to_string(), Platform::current(), None, None, false, "env".to_string(), OutputFormat::Dir)]
+    options: Options,
+) {
+    let pack_file = options.pack_options.output_file.clone();
+    let pack_result = pixi_pack::pack(options.pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+    let checksums = DirectoryChecksums::new(&pack_file).unwrap();
+
+    let mut unpack_options = options.unpack_options;
+    unpack_options.pack_file = pack_file.clone();
+    let env_dir = unpack_options.output_directory.join("env");
+    let unpack_result = pixi_pack::unpack(unpack_options).await;
+    assert!(unpack_result.is_ok(), "{:?}", unpack_result);
+    assert!(env_dir.join("conda-meta").is_dir());
+
+    // The pack is used in place and must not be modified.
+    assert_eq!(DirectoryChecksums::new(&pack_file).unwrap(), checksums);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_idempotent_unpack(options: Options) {
+    let pack_result = pixi_pack::pack(options.pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let mut unpack_options = options.unpack_options;
+    unpack_options.idempotent = true;
+    let unpack_result = pixi_pack::unpack(unpack_options.clone()).await;
+    assert_eq!(unpack_result.unwrap(), UnpackOutcome::Installed);
+    let unpack_result = pixi_pack::unpack(unpack_options).await;
+    assert_eq!(unpack_result.unwrap(), UnpackOutcome::AlreadyInstalled);
+}
+
 #[rstest]
 #[tokio::test]
 async fn test_run_packed_executable(options: Options, required_fs_objects: Vec<&'static str>) {
     let temp_dir = tempfile::tempdir().unwrap();
     let mut pack_options = options.pack_options;
-    pack_options.create_executable = true;
+    pack_options.output_format = OutputFormat::Executable;
 
     #[cfg(target_os = "windows")]
     {
@@ -557,3 +954,75 @@ async fn test_run_packed_executable(options: Options, required_fs_objects: Vec<&
     // Keep the temporary directory alive until the end of the test
     drop(temp_dir);
 }
+
+#[cfg(not(target_os = "windows"))]
+#[rstest]
+#[tokio::test]
+async fn test_run_packed_executable_env_name_and_shell(options: Options) {
+    let mut pack_options = options.pack_options;
+    pack_options.output_format = OutputFormat::Executable;
+    pack_options.output_file = options.output_dir.path().join("environment.sh");
+    let pack_file = pack_options.output_file.clone();
+
+    let pack_result = pixi_pack::pack(pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let output = Command::new(&pack_file)
+        .arg("-o")
+        .arg(options.output_dir.path())
+        .arg("--env-name")
+        .arg("custom")
+        .arg("--shell")
+        .arg("fish")
+        .output()
+        .expect("Failed to execute packed file for extraction");
+    assert!(
+        output.status.success(),
+        "Packed file execution failed: {:?}",
+        output
+    );
+
+    assert!(options.output_dir.path().join("custom").is_dir());
+    assert!(options.output_dir.path().join("activate.fish").is_file());
+}
+
+#[cfg(not(target_os = "windows"))]
+#[rstest]
+#[tokio::test]
+async fn test_exotic_paths(options: Options) {
+    let exotic_dir = options.output_dir.path().join("with spaces ünïcødé");
+    fs::create_dir(&exotic_dir).unwrap();
+    let mut pack_options = options.pack_options;
+    pack_options.output_format = OutputFormat::Executable;
+    pack_options.output_file = exotic_dir.join("my environment.sh");
+    let pack_file = pack_options.output_file.clone();
+
+    let pack_result = pixi_pack::pack(pack_options).await;
+    assert!(pack_result.is_ok(), "{:?}", pack_result);
+
+    let output = Command::new(&pack_file)
+        .arg("-o")
+        .arg(&exotic_dir)
+        .arg("--env-name")
+        .arg("my env")
+        .output()
+        .expect("Failed to execute packed file for extraction");
+    assert!(
+        output.status.success(),
+        "Packed file execution failed: {:?}",
+        output
+    );
+
+    let output = Command::new("bash")
+        .arg("-c")
+        .arg(r#". "$1" && python -c 'import sys; print(sys.prefix)'"#)
+        .arg("bash")
+        .arg(exotic_dir.join("activate.sh"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        PathBuf::from(String::from_utf8(output.stdout).unwrap().trim()),
+        exotic_dir.join("my env")
+    );
+}